@@ -0,0 +1,220 @@
+//! Conversions between rust values and the values of the vm.
+//!
+//! A value a tox function returns is converted as soon as the function returns as the
+//! garbage collector may free the objects it points to once the vm runs again. So there is
+//! no conversion from a `Value` into a `Value`.
+use crate::Error;
+use std::ptr;
+use vm::{ArrayObject, NativeType, StringObject, Value, ValueType};
+
+/// A rust value a tox function can be called with or a native can return
+pub trait IntoValue {
+    /// The tox type of the value. The type of the items of an empty array is unknown so
+    /// it is `Nil`
+    fn native_type(&self) -> NativeType;
+
+    /// Creates the value. Any object is created outside of a vm
+    fn into_value(self) -> Value;
+}
+
+/// A rust value a tox function can return
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, Error>;
+}
+
+/// The arguments of a call to a tox function
+pub trait IntoArgs {
+    fn native_types(&self) -> Vec<NativeType>;
+
+    fn into_values(self) -> Vec<Value>;
+}
+
+impl IntoValue for i64 {
+    fn native_type(&self) -> NativeType {
+        NativeType::Int
+    }
+
+    fn into_value(self) -> Value {
+        Value::int(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn native_type(&self) -> NativeType {
+        NativeType::Float
+    }
+
+    fn into_value(self) -> Value {
+        Value::float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn native_type(&self) -> NativeType {
+        NativeType::Bool
+    }
+
+    fn into_value(self) -> Value {
+        Value::bool(self)
+    }
+}
+
+impl IntoValue for () {
+    fn native_type(&self) -> NativeType {
+        NativeType::Nil
+    }
+
+    fn into_value(self) -> Value {
+        Value::nil()
+    }
+}
+
+impl IntoValue for String {
+    fn native_type(&self) -> NativeType {
+        NativeType::Str
+    }
+
+    fn into_value(self) -> Value {
+        Value::object(StringObject::from_owned(self, ptr::null_mut()))
+    }
+}
+
+impl IntoValue for &str {
+    fn native_type(&self) -> NativeType {
+        NativeType::Str
+    }
+
+    fn into_value(self) -> Value {
+        self.to_owned().into_value()
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn native_type(&self) -> NativeType {
+        let item = self.first().map_or(NativeType::Nil, IntoValue::native_type);
+
+        NativeType::Array(Box::new(item))
+    }
+
+    fn into_value(self) -> Value {
+        let items = self.into_iter().map(IntoValue::into_value).collect();
+
+        Value::object(ArrayObject::new(items, ptr::null_mut()))
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value.ty() {
+            ValueType::Int => Ok(value.as_int()),
+            _ => Err(mismatch("int", value)),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value.ty() {
+            ValueType::Float => Ok(value.as_float()),
+            _ => Err(mismatch("float", value)),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value.ty() {
+            ValueType::Bool => Ok(value.as_bool()),
+            _ => Err(mismatch("bool", value)),
+        }
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        match value.ty() {
+            ValueType::Nil => Ok(()),
+            _ => Err(mismatch("nil", value)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        if value.is_string() {
+            Ok(value.as_string().value().to_owned())
+        } else {
+            Err(mismatch("str", value))
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, Error> {
+        if value.is_array() {
+            value
+                .as_array()
+                .items
+                .iter()
+                .map(|item| T::from_value(*item))
+                .collect()
+        } else {
+            Err(mismatch("array", value))
+        }
+    }
+}
+
+macro_rules! into_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn native_types(&self) -> Vec<NativeType> {
+                let ($(ref $arg,)*) = *self;
+
+                vec![$($arg.native_type()),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn into_values(self) -> Vec<Value> {
+                let ($($arg,)*) = self;
+
+                vec![$($arg.into_value()),*]
+            }
+        }
+    };
+}
+
+into_args!();
+into_args!(A);
+into_args!(A, B);
+into_args!(A, B, C);
+into_args!(A, B, C, D);
+into_args!(A, B, C, D, E);
+into_args!(A, B, C, D, E, F);
+
+/// Whether a value of type `found` can be passed where the tox type `expected` is
+/// wanted. An empty array can be passed as any array
+pub(crate) fn accepts(expected: &NativeType, found: &NativeType) -> bool {
+    match (expected, found) {
+        (NativeType::Array(_), NativeType::Array(ref item)) if **item == NativeType::Nil => true,
+        (NativeType::Array(ref expected), NativeType::Array(ref found)) => accepts(expected, found),
+        _ => expected == found,
+    }
+}
+
+fn mismatch(expected: &str, value: Value) -> Error {
+    let found = match value.ty() {
+        ValueType::Int => "int",
+        ValueType::Float => "float",
+        ValueType::Bool => "bool",
+        ValueType::Nil => "nil",
+        ValueType::Object if value.is_string() => "str",
+        ValueType::Object if value.is_array() => "array",
+        ValueType::Object => "object",
+    };
+
+    Error::Conversion(format!(
+        "Expected a value of type `{}` but found `{}`",
+        expected, found
+    ))
+}
@@ -0,0 +1,465 @@
+//! Embeds tox within a rust program.
+//!
+//! An `Engine` compiles source code into a `Script` whose functions can be called with
+//! rust values. Errors are returned along with the diagnostics rendered against the
+//! source rather than printed.
+//!
+//! ```
+//! extern crate engine;
+//!
+//! use engine::Engine;
+//!
+//! let source = "fn add(a: int, b: int) -> int {
+//!     return a + b;
+//! }
+//!
+//! fn main() {}";
+//!
+//! let mut script = Engine::new().compile(source).unwrap();
+//! let sum: i64 = script.call("add", (1, 2)).unwrap();
+//!
+//! assert_eq!(sum, 3);
+//! ```
+extern crate frontend;
+extern crate syntax;
+extern crate util;
+extern crate vm;
+
+mod convert;
+
+pub use crate::convert::{FromValue, IntoArgs, IntoValue};
+pub use util::emmiter::ColourMode;
+pub use vm::{NativeRegistry, NativeType, Value};
+
+use crate::convert::accepts;
+use frontend::query::{self, Signature};
+use frontend::{compile, fold, monomorphise, Infer};
+use std::collections::HashMap;
+use std::error;
+use std::fmt::{self, Display};
+use std::io::Write;
+use std::rc::Rc;
+use syntax::parser::Parser;
+use util::emmiter::Reporter;
+use util::symbol::{SymbolFactory, Symbols};
+use vm::{Program, State, VM};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The source failed to parse, type check or compile. Holds the rendered diagnostics
+    Compile(String),
+    /// The program raised an error while it was running. Holds the rendered error and the
+    /// calls that lead to it
+    Runtime(String),
+    /// The script has no function with the name
+    NoFunction(String),
+    /// A function was called with the wrong number of arguments
+    Arity {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    /// A value could not be converted between rust and tox
+    Conversion(String),
+}
+
+/// Compiles scripts that can call the natives of its registry
+#[derive(Debug)]
+pub struct Engine {
+    natives: NativeRegistry,
+    /// Whether errors are rendered with colours. Never by default
+    colour: ColourMode,
+}
+
+/// A compiled program along with its heap and globals
+pub struct Script {
+    source: String,
+    symbols: Symbols<()>,
+    program: Program,
+    /// The signature of each function by name
+    signatures: HashMap<String, Signature>,
+    state: Option<State>,
+    colour: ColourMode,
+    /// Where `print` writes to. Stdout if `None`
+    output: Option<Box<dyn Write>>,
+}
+
+impl Engine {
+    /// An engine whose scripts can call the builtin natives
+    pub fn new() -> Self {
+        Self::with_natives(NativeRegistry::new())
+    }
+
+    pub fn with_natives(natives: NativeRegistry) -> Self {
+        Engine {
+            natives,
+            colour: ColourMode::Never,
+        }
+    }
+
+    /// Sets whether the errors of the scripts compiled afterwards are rendered with colours
+    pub fn set_colour(&mut self, colour: ColourMode) {
+        self.colour = colour;
+    }
+
+    /// Registers a native function that the scripts compiled afterwards can call. The
+    /// function returns a rust value which is converted into a new tox value, so a string
+    /// or an array can be returned as a `String` or a `Vec`
+    pub fn register<F, R>(
+        &mut self,
+        name: &str,
+        params: Vec<NativeType>,
+        returns: NativeType,
+        function: F,
+    ) where
+        F: Fn(&[Value]) -> R + 'static,
+        R: IntoValue,
+    {
+        self.natives
+            .register(name, params, returns, move |args| function(args).into_value())
+    }
+
+    /// Parses, type checks and compiles the source. Nothing is run until a function of
+    /// the script is called
+    pub fn compile(&self, source: &str) -> Result<Script, Error> {
+        let strings = Rc::new(SymbolFactory::new());
+        let mut symbols = Symbols::new(Rc::clone(&strings));
+        let mut reporter = Reporter::new();
+        reporter.set_colour(self.colour);
+
+        let ast = Parser::new(source, reporter.clone(), &mut symbols)
+            .parse()
+            .map_err(|_| Error::Compile(reporter.render(source)))?;
+
+        let typed_ast = Infer::new()
+            .infer(ast, &strings, &self.natives, &mut reporter)
+            .map_err(|_| Error::Compile(reporter.render(source)))?;
+
+        let typed_ast = monomorphise(typed_ast, &mut symbols);
+        let typed_ast = fold(typed_ast);
+
+        let (program, objects) = compile(&typed_ast, &symbols, &mut reporter)
+            .map_err(|_| Error::Compile(reporter.render(source)))?;
+
+        let signatures = typed_ast
+            .functions
+            .iter()
+            .filter_map(|function| {
+                query::signature(&typed_ast, function.name)
+                    .map(|signature| (symbols.name(function.name), signature))
+            })
+            .collect();
+
+        // the heap takes ownership of the objects the program allocated
+        let main = symbols.symbol("main");
+        let state = State::new(&self.natives, &mut symbols);
+        let state = VM::with_state(main, &program, objects, state)
+            .expect("The type checker ensures there is a main function")
+            .into_state();
+
+        Ok(Script {
+            source: source.to_owned(),
+            symbols,
+            program,
+            signatures,
+            state: Some(state),
+            colour: self.colour,
+            output: None,
+        })
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Script {
+    /// Sets where `print` writes to; stdout by default
+    pub fn set_output<W: Write + 'static>(&mut self, output: W) {
+        self.output = Some(Box::new(output));
+    }
+
+    /// Runs the `main` function. The globals are set at the start of `main` so run it
+    /// before calling a function that uses them
+    pub fn run(&mut self) -> Result<(), Error> {
+        self.call("main", ())
+    }
+
+    /// Calls a function with the arguments and converts the value it returns. The heap and
+    /// globals are kept from one call to the next
+    pub fn call<A: IntoArgs, R: FromValue>(&mut self, name: &str, args: A) -> Result<R, Error> {
+        let signature = match self.signatures.get(name) {
+            Some(signature) => signature,
+            None => return Err(Error::NoFunction(name.to_owned())),
+        };
+
+        let types = args.native_types();
+
+        if types.len() != signature.params.len() {
+            return Err(Error::Arity {
+                function: name.to_owned(),
+                expected: signature.params.len(),
+                found: types.len(),
+            });
+        }
+
+        for (param, ty) in signature.params.iter().zip(types.iter()) {
+            match *param {
+                Some(ref param) if accepts(param, ty) => (),
+                Some(ref param) => {
+                    return Err(Error::Conversion(format!(
+                        "`{}` expected an argument of type `{}` but found `{}`",
+                        name, param, ty
+                    )))
+                }
+                None => {
+                    return Err(Error::Conversion(format!(
+                        "`{}` takes an argument that can't be passed from rust",
+                        name
+                    )))
+                }
+            }
+        }
+
+        let main = self.symbols.symbol("main");
+        let function = self.symbols.symbol(name);
+        let state = self
+            .state
+            .take()
+            .expect("The state is only taken during a call");
+
+        let mut vm = VM::with_state(main, &self.program, ::std::ptr::null_mut(), state)
+            .expect("The type checker ensures there is a main function");
+
+        if let Some(ref mut output) = self.output {
+            vm.set_output(output);
+        }
+
+        let (source, symbols, colour) = (&self.source, &self.symbols, self.colour);
+
+        // the value is converted before any object it points to can be freed
+        let result = vm
+            .call(function, args.into_values())
+            .map_err(|error| Error::Runtime(error.render(source, symbols, colour)))
+            .and_then(R::from_value);
+
+        self.state = Some(vm.into_state());
+
+        result
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Compile(ref diagnostics) | Error::Runtime(ref diagnostics) => {
+                write!(f, "{}", diagnostics)
+            }
+            Error::NoFunction(ref name) => write!(f, "There is no function called `{}`", name),
+            Error::Arity {
+                ref function,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` expected {} arguments but was called with {}",
+                function, expected, found
+            ),
+            Error::Conversion(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use super::{ColourMode, Engine, Error, NativeRegistry, NativeType};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    /// An output that can be read once the script has written to it
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const SOURCE: &str = "fn greet(name: str) -> str {
+    return \"Hello \" + name;
+}
+
+fn sum(numbers: [int]) -> int {
+    let total = 0;
+
+    for (let i = 0; i < 3; i = i + 1) {
+        total = total + numbers[i];
+    }
+
+    return total;
+}
+
+fn halve(n: float) -> float {
+    return n / 2.0;
+}
+
+fn divide(a: int, b: int) -> int {
+    return a / b;
+}
+
+fn main() {}";
+
+    #[test]
+    fn call() {
+        let mut script = Engine::new().compile(SOURCE).unwrap();
+
+        script.run().unwrap();
+
+        let greeting: String = script.call("greet", ("tox",)).unwrap();
+        let total: i64 = script.call("sum", (vec![1, 2, 3],)).unwrap();
+        let half: f64 = script.call("halve", (5.0,)).unwrap();
+
+        assert_eq!(greeting, "Hello tox");
+        assert_eq!(total, 6);
+        assert_eq!(half, 2.5);
+    }
+
+    #[test]
+    fn errors() {
+        let mut script = Engine::new().compile(SOURCE).unwrap();
+
+        match Engine::new().compile("fn main() { let a: int = true; }") {
+            Err(Error::Compile(diagnostics)) => {
+                assert!(diagnostics.contains("let a: int"));
+                assert!(!diagnostics.contains('\u{1b}'));
+            }
+            _ => panic!("Expected a compile error"),
+        }
+
+        assert_eq!(
+            script.call::<_, i64>("missing", ()),
+            Err(Error::NoFunction("missing".into()))
+        );
+
+        assert_eq!(
+            script.call::<_, i64>("divide", (1,)),
+            Err(Error::Arity {
+                function: "divide".into(),
+                expected: 2,
+                found: 1,
+            })
+        );
+
+        assert!(matches!(
+            script.call::<_, i64>("divide", (1.0, 2)),
+            Err(Error::Conversion(_))
+        ));
+
+        assert!(matches!(
+            script.call::<_, bool>("divide", (4, 2)),
+            Err(Error::Conversion(_))
+        ));
+
+        match script.call::<_, i64>("divide", (1, 0)) {
+            Err(Error::Runtime(error)) => assert!(error.contains("in `divide` on line")),
+            other => panic!("Expected a runtime error but found {:?}", other),
+        }
+
+        // the script can still be used after an error
+        assert_eq!(script.call("divide", (9, 3)), Ok(3));
+    }
+
+    #[test]
+    fn output() {
+        let mut script = Engine::new()
+            .compile("fn main() { print 1 + 2; print true; }")
+            .unwrap();
+
+        let output = Output::default();
+
+        script.set_output(output.clone());
+        script.run().unwrap();
+
+        assert_eq!(&*output.0.borrow(), b"3\ntrue\n");
+
+        let mut engine = Engine::new();
+        engine.set_colour(ColourMode::Always);
+
+        match engine.compile("fn main() { let a: int = true; }") {
+            Err(Error::Compile(diagnostics)) => assert!(diagnostics.contains('\u{1b}')),
+            _ => panic!("Expected a compile error"),
+        }
+    }
+
+    #[test]
+    fn natives() {
+        let mut engine = Engine::new();
+
+        engine.register("square", vec![NativeType::Int], NativeType::Int, |args| {
+            args[0].as_int() * args[0].as_int()
+        });
+        engine.register("shout", vec![NativeType::Str], NativeType::Str, |args| {
+            args[0].as_string().value().to_uppercase()
+        });
+        engine.register(
+            "letters",
+            vec![NativeType::Str],
+            NativeType::Array(Box::new(NativeType::Str)),
+            |args| {
+                let word = args[0].as_string().value();
+
+                word.chars().map(String::from).collect::<Vec<_>>()
+            },
+        );
+
+        let source = "fn twice(n: int) -> int { return square(n) * 2; }
+fn loud(word: str) -> str { return shout(word) + \"!\"; }
+fn last(word: str) -> str { let all = letters(word); return all[len(all) - 1]; }
+fn main() {}";
+
+        let mut script = engine.compile(source).unwrap();
+
+        assert_eq!(script.call("twice", (3,)), Ok(18));
+        assert_eq!(script.call("loud", ("hey",)), Ok("HEY!".to_owned()));
+        assert_eq!(script.call("last", ("tox",)), Ok("x".to_owned()));
+    }
+
+    #[test]
+    fn native_returns_argument() {
+        let mut natives = NativeRegistry::new();
+
+        // the string returned is already owned by the heap
+        natives.register("same", vec![NativeType::Str], NativeType::Str, |args| {
+            args[0]
+        });
+
+        let source = "fn main() {
+    let word = \"a\";
+
+    for (let i = 0; i < 2000; i = i + 1) {
+        word = same(word + \"b\");
+        word = same(substring(word, 0, 10));
+    }
+
+    print word;
+}";
+
+        let mut script = Engine::with_natives(natives).compile(source).unwrap();
+        let output = Output::default();
+
+        script.set_output(output.clone());
+        script.run().unwrap();
+
+        assert_eq!(&*output.0.borrow(), b"abbbbbbbbb\n");
+    }
+}
@@ -9,6 +9,17 @@ use util::symbol::Symbol;
 pub struct Program {
     pub functions: Vec<Function>,
     pub classes: Vec<Class>,
+    pub globals: Vec<Global>,
+}
+
+/// A variable that every function can use
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: Symbol,
+    pub ty: Type,
+    /// The value the global is set to before `main` runs. A global that was set by an
+    /// earlier program i.e a previous repl input has no value
+    pub value: Option<Spanned<TypedExpression>>,
 }
 
 #[derive(Debug, Clone)]
@@ -53,9 +64,26 @@ pub struct FunctionParam {
 
 #[derive(Debug, Clone)]
 pub struct MatchArm {
-    pub pattern: Option<Spanned<TypedExpression>>,
+    pub pattern: Spanned<TypedPattern>,
     pub body: Spanned<TypedStatement>,
-    pub is_all: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct TypedPattern {
+    pub pattern: Pattern,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard,
+    Binding(Symbol),
+    Literal(Literal),
+    Variant {
+        enum_name: Symbol,
+        tag: u32,
+        inner: Option<Box<Spanned<TypedPattern>>>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -95,6 +123,7 @@ pub enum Expression {
     Array(Vec<Spanned<TypedExpression>>),
     Assign(Symbol, AssignOperator, Spanned<TypedExpression>),
     Binary(Spanned<TypedExpression>, Op, Spanned<TypedExpression>),
+    Builtin(Builtin, Vec<Spanned<TypedExpression>>),
     Call(Symbol, Vec<Spanned<TypedExpression>>),
 
     Cast(Spanned<TypedExpression>, Type),
@@ -115,7 +144,23 @@ pub enum Expression {
 
     Grouping(Spanned<TypedExpression>),
 
+    /// The pieces of an interpolated string, which are all strs
+    Interpolation(Vec<Spanned<TypedExpression>>),
+
     Index(Spanned<TypedExpression>, Spanned<TypedExpression>),
+    /// Array, Start, End
+    Slice(
+        Spanned<TypedExpression>,
+        Option<Spanned<TypedExpression>>,
+        Option<Spanned<TypedExpression>>,
+    ),
+    /// Array, Index, Operator, Value
+    SetIndex(
+        Spanned<TypedExpression>,
+        Spanned<TypedExpression>,
+        AssignOperator,
+        Spanned<TypedExpression>,
+    ),
     InstanceMethodCall {
         method_name: Symbol,
         instance: Spanned<TypedExpression>,
@@ -154,3 +199,52 @@ pub enum Expression {
         tag: u32,
     },
 }
+
+/// The functions that work on any array, and the ones that return an `Option`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    /// `len(xs) -> int` also works on a str
+    Len,
+    /// `push(xs, item)`
+    Push,
+    /// `pop(xs) -> item`
+    Pop,
+    /// `insert(xs, index, item)`
+    Insert,
+    /// `remove(xs, index) -> item`
+    Remove,
+    /// `contains(xs, item) -> bool`
+    Contains,
+    /// `parse_int(s) -> Option<int>`
+    ParseInt,
+    /// `parse_float(s) -> Option<float>`
+    ParseFloat,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "len" => Some(Builtin::Len),
+            "push" => Some(Builtin::Push),
+            "pop" => Some(Builtin::Pop),
+            "insert" => Some(Builtin::Insert),
+            "remove" => Some(Builtin::Remove),
+            "contains" => Some(Builtin::Contains),
+            "parse_int" => Some(Builtin::ParseInt),
+            "parse_float" => Some(Builtin::ParseFloat),
+            _ => None,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+            Builtin::Push => "push",
+            Builtin::Pop => "pop",
+            Builtin::Insert => "insert",
+            Builtin::Remove => "remove",
+            Builtin::Contains => "contains",
+            Builtin::ParseInt => "parse_int",
+            Builtin::ParseFloat => "parse_float",
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Free variable analysis for closures.
+//!
+//! A variable is free within a closure if it is used but not declared within the closure.
+//! The free variables of a closure are the variables it has to capture from the functions
+//! that enclose it.
+use crate::ast as t;
+use std::collections::HashSet;
+use util::pos::Spanned;
+use util::symbol::Symbol;
+
+/// Returns the free variables of the closure in the order they are first used
+pub(crate) fn free_variables(function: &t::Function) -> Vec<Symbol> {
+    let mut finder = Finder::default();
+
+    finder.function(function);
+
+    finder.free
+}
+
+#[derive(Debug, Default)]
+struct Finder {
+    /// The variables declared in each scope
+    scopes: Vec<HashSet<Symbol>>,
+    free: Vec<Symbol>,
+}
+
+impl Finder {
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashSet::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, symbol: Symbol) {
+        self.scopes.last_mut().unwrap().insert(symbol);
+    }
+
+    fn used(&mut self, symbol: Symbol) {
+        if self.scopes.iter().any(|scope| scope.contains(&symbol)) {
+            return;
+        }
+
+        if !self.free.contains(&symbol) {
+            self.free.push(symbol);
+        }
+    }
+
+    fn function(&mut self, function: &t::Function) {
+        self.begin_scope();
+
+        for param in function.params.iter() {
+            self.declare(param.name);
+        }
+
+        self.statement(&function.body);
+
+        self.end_scope();
+    }
+
+    fn statement(&mut self, statement: &Spanned<t::TypedStatement>) {
+        match statement.value.statement.value {
+            t::Statement::Break | t::Statement::Continue => (),
+            t::Statement::Block(ref statements) => {
+                self.begin_scope();
+
+                for statement in statements {
+                    self.statement(statement);
+                }
+
+                self.end_scope();
+            }
+            t::Statement::Expr(ref expr)
+            | t::Statement::Print(ref expr)
+            | t::Statement::Return(ref expr) => self.expression(expr),
+            t::Statement::If {
+                ref cond,
+                ref then,
+                ref otherwise,
+            } => {
+                self.expression(cond);
+                self.statement(then);
+
+                if let Some(ref otherwise) = *otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            t::Statement::While(ref cond, ref body) => {
+                self.expression(cond);
+                self.statement(body);
+            }
+            t::Statement::Let {
+                ref ident,
+                ref expr,
+                ..
+            } => {
+                if let Some(ref expr) = *expr {
+                    self.expression(expr);
+                }
+
+                self.declare(*ident);
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Spanned<t::TypedPattern>) {
+        match pattern.value.pattern {
+            t::Pattern::Binding(symbol) => self.declare(symbol),
+            t::Pattern::Variant {
+                inner: Some(ref inner),
+                ..
+            } => self.pattern(inner),
+            _ => (),
+        }
+    }
+
+    fn expression(&mut self, expr: &Spanned<t::TypedExpression>) {
+        match expr.value.expr.value {
+            t::Expression::Literal(_) | t::Expression::VariantNoData { .. } => (),
+
+            t::Expression::Var(symbol, _) => self.used(symbol),
+
+            t::Expression::Assign(symbol, _, ref expr) => {
+                self.used(symbol);
+                self.expression(expr);
+            }
+
+            t::Expression::Call(symbol, ref args) => {
+                self.used(symbol); // A closure stored in a variable
+
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+
+            t::Expression::Array(ref items)
+            | t::Expression::Builtin(_, ref items)
+            | t::Expression::Interpolation(ref items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+
+            t::Expression::Slice(ref target, ref start, ref end) => {
+                self.expression(target);
+
+                for bound in start.iter().chain(end) {
+                    self.expression(bound);
+                }
+            }
+
+            t::Expression::Cast(ref expr, _)
+            | t::Expression::Grouping(ref expr)
+            | t::Expression::Unary(_, ref expr)
+            | t::Expression::VariantWithData {
+                inner: ref expr, ..
+            }
+            | t::Expression::GetProperty {
+                property: ref expr, ..
+            }
+            | t::Expression::GetMethod {
+                method: ref expr, ..
+            } => self.expression(expr),
+
+            t::Expression::Binary(ref lhs, _, ref rhs)
+            | t::Expression::Index(ref lhs, ref rhs)
+            | t::Expression::Set(_, ref lhs, ref rhs) => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+
+            t::Expression::Ternary(ref cond, ref lhs, ref rhs)
+            | t::Expression::SetIndex(ref cond, ref lhs, _, ref rhs) => {
+                self.expression(cond);
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+
+            t::Expression::Closure(ref function) => self.function(function),
+
+            t::Expression::Match { ref cond, ref arms } => {
+                self.expression(cond);
+
+                for arm in arms.value.iter() {
+                    self.begin_scope();
+                    self.pattern(&arm.value.pattern);
+                    self.statement(&arm.value.body);
+                    self.end_scope();
+                }
+            }
+
+            t::Expression::ClassLiteral { ref properties, .. } => {
+                for property in properties {
+                    self.expression(&property.value.expr);
+                }
+            }
+
+            t::Expression::InstanceMethodCall {
+                ref instance,
+                ref params,
+                ..
+            } => {
+                self.expression(instance);
+
+                for param in params {
+                    self.expression(param);
+                }
+            }
+
+            t::Expression::StaticMethodCall { ref params, .. } => {
+                for param in params {
+                    self.expression(param);
+                }
+            }
+        }
+    }
+}
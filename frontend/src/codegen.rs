@@ -1,14 +1,20 @@
 use super::infer::types::{Type, TypeCon};
 use crate::ast;
-use fnv::FnvHashMap;
+use crate::capture::free_variables;
+use fnv::{FnvHashMap, FnvHashSet};
 use opcode;
+use std::fmt::Display;
 use std::hash::Hash;
 use util::emmiter::Reporter;
-use util::pos::{Span, Spanned};
+use util::pos::{Span, Spanned, EMPTYSPAN};
 use util::symbol::{Symbol, Symbols};
 use vm::{Chunk, Class, Function, FunctionObject, Program, RawObject, StringObject, Value};
 type ParseResult<T> = Result<T, ()>;
 
+/// The most strings a CONCATN joins at once so that a string with many holes doesn't
+/// fill the stack
+const CONCAT_BATCH: usize = 32;
+
 #[derive(Debug, Clone, Copy)]
 struct LoopDescription {
     /// The index of the start label
@@ -35,11 +41,16 @@ impl<K: Hash + Eq + Copy, V: Clone> StackedMap<K, V> {
         self.scopes.push(None);
     }
 
-    pub fn end_scope(&mut self) {
+    /// Removes everything entered since the last `begin_scope` and returns the values removed
+    pub fn end_scope(&mut self) -> Vec<V> {
+        let mut removed = Vec::new();
+
         while let Some(Some(value)) = self.scopes.pop() {
             let mapping = self.table.get_mut(&value).expect("Symbol not in Symbols");
-            mapping.pop();
+            removed.extend(mapping.pop());
         }
+
+        removed
     }
 
     /// Enters a peice of data into the current scope
@@ -62,42 +73,73 @@ pub struct Builder<'a> {
     locals: StackedMap<Symbol, usize>,
 
     params: FnvHashMap<Symbol, usize>,
+    /// The variables captured from the enclosing functions and their index in the closure
+    upvalues: FnvHashMap<Symbol, usize>,
+    /// The slots of the locals that have been captured by a closure
+    captured: FnvHashSet<usize>,
     current_loop: Option<LoopDescription>,
     ///  A linked list of all the objects allocated. This
     /// is passed to the vm so runtime collection can be done
-    pub objects: RawObject,
+    pub objects: &'a mut RawObject,
 
     symbols: &'a Symbols<()>,
+    /// The names of the global variables
+    globals: &'a FnvHashSet<Symbol>,
+    /// The names of the functions the program declares. A call to any other function is a
+    /// call to a native function
+    functions: &'a FnvHashSet<Symbol>,
     /// The reporter used to reporter any errors
     reporter: &'a mut Reporter,
     /// The slot of the variable
     slots: u32,
-    ///
-    line: u32,
+    /// The span of the code currently being compiled
+    span: Span,
 }
 
 impl<'a> Builder<'a> {
     pub fn new(
         reporter: &'a mut Reporter,
         symbols: &'a Symbols<()>,
-        objects: RawObject,
+        globals: &'a FnvHashSet<Symbol>,
+        functions: &'a FnvHashSet<Symbol>,
+        objects: &'a mut RawObject,
         params: FnvHashMap<Symbol, usize>,
     ) -> Self {
         Builder {
             chunk: Chunk::new(),
             locals: StackedMap::new(),
-            line: 0,
+            span: EMPTYSPAN,
             slots: 0,
             current_loop: None,
             symbols,
+            globals,
+            functions,
             params,
+            upvalues: FnvHashMap::default(),
+            captured: FnvHashSet::default(),
             objects,
             reporter,
         }
     }
 
+    /// Links a newly created object into the list of objects
+    fn allocate(&mut self, object: RawObject) -> RawObject {
+        *self.objects = object;
+        object
+    }
+
+    /// Reports an operator used by a specialised generic function on a type that the
+    /// operator isn't defined for
+    fn undefined_operator<O: Display>(&mut self, op: O, ty: &Type, span: Span) -> ParseResult<()> {
+        let msg = format!("`{}` is not defined for `{}`", op, ty.print(self.symbols));
+
+        self.reporter.error(msg, span);
+
+        Err(())
+    }
+
     pub fn emit_byte(&mut self, byte: u8) {
-        self.chunk.write(byte, self.line)
+        self.chunk.write(byte, self.span)
     }
 
     pub fn new_slot(&mut self) -> u32 {
@@ -133,27 +175,70 @@ impl<'a> Builder<'a> {
         self.emit_byte(byte2);
     }
 
+    /// Emits an operand using a variable length encoding so that constants, slots and
+    /// symbols aren't limited to a single byte
+    pub fn emit_operand(&mut self, operand: usize) {
+        self.chunk.write_operand(operand, self.span)
+    }
+
+    /// Emits an instruction followed by its operand
+    pub fn emit_instruction(&mut self, byte: u8, operand: usize) {
+        self.emit_byte(byte);
+        self.emit_operand(operand);
+    }
+
     pub fn emit_constant(&mut self, constant: Value, span: Span) -> ParseResult<()> {
         let value = self.make_constant(constant, span)?;
-        self.emit_bytes(opcode::CONSTANT, value);
+        self.emit_instruction(opcode::CONSTANT, value);
         Ok(())
     }
 
-    pub fn make_constant(&mut self, value: Value, span: Span) -> ParseResult<u8> {
-        let index = self.chunk.add_constant(value);
+    pub fn make_constant(&mut self, value: Value, _: Span) -> ParseResult<usize> {
+        Ok(self.chunk.add_constant(value))
+    }
 
-        if index > 256 {
-            self.reporter.error("too many constants in one chunk", span);
-            Err(())
-        } else {
-            Ok(index as u8)
+    /// Ends the current scope of locals; any local captured by a closure is moved into
+    /// its upvalue so each time the scope is entered a fresh variable is captured
+    pub fn end_scope(&mut self) {
+        for slot in self.locals.end_scope() {
+            if self.captured.contains(&slot) {
+                self.emit_instruction(opcode::CLOSEUPVALUE, slot);
+            }
         }
     }
 
-    pub fn set_span(&mut self, span: Span) {
-        if span.start.line > self.line {
-            self.line = span.start.line
+    /// Returns the opcodes used to read and write the variable and its operand
+    fn variable(&self, ident: Symbol) -> Option<(u8, u8, usize)> {
+        let local = self.locals.get(&ident);
+        let param = self.params.get(&ident);
+        let upvalue = self.upvalues.get(&ident);
+        let global = Some(ident).filter(|ident| self.globals.contains(ident));
+
+        local
+            .map(|slot| (opcode::GETLOCAL, opcode::SETLOCAL, *slot))
+            .or_else(|| param.map(|slot| (opcode::GETPARAM, opcode::SETPARAM, *slot)))
+            .or_else(|| upvalue.map(|index| (opcode::GETUPVALUE, opcode::SETUPVALUE, *index)))
+            .or_else(|| {
+                global.map(|ident| (opcode::GETGLOBAL, opcode::SETGLOBAL, ident.0 as usize))
+            })
+    }
+
+    /// Sets the globals that have a value before the rest of `main` runs
+    pub fn compile_globals(&mut self, globals: &[ast::Global]) -> ParseResult<()> {
+        for global in globals.iter() {
+            if let Some(ref value) = global.value {
+                self.compile_expression(value)?;
+                self.emit_instruction(opcode::SETGLOBAL, global.name.0 as usize);
+                self.emit_byte(opcode::POP);
+            }
         }
+
+        Ok(())
+    }
+
+    /// Sets the span recorded for the bytes that are emitted, returning the previous one
+    pub fn set_span(&mut self, span: Span) -> Span {
+        ::std::mem::replace(&mut self.span, span)
     }
 
     pub fn compile_statement(
@@ -169,7 +254,7 @@ impl<'a> Builder<'a> {
                 for statement in statements {
                     self.compile_statement(statement)?;
                 }
-                self.locals.end_scope();
+                self.end_scope();
 
                 Ok(())
             }
@@ -192,6 +277,12 @@ impl<'a> Builder<'a> {
             Statement::Expr(ref expr) => {
                 self.compile_expression(expr)?;
 
+                // Setting a property leaves nothing on the stack
+                if let ast::Expression::Set(..) = expr.value.expr.value {
+                } else {
+                    self.emit_byte(opcode::POP); // the value isn't used so don't keep it alive
+                }
+
                 Ok(())
             }
 
@@ -223,10 +314,14 @@ impl<'a> Builder<'a> {
 
                 self.compile_statement(then)?;
 
+                let end_label = self.emit_jump(opcode::JUMP);
+
                 self.patch_jump(false_label);
 
                 self.emit_byte(opcode::POP);
 
+                self.patch_jump(end_label);
+
                 Ok(())
             }
 
@@ -272,7 +367,8 @@ impl<'a> Builder<'a> {
 
                 self.locals.insert(*ident, slot as usize);
 
-                self.emit_bytes(opcode::SETLOCAL, slot as u8); // Write the symbol id
+                self.emit_instruction(opcode::SETLOCAL, slot as usize); // Write the symbol id
+                self.emit_byte(opcode::POP);
 
                 Ok(())
             }
@@ -304,87 +400,180 @@ impl<'a> Builder<'a> {
         }
     }
 
+    pub fn compile_literal(&mut self, literal: &ast::Literal, span: Span) -> ParseResult<()> {
+        use crate::ast::Literal;
+
+        match *literal {
+            Literal::False(_) => {
+                self.emit_byte(opcode::FALSE);
+            }
+            Literal::True(_) => {
+                self.emit_byte(opcode::TRUE);
+            }
+            Literal::Nil => {
+                self.emit_byte(opcode::NIL);
+            }
+            Literal::Int(ref n) => {
+                self.emit_constant(Value::int(*n), span)?;
+            }
+            Literal::Float(ref f) => {
+                self.emit_constant(Value::float(*f), span)?;
+            }
+            Literal::Str(ref string) => {
+                // the program can outlive the ast i.e when it is written to a file
+                let object = self.allocate(StringObject::from_owned(string.clone(), *self.objects));
+
+                self.emit_constant(Value::object(object), span)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compiles the tests of a pattern against the value stored in `slot` and binds any
+    /// variables. Returns the jumps that are taken if the pattern does not match; each
+    /// one leaves the result of the failed test on the stack
+    pub fn compile_pattern(
+        &mut self,
+        pattern: &Spanned<ast::TypedPattern>,
+        slot: u32,
+    ) -> ParseResult<Vec<usize>> {
+        use crate::ast::Pattern;
+        self.set_span(pattern.span);
+
+        let mut fail_jumps = Vec::new();
+
+        match pattern.value.pattern {
+            Pattern::Wildcard => (),
+
+            Pattern::Binding(ref ident) => {
+                self.locals.insert(*ident, slot as usize);
+            }
+
+            Pattern::Literal(ref literal) => {
+                self.emit_instruction(opcode::GETLOCAL, slot as usize);
+                self.compile_literal(literal, pattern.span)?;
+                self.emit_byte(opcode::EQUAL);
+
+                fail_jumps.push(self.emit_jump(opcode::JUMPNOT));
+                self.emit_byte(opcode::POP);
+            }
+
+            Pattern::Variant { tag, ref inner, .. } => {
+                self.emit_instruction(opcode::GETLOCAL, slot as usize);
+                self.emit_byte(opcode::ENUMTAG);
+                self.emit_constant(Value::int(i64::from(tag)), pattern.span)?;
+                self.emit_byte(opcode::EQUAL);
+
+                fail_jumps.push(self.emit_jump(opcode::JUMPNOT));
+                self.emit_byte(opcode::POP);
+
+                if let Some(ref inner) = *inner {
+                    let inner_slot = self.new_slot();
+
+                    self.emit_instruction(opcode::GETLOCAL, slot as usize);
+                    self.emit_byte(opcode::ENUMPAYLOAD);
+                    self.emit_instruction(opcode::SETLOCAL, inner_slot as usize);
+                    self.emit_byte(opcode::POP);
+
+                    fail_jumps.extend(self.compile_pattern(inner, inner_slot)?);
+                }
+            }
+        }
+
+        Ok(fail_jumps)
+    }
+
     pub fn compile_expression(&mut self, expr: &Spanned<ast::TypedExpression>) -> ParseResult<()> {
-        use crate::ast::{AssignOperator, Expression, Literal, Op};
-        self.set_span(expr.span);
+        // The instruction of an expression is emitted after its operands so the span is
+        // restored once they are compiled
+        let span = self.set_span(expr.span);
+        let result = self.compile_expression_value(expr);
+        self.set_span(span);
+        result
+    }
+
+    fn compile_expression_value(
+        &mut self,
+        expr: &Spanned<ast::TypedExpression>,
+    ) -> ParseResult<()> {
+        use crate::ast::{AssignOperator, Builtin, Expression, Op};
 
         match expr.value.expr.value {
             Expression::Assign(ref ident, ref op, ref expr) => {
-                let pos = if let Some(pos) = self.locals.get(ident) {
-                    *pos
-                } else if let Some(pos) = self.params.get(ident) {
-                    *pos
+                let (get, set, pos) = if let Some(variable) = self.variable(*ident) {
+                    variable
                 } else {
-                    unreachable!(); // Params are treated as locals so it should be present
+                    unreachable!(); // The type checker ensures the variable exists
                 };
 
                 match *op {
                     AssignOperator::Equal => {
                         self.compile_expression(expr)?;
-                        self.emit_bytes(opcode::SETLOCAL, pos as u8);
+                        self.emit_instruction(set, pos);
                     }
                     AssignOperator::MinusEqual => {
-                        self.emit_bytes(opcode::GETLOCAL, pos as u8); // get the var
+                        self.emit_instruction(get, pos); // get the var
 
                         let opcode = match expr.value.ty {
                             Type::App(TypeCon::Int, _) => opcode::SUB,
                             Type::App(TypeCon::Float, _) => opcode::SUBF,
-                            _ => unreachable!(), // type checker should prevent this
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         };
 
                         self.compile_expression(expr)?; // get the expr
 
                         self.emit_byte(opcode);
 
-                        self.emit_bytes(opcode::SETLOCAL, pos as u8); // store it in x
+                        self.emit_instruction(set, pos); // store it in x
                     }
 
                     AssignOperator::PlusEqual => {
-                        self.emit_bytes(opcode::GETLOCAL, pos as u8); // get the var
+                        self.emit_instruction(get, pos); // get the var
 
                         let opcode = match expr.value.ty {
                             Type::App(TypeCon::Int, _) => opcode::ADD,
                             Type::App(TypeCon::Float, _) => opcode::ADDF,
-                            _ => unreachable!(), // type checker should prevent this
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         };
 
                         self.compile_expression(expr)?; // get the expr
 
                         self.emit_byte(opcode);
 
-                        self.emit_bytes(opcode::SETLOCAL, pos as u8); // store it in x
+                        self.emit_instruction(set, pos); // store it in x
                     }
 
                     AssignOperator::SlashEqual => {
-                        self.emit_bytes(opcode::GETLOCAL, pos as u8); // get the var
+                        self.emit_instruction(get, pos); // get the var
 
                         let opcode = match expr.value.ty {
                             Type::App(TypeCon::Int, _) => opcode::DIV,
                             Type::App(TypeCon::Float, _) => opcode::DIVF,
-                            _ => unreachable!(), // type checker should prevent this
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         };
 
                         self.compile_expression(expr)?; // get the expr
 
                         self.emit_byte(opcode);
 
-                        self.emit_bytes(opcode::SETLOCAL, pos as u8); // store it in x
+                        self.emit_instruction(set, pos); // store it in x
                     }
 
                     AssignOperator::StarEqual => {
-                        self.emit_bytes(opcode::GETLOCAL, pos as u8); // get the var
+                        self.emit_instruction(get, pos); // get the var
 
                         let opcode = match expr.value.ty {
                             Type::App(TypeCon::Int, _) => opcode::MUL,
                             Type::App(TypeCon::Float, _) => opcode::MULF,
-                            _ => unreachable!(), // type checker should prevent this
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         };
 
                         self.compile_expression(expr)?; // get the expr
 
                         self.emit_byte(opcode);
 
-                        self.emit_bytes(opcode::SETLOCAL, pos as u8); // store it in x
+                        self.emit_instruction(set, pos); // store it in x
                     }
                 }
             }
@@ -395,11 +584,11 @@ impl<'a> Builder<'a> {
                     self.compile_expression(expr)?;
                 }
 
-                self.emit_bytes(opcode::ARRAY, exprs.len() as u8);
+                self.emit_instruction(opcode::ARRAY, exprs.len());
             }
 
             Expression::Index(ref target, ref index) => {
-                match expr.value.ty {
+                match target.value.ty {
                     Type::App(TypeCon::Str, _) => {
                         self.compile_expression(target)?;
                         self.compile_expression(index)?;
@@ -418,28 +607,106 @@ impl<'a> Builder<'a> {
                 }
             }
 
-            Expression::Literal(ref literal) => match *literal {
-                Literal::False(_) => {
-                    self.emit_byte(opcode::FALSE);
-                }
-                Literal::True(_) => {
-                    self.emit_byte(opcode::TRUE);
+            Expression::SetIndex(ref target, ref index, ref op, ref value) => {
+                self.compile_expression(target)?;
+                self.compile_expression(index)?;
+
+                let opcode = match (op, &value.value.ty) {
+                    (AssignOperator::Equal, _) => None,
+                    (AssignOperator::PlusEqual, Type::App(TypeCon::Int, _)) => Some(opcode::ADD),
+                    (AssignOperator::PlusEqual, Type::App(TypeCon::Float, _)) => Some(opcode::ADDF),
+                    (AssignOperator::PlusEqual, Type::App(TypeCon::Str, _)) => Some(opcode::CONCAT),
+                    (AssignOperator::MinusEqual, Type::App(TypeCon::Int, _)) => Some(opcode::SUB),
+                    (AssignOperator::MinusEqual, Type::App(TypeCon::Float, _)) => {
+                        Some(opcode::SUBF)
+                    }
+                    (AssignOperator::StarEqual, Type::App(TypeCon::Int, _)) => Some(opcode::MUL),
+                    (AssignOperator::StarEqual, Type::App(TypeCon::Float, _)) => Some(opcode::MULF),
+                    (AssignOperator::SlashEqual, Type::App(TypeCon::Int, _)) => Some(opcode::DIV),
+                    (AssignOperator::SlashEqual, Type::App(TypeCon::Float, _)) => {
+                        Some(opcode::DIVF)
+                    }
+                    _ => unreachable!(), // type checker should prevent this
+                };
+
+                if let Some(opcode) = opcode {
+                    // the array and index are kept below the item for the SETINDEX
+                    self.emit_byte(opcode::DUP2);
+                    self.emit_byte(opcode::INDEXARRAY);
+                    self.compile_expression(value)?;
+                    self.emit_byte(opcode);
+                } else {
+                    self.compile_expression(value)?;
                 }
-                Literal::Nil => {
-                    self.emit_byte(opcode::NIL);
+
+                self.emit_byte(opcode::SETINDEX);
+            }
+
+            Expression::Builtin(builtin, ref args) => {
+                for arg in args {
+                    self.compile_expression(arg)?;
                 }
-                Literal::Int(ref n) => {
-                    self.emit_constant(Value::int(*n), expr.value.expr.span)?;
+
+                match (builtin, &expr.value.ty) {
+                    (Builtin::ParseInt, Type::Enum { name, .. }) => {
+                        self.emit_instruction(opcode::PARSEINT, name.0 as usize);
+                        return Ok(());
+                    }
+                    (Builtin::ParseFloat, Type::Enum { name, .. }) => {
+                        self.emit_instruction(opcode::PARSEFLOAT, name.0 as usize);
+                        return Ok(());
+                    }
+                    _ => (),
                 }
-                Literal::Float(ref f) => {
-                    self.emit_constant(Value::float(*f), expr.value.expr.span)?;
+
+                self.emit_byte(match builtin {
+                    Builtin::Len => match args[0].value.ty {
+                        Type::App(TypeCon::Str, _) => opcode::STRLEN,
+                        _ => opcode::ARRAYLEN,
+                    },
+                    Builtin::Push => opcode::ARRAYPUSH,
+                    Builtin::Pop => opcode::ARRAYPOP,
+                    Builtin::Insert => opcode::ARRAYINSERT,
+                    Builtin::Remove => opcode::ARRAYREMOVE,
+                    Builtin::Contains => opcode::ARRAYCONTAINS,
+                    Builtin::ParseInt | Builtin::ParseFloat => unreachable!(),
+                });
+            }
+
+            Expression::Interpolation(ref pieces) => {
+                let mut pushed = 0;
+
+                for piece in pieces {
+                    // the string joined so far is the first of the next batch
+                    if pushed == CONCAT_BATCH {
+                        self.emit_instruction(opcode::CONCATN, pushed);
+                        pushed = 1;
+                    }
+
+                    self.compile_expression(piece)?;
+                    pushed += 1;
                 }
-                Literal::Str(ref string) => {
-                    let object = StringObject::new(string, self.objects);
 
-                    self.emit_constant(Value::object(object), expr.value.expr.span)?;
+                self.emit_instruction(opcode::CONCATN, pushed);
+            }
+
+            Expression::Slice(ref target, ref start, ref end) => {
+                self.compile_expression(target)?;
+
+                // a missing bound is passed as nil
+                for bound in &[start, end] {
+                    match bound {
+                        Some(bound) => self.compile_expression(bound)?,
+                        None => self.emit_byte(opcode::NIL),
+                    }
                 }
-            },
+
+                self.emit_byte(opcode::SLICEARRAY);
+            }
+
+            Expression::Literal(ref literal) => {
+                self.compile_literal(literal, expr.value.expr.span)?;
+            }
 
             Expression::Binary(ref lhs, ref op, ref rhs) => {
                 if *op == Op::And {
@@ -463,52 +730,61 @@ impl<'a> Builder<'a> {
                         (Type::App(TypeCon::Int, _), Op::Star) => self.emit_byte(opcode::MUL),
                         (Type::App(TypeCon::Float, _), Op::Star) => self.emit_byte(opcode::MULF),
 
+                        (Type::App(TypeCon::Int, _), Op::Modulo) => self.emit_byte(opcode::MOD),
+                        (Type::App(TypeCon::Float, _), Op::Modulo) => self.emit_byte(opcode::MODF),
+
+                        (Type::App(TypeCon::Int, _), Op::Exponential) => {
+                            self.emit_byte(opcode::POW)
+                        }
+                        (Type::App(TypeCon::Float, _), Op::Exponential) => {
+                            self.emit_byte(opcode::POWF)
+                        }
+
                         // For comparisson the lhs and the rhs should be the same so only
                         // check the type of the lhs
                         (Type::App(TypeCon::Bool, _), Op::LessThan) => match lhs.value.ty {
                             Type::App(TypeCon::Int, _) => self.emit_byte(opcode::LESS),
                             Type::App(TypeCon::Float, _) => self.emit_byte(opcode::LESSF),
-                            _ => unreachable!(),
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         },
 
                         (Type::App(TypeCon::Bool, _), Op::LessThanEqual) => match lhs.value.ty {
                             Type::App(TypeCon::Int, _) => {
-                                self.emit_bytes(opcode::LESS, opcode::NOT)
+                                self.emit_bytes(opcode::GREATER, opcode::NOT)
                             }
                             Type::App(TypeCon::Float, _) => {
-                                self.emit_bytes(opcode::LESSF, opcode::NOT)
+                                self.emit_bytes(opcode::GREATERF, opcode::NOT)
                             }
-                            _ => unreachable!(),
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         },
 
                         (Type::App(TypeCon::Bool, _), Op::GreaterThan) => match lhs.value.ty {
                             Type::App(TypeCon::Int, _) => self.emit_byte(opcode::GREATER),
                             Type::App(TypeCon::Float, _) => self.emit_byte(opcode::GREATERF),
-                            _ => unreachable!(),
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         },
 
                         (Type::App(TypeCon::Bool, _), Op::GreaterThanEqual) => match lhs.value.ty {
                             Type::App(TypeCon::Int, _) => {
-                                self.emit_bytes(opcode::GREATER, opcode::NOT)
+                                self.emit_bytes(opcode::LESS, opcode::NOT)
                             }
                             Type::App(TypeCon::Float, _) => {
-                                self.emit_bytes(opcode::GREATERF, opcode::NOT)
+                                self.emit_bytes(opcode::LESSF, opcode::NOT)
                             }
-                            _ => unreachable!(),
+                            ref ty => return self.undefined_operator(op, ty, expr.span),
                         },
 
                         (Type::App(TypeCon::Str, _), Op::Plus) => self.emit_byte(opcode::CONCAT),
+                        (Type::App(TypeCon::Array(_), _), Op::Plus) => {
+                            self.emit_byte(opcode::CONCATARRAY)
+                        }
 
                         (_, Op::EqualEqual) => self.emit_byte(opcode::EQUAL),
                         (_, Op::BangEqual) => self.emit_bytes(opcode::EQUAL, opcode::NOT),
 
-                        // #[cfg(not(feature = "debug"))]
-                        // _ => unsafe {
-                        //     ::std::hint::unreachable_unchecked() // only in release mode for that extra speed boost
-                        // },
-
-                        // #[cfg(feature = "debug")]
-                        (ref ty, ref op) => unimplemented!(" ty {:?} op {:?}", ty, op),
+                        // a generic function can be specialised with a type that the
+                        // operator isn't defined for
+                        (_, op) => return self.undefined_operator(op, &lhs.value.ty, expr.span),
                     }
                 }
             }
@@ -542,20 +818,28 @@ impl<'a> Builder<'a> {
             }
 
             Expression::Call(ref callee, ref args) => {
+                if let Some((get, _, pos)) = self.variable(*callee) {
+                    // A closure stored in a variable
+                    self.emit_instruction(get, pos);
+
+                    for arg in args {
+                        self.compile_expression(arg)?;
+                    }
+
+                    self.emit_instruction(opcode::CALLCLOSURE, args.len());
+
+                    return Ok(());
+                }
+
                 for arg in args {
                     self.compile_expression(arg)?;
                 }
 
-                let name = self.symbols.name(*callee);
-
-                match name.as_str() {
-                    "clock" | "random" | "read" | "fopen" => {
-                        self.emit_bytes(opcode::CALLNATIVE, callee.0 as u8)
-                    }
-                    _ => {
-                        self.emit_bytes(opcode::CALL, callee.0 as u8);
-                        self.emit_byte(args.len() as u8)
-                    }
+                if self.functions.contains(callee) {
+                    self.emit_instruction(opcode::CALL, callee.0 as usize);
+                    self.emit_operand(args.len())
+                } else {
+                    self.emit_instruction(opcode::CALLNATIVE, callee.0 as usize)
                 }
             }
 
@@ -568,12 +852,12 @@ impl<'a> Builder<'a> {
                     self.compile_expression(&property.value.expr)?;
                 }
 
-                self.emit_bytes(opcode::CLASSINSTANCE, symbol.0 as u8);
-                self.emit_byte(properties.len() as u8);
+                self.emit_instruction(opcode::CLASSINSTANCE, symbol.0 as usize);
+                self.emit_operand(properties.len());
 
-                for property in properties.iter().rev() {
-                    //rev because poped of stack
-                    self.emit_byte(property.value.name.0 as u8);
+                for property in properties.iter() {
+                    // the first property is on top of the stack
+                    self.emit_operand(property.value.name.0 as usize);
                 }
             }
 
@@ -589,7 +873,8 @@ impl<'a> Builder<'a> {
                 self.compile_expression(instance)?;
 
                 self.emit_byte(opcode::CALLINSTANCEMETHOD);
-                self.emit_bytes(method_name.0 as u8, params.len() as u8);
+                self.emit_operand(method_name.0 as usize);
+                self.emit_operand(params.len());
             }
 
             Expression::StaticMethodCall {
@@ -602,8 +887,9 @@ impl<'a> Builder<'a> {
                 }
 
                 self.emit_byte(opcode::CALLSTATICMETHOD);
-                self.emit_bytes(class_name.0 as u8, method_name.0 as u8);
-                self.emit_byte(params.len() as u8);
+                self.emit_operand(class_name.0 as usize);
+                self.emit_operand(method_name.0 as usize);
+                self.emit_operand(params.len());
             }
 
             Expression::GetProperty {
@@ -612,7 +898,7 @@ impl<'a> Builder<'a> {
                 ..
             } => {
                 self.compile_expression(property)?;
-                self.emit_bytes(opcode::GETPROPERTY, property_name.0 as u8)
+                self.emit_instruction(opcode::GETPROPERTY, property_name.0 as usize)
             }
 
             Expression::GetMethod {
@@ -621,7 +907,7 @@ impl<'a> Builder<'a> {
                 ..
             } => {
                 self.compile_expression(method)?;
-                self.emit_bytes(opcode::GETMETHOD, method_name.0 as u8)
+                self.emit_instruction(opcode::GETMETHOD, method_name.0 as usize)
             }
 
             Expression::Grouping(ref expr) => {
@@ -631,25 +917,40 @@ impl<'a> Builder<'a> {
             Expression::Match { ref cond, ref arms } => {
                 self.compile_expression(cond)?;
 
+                // The value being matched on is kept in a hidden local so each arm can test it
+                let slot = self.new_slot();
+
+                self.emit_instruction(opcode::SETLOCAL, slot as usize);
+                self.emit_byte(opcode::POP);
+
                 let mut jumps = Vec::new();
 
                 for arm in arms.value.iter() {
-                    println!("{}", arm.value.is_all);
-                    if arm.value.is_all {
-                        self.compile_statement(&arm.value.body)?;
-                        jumps.push(self.emit_jump(opcode::JUMP));
-                    } else {
-                        self.compile_expression(arm.value.pattern.as_ref().unwrap())?;
-                        self.compile_expression(cond)?; //TODO: check if legal i.e if a+1
-                        self.emit_byte(opcode::EQUAL);
+                    self.locals.begin_scope();
+
+                    let fail_jumps = self.compile_pattern(&arm.value.pattern, slot)?;
 
-                        let offset = self.emit_jump(opcode::JUMPNOT);
+                    match arm.value.body.value.statement.value {
+                        // The value of the arm is the value of the match so keep it
+                        ast::Statement::Expr(ref expr) => self.compile_expression(expr)?,
+                        _ => self.compile_statement(&arm.value.body)?,
+                    }
 
-                        self.compile_statement(&arm.value.body)?;
+                    self.end_scope();
+
+                    if fail_jumps.is_empty() {
+                        // An irrefutable pattern means the later arms are unreachable
                         jumps.push(self.emit_jump(opcode::JUMP));
+                        break;
+                    }
 
-                        self.patch_jump(offset);
+                    jumps.push(self.emit_jump(opcode::JUMP));
+
+                    for label in fail_jumps {
+                        self.patch_jump(label);
                     }
+
+                    self.emit_byte(opcode::POP); // pop the failed test
                 }
 
                 for label in jumps {
@@ -692,10 +993,8 @@ impl<'a> Builder<'a> {
             }
 
             Expression::Var(ref ident, _) => {
-                if let Some(pos) = self.locals.get(ident).cloned() {
-                    self.emit_bytes(opcode::GETLOCAL, pos as u8);
-                } else if let Some(offset) = self.params.get(ident).cloned() {
-                    self.emit_bytes(opcode::GETPARAM, offset as u8);
+                if let Some((get, _, pos)) = self.variable(*ident) {
+                    self.emit_instruction(get, pos);
                 } else {
                     self.reporter.error("Undefined variable", expr.span);
                     return Err(()); // Params are treated as locals so it should be present
@@ -707,7 +1006,8 @@ impl<'a> Builder<'a> {
                 ref tag,
             } => {
                 self.emit_byte(opcode::ENUM);
-                self.emit_bytes(enum_name.value.0 as u8, *tag as u8);
+                self.emit_operand(enum_name.value.0 as usize);
+                self.emit_operand(*tag as usize);
             }
 
             Expression::VariantWithData {
@@ -715,23 +1015,71 @@ impl<'a> Builder<'a> {
                 ref tag,
                 ref inner,
             } => {
-                self.emit_byte(opcode::ENUM);
-                self.emit_bytes(enum_name.value.0 as u8, *tag as u8);
                 self.compile_expression(inner)?;
+                self.emit_byte(opcode::ENUMDATA);
+                self.emit_operand(enum_name.value.0 as usize);
+                self.emit_operand(*tag as usize);
             }
 
             Expression::Closure(ref func) => {
-                let closure = compile_function(func, self.symbols, self.reporter, self.objects)?;
+                let mut upvalues = FnvHashMap::default();
+                let mut captures = Vec::new();
+
+                for symbol in free_variables(func) {
+                    let capture = if let Some(slot) = self.locals.get(&symbol).cloned() {
+                        self.captured.insert(slot);
+                        (0, slot) // a local
+                    } else if let Some(slot) = self.params.get(&symbol) {
+                        (1, *slot) // a param
+                    } else if let Some(index) = self.upvalues.get(&symbol) {
+                        (2, *index) // captured by the enclosing closure
+                    } else {
+                        continue; // a function, a global or a variable declared later on
+                    };
 
-                let func = FunctionObject::new(closure.params.len(), closure, self.objects);
+                    upvalues.insert(symbol, captures.len());
+                    captures.push(capture);
+                }
+
+                let mut builder = Builder::new(
+                    self.reporter,
+                    self.symbols,
+                    self.globals,
+                    self.functions,
+                    &mut *self.objects,
+                    func.params
+                        .iter()
+                        .enumerate()
+                        .map(|(i, param)| (param.name, i))
+                        .collect(),
+                );
+
+                builder.upvalues = upvalues;
+                builder.compile_statement(&func.body)?;
+
+                let closure = Function {
+                    name: func.name,
+                    body: builder.chunk,
+                    params: builder.params,
+                };
+
+                let func = FunctionObject::new(closure.params.len(), closure, *self.objects);
+                let func = self.allocate(func);
+                let constant = self.make_constant(Value::object(func), expr.span)?;
+
+                self.emit_instruction(opcode::CLOSURE, constant);
+                self.emit_operand(captures.len());
 
-                self.emit_constant(Value::object(func), expr.span)?;
+                for (kind, index) in captures {
+                    self.emit_byte(kind);
+                    self.emit_operand(index);
+                }
             }
 
             Expression::Set(ref property, ref instance, ref value) => {
                 self.compile_expression(value)?;
                 self.compile_expression(instance)?;
-                self.emit_bytes(opcode::SETPROPERTY, property.0 as u8);
+                self.emit_instruction(opcode::SETPROPERTY, property.0 as usize);
             }
         }
 
@@ -776,15 +1124,25 @@ impl<'a> Builder<'a> {
 fn compile_class(
     class: &ast::Class,
     symbols: &Symbols<()>,
+    globals: &FnvHashSet<Symbol>,
+    functions: &FnvHashSet<Symbol>,
     reporter: &mut Reporter,
-    objects: RawObject,
+    objects: &mut RawObject,
 ) -> ParseResult<Class> {
     let mut methods = FnvHashMap::default();
 
     for method in class.methods.iter() {
         methods.insert(
             method.name,
-            compile_function(method, symbols, reporter, objects)?,
+            compile_function(
+                method,
+                &[],
+                symbols,
+                globals,
+                functions,
+                reporter,
+                &mut *objects,
+            )?,
         );
     }
 
@@ -794,11 +1152,15 @@ fn compile_class(
     })
 }
 
+/// Compiles a function, setting the given globals before its body is run
 fn compile_function(
     func: &ast::Function,
+    globals: &[ast::Global],
     symbols: &Symbols<()>,
+    global_names: &FnvHashSet<Symbol>,
+    functions: &FnvHashSet<Symbol>,
     reporter: &mut Reporter,
-    objects: RawObject,
+    objects: &mut RawObject,
 ) -> ParseResult<Function> {
     let mut params = FnvHashMap::default();
 
@@ -806,8 +1168,9 @@ fn compile_function(
         params.insert(param.name, i);
     } // store param id and the index in the vec
 
-    let mut builder = Builder::new(reporter, symbols, objects, params);
+    let mut builder = Builder::new(reporter, symbols, global_names, functions, objects, params);
 
+    builder.compile_globals(globals)?;
     builder.compile_statement(&func.body)?;
 
     Ok(Function {
@@ -826,17 +1189,37 @@ pub fn compile(
     let mut funcs = FnvHashMap::default();
     let mut classes: FnvHashMap<Symbol, Class> = FnvHashMap::default();
 
-    let objects = ::std::ptr::null::<RawObject>() as RawObject;
+    let mut objects = ::std::ptr::null::<RawObject>() as RawObject;
+
+    let globals: FnvHashSet<Symbol> = ast.globals.iter().map(|global| global.name).collect();
+    let functions: FnvHashSet<Symbol> =
+        ast.functions.iter().map(|function| function.name).collect();
 
     for function in ast.functions.iter() {
+        // The globals are set at the start of main
+        let initialised = if symbols.name(function.name) == "main" {
+            &ast.globals[..]
+        } else {
+            &[]
+        };
+
         funcs.insert(
             function.name,
-            compile_function(function, symbols, reporter, objects)?,
+            compile_function(
+                function,
+                initialised,
+                symbols,
+                &globals,
+                &functions,
+                reporter,
+                &mut objects,
+            )?,
         );
     }
 
     for class in ast.classes.iter() {
-        let mut compiled_class = compile_class(class, symbols, reporter, objects)?;
+        let mut compiled_class =
+            compile_class(class, symbols, &globals, &functions, reporter, &mut objects)?;
 
         if let Some(ref superclass) = class.superclass {
             let superclass = &classes[&superclass.value];
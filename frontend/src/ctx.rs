@@ -1,21 +1,23 @@
 use crate::infer::env::VarEntry;
-use crate::infer::types::{Type, TypeCon};
+use crate::infer::types::{Type, TypeCon, TypeVar, Variant};
 
+use std::collections::HashMap;
 use std::rc::Rc;
 use util::emmiter::Reporter;
 use util::pos::Span;
 use util::symbol::{Symbol, SymbolFactory, Symbols};
+use vm::{NativeRegistry, NativeType};
 
-#[derive(Debug)]
-pub struct CompileCtx<'a> {
+#[derive(Debug, Clone)]
+pub struct CompileCtx {
     symbols: Symbols<()>,
     types: Symbols<Type>,
     vars: Symbols<VarEntry>,
-    reporter: &'a mut Reporter,
+    reporter: Reporter,
 }
 
-impl<'a> CompileCtx<'a> {
-    pub fn new(strings: &Rc<SymbolFactory>, reporter: &'a mut Reporter) -> Self {
+impl CompileCtx {
+    pub fn new(strings: &Rc<SymbolFactory>, reporter: &Reporter, natives: &NativeRegistry) -> Self {
         let mut types = Symbols::new(Rc::clone(strings));
         let string_symbol = types.symbol("str");
         let int_symbol = types.symbol("int");
@@ -29,34 +31,28 @@ impl<'a> CompileCtx<'a> {
         types.enter(nil_symbol, Type::Nil);
         types.enter(string_symbol, Type::App(TypeCon::Str, vec![]));
 
+        let option_symbol = types.symbol("Option");
+        let type_var = TypeVar::new();
+        let option = option_type(&mut types, Type::Var(type_var));
+
+        types.enter(
+            option_symbol,
+            Type::Generic(vec![type_var], Box::new(option)),
+        );
+
         let mut vars = Symbols::new(Rc::clone(strings));
 
-        {
-            let mut add_builtin = |name: &str, mut params: Vec<Type>, returns: Type| {
-                let symbol = vars.symbol(name);
-                params.push(returns);
-                vars.enter(
-                    symbol,
-                    VarEntry::Fun {
-                        ty: Type::Generic(vec![], Box::new(Type::App(TypeCon::Arrow, params))),
-                    },
-                );
-            };
-
-            add_builtin("clock", vec![], Type::App(TypeCon::Float, vec![]));
-            add_builtin(
-                "random",
-                vec![
-                    Type::App(TypeCon::Int, vec![]),
-                    Type::App(TypeCon::Int, vec![]),
-                ],
-                Type::App(TypeCon::Int, vec![]),
-            );
-            add_builtin("read", vec![], Type::App(TypeCon::Str, vec![]));
-            add_builtin(
-                "fopen",
-                vec![Type::App(TypeCon::Str, vec![])],
-                Type::App(TypeCon::Str, vec![]),
+        for native in natives.iter() {
+            let symbol = vars.symbol(&native.name);
+
+            let mut params: Vec<Type> = native.params.iter().map(native_type).collect();
+            params.push(native_type(&native.returns));
+
+            vars.enter(
+                symbol,
+                VarEntry::Fun {
+                    ty: Type::Generic(vec![], Box::new(Type::App(TypeCon::Arrow, params))),
+                },
             );
         }
 
@@ -64,7 +60,7 @@ impl<'a> CompileCtx<'a> {
             symbols: Symbols::new(Rc::clone(strings)),
             types,
             vars,
-            reporter,
+            reporter: reporter.clone(),
         }
     }
 
@@ -84,6 +80,11 @@ impl<'a> CompileCtx<'a> {
         self.reporter.remove_error();
     }
 
+    /// `Option<inner>`, which every program can use without declaring it
+    pub fn option(&mut self, inner: Type) -> Type {
+        option_type(&mut self.types, inner)
+    }
+
     /// Check for a type in the type Env
     pub fn look_type(&mut self, symbol: Symbol) -> Option<&Type> {
         self.types.look(symbol)
@@ -94,6 +95,11 @@ impl<'a> CompileCtx<'a> {
         self.vars.name(symbol)
     }
 
+    /// The symbol of the name, interning it if it hasn't been seen
+    pub fn symbol(&mut self, name: &str) -> Symbol {
+        self.symbols.symbol(name)
+    }
+
     pub fn symbols(&self) -> &Symbols<()> {
         &self.symbols
     }
@@ -123,3 +129,41 @@ impl<'a> CompileCtx<'a> {
         self.vars.enter(symbol, data)
     }
 }
+
+/// `enum Option<T> { Some(T), None }`. The vm relies on the tags of the variants when it
+/// creates an `Option`
+fn option_type(types: &mut Symbols<Type>, inner: Type) -> Type {
+    let mut variants = HashMap::new();
+
+    variants.insert(
+        types.symbol("Some"),
+        Variant {
+            tag: 0,
+            inner: Some(inner),
+        },
+    );
+    variants.insert(
+        types.symbol("None"),
+        Variant {
+            tag: 1,
+            inner: None,
+        },
+    );
+
+    Type::Enum {
+        name: types.symbol("Option"),
+        variants,
+    }
+}
+
+/// The type the type checker uses for a param or return type of a native function
+fn native_type(ty: &NativeType) -> Type {
+    match *ty {
+        NativeType::Int => Type::App(TypeCon::Int, vec![]),
+        NativeType::Float => Type::App(TypeCon::Float, vec![]),
+        NativeType::Bool => Type::App(TypeCon::Bool, vec![]),
+        NativeType::Str => Type::App(TypeCon::Str, vec![]),
+        NativeType::Nil => Type::Nil,
+        NativeType::Array(ref ty) => Type::App(TypeCon::Array(Box::new(native_type(ty))), vec![]),
+    }
+}
@@ -0,0 +1,375 @@
+//! Writes a typed tree out with a node on each line and its children indented below it,
+//! which is what `--emit=typed-ast` shows. Each expression is followed by its type.
+use crate::ast::*;
+use crate::infer::types::Type;
+use util::pos::Spanned;
+use util::symbol::{Symbol, Symbols};
+
+const INDENT: &str = "  ";
+
+impl Program {
+    /// Writes out the globals, then the classes and then the functions of the program
+    pub fn dump(&self, symbols: &Symbols<()>) -> String {
+        let mut tree = Tree {
+            symbols,
+            depth: 0,
+            out: String::new(),
+        };
+
+        for global in &self.globals {
+            let text = format!("global {}: {}", tree.name(global.name), tree.ty(&global.ty));
+
+            tree.node(text, |tree| {
+                if let Some(ref value) = global.value {
+                    tree.expression(&value.value)
+                }
+            })
+        }
+
+        for class in &self.classes {
+            tree.class(class)
+        }
+
+        for function in &self.functions {
+            tree.function("fn", function)
+        }
+
+        tree.out
+    }
+}
+
+struct Tree<'a> {
+    symbols: &'a Symbols<()>,
+    depth: usize,
+    out: String,
+}
+
+impl<'a> Tree<'a> {
+    fn line(&mut self, text: String) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+
+        self.out.push_str(&text);
+        self.out.push('\n');
+    }
+
+    /// Writes a line with the children written by `children` below it
+    fn node<F: FnOnce(&mut Self)>(&mut self, text: String, children: F) {
+        self.line(text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn name(&self, symbol: Symbol) -> String {
+        self.symbols.name(symbol)
+    }
+
+    fn ty(&self, ty: &Type) -> String {
+        ty.print(self.symbols)
+    }
+
+    /// Names a variant by looking its tag up in the enum's type
+    fn variant(&self, enum_name: Symbol, tag: u32, ty: &Type) -> String {
+        let mut ty = ty;
+
+        while let Type::Generic(_, ref inner) = *ty {
+            ty = inner;
+        }
+
+        let variant = match *ty {
+            Type::Enum { ref variants, .. } => variants
+                .iter()
+                .find(|&(_, variant)| variant.tag == tag)
+                .map(|(name, _)| self.name(*name)),
+            _ => None,
+        };
+
+        format!(
+            "{}::{}",
+            self.name(enum_name),
+            variant.unwrap_or_else(|| tag.to_string())
+        )
+    }
+
+    fn class(&mut self, class: &Class) {
+        let mut text = format!("class {}", self.name(class.name));
+
+        if let Some(ref superclass) = class.superclass {
+            text.push_str(&format!(" extends {}", self.name(superclass.value)));
+        }
+
+        self.node(text, |tree| {
+            for property in &class.properties {
+                let text = format!("{}: {}", tree.name(property.name), tree.ty(&property.ty));
+                tree.line(text)
+            }
+
+            for method in &class.methods {
+                tree.function("fn", method)
+            }
+        })
+    }
+
+    /// Writes a function or closure, which `keyword` tells apart
+    fn function(&mut self, keyword: &str, function: &Function) {
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .map(|param| format!("{}: {}", self.name(param.name), self.ty(&param.ty)))
+            .collect();
+
+        let text = format!(
+            "{} {}({}) -> {}",
+            keyword,
+            self.name(function.name),
+            params.join(", "),
+            self.ty(&function.returns)
+        );
+
+        self.node(text, |tree| tree.statement(&function.body.value))
+    }
+
+    fn statement(&mut self, statement: &TypedStatement) {
+        match statement.statement.value {
+            Statement::Block(ref statements) => self.node("block".into(), |tree| {
+                for statement in statements {
+                    tree.statement(&statement.value)
+                }
+            }),
+            Statement::Break => self.line("break".into()),
+            Statement::Continue => self.line("continue".into()),
+            Statement::Expr(ref expr) => {
+                self.node("expr".into(), |tree| tree.expression(&expr.value))
+            }
+            Statement::If {
+                ref cond,
+                ref then,
+                ref otherwise,
+            } => self.node("if".into(), |tree| {
+                tree.expression(&cond.value);
+                tree.statement(&then.value);
+
+                if let Some(ref otherwise) = *otherwise {
+                    tree.node("else".into(), |tree| tree.statement(&otherwise.value))
+                }
+            }),
+            Statement::Print(ref expr) => {
+                self.node("print".into(), |tree| tree.expression(&expr.value))
+            }
+            Statement::While(ref cond, ref body) => self.node("while".into(), |tree| {
+                tree.expression(&cond.value);
+                tree.statement(&body.value)
+            }),
+            Statement::Let {
+                ref ident,
+                ref ty,
+                ref expr,
+            } => {
+                let text = format!("let {}: {}", self.name(*ident), self.ty(ty));
+
+                self.node(text, |tree| {
+                    if let Some(ref expr) = *expr {
+                        tree.expression(&expr.value)
+                    }
+                })
+            }
+            Statement::Return(ref expr) => {
+                self.node("return".into(), |tree| tree.expression(&expr.value))
+            }
+        }
+    }
+
+    fn expressions(&mut self, exprs: &[Spanned<TypedExpression>]) {
+        for expr in exprs {
+            self.expression(&expr.value)
+        }
+    }
+
+    fn expression(&mut self, expr: &TypedExpression) {
+        let ty = self.ty(&expr.ty);
+        let typed = |text: String| format!("{}: {}", text, ty);
+
+        match expr.expr.value {
+            Expression::Array(ref items) => {
+                self.node(typed("array".into()), |tree| tree.expressions(items))
+            }
+            Expression::Assign(name, ref op, ref value) => {
+                let text = typed(format!("assign {} {}", self.name(name), op));
+                self.node(text, |tree| tree.expression(&value.value))
+            }
+            Expression::Binary(ref lhs, ref op, ref rhs) => {
+                self.node(typed(format!("binary {}", op)), |tree| {
+                    tree.expression(&lhs.value);
+                    tree.expression(&rhs.value)
+                })
+            }
+            Expression::Builtin(builtin, ref args) => {
+                let text = typed(format!("builtin {}", builtin.name()));
+                self.node(text, |tree| tree.expressions(args))
+            }
+            Expression::Call(name, ref args) => {
+                let text = typed(format!("call {}", self.name(name)));
+                self.node(text, |tree| tree.expressions(args))
+            }
+            Expression::Cast(ref from, _) => {
+                self.node(typed("cast".into()), |tree| tree.expression(&from.value))
+            }
+            Expression::Closure(ref function) => self.function("closure", function),
+            Expression::ClassLiteral {
+                symbol,
+                ref properties,
+            } => {
+                let text = typed(format!("class literal {}", self.name(symbol)));
+
+                self.node(text, |tree| {
+                    for property in properties {
+                        let text = tree.name(property.value.name);
+                        tree.node(text, |tree| tree.expression(&property.value.expr.value))
+                    }
+                })
+            }
+            Expression::GetProperty {
+                property_name,
+                ref property,
+            } => {
+                let text = typed(format!("get {}", self.name(property_name)));
+                self.node(text, |tree| tree.expression(&property.value))
+            }
+            Expression::GetMethod {
+                method_name,
+                ref method,
+            } => {
+                let text = typed(format!("get method {}", self.name(method_name)));
+                self.node(text, |tree| tree.expression(&method.value))
+            }
+            Expression::Grouping(ref expr) => {
+                self.node(typed("group".into()), |tree| tree.expression(&expr.value))
+            }
+            Expression::Interpolation(ref pieces) => self
+                .node(typed("interpolation".into()), |tree| {
+                    tree.expressions(pieces)
+                }),
+            Expression::Index(ref target, ref index) => self.node(typed("index".into()), |tree| {
+                tree.expression(&target.value);
+                tree.expression(&index.value)
+            }),
+            Expression::Slice(ref target, ref start, ref end) => {
+                self.node(typed("slice".into()), |tree| {
+                    tree.expression(&target.value);
+
+                    if let Some(ref start) = *start {
+                        tree.node("start".into(), |tree| tree.expression(&start.value))
+                    }
+
+                    if let Some(ref end) = *end {
+                        tree.node("end".into(), |tree| tree.expression(&end.value))
+                    }
+                })
+            }
+            Expression::SetIndex(ref target, ref index, ref op, ref value) => {
+                self.node(typed(format!("set index {}", op)), |tree| {
+                    tree.expression(&target.value);
+                    tree.expression(&index.value);
+                    tree.expression(&value.value)
+                })
+            }
+            Expression::InstanceMethodCall {
+                method_name,
+                ref instance,
+                ref params,
+            } => {
+                let text = typed(format!("call method {}", self.name(method_name)));
+
+                self.node(text, |tree| {
+                    tree.expression(&instance.value);
+                    tree.expressions(params)
+                })
+            }
+            Expression::Literal(ref literal) => self.line(typed(format!("literal {}", literal))),
+            Expression::Match { ref cond, ref arms } => self.node(typed("match".into()), |tree| {
+                tree.expression(&cond.value);
+
+                for arm in &arms.value {
+                    let text = format!("arm {}", tree.pattern(&arm.value.pattern.value));
+                    tree.node(text, |tree| tree.statement(&arm.value.body.value))
+                }
+            }),
+            Expression::Set(name, ref object, ref value) => {
+                let text = typed(format!("set {}", self.name(name)));
+
+                self.node(text, |tree| {
+                    tree.expression(&object.value);
+                    tree.expression(&value.value)
+                })
+            }
+            Expression::StaticMethodCall {
+                class_name,
+                method_name,
+                ref params,
+            } => {
+                let text = typed(format!(
+                    "call {}.{}",
+                    self.name(class_name),
+                    self.name(method_name)
+                ));
+
+                self.node(text, |tree| tree.expressions(params))
+            }
+            Expression::Ternary(ref cond, ref then, ref otherwise) => {
+                self.node(typed("ternary".into()), |tree| {
+                    tree.expression(&cond.value);
+                    tree.expression(&then.value);
+                    tree.expression(&otherwise.value)
+                })
+            }
+            Expression::Unary(ref op, ref expr) => self
+                .node(typed(format!("unary {}", op)), |tree| {
+                    tree.expression(&expr.value)
+                }),
+            Expression::Var(name, _) => self.line(typed(format!("var {}", self.name(name)))),
+            Expression::VariantWithData {
+                ref enum_name,
+                tag,
+                ref inner,
+            } => {
+                let text = typed(format!(
+                    "variant {}",
+                    self.variant(enum_name.value, tag, &expr.ty)
+                ));
+                self.node(text, |tree| tree.expression(&inner.value))
+            }
+            Expression::VariantNoData { ref enum_name, tag } => {
+                let text = typed(format!(
+                    "variant {}",
+                    self.variant(enum_name.value, tag, &expr.ty)
+                ));
+                self.line(text)
+            }
+        }
+    }
+
+    fn pattern(&self, pattern: &TypedPattern) -> String {
+        let text = match pattern.pattern {
+            Pattern::Wildcard => "_".into(),
+            Pattern::Binding(name) => self.name(name),
+            Pattern::Literal(ref literal) => literal.to_string(),
+            Pattern::Variant {
+                enum_name,
+                tag,
+                ref inner,
+            } => {
+                let mut text = self.variant(enum_name, tag, &pattern.ty);
+
+                if let Some(ref inner) = *inner {
+                    text.push_str(&format!("({})", self.pattern(&inner.value)));
+                }
+
+                text
+            }
+        };
+
+        format!("{}: {}", text, self.ty(&pattern.ty))
+    }
+}
@@ -0,0 +1,456 @@
+//! Constant folding and algebraic simplification.
+//!
+//! Operators whose operands are literals are evaluated at compile time i.e `1 + 2 * 3`
+//! becomes `7` and `"a" + "b"` becomes `"ab"`. Conditions that are known at compile time
+//! pick their branch so `if (false) {..}` is removed. Anything that could fail at
+//! runtime, like a division by zero or an overflow, is left alone so it still fails when
+//! the program is run. A folded expression keeps the span of the expression it replaces.
+use crate::ast as t;
+use crate::ast::{Literal, Op, UnaryOp};
+use crate::infer::types::{Type, TypeCon};
+use util::pos::{Span, Spanned, EMPTYSPAN};
+
+/// Folds the constant expressions of every function, method and global within the program
+pub fn fold(mut program: t::Program) -> t::Program {
+    for function in program.functions.iter_mut() {
+        fold_function(function);
+    }
+
+    for class in program.classes.iter_mut() {
+        for method in class.methods.iter_mut() {
+            fold_function(method);
+        }
+    }
+
+    for global in program.globals.iter_mut() {
+        global.value = global.value.take().map(expression);
+    }
+
+    program
+}
+
+fn fold_function(function: &mut t::Function) {
+    let body = ::std::mem::replace(&mut *function.body, empty(EMPTYSPAN));
+
+    *function.body = statement(body);
+}
+
+/// A block with no statements used in place of a branch that can never run
+fn empty(span: Span) -> Spanned<t::TypedStatement> {
+    Spanned::new(
+        t::TypedStatement {
+            statement: Box::new(Spanned::new(t::Statement::Block(vec![]), span)),
+            ty: Type::Nil,
+        },
+        span,
+    )
+}
+
+fn literal(literal: Literal, ty: Type, span: Span) -> Spanned<t::TypedExpression> {
+    Spanned::new(
+        t::TypedExpression {
+            expr: Box::new(Spanned::new(t::Expression::Literal(literal), span)),
+            ty,
+        },
+        span,
+    )
+}
+
+/// The value of a boolean literal
+fn as_bool(expr: &Spanned<t::TypedExpression>) -> Option<bool> {
+    as_literal(expr).and_then(as_bool_literal)
+}
+
+fn as_literal(expr: &Spanned<t::TypedExpression>) -> Option<&Literal> {
+    match expr.value.expr.value {
+        t::Expression::Literal(ref literal) => Some(literal),
+        _ => None,
+    }
+}
+
+fn as_bool_literal(literal: &Literal) -> Option<bool> {
+    match *literal {
+        Literal::True(_) => Some(true),
+        Literal::False(_) => Some(false),
+        _ => None,
+    }
+}
+
+fn bool_literal(value: bool) -> Literal {
+    if value {
+        Literal::True(true)
+    } else {
+        Literal::False(false)
+    }
+}
+
+fn statement(statement: Spanned<t::TypedStatement>) -> Spanned<t::TypedStatement> {
+    let span = statement.span;
+    let ty = statement.value.ty;
+    let inner_span = statement.value.statement.span;
+
+    let folded = match statement.value.statement.value {
+        s @ t::Statement::Break | s @ t::Statement::Continue => s,
+
+        t::Statement::Block(statements) => {
+            t::Statement::Block(statements.into_iter().map(self::statement).collect())
+        }
+
+        t::Statement::Expr(expr) => t::Statement::Expr(expression(expr)),
+        t::Statement::Print(expr) => t::Statement::Print(expression(expr)),
+        t::Statement::Return(expr) => t::Statement::Return(expression(expr)),
+
+        t::Statement::If {
+            cond,
+            then,
+            otherwise,
+        } => {
+            let cond = expression(cond);
+
+            match as_bool(&cond) {
+                Some(true) => return self::statement(then),
+                Some(false) => {
+                    return match otherwise {
+                        Some(otherwise) => self::statement(otherwise),
+                        None => empty(span),
+                    };
+                }
+                None => t::Statement::If {
+                    cond,
+                    then: self::statement(then),
+                    otherwise: otherwise.map(self::statement),
+                },
+            }
+        }
+
+        t::Statement::While(cond, body) => {
+            let cond = expression(cond);
+
+            if as_bool(&cond) == Some(false) {
+                return empty(span); // the body can never run
+            }
+
+            t::Statement::While(cond, self::statement(body))
+        }
+
+        t::Statement::Let { ident, ty, expr } => t::Statement::Let {
+            ident,
+            ty,
+            expr: expr.map(expression),
+        },
+    };
+
+    Spanned::new(
+        t::TypedStatement {
+            statement: Box::new(Spanned::new(folded, inner_span)),
+            ty,
+        },
+        span,
+    )
+}
+
+fn expressions(exprs: Vec<Spanned<t::TypedExpression>>) -> Vec<Spanned<t::TypedExpression>> {
+    exprs.into_iter().map(expression).collect()
+}
+
+fn expression(expr: Spanned<t::TypedExpression>) -> Spanned<t::TypedExpression> {
+    let span = expr.span;
+    let ty = expr.value.ty;
+    let inner_span = expr.value.expr.span;
+
+    let folded = match expr.value.expr.value {
+        e @ t::Expression::Literal(_)
+        | e @ t::Expression::Var(_, _)
+        | e @ t::Expression::VariantNoData { .. } => e,
+
+        t::Expression::Array(items) => t::Expression::Array(expressions(items)),
+
+        t::Expression::Assign(ident, op, value) => {
+            t::Expression::Assign(ident, op, expression(value))
+        }
+
+        t::Expression::Binary(lhs, op, rhs) => {
+            return binary(expression(lhs), op, expression(rhs), ty, span);
+        }
+
+        t::Expression::Builtin(builtin, args) => t::Expression::Builtin(builtin, expressions(args)),
+
+        t::Expression::Call(ident, args) => t::Expression::Call(ident, expressions(args)),
+
+        t::Expression::Interpolation(pieces) => t::Expression::Interpolation(expressions(pieces)),
+
+        t::Expression::Cast(expr, to) => t::Expression::Cast(expression(expr), to),
+
+        t::Expression::Closure(mut function) => {
+            fold_function(&mut function);
+            t::Expression::Closure(function)
+        }
+
+        t::Expression::ClassLiteral { symbol, properties } => t::Expression::ClassLiteral {
+            symbol,
+            properties: properties
+                .into_iter()
+                .map(|property| {
+                    let span = property.span;
+                    let property = property.value;
+
+                    Spanned::new(
+                        t::ClassLiteralProperty {
+                            name: property.name,
+                            expr: expression(property.expr),
+                        },
+                        span,
+                    )
+                })
+                .collect(),
+        },
+
+        t::Expression::GetProperty {
+            property_name,
+            property,
+        } => t::Expression::GetProperty {
+            property_name,
+            property: expression(property),
+        },
+
+        t::Expression::GetMethod {
+            method_name,
+            method,
+        } => t::Expression::GetMethod {
+            method_name,
+            method: expression(method),
+        },
+
+        t::Expression::Grouping(inner) => {
+            let inner = expression(inner);
+
+            if let Some(literal) = as_literal(&inner) {
+                return self::literal(literal.clone(), ty, span);
+            }
+
+            t::Expression::Grouping(inner)
+        }
+
+        t::Expression::Index(target, index) => {
+            t::Expression::Index(expression(target), expression(index))
+        }
+
+        t::Expression::Slice(target, start, end) => t::Expression::Slice(
+            expression(target),
+            start.map(expression),
+            end.map(expression),
+        ),
+
+        t::Expression::SetIndex(target, index, op, value) => {
+            t::Expression::SetIndex(expression(target), expression(index), op, expression(value))
+        }
+
+        t::Expression::InstanceMethodCall {
+            method_name,
+            instance,
+            params,
+        } => t::Expression::InstanceMethodCall {
+            method_name,
+            instance: expression(instance),
+            params: expressions(params),
+        },
+
+        t::Expression::Match { cond, arms } => {
+            let arms_span = arms.span;
+
+            t::Expression::Match {
+                cond: expression(cond),
+                arms: Spanned::new(
+                    arms.value
+                        .into_iter()
+                        .map(|arm| {
+                            let span = arm.span;
+                            let arm = arm.value;
+
+                            Spanned::new(
+                                t::MatchArm {
+                                    pattern: arm.pattern,
+                                    body: statement(arm.body),
+                                },
+                                span,
+                            )
+                        })
+                        .collect(),
+                    arms_span,
+                ),
+            }
+        }
+
+        t::Expression::Set(property, instance, value) => {
+            t::Expression::Set(property, expression(instance), expression(value))
+        }
+
+        t::Expression::StaticMethodCall {
+            class_name,
+            method_name,
+            params,
+        } => t::Expression::StaticMethodCall {
+            class_name,
+            method_name,
+            params: expressions(params),
+        },
+
+        t::Expression::Ternary(cond, if_true, if_false) => {
+            let cond = expression(cond);
+
+            match as_bool(&cond) {
+                Some(true) => return expression(if_true),
+                Some(false) => return expression(if_false),
+                None => t::Expression::Ternary(cond, expression(if_true), expression(if_false)),
+            }
+        }
+
+        t::Expression::Unary(op, inner) => {
+            let inner = expression(inner);
+
+            let folded = match (&op, as_literal(&inner)) {
+                (UnaryOp::Bang, Some(Literal::True(_))) => Some(Literal::False(false)),
+                (UnaryOp::Bang, Some(Literal::False(_))) => Some(Literal::True(true)),
+                (UnaryOp::Minus, Some(Literal::Int(i))) => i.checked_neg().map(Literal::Int),
+                (UnaryOp::Minus, Some(Literal::Float(f))) => Some(Literal::Float(-f)),
+                _ => None,
+            };
+
+            match folded {
+                Some(folded) => return literal(folded, ty, span),
+                None => t::Expression::Unary(op, inner),
+            }
+        }
+
+        t::Expression::VariantWithData {
+            enum_name,
+            tag,
+            inner,
+        } => t::Expression::VariantWithData {
+            enum_name,
+            tag,
+            inner: expression(inner),
+        },
+    };
+
+    Spanned::new(
+        t::TypedExpression {
+            expr: Box::new(Spanned::new(folded, inner_span)),
+            ty,
+        },
+        span,
+    )
+}
+
+/// Folds a binary expression whose operands have already been folded
+fn binary(
+    lhs: Spanned<t::TypedExpression>,
+    op: Op,
+    rhs: Spanned<t::TypedExpression>,
+    ty: Type,
+    span: Span,
+) -> Spanned<t::TypedExpression> {
+    // `and`/`or` only evaluate the rhs when the lhs doesn't decide the result
+    match (&op, as_bool(&lhs)) {
+        (Op::And, Some(false)) | (Op::Or, Some(true)) => return lhs,
+        (Op::And, Some(true)) | (Op::Or, Some(false)) => return rhs,
+        _ => (),
+    }
+
+    let folded = match (as_literal(&lhs), as_literal(&rhs)) {
+        (Some(a), Some(b)) => fold_literals(a, &op, b),
+        _ => None,
+    };
+
+    if let Some(folded) = folded {
+        return literal(folded, ty, span);
+    }
+
+    // Algebraic identities that hold for any int
+    if let Type::App(TypeCon::Int, _) = ty {
+        let is_int = |expr: &Spanned<t::TypedExpression>, value: i64| match as_literal(expr) {
+            Some(Literal::Int(i)) => *i == value,
+            _ => false,
+        };
+
+        match op {
+            Op::Plus if is_int(&lhs, 0) => return rhs,
+            Op::Plus | Op::Minus if is_int(&rhs, 0) => return lhs,
+            Op::Star if is_int(&lhs, 1) => return rhs,
+            Op::Star | Op::Slash if is_int(&rhs, 1) => return lhs,
+            _ => (),
+        }
+    }
+
+    Spanned::new(
+        t::TypedExpression {
+            expr: Box::new(Spanned::new(t::Expression::Binary(lhs, op, rhs), span)),
+            ty,
+        },
+        span,
+    )
+}
+
+/// Evaluates an operator on two literals. Returns `None` if the result can only be
+/// known at runtime
+fn fold_literals(lhs: &Literal, op: &Op, rhs: &Literal) -> Option<Literal> {
+    use crate::ast::Literal::*;
+
+    let folded = match (lhs, rhs) {
+        (Int(a), Int(b)) => match *op {
+            Op::Plus => Int(a.checked_add(*b)?),
+            Op::Minus => Int(a.checked_sub(*b)?),
+            Op::Star => Int(a.checked_mul(*b)?),
+            Op::Slash => Int(a.checked_div(*b)?),
+            Op::Modulo => Int(a.checked_rem(*b)?),
+            Op::Exponential if *b >= 0 && *b <= i64::from(u32::MAX) => {
+                Int(a.checked_pow(*b as u32)?)
+            }
+            Op::LessThan => bool_literal(a < b),
+            Op::LessThanEqual => bool_literal(a <= b),
+            Op::GreaterThan => bool_literal(a > b),
+            Op::GreaterThanEqual => bool_literal(a >= b),
+            Op::EqualEqual => bool_literal(a == b),
+            Op::BangEqual => bool_literal(a != b),
+            _ => return None,
+        },
+
+        (Float(a), Float(b)) => match *op {
+            Op::Plus => Float(a + b),
+            Op::Minus => Float(a - b),
+            Op::Star => Float(a * b),
+            Op::Slash => Float(a / b),
+            Op::Modulo => Float(a % b),
+            Op::Exponential => Float(a.powf(*b)),
+            Op::LessThan => bool_literal(a < b),
+            Op::LessThanEqual => bool_literal(a <= b),
+            Op::GreaterThan => bool_literal(a > b),
+            Op::GreaterThanEqual => bool_literal(a >= b),
+            Op::EqualEqual => bool_literal(a == b),
+            Op::BangEqual => bool_literal(a != b),
+            _ => return None,
+        },
+
+        (Str(a), Str(b)) => match *op {
+            Op::Plus => Str(format!("{}{}", a, b)),
+            Op::EqualEqual => bool_literal(a == b),
+            Op::BangEqual => bool_literal(a != b),
+            _ => return None,
+        },
+
+        (True(_), _) | (False(_), _) => {
+            let a = as_bool_literal(lhs)?;
+            let b = as_bool_literal(rhs)?;
+
+            match *op {
+                Op::EqualEqual => bool_literal(a == b),
+                Op::BangEqual => bool_literal(a != b),
+                _ => return None,
+            }
+        }
+
+        _ => return None,
+    };
+
+    Some(folded)
+}
@@ -0,0 +1,360 @@
+//! Exhaustiveness and reachability checking for `match` expressions.
+//!
+//! The typed patterns are lowered into a small pattern language and checked using the
+//! usefulness algorithm from Luc Maranget's "Warnings for pattern matching". A pattern is
+//! useful if it matches a value that none of the patterns above it match; an arm whose
+//! pattern is not useful is unreachable and a match is exhaustive when `_` is not useful.
+use crate::ast as t;
+use crate::ctx::CompileCtx;
+use crate::infer::types::Type;
+use crate::infer::{Infer, InferResult};
+use std::collections::{HashMap, HashSet};
+use util::pos::{Span, Spanned};
+use util::symbol::Symbol;
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// Matches any value i.e `_`
+    Wild,
+    /// An enum variant with an optional payload i.e `List::Head(List::Tail)`
+    Variant {
+        enum_name: Symbol,
+        tag: u32,
+        inner: Option<Box<Pattern>>,
+    },
+    /// A literal value. There are infinitely many literals so a column of literals is
+    /// never complete.
+    Literal(String),
+}
+
+/// A variant's name and whether it stores any data, indexed by its tag
+type Variants = Vec<(Symbol, bool)>;
+
+#[derive(Debug, Default)]
+struct Checker {
+    enums: HashMap<Symbol, Variants>,
+}
+
+impl Infer {
+    /// Warns about any arm that can never be reached and reports an error if a match on an
+    /// enum does not cover every variant
+    pub(crate) fn check_match(
+        &self,
+        cond_ty: &Type,
+        arms: &[Spanned<t::MatchArm>],
+        whole_span: Span,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<()> {
+        let mut checker = Checker::default();
+        let mut rows: Vec<Vec<Pattern>> = Vec::new();
+
+        for arm in arms {
+            let pattern = checker.lower(&arm.value.pattern, ctx);
+
+            if checker
+                .useful(&rows, ::std::slice::from_ref(&pattern))
+                .is_none()
+            {
+                ctx.warn("Unreachable pattern", arm.span);
+            }
+
+            rows.push(vec![pattern]);
+        }
+
+        let enum_name = match *cond_ty {
+            Type::Enum { ref name, .. } => *name,
+            Type::Generic(_, ref ty) => match **ty {
+                Type::Enum { ref name, .. } => *name,
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+
+        if !checker.add_enum(enum_name, ctx) {
+            return Ok(());
+        }
+
+        let mut missing = Vec::new();
+
+        for (tag, &(_, has_inner)) in checker.enums[&enum_name].iter().enumerate() {
+            let variant = Pattern::Variant {
+                enum_name,
+                tag: tag as u32,
+                inner: if has_inner {
+                    Some(Box::new(Pattern::Wild))
+                } else {
+                    None
+                },
+            };
+
+            if let Some(mut witness) = checker.useful(&rows, &[variant]) {
+                missing.push(format!("`{}`", checker.describe(&witness.remove(0), ctx)));
+            }
+        }
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let msg = format!(
+            "Non-exhaustive patterns: {} not covered",
+            missing.join(", ")
+        );
+
+        ctx.error(msg, whole_span);
+        Err(())
+    }
+}
+
+impl Checker {
+    /// Records the variants of the enum; returns false if `name` is not an enum
+    fn add_enum(&mut self, name: Symbol, ctx: &mut CompileCtx) -> bool {
+        if self.enums.contains_key(&name) {
+            return true;
+        }
+
+        let variants = match ctx.look_type(name) {
+            Some(ty) => match *ty {
+                Type::Generic(_, ref ty) => match **ty {
+                    Type::Enum { ref variants, .. } => variants,
+                    _ => return false,
+                },
+                Type::Enum { ref variants, .. } => variants,
+                _ => return false,
+            },
+            None => return false,
+        };
+
+        let mut ordered = vec![None; variants.len()];
+
+        for (name, variant) in variants {
+            ordered[variant.tag as usize] = Some((*name, variant.inner.is_some()));
+        }
+
+        self.enums
+            .insert(name, ordered.into_iter().map(Option::unwrap).collect());
+
+        true
+    }
+
+    /// Converts a typed pattern into a `Pattern`
+    fn lower(&mut self, pattern: &Spanned<t::TypedPattern>, ctx: &mut CompileCtx) -> Pattern {
+        match pattern.value.pattern {
+            t::Pattern::Wildcard | t::Pattern::Binding(_) => Pattern::Wild,
+            t::Pattern::Literal(ref literal) => Pattern::Literal(describe_literal(literal)),
+            t::Pattern::Variant {
+                enum_name,
+                tag,
+                ref inner,
+            } => {
+                self.add_enum(enum_name, ctx);
+
+                Pattern::Variant {
+                    enum_name,
+                    tag,
+                    inner: inner.as_ref().map(|inner| Box::new(self.lower(inner, ctx))),
+                }
+            }
+        }
+    }
+
+    /// Checks if `row` matches a value that none of the `rows` match.
+    /// If it does, a witness is returned; a list of patterns (one per column) that
+    /// describe the value
+    fn useful(&self, rows: &[Vec<Pattern>], row: &[Pattern]) -> Option<Vec<Pattern>> {
+        if row.is_empty() {
+            return if rows.is_empty() { Some(vec![]) } else { None };
+        }
+
+        match row[0] {
+            Pattern::Variant { enum_name, tag, .. } => {
+                self.useful_variant(rows, row, enum_name, tag)
+            }
+
+            Pattern::Literal(ref literal) => {
+                let rows: Vec<_> = rows
+                    .iter()
+                    .filter(|r| match r[0] {
+                        Pattern::Literal(ref other) => other == literal,
+                        _ => true,
+                    })
+                    .map(|r| r[1..].to_vec())
+                    .collect();
+
+                self.useful(&rows, &row[1..])
+                    .map(|witness| prepend(row[0].clone(), witness))
+            }
+
+            Pattern::Wild => {
+                let mut enum_name = None;
+                let mut seen = HashSet::new();
+
+                for r in rows {
+                    if let Pattern::Variant {
+                        enum_name: name,
+                        tag,
+                        ..
+                    } = r[0]
+                    {
+                        enum_name = Some(name);
+                        seen.insert(tag);
+                    }
+                }
+
+                if let Some(enum_name) = enum_name {
+                    let variants = &self.enums[&enum_name];
+
+                    if seen.len() == variants.len() {
+                        // Every variant is present so `_` is only useful if it is useful
+                        // underneath one of them
+                        return (0..variants.len() as u32)
+                            .filter_map(|tag| self.useful_variant(rows, row, enum_name, tag))
+                            .next();
+                    }
+                }
+
+                let rows: Vec<_> = rows
+                    .iter()
+                    .filter(|r| matches!(r[0], Pattern::Wild))
+                    .map(|r| r[1..].to_vec())
+                    .collect();
+
+                self.useful(&rows, &row[1..]).map(|witness| {
+                    let head = match enum_name {
+                        Some(enum_name) => {
+                            let (tag, &(_, has_inner)) = self.enums[&enum_name]
+                                .iter()
+                                .enumerate()
+                                .find(|&(tag, _)| !seen.contains(&(tag as u32)))
+                                .unwrap();
+
+                            Pattern::Variant {
+                                enum_name,
+                                tag: tag as u32,
+                                inner: if has_inner {
+                                    Some(Box::new(Pattern::Wild))
+                                } else {
+                                    None
+                                },
+                            }
+                        }
+                        None => Pattern::Wild,
+                    };
+
+                    prepend(head, witness)
+                })
+            }
+        }
+    }
+
+    /// Checks usefulness of `row` specialized to the given variant and rebuilds the
+    /// variant around the witness
+    fn useful_variant(
+        &self,
+        rows: &[Vec<Pattern>],
+        row: &[Pattern],
+        enum_name: Symbol,
+        tag: u32,
+    ) -> Option<Vec<Pattern>> {
+        let has_inner = self.enums[&enum_name][tag as usize].1;
+
+        let rows: Vec<_> = rows
+            .iter()
+            .filter_map(|r| specialize(r, tag, has_inner))
+            .collect();
+
+        let row = specialize(row, tag, has_inner)?;
+
+        self.useful(&rows, &row).map(|mut witness| {
+            let inner = if has_inner {
+                Some(Box::new(witness.remove(0)))
+            } else {
+                None
+            };
+
+            prepend(
+                Pattern::Variant {
+                    enum_name,
+                    tag,
+                    inner,
+                },
+                witness,
+            )
+        })
+    }
+
+    fn describe(&self, pattern: &Pattern, ctx: &CompileCtx) -> String {
+        match *pattern {
+            Pattern::Wild => "_".into(),
+            Pattern::Literal(ref literal) => literal.clone(),
+            Pattern::Variant {
+                enum_name,
+                tag,
+                ref inner,
+            } => {
+                let variant = self.enums[&enum_name][tag as usize].0;
+                let mut fmt_string = format!("{}::{}", ctx.name(enum_name), ctx.name(variant));
+
+                if let Some(ref inner) = *inner {
+                    fmt_string.push_str(&format!("({})", self.describe(inner, ctx)));
+                }
+
+                fmt_string
+            }
+        }
+    }
+}
+
+/// Specializes a row to a variant. The row is removed if it can't match the variant,
+/// otherwise its head is replaced by the variant's payload
+fn specialize(row: &[Pattern], tag: u32, has_inner: bool) -> Option<Vec<Pattern>> {
+    let mut specialized = Vec::with_capacity(row.len());
+
+    match row[0] {
+        Pattern::Variant {
+            tag: other,
+            ref inner,
+            ..
+        } => {
+            if other != tag {
+                return None;
+            }
+
+            if has_inner {
+                specialized.push(
+                    inner
+                        .as_ref()
+                        .map_or(Pattern::Wild, |inner| (**inner).clone()),
+                );
+            }
+        }
+
+        Pattern::Wild => {
+            if has_inner {
+                specialized.push(Pattern::Wild);
+            }
+        }
+
+        Pattern::Literal(_) => return None,
+    }
+
+    specialized.extend_from_slice(&row[1..]);
+
+    Some(specialized)
+}
+
+fn prepend(head: Pattern, mut rest: Vec<Pattern>) -> Vec<Pattern> {
+    rest.insert(0, head);
+    rest
+}
+
+fn describe_literal(literal: &t::Literal) -> String {
+    match *literal {
+        t::Literal::Float(f) => f.to_string(),
+        t::Literal::Int(i) => i.to_string(),
+        t::Literal::Str(ref s) => format!("{:?}", s),
+        t::Literal::True(_) => "true".into(),
+        t::Literal::False(_) => "false".into(),
+        t::Literal::Nil => "nil".into(),
+    }
+}
@@ -73,6 +73,10 @@ impl Infer {
 
             Expression::Grouping { expr: inner } => self.infer_grouping(*inner, expr.span, ctx),
 
+            Expression::Interpolation(segments) => {
+                self.infer_interpolation(segments, expr.span, ctx)
+            }
+
             Expression::Get { object, property } => {
                 self.infer_get(*object, property, expr.span, ctx)
             }
@@ -83,6 +87,17 @@ impl Infer {
                 self.infer_subscript(*target, *index, expr.span, ctx)
             }
 
+            Expression::Slice { target, start, end } => {
+                self.infer_slice(*target, start, end, expr.span, ctx)
+            }
+
+            Expression::SetSubScript {
+                target,
+                index,
+                kind,
+                value,
+            } => self.infer_set_subscript(*target, *index, kind, *value, expr.span, ctx),
+
             Expression::Literal(literal) => self.infer_literal(literal, expr.span),
 
             Expression::Set {
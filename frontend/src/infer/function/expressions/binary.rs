@@ -44,7 +44,32 @@ impl Infer {
                 )
             }
 
-            Op::Plus | Op::Slash | Op::Star | Op::Minus | Op::Modulo | Op::Exponential => {
+            Op::Modulo | Op::Exponential => {
+                self.unify(&lhs.value.ty, &rhs.value.ty, span, ctx)?;
+
+                match lhs.value.ty {
+                    Type::App(TypeCon::Int, _) | Type::App(TypeCon::Float, _) => (),
+                    ref ty => {
+                        let msg = format!(
+                            "`{}` can only be used on an int or a float not `{}`",
+                            if op.value == Op::Modulo { "%" } else { "^" },
+                            ty.print(ctx.symbols())
+                        );
+
+                        ctx.error(msg, whole_span);
+                        return Err(());
+                    }
+                }
+
+                let ty = lhs.value.ty.clone();
+
+                (
+                    Spanned::new(t::Expression::Binary(lhs, op.value, rhs), whole_span),
+                    ty,
+                )
+            }
+
+            Op::Plus | Op::Slash | Op::Star | Op::Minus => {
                 match self.unify(&lhs.value.ty, &rhs.value.ty, span, ctx) {
                     Ok(()) => (),
                     Err(_) => {
@@ -59,6 +84,19 @@ impl Infer {
                     }
                 }
 
+                // arrays are joined with `+` but can't be used with the other operators
+                if let Type::App(TypeCon::Array(_), _) = lhs.value.ty {
+                    if op.value != Op::Plus {
+                        let msg = format!(
+                            "Only `+` can be used on `{}`",
+                            lhs.value.ty.print(ctx.symbols())
+                        );
+
+                        ctx.error(msg, op.span);
+                        return Err(());
+                    }
+                }
+
                 let ty = lhs.value.ty.clone();
 
                 (
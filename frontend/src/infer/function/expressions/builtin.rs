@@ -0,0 +1,118 @@
+use crate::ast as t;
+use crate::ast::Builtin;
+use crate::ctx::CompileCtx;
+
+use crate::infer::types::{Type, TypeCon};
+use crate::infer::{Infer, InferResult};
+use syntax::ast::Expression;
+use util::pos::{Span, Spanned};
+use util::symbol::Symbol;
+
+impl Infer {
+    /// A call to one of the builtin functions. For the ones that work on arrays the first arg
+    /// is the array and the types of the others depend on the type of its items
+    pub(crate) fn infer_builtin(
+        &mut self,
+        builtin: Builtin,
+        name: Spanned<Symbol>,
+        args: Vec<Spanned<Expression>>,
+        whole_span: Span,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<Spanned<t::TypedExpression>> {
+        let arity = match builtin {
+            Builtin::Len | Builtin::Pop | Builtin::ParseInt | Builtin::ParseFloat => 1,
+            Builtin::Push | Builtin::Remove | Builtin::Contains => 2,
+            Builtin::Insert => 3,
+        };
+
+        if args.len() != arity {
+            let msg = format!("Expected `{}` args found `{}` ", arity, args.len());
+            ctx.error(msg, whole_span);
+            return Err(());
+        }
+
+        let mut typed_args = Vec::with_capacity(args.len());
+
+        for arg in args {
+            typed_args.push(self.infer_expr(arg, ctx)?);
+        }
+
+        let int = Type::App(TypeCon::Int, vec![]);
+        let string = Type::App(TypeCon::Str, vec![]);
+
+        let parsed = match builtin {
+            Builtin::ParseInt => Some(int.clone()),
+            Builtin::ParseFloat => Some(Type::App(TypeCon::Float, vec![])),
+            _ => None,
+        };
+
+        if let Some(parsed) = parsed {
+            self.unify(&string, &typed_args[0].value.ty, typed_args[0].span, ctx)?;
+
+            let ty = ctx.option(parsed);
+
+            return Ok(builtin_expression(builtin, typed_args, ty, whole_span));
+        }
+
+        if builtin == Builtin::Len && typed_args[0].value.ty == string {
+            return Ok(builtin_expression(builtin, typed_args, int, whole_span));
+        }
+
+        let item_ty = match typed_args[0].value.ty {
+            Type::App(TypeCon::Array(ref ty), _) => *ty.clone(),
+            ref ty => {
+                let msg = format!(
+                    "`{}` expects an array not `{}`",
+                    ctx.name(name.value),
+                    ty.print(ctx.symbols())
+                );
+
+                ctx.error(msg, typed_args[0].span);
+                return Err(());
+            }
+        };
+
+        let ty = match builtin {
+            Builtin::Len => int,
+            Builtin::Pop => item_ty,
+            Builtin::Push => {
+                self.unify(&item_ty, &typed_args[1].value.ty, typed_args[1].span, ctx)?;
+                Type::Nil
+            }
+            Builtin::Insert => {
+                self.unify(&int, &typed_args[1].value.ty, typed_args[1].span, ctx)?;
+                self.unify(&item_ty, &typed_args[2].value.ty, typed_args[2].span, ctx)?;
+                Type::Nil
+            }
+            Builtin::Remove => {
+                self.unify(&int, &typed_args[1].value.ty, typed_args[1].span, ctx)?;
+                item_ty
+            }
+            Builtin::Contains => {
+                self.unify(&item_ty, &typed_args[1].value.ty, typed_args[1].span, ctx)?;
+                Type::App(TypeCon::Bool, vec![])
+            }
+            Builtin::ParseInt | Builtin::ParseFloat => unreachable!(),
+        };
+
+        Ok(builtin_expression(builtin, typed_args, ty, whole_span))
+    }
+}
+
+fn builtin_expression(
+    builtin: Builtin,
+    args: Vec<Spanned<t::TypedExpression>>,
+    ty: Type,
+    whole_span: Span,
+) -> Spanned<t::TypedExpression> {
+    Spanned::new(
+        t::TypedExpression {
+            expr: Box::new(Spanned::new(
+                t::Expression::Builtin(builtin, args),
+                whole_span,
+            )),
+            ty,
+        },
+        whole_span,
+    )
+}
@@ -1,4 +1,5 @@
 use crate::ast as t;
+use crate::ast::Builtin;
 use crate::ctx::CompileCtx;
 use crate::infer::types;
 use crate::infer::{Infer, InferResult};
@@ -31,6 +32,9 @@ impl Infer {
             Expression::Var(ref symbol) => {
                 let func = if let Some(func) = ctx.look_var(symbol.value).cloned() {
                     func
+                } else if let Some(builtin) = Builtin::from_name(&ctx.name(symbol.value)) {
+                    // a function that is declared with the same name is called instead
+                    return self.infer_builtin(builtin, symbol.clone(), args, whole_span, ctx);
                 } else {
                     let msg = format!("Undefined function `{}`", ctx.name(symbol.value));
 
@@ -39,81 +43,87 @@ impl Infer {
                     return Err(());
                 };
 
-                match func.get_ty() {
-                    types::Type::Generic(ref typevars, ref ty) => match **ty {
-                        types::Type::App(types::TypeCon::Arrow, ref func_types) => {
-                            if func_types.len() - 1 != args.len() {
-                                // minus one because the return type is stored along with the argument types
+                let (typevars, ty) = match func.get_ty() {
+                    types::Type::Generic(typevars, ty) => (typevars, *ty),
+                    // Closures that are passed in as arguments have a plain function type
+                    ty @ types::Type::App(types::TypeCon::Arrow, _) => (vec![], ty),
+                    _ => {
+                        let msg = format!("`{}` is not callable", ctx.name(symbol.value));
 
-                                let msg = format!(
-                                    "Expected `{}` args found `{}` ",
-                                    func_types.len() - 1,
-                                    args.len()
-                                );
-                                ctx.error(msg, whole_span);
-                                return Err(());
-                            }
+                        ctx.error(msg, callee.span);
+                        return Err(());
+                    }
+                };
 
-                            let mut mappings = HashMap::new();
+                match ty {
+                    types::Type::App(types::TypeCon::Arrow, ref func_types) => {
+                        if func_types.len() - 1 != args.len() {
+                            // minus one because the return type is stored along with the argument types
+
+                            let msg = format!(
+                                "Expected `{}` args found `{}` ",
+                                func_types.len() - 1,
+                                args.len()
+                            );
+                            ctx.error(msg, whole_span);
+                            return Err(());
+                        }
 
-                            let mut arg_types = Vec::new();
+                        let mut mappings = HashMap::new();
 
-                            for (ty, type_var) in types.value.into_iter().zip(typevars.iter()) {
-                                mappings.insert(*type_var, self.trans_type(&ty, ctx)?);
-                            }
+                        let mut arg_types = Vec::new();
 
-                            struct CallExpression {
-                                expr: Spanned<t::TypedExpression>,
-                                ty: types::Type,
-                            };
+                        for (ty, type_var) in types.value.into_iter().zip(typevars.iter()) {
+                            mappings.insert(*type_var, self.trans_type(&ty, ctx)?);
+                        }
 
-                            for arg in args {
-                                let span = arg.span;
-                                let typed_expr = self.infer_expr(arg, ctx)?;
-                                let ty = typed_expr.value.ty.clone();
+                        struct CallExpression {
+                            expr: Spanned<t::TypedExpression>,
+                            ty: types::Type,
+                        };
 
-                                arg_types.push(Spanned {
-                                    value: CallExpression {
-                                        expr: typed_expr,
-                                        ty,
-                                    },
-                                    span,
-                                });
-                            }
+                        for arg in args {
+                            let span = arg.span;
+                            let typed_expr = self.infer_expr(arg, ctx)?;
+                            let ty = typed_expr.value.ty.clone();
 
-                            for (call_expression, def_type) in arg_types.iter_mut().zip(func_types)
-                            {
-                                self.unify(
-                                    &self.subst(def_type, &mut mappings),
-                                    &self.subst(&call_expression.value.ty, &mut mappings),
-                                    call_expression.span,
-                                    ctx,
-                                )?;
+                            arg_types.push(Spanned {
+                                value: CallExpression {
+                                    expr: typed_expr,
+                                    ty,
+                                },
+                                span,
+                            });
+                        }
 
-                                call_expression.value.ty =
-                                    self.subst(&call_expression.value.ty, &mut mappings);
-                            }
+                        for (call_expression, def_type) in arg_types.iter_mut().zip(func_types) {
+                            self.unify(
+                                &self.subst(def_type, &mut mappings),
+                                &self.subst(&call_expression.value.ty, &mut mappings),
+                                call_expression.span,
+                                ctx,
+                            )?;
 
-                            Ok(Spanned {
-                                value: t::TypedExpression {
-                                    expr: Box::new(Spanned {
-                                        value: t::Expression::Call(
-                                            symbol.value,
-                                            arg_types
-                                                .into_iter()
-                                                .map(|arg| arg.value.expr)
-                                                .collect(),
-                                        ),
-                                        span: whole_span,
-                                    }),
-                                    ty: self.subst(func_types.last().unwrap(), &mut mappings),
-                                },
-                                span: whole_span,
-                            })
+                            call_expression.value.ty =
+                                self.subst(&call_expression.value.ty, &mut mappings);
                         }
 
-                        _ => unreachable!(), // Only other possible generic types are structs. Structs are stored in a different environment and they it cannot be a struct
-                    },
+                        Ok(Spanned {
+                            value: t::TypedExpression {
+                                expr: Box::new(Spanned {
+                                    value: t::Expression::Call(
+                                        symbol.value,
+                                        arg_types.into_iter().map(|arg| arg.value.expr).collect(),
+                                    ),
+                                    span: whole_span,
+                                }),
+                                ty: self.subst(func_types.last().unwrap(), &mut mappings),
+                            },
+                            span: whole_span,
+                        })
+                    }
+
+                    // A variable holding an instance of a class is generic over the class's type params
                     _ => {
                         let msg = format!("`{}` is not callable", ctx.name(symbol.value));
 
@@ -189,77 +199,56 @@ impl Infer {
                                 t::Expression::GetMethod {
                                     method_name,
                                     method,
-                                } => match method.value.expr.value {
-                                    t::Expression::Var(_, types::Type::Generic(_, ref ty)) => {
-                                        match **ty {
-                                            types::Type::Class(class_name, _, _, _) => {
-                                                Ok(Spanned {
-                                                    value: t::TypedExpression {
-                                                        expr: Box::new(Spanned {
-                                                            value:
-                                                                t::Expression::StaticMethodCall {
-                                                                    class_name,
-                                                                    method_name,
-                                                                    params: arg_types
-                                                                        .into_iter()
-                                                                        .map(|arg| arg.value.expr)
-                                                                        .collect(),
-                                                                },
-                                                            span: whole_span,
-                                                        }),
-                                                        ty: self.subst(
-                                                            func_types.last().unwrap(),
-                                                            &mut mappings,
-                                                        ),
-                                                    },
-                                                    span: whole_span,
-                                                })
+                                } => {
+                                    let class_name = match method.value.expr.value {
+                                        t::Expression::Var(_, types::Type::Generic(_, ref ty)) => {
+                                            match **ty {
+                                                types::Type::Class(class_name, _, _, _) => {
+                                                    Some(class_name)
+                                                }
+                                                _ => None,
                                             }
-
-                                            _ => Ok(Spanned {
-                                                value: t::TypedExpression {
-                                                    expr: Box::new(Spanned {
-                                                        value: t::Expression::InstanceMethodCall {
-                                                            method_name,
-                                                            instance: method.clone(),
-                                                            params: arg_types
-                                                                .into_iter()
-                                                                .map(|arg| arg.value.expr)
-                                                                .collect(),
-                                                        },
-                                                        span: whole_span,
-                                                    }),
-                                                    ty: self.subst(
-                                                        func_types.last().unwrap(),
-                                                        &mut mappings,
-                                                    ),
-                                                },
-                                                span: whole_span,
-                                            }),
                                         }
-                                    }
 
-                                    t::Expression::ClassLiteral { .. } => Ok(Spanned {
+                                        // Annotated types such as `Foo<int>` are not wrapped in a `Type::Generic`
+                                        t::Expression::Var(
+                                            _,
+                                            types::Type::Class(class_name, _, _, _),
+                                        ) => Some(class_name),
+
+                                        t::Expression::ClassLiteral { .. } => None,
+
+                                        ref e => unreachable!("{:?}", e),
+                                    };
+
+                                    let params =
+                                        arg_types.into_iter().map(|arg| arg.value.expr).collect();
+
+                                    let expr = match class_name {
+                                        Some(class_name) => t::Expression::StaticMethodCall {
+                                            class_name,
+                                            method_name,
+                                            params,
+                                        },
+                                        None => t::Expression::InstanceMethodCall {
+                                            method_name,
+                                            instance: method.clone(),
+                                            params,
+                                        },
+                                    };
+
+                                    Ok(Spanned {
                                         value: t::TypedExpression {
                                             expr: Box::new(Spanned {
-                                                value: t::Expression::InstanceMethodCall {
-                                                    method_name,
-                                                    instance: method.clone(),
-                                                    params: arg_types
-                                                        .into_iter()
-                                                        .map(|arg| arg.value.expr)
-                                                        .collect(),
-                                                },
+                                                value: expr,
                                                 span: whole_span,
                                             }),
                                             ty: self
                                                 .subst(func_types.last().unwrap(), &mut mappings),
                                         },
                                         span: whole_span,
-                                    }),
-
-                                    ref e => unreachable!("{:?}", e),
-                                },
+                                    })
+                                }
 
                                 _ => unreachable!(),
                             }
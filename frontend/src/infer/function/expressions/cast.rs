@@ -16,7 +16,29 @@ impl Infer {
         let from = self.infer_expr(from, ctx)?;
         let to = self.trans_type(&to, ctx)?;
 
-        match (&from.value.ty, &to) {
+        self.check_cast(&from.value.ty, &to, whole_span, ctx)?;
+
+        Ok(Spanned::new(
+            t::TypedExpression {
+                expr: Box::new(Spanned::new(
+                    t::Expression::Cast(from, to.clone()),
+                    whole_span,
+                )),
+                ty: to,
+            },
+            whole_span,
+        ))
+    }
+
+    /// Reports an error if a value of the `from` type can't be cast to the `to` type
+    pub(crate) fn check_cast(
+        &self,
+        from: &types::Type,
+        to: &types::Type,
+        whole_span: Span,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<()> {
+        match (from, to) {
             (
                 types::Type::App(types::TypeCon::Int, _),
                 types::Type::App(types::TypeCon::Float, _),
@@ -53,15 +75,6 @@ impl Infer {
             }
         }
 
-        Ok(Spanned::new(
-            t::TypedExpression {
-                expr: Box::new(Spanned::new(
-                    t::Expression::Cast(from, to.clone()),
-                    whole_span,
-                )),
-                ty: to,
-            },
-            whole_span,
-        ))
+        Ok(())
     }
 }
@@ -2,6 +2,7 @@ use crate::ast as t;
 use crate::ctx::CompileCtx;
 use crate::infer::types::Type;
 use crate::infer::{Infer, InferResult};
+use std::collections::HashMap;
 use syntax::ast::Expression;
 use util::pos::{Span, Spanned};
 use util::symbol::Symbol;
@@ -16,77 +17,74 @@ impl Infer {
     ) -> InferResult<Spanned<t::TypedExpression>> {
         let class_instance = self.infer_expr(object, ctx)?;
 
-        match class_instance.value.ty.clone() {
-            Type::Generic(_, ref ty) => match **ty {
-                Type::Class(ref class_name, _, _, _) => {
-                    match ctx.look_type(*class_name).unwrap().clone() {
-                        // We look at the canical type within the environment due to constructor functions not having  the right type informantion as methods are missing
-                        Type::Generic(_, ref ty) => match **ty {
-                            Type::Class(_, ref propertys, ref methods, _) => {
-                                for property_type in propertys {
-                                    if property_type.name == property.value {
-                                        return Ok(Spanned::new(
-                                            t::TypedExpression {
-                                                expr: Box::new(Spanned::new(
-                                                    t::Expression::GetProperty {
-                                                        property_name: property.value,
-                                                        property: class_instance,
-                                                    },
-                                                    whole_span,
-                                                )),
-                                                ty: property_type.ty.clone(),
-                                            },
-                                            whole_span,
-                                        ));
-                                    }
-                                }
+        let instance_ty = match class_instance.value.ty.clone() {
+            Type::Generic(_, ty) => *ty,
+            ty => ty,
+        }; // Type annotations such as `Foo<int>` are not wrapped in a `Type::Generic`
 
-                                for method_type in methods {
-                                    if method_type.name == property.value {
-                                        return Ok(Spanned::new(
-                                            t::TypedExpression {
-                                                expr: Box::new(Spanned::new(
-                                                    t::Expression::GetMethod {
-                                                        method_name: property.value,
-                                                        method: class_instance,
-                                                    },
-                                                    whole_span,
-                                                )),
-                                                ty: method_type.ty.clone(),
-                                            },
-                                            whole_span,
-                                        ));
-                                    }
-                                }
+        match instance_ty {
+            Type::Class(ref class_name, ref instance_propertys, _, _) => {
+                match ctx.look_type(*class_name).unwrap().clone() {
+                    // We look at the canical type within the environment due to constructor functions not having  the right type informantion as methods are missing
+                    Type::Generic(_, ref ty) => match **ty {
+                        Type::Class(_, ref propertys, ref methods, _) => {
+                            let mut mappings = HashMap::new();
 
-                                let msg = format!(
-                                    "class `{}` doesn't have a field/method named `{}`",
-                                    ctx.name(*class_name),
-                                    ctx.name(property.value)
-                                );
+                            for (canonical, instance) in propertys.iter().zip(instance_propertys) {
+                                self.bind(&canonical.ty, &instance.ty, &mut mappings);
+                            } // The instance of a generic class has the type params substituted
 
-                                ctx.error(msg, whole_span);
-                                Err(())
+                            for property_type in propertys {
+                                if property_type.name == property.value {
+                                    return Ok(Spanned::new(
+                                        t::TypedExpression {
+                                            expr: Box::new(Spanned::new(
+                                                t::Expression::GetProperty {
+                                                    property_name: property.value,
+                                                    property: class_instance,
+                                                },
+                                                whole_span,
+                                            )),
+                                            ty: self.subst(&property_type.ty, &mut mappings),
+                                        },
+                                        whole_span,
+                                    ));
+                                }
                             }
-                            _ => unreachable!(),
-                        },
 
-                        _ => unreachable!(),
-                    }
-                }
+                            for method_type in methods {
+                                if method_type.name == property.value {
+                                    return Ok(Spanned::new(
+                                        t::TypedExpression {
+                                            expr: Box::new(Spanned::new(
+                                                t::Expression::GetMethod {
+                                                    method_name: property.value,
+                                                    method: class_instance,
+                                                },
+                                                whole_span,
+                                            )),
+                                            ty: self.subst(&method_type.ty, &mut mappings),
+                                        },
+                                        whole_span,
+                                    ));
+                                }
+                            }
+
+                            let msg = format!(
+                                "class `{}` doesn't have a field/method named `{}`",
+                                ctx.name(*class_name),
+                                ctx.name(property.value)
+                            );
 
-                ref err_type => {
-                    println!("{:?}", err_type);
-                    let msg = format!(
-                        "Type {} dosen't have the method/field {}",
-                        err_type.print(ctx.symbols()),
-                        ctx.name(property.value)
-                    );
+                            ctx.error(msg, whole_span);
+                            Err(())
+                        }
+                        _ => unreachable!(),
+                    },
 
-                    ctx.error(msg, whole_span);
-                    Err(())
+                    _ => unreachable!(),
                 }
-            },
+            }
 
             ref err_type => {
                 let msg = format!(
@@ -95,8 +93,6 @@ impl Infer {
                     ctx.name(property.value)
                 );
 
-                println!("{:?}", err_type);
-
                 ctx.error(msg, whole_span);
                 Err(())
             }
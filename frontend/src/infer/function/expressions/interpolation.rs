@@ -0,0 +1,70 @@
+use crate::ast as t;
+use crate::ctx::CompileCtx;
+
+use crate::infer::types::{Type, TypeCon};
+use crate::infer::{Infer, InferResult};
+use syntax::ast::{Literal, Segment};
+use util::pos::{Span, Spanned};
+
+impl Infer {
+    /// `"text ${expr} text"`. Each hole is cast to a str the same way `as str` would and
+    /// the empty text around the holes is left out
+    pub(crate) fn infer_interpolation(
+        &mut self,
+        segments: Vec<Segment>,
+        whole_span: Span,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<Spanned<t::TypedExpression>> {
+        let str_ty = Type::App(TypeCon::Str, vec![]);
+        let mut pieces = Vec::with_capacity(segments.len());
+
+        for segment in segments {
+            match segment {
+                Segment::Text(ref text) if text.value.is_empty() => (),
+                Segment::Text(text) => pieces.push(Spanned::new(
+                    t::TypedExpression {
+                        expr: Box::new(Spanned::new(
+                            t::Expression::Literal(Literal::Str(text.value)),
+                            text.span,
+                        )),
+                        ty: str_ty.clone(),
+                    },
+                    text.span,
+                )),
+                Segment::Hole(expr) => {
+                    let span = expr.span;
+                    let expr = self.infer_expr(expr, ctx)?;
+
+                    if expr.value.ty == str_ty {
+                        pieces.push(expr);
+                        continue;
+                    }
+
+                    self.check_cast(&expr.value.ty, &str_ty, span, ctx)?;
+
+                    pieces.push(Spanned::new(
+                        t::TypedExpression {
+                            expr: Box::new(Spanned::new(
+                                t::Expression::Cast(expr, str_ty.clone()),
+                                span,
+                            )),
+                            ty: str_ty.clone(),
+                        },
+                        span,
+                    ))
+                }
+            }
+        }
+
+        Ok(Spanned::new(
+            t::TypedExpression {
+                expr: Box::new(Spanned::new(
+                    t::Expression::Interpolation(pieces),
+                    whole_span,
+                )),
+                ty: str_ty,
+            },
+            whole_span,
+        ))
+    }
+}
@@ -1,11 +1,13 @@
 mod array;
 mod assign;
 mod binary;
+mod builtin;
 mod call;
 mod cast;
 mod class_literal;
 mod get;
 mod grouping;
+mod interpolation;
 mod literal;
 mod pattern;
 mod set;
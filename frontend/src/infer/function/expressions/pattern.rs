@@ -1,9 +1,11 @@
 use crate::ast as t;
 use crate::ctx::CompileCtx;
 
+use crate::infer::env::VarEntry;
 use crate::infer::types::Type;
 use crate::infer::{Infer, InferResult};
-use syntax::ast::{Expression, MatchArm};
+use std::collections::HashMap;
+use syntax::ast::{Expression, MatchArm, Pattern};
 use util::pos::{Span, Spanned};
 
 impl Infer {
@@ -22,74 +24,29 @@ impl Infer {
 
         let mut typed_arms = Vec::new();
         let arms_span = arms.span;
-        let mut arms = arms.value;
 
-        if !arms.is_empty() {
-            let first = arms.remove(0);
-            let first_span = first.span;
+        for (i, arm) in arms.value.into_iter().enumerate() {
+            let span = arm.span;
 
-            if first.value.pattern.is_none() {
-                let first_body = self.infer_statement(first.value.body, ctx)?;
-                return_type = first_body.value.ty.clone();
+            ctx.begin_scope(); // Bindings are only visible within the arm
 
-                typed_arms.push(Spanned::new(
-                    t::MatchArm {
-                        pattern: None,
-                        body: first_body,
-                        is_all: true,
-                    },
-                    first_span,
-                ));
-            } else {
-                let first_pattern = self.infer_expr(first.value.pattern.unwrap(), ctx)?;
-                let first_body = self.infer_statement(first.value.body, ctx)?;
+            let pattern = self.infer_pattern(arm.value.pattern, &pattern_type, ctx)?;
 
-                return_type = first_body.value.ty.clone();
+            let body = self.infer_statement(arm.value.body, ctx)?;
 
-                typed_arms.push(Spanned::new(
-                    t::MatchArm {
-                        pattern: Some(first_pattern),
-                        body: first_body,
-                        is_all: false,
-                    },
-                    first_span,
-                ));
-            }
+            ctx.end_scope();
 
-            for arm in arms {
-                let span = arm.span;
-                let is_all = arm.value.is_all;
-
-                if is_all {
-                    typed_arms.push(Spanned::new(
-                        t::MatchArm {
-                            pattern: None,
-                            body: self.infer_statement(arm.value.body, ctx)?,
-                            is_all: true,
-                        },
-                        span,
-                    ));
-                } else {
-                    let lhs = self.infer_expr(arm.value.pattern.unwrap(), ctx)?; // unwrap
-
-                    self.unify(&lhs.value.ty, &pattern_type, lhs.span, ctx)?;
-
-                    let rhs = self.infer_statement(arm.value.body, ctx)?;
-
-                    self.unify(&rhs.value.ty, &return_type, rhs.span, ctx)?;
-
-                    typed_arms.push(Spanned::new(
-                        t::MatchArm {
-                            pattern: Some(lhs),
-                            body: rhs,
-                            is_all: false,
-                        },
-                        span,
-                    ));
-                }
+            if i == 0 {
+                return_type = body.value.ty.clone();
+            } else {
+                self.unify(&body.value.ty, &return_type, body.span, ctx)?;
             }
+
+            typed_arms.push(Spanned::new(t::MatchArm { pattern, body }, span));
         }
 
+        self.check_match(&pattern_type, &typed_arms, whole_span, ctx)?;
+
         Ok(Spanned::new(
             t::TypedExpression {
                 expr: Box::new(Spanned::new(
@@ -104,4 +61,123 @@ impl Infer {
             whole_span,
         ))
     }
+
+    /// Type checks a pattern against the type of the value being matched on and adds
+    /// any bindings to the current scope
+    pub(crate) fn infer_pattern(
+        &mut self,
+        pattern: Spanned<Pattern>,
+        expected: &Type,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<Spanned<t::TypedPattern>> {
+        let span = pattern.span;
+
+        let pattern = match pattern.value {
+            Pattern::Wildcard => t::Pattern::Wildcard,
+
+            Pattern::Binding(ident) => {
+                ctx.add_var(ident.value, VarEntry::Var(expected.clone()));
+                t::Pattern::Binding(ident.value)
+            }
+
+            Pattern::Literal(literal) => {
+                let ty = self.infer_literal(literal.clone(), span)?.value.ty;
+
+                self.unify(expected, &ty, span, ctx)?;
+
+                t::Pattern::Literal(literal)
+            }
+
+            Pattern::Variant {
+                enum_name,
+                variant,
+                inner,
+            } => {
+                let (typevars, declared) = match ctx.look_type(enum_name.value).cloned() {
+                    Some(Type::Generic(typevars, ty)) => match *ty {
+                        ty @ Type::Enum { .. } => (typevars, ty),
+                        _ => {
+                            let msg = format!("`{}` is not an enum", ctx.name(enum_name.value));
+                            ctx.error(msg, enum_name.span);
+                            return Err(());
+                        }
+                    },
+                    _ => {
+                        let msg = format!("`{}` is not an enum", ctx.name(enum_name.value));
+                        ctx.error(msg, enum_name.span);
+                        return Err(());
+                    }
+                };
+
+                self.unify(expected, &declared, span, ctx)?;
+
+                // The declared enum is generic so its type params are filled in from
+                // the type being matched on before the data is checked
+                let mut mappings = HashMap::new();
+
+                self.bind(&declared, expected, &mut mappings);
+
+                let variant_ty = match self.subst(&declared, &mut mappings) {
+                    Type::Enum { mut variants, .. } if variants.contains_key(&variant.value) => {
+                        variants.remove(&variant.value).unwrap()
+                    }
+                    _ => {
+                        let msg = format!("Unknown enum variant `{}`", ctx.name(variant.value));
+                        ctx.error(msg, variant.span);
+                        return Err(());
+                    }
+                };
+
+                let inner = match (variant_ty.inner, inner) {
+                    // Nothing has filled in the type param yet (e.g. the value was built from
+                    // a variant without data) so a literal decides what it is
+                    (Some(Type::Var(ref tv)), Some(inner)) if typevars.contains(tv) => {
+                        let ty = match inner.value {
+                            Pattern::Literal(ref literal) => {
+                                self.infer_literal(literal.clone(), inner.span)?.value.ty
+                            }
+                            _ => Type::Var(*tv),
+                        };
+
+                        Some(Box::new(self.infer_pattern(*inner, &ty, ctx)?))
+                    }
+                    (Some(ref ty), Some(inner)) => {
+                        Some(Box::new(self.infer_pattern(*inner, ty, ctx)?))
+                    }
+                    (None, None) => None,
+                    (None, Some(_)) => {
+                        let msg = format!(
+                            "The variant `{}` dosen't store any data",
+                            ctx.name(variant.value)
+                        );
+                        ctx.error(msg, variant.span);
+                        return Err(());
+                    }
+                    (Some(ref ty), None) => {
+                        let msg = format!(
+                            "The variant `{}` should store the data of type {} ",
+                            ctx.name(variant.value),
+                            ty.print(ctx.symbols())
+                        );
+                        ctx.error(msg, variant.span);
+                        return Err(());
+                    }
+                };
+
+                t::Pattern::Variant {
+                    enum_name: enum_name.value,
+                    tag: variant_ty.tag,
+                    inner,
+                }
+            }
+        };
+
+        Ok(Spanned::new(
+            t::TypedPattern {
+                pattern,
+                ty: expected.clone(),
+            },
+            span,
+        ))
+    }
 }
@@ -3,7 +3,7 @@ use crate::ctx::CompileCtx;
 
 use crate::infer::types::{Type, TypeCon};
 use crate::infer::{Infer, InferResult};
-use syntax::ast::Expression;
+use syntax::ast::{AssignOperator, Expression};
 use util::pos::{Span, Spanned};
 
 impl Infer {
@@ -55,10 +55,10 @@ impl Infer {
                         let var = Spanned::new(
                             t::TypedExpression {
                                 expr: Box::new(Spanned::new(
-                                    t::Expression::Var(symbol.value, index_ty.value.ty.clone()),
+                                    t::Expression::Var(symbol.value, target_ty.clone()),
                                     target_span,
                                 )),
-                                ty: index_ty.value.ty.clone(),
+                                ty: target_ty.clone(),
                             },
                             target_span,
                         );
@@ -105,4 +105,141 @@ impl Infer {
             span: whole_span,
         })
     }
+
+    /// `target[start..end]` copies the items between the bounds into a new array
+    pub(crate) fn infer_slice(
+        &mut self,
+        target: Spanned<Expression>,
+        start: Option<Box<Spanned<Expression>>>,
+        end: Option<Box<Spanned<Expression>>>,
+        whole_span: Span,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<Spanned<t::TypedExpression>> {
+        let target_span = target.span;
+        let target = self.infer_expr(target, ctx)?;
+
+        if let Type::App(TypeCon::Array(_), _) = target.value.ty {
+        } else {
+            let msg = format!(
+                " Cannot slice type `{}` ",
+                target.value.ty.print(ctx.symbols())
+            );
+            ctx.error(msg, target_span);
+            return Err(());
+        }
+
+        let start = self.infer_slice_bound(start, ctx)?;
+        let end = self.infer_slice_bound(end, ctx)?;
+        let ty = target.value.ty.clone();
+
+        Ok(Spanned::new(
+            t::TypedExpression {
+                expr: Box::new(Spanned::new(
+                    t::Expression::Slice(target, start, end),
+                    whole_span,
+                )),
+                ty,
+            },
+            whole_span,
+        ))
+    }
+
+    fn infer_slice_bound(
+        &mut self,
+        bound: Option<Box<Spanned<Expression>>>,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<Option<Spanned<t::TypedExpression>>> {
+        match bound {
+            Some(bound) => {
+                let span = bound.span;
+                let bound = self.infer_expr(*bound, ctx)?;
+
+                self.unify(&bound.value.ty, &Type::App(TypeCon::Int, vec![]), span, ctx)?;
+
+                Ok(Some(bound))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// `target[index] = value`. Only the items of an array can be assigned to and `+=` etc
+    /// can only be used on items that are ints or floats, or strs with `+=`
+    pub(crate) fn infer_set_subscript(
+        &mut self,
+        target: Spanned<Expression>,
+        index: Spanned<Expression>,
+        kind: Spanned<AssignOperator>,
+        value: Spanned<Expression>,
+        whole_span: Span,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<Spanned<t::TypedExpression>> {
+        let target_span = target.span;
+        let index_span = index.span;
+        let value_span = value.span;
+
+        let target = self.infer_expr(target, ctx)?;
+        let index = self.infer_expr(index, ctx)?;
+        let value = self.infer_expr(value, ctx)?;
+
+        self.unify(
+            &index.value.ty,
+            &Type::App(TypeCon::Int, vec![]),
+            index_span,
+            ctx,
+        )?;
+
+        let item_ty = match target.value.ty {
+            Type::App(TypeCon::Array(ref ty), _) => *ty.clone(),
+            Type::App(TypeCon::Str, _) => {
+                ctx.error(
+                    "The characters of a `str` can't be assigned to",
+                    target_span,
+                );
+                return Err(());
+            }
+            ref ty => {
+                let msg = format!(" Cannot index type `{}` ", ty.print(ctx.symbols()));
+                ctx.error(msg, target_span);
+                return Err(());
+            }
+        };
+
+        self.unify(&item_ty, &value.value.ty, value_span, ctx)?;
+
+        let operator = match kind.value {
+            AssignOperator::Equal => None,
+            AssignOperator::PlusEqual => Some("+="),
+            AssignOperator::MinusEqual => Some("-="),
+            AssignOperator::StarEqual => Some("*="),
+            AssignOperator::SlashEqual => Some("/="),
+        };
+
+        if let Some(operator) = operator {
+            match item_ty {
+                Type::App(TypeCon::Int, _) | Type::App(TypeCon::Float, _) => (),
+                Type::App(TypeCon::Str, _) if operator == "+=" => (),
+                ref ty => {
+                    let msg = format!(
+                        "`{}` can't be used on an item of type `{}`",
+                        operator,
+                        ty.print(ctx.symbols())
+                    );
+
+                    ctx.error(msg, kind.span);
+                    return Err(());
+                }
+            }
+        }
+
+        Ok(Spanned::new(
+            t::TypedExpression {
+                expr: Box::new(Spanned::new(
+                    t::Expression::SetIndex(target, index, kind.value, value),
+                    whole_span,
+                )),
+                ty: item_ty,
+            },
+            whole_span,
+        ))
+    }
 }
@@ -66,10 +66,11 @@ impl Infer {
 
                         let mut mappings = HashMap::new();
 
-                        mappings.insert(
-                            typevars[(variant_ty.tag) as usize],
-                            inner_ty.value.ty.clone(),
-                        );
+                        if let Some(declared) = variant_ty.inner.as_ref() {
+                            self.bind(declared, &inner_ty.value.ty, &mut mappings);
+                        }
+
+                        mappings.retain(|tv, _| typevars.contains(tv));
 
                         self.unify(
                             &self.subst(variant_ty.inner.as_ref().unwrap(), &mut mappings),
@@ -87,10 +88,12 @@ impl Infer {
                             whole_span,
                         );
 
+                        // The type params that the data fills in are kept so that
+                        // matching on the value knows the type of its data
                         Ok(Spanned::new(
                             t::TypedExpression {
                                 expr: Box::new(expr),
-                                ty: *ty.clone(),
+                                ty: self.subst(ty, &mut mappings),
                             },
                             whole_span,
                         ))
@@ -246,12 +246,22 @@ impl Infer {
 
             Statement::VarDeclaration { ident, ty, expr } => {
                 if let Some(expr) = expr {
-                    let expr_tyexpr = self.infer_expr(expr, ctx)?;
+                    let mut expr_tyexpr = self.infer_expr(expr, ctx)?;
 
                     if let Some(ty) = ty {
                         let t = self.trans_type(&ty, ctx)?;
 
-                        self.unify(&expr_tyexpr.value.ty, &t, ty.span, ctx)?;
+                        match (&*expr_tyexpr.value.expr, &t) {
+                            // `[]` has no items to give it a type so it takes the declared type
+                            (
+                                Spanned {
+                                    value: t::Expression::Array(ref items),
+                                    ..
+                                },
+                                Type::App(TypeCon::Array(_), _),
+                            ) if items.is_empty() => expr_tyexpr.value.ty = t.clone(),
+                            _ => self.unify(&expr_tyexpr.value.ty, &t, ty.span, ctx)?,
+                        }
 
                         ctx.add_var(ident.value, VarEntry::Var(t.clone()));
 
@@ -0,0 +1,139 @@
+use crate::ast as t;
+use crate::ctx::CompileCtx;
+use crate::infer::types::Type;
+use crate::infer::{Infer, InferResult};
+use syntax::ast::{AssignOperator, Literal, Statement};
+use util::pos::{Spanned, EMPTYSPAN};
+
+impl Infer {
+    /// Infers a statement outside of any function. A `let` declares a global that every
+    /// function declared after it can use. Within a script the statement is added to the
+    /// body of `main`
+    pub(crate) fn infer_top_level(
+        &mut self,
+        statement: Spanned<Statement>,
+        new_program: &mut t::Program,
+        ctx: &mut CompileCtx,
+    ) -> InferResult<()> {
+        let span = statement.span;
+
+        match statement.value {
+            Statement::VarDeclaration { expr: None, .. } => {
+                ctx.error("A global must be given a value", span);
+                Err(())
+            }
+
+            Statement::VarDeclaration { .. } => {
+                // declared in the outermost scope so the functions can see it
+                let typed = self.infer_statement(statement, ctx)?;
+
+                let (ident, ty, expr) = match typed.value.statement.value {
+                    t::Statement::Let { ident, ty, expr } => (ident, ty, expr.unwrap()),
+                    _ => unreachable!(),
+                };
+
+                match self.script {
+                    // a script sets the global when the statement is reached
+                    Some(ref mut body) => {
+                        new_program.globals.push(t::Global {
+                            name: ident,
+                            ty: ty.clone(),
+                            value: None,
+                        });
+
+                        let assign = t::TypedExpression {
+                            expr: Box::new(Spanned::new(
+                                t::Expression::Assign(ident, AssignOperator::Equal, expr),
+                                span,
+                            )),
+                            ty: ty.clone(),
+                        };
+
+                        body.push(Spanned::new(
+                            t::TypedStatement {
+                                statement: Box::new(Spanned::new(
+                                    t::Statement::Expr(Spanned::new(assign, span)),
+                                    span,
+                                )),
+                                ty,
+                            },
+                            span,
+                        ));
+                    }
+
+                    None => new_program.globals.push(t::Global {
+                        name: ident,
+                        ty,
+                        value: Some(expr),
+                    }),
+                }
+
+                Ok(())
+            }
+
+            _ if self.script.is_some() => {
+                let typed = self.infer_statement(statement, ctx)?;
+
+                self.script.as_mut().unwrap().push(typed);
+
+                Ok(())
+            }
+
+            _ => {
+                ctx.error(
+                    "Only a `let` can be used outside of a function unless the program is a script",
+                    span,
+                );
+                Err(())
+            }
+        }
+    }
+
+    /// Creates the `main` function of a script which runs the statements outside of the
+    /// functions in order
+    pub(crate) fn script_main(&mut self, ctx: &mut CompileCtx) -> InferResult<t::Function> {
+        let mut body = self.script.take().unwrap_or_default();
+
+        let span = body.last().map_or(EMPTYSPAN, |statement| statement.span);
+
+        // a `return` outside of a function returns from `main`
+        self.unify(&Type::Nil, &self.body, span, ctx)?;
+        self.body = Type::Nil;
+
+        body.push(Spanned::new(
+            t::TypedStatement {
+                statement: Box::new(Spanned::new(
+                    t::Statement::Return(Spanned::new(
+                        t::TypedExpression {
+                            expr: Box::new(Spanned::new(
+                                t::Expression::Literal(Literal::Nil),
+                                span,
+                            )),
+                            ty: Type::Nil,
+                        },
+                        span,
+                    )),
+                    span,
+                )),
+                ty: Type::Nil,
+            },
+            span,
+        ));
+
+        let main = ctx.symbol("main");
+        self.set_main(main);
+
+        Ok(t::Function {
+            name: main,
+            params: vec![],
+            body: Box::new(Spanned::new(
+                t::TypedStatement {
+                    statement: Box::new(Spanned::new(t::Statement::Block(body), span)),
+                    ty: Type::Nil,
+                },
+                span,
+            )),
+            returns: Type::Nil,
+        })
+    }
+}
@@ -5,7 +5,9 @@ pub(crate) mod env;
 // mod resolver;
 mod alias;
 mod class;
+mod exhaustive;
 mod function;
+mod global;
 pub(crate) mod subst;
 mod sum;
 pub(crate) mod types;
@@ -14,14 +16,18 @@ mod user_types;
 
 pub(crate) type InferResult<T> = Result<T, ()>;
 // pub use self::resolver::Resolver;
+use crate::ast::TypedStatement;
 use crate::infer::types::Type;
 use std::rc::Rc;
+use util::pos::{Span, Spanned};
 use util::symbol::Symbol;
 
 #[derive(Debug)]
 pub struct Infer {
     body: Type,
     main: Option<Symbol>,
+    /// The body of `main` if the program is a script
+    script: Option<Vec<Spanned<TypedStatement>>>,
 }
 
 impl Default for Infer {
@@ -29,6 +35,7 @@ impl Default for Infer {
         Self {
             body: Type::Nil,
             main: None,
+            script: None,
         }
     }
 }
@@ -38,45 +45,122 @@ impl Infer {
         Self::default()
     }
 
-    /// Runs type inference returns a version of the ast which has the type of each operation
+    /// Infers the program as a script. The statements outside of the functions become
+    /// the body of `main` so a script can't declare its own
+    pub fn script() -> Self {
+        Self {
+            script: Some(Vec::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Runs type inference returns a version of the ast which has the type of each operation.
+    /// The program can call any of the natives
     pub fn infer(
         &mut self,
         program: ::syntax::ast::Program,
         strings: &Rc<::util::symbol::SymbolFactory>,
+        natives: &::vm::NativeRegistry,
         reporter: &mut ::util::emmiter::Reporter,
     ) -> InferResult<super::ast::Program> {
-        let mut ctx = crate::ctx::CompileCtx::new(strings, reporter);
+        let mut ctx = crate::ctx::CompileCtx::new(strings, reporter, natives);
 
         let mut new_program = super::ast::Program {
             functions: Vec::new(),
             classes: Vec::new(),
+            globals: Vec::new(),
         };
 
+        self.infer_declarations(program, &mut new_program, &mut ctx)?;
+
+        if self.script.is_some() {
+            let main = self.script_main(&mut ctx)?;
+            new_program.functions.push(main);
+        }
+
+        if self.main.is_none() {
+            ctx.global_error("Main method is missing");
+            return Err(());
+        }
+
+        Ok(new_program)
+    }
+
+    /// Infers the aliases, enums, classes and functions of the program adding the typed
+    /// classes and functions to `new_program`
+    pub(crate) fn infer_declarations(
+        &mut self,
+        program: ::syntax::ast::Program,
+        new_program: &mut super::ast::Program,
+        ctx: &mut crate::ctx::CompileCtx,
+    ) -> InferResult<()> {
+        // the imports are loaded by `syntax::loader` which leaves none behind
+        if let Some(import) = program.imports.first() {
+            ctx.error(
+                "An `import` can only be used in a program loaded from a file",
+                import.span,
+            );
+            return Err(());
+        }
+
         for alias in program.aliases.iter() {
-            self.infer_alias(alias, &mut ctx)?;
+            self.infer_alias(alias, ctx)?;
         }
 
         for sum in program.enums {
-            self.infer_enum(sum, &mut ctx)?;
+            self.infer_enum(sum, ctx)?;
         }
 
         for class in program.classes {
-            new_program.classes.push(self.infer_class(class, &mut ctx)?);
+            new_program.classes.push(self.infer_class(class, ctx)?);
         }
 
-        for function in program.functions {
+        // the functions and the statements outside of them are inferred in the order they
+        // are written so each can only use what was declared before it. A file comes after
+        // the files it imports
+        let files = program.files;
+        let order = |span: Span| {
+            let file = files.iter().position(|file| *file == span.start.file);
+
+            (file, span.start.absolute)
+        };
+
+        let mut functions = program.functions.into_iter().peekable();
+        let mut statements = program.statements.into_iter().peekable();
+
+        loop {
+            let function_first = match (functions.peek(), statements.peek()) {
+                (Some(function), Some(statement)) => {
+                    order(function.span) < order(statement.span)
+                }
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+
+            if !function_first {
+                let statement = statements.next().unwrap();
+                self.infer_top_level(statement, new_program, ctx)?;
+                continue;
+            }
+
+            let function = functions.next().unwrap();
+
+            if self.script.is_some() && ctx.name(function.value.name.value.name.value) == "main" {
+                ctx.error(
+                    "A script can't declare a `main` function",
+                    function.value.name.value.name.span,
+                );
+                return Err(());
+            }
+
             new_program
                 .functions
-                .push(self.infer_function(function, &mut ctx)?);
+                .push(self.infer_function(function, ctx)?);
             self.body = Type::Nil; // resets the body for the next run
         }
 
-        if self.main.is_none() {
-            ctx.global_error("Main method is missing");
-            return Err(());
-        }
-
-        Ok(new_program)
+        Ok(())
     }
 
     pub fn set_main(&mut self, symbol: Symbol) {
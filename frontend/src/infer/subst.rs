@@ -1,5 +1,5 @@
 use super::Infer;
-use crate::infer::types::{Method, Property, Type, TypeVar, Variant};
+use crate::infer::types::{Method, Property, Type, TypeCon, TypeVar, Variant};
 use std::collections::HashMap;
 
 impl Infer {
@@ -7,7 +7,12 @@ impl Infer {
     pub fn subst(&self, ty: &Type, substions: &mut HashMap<TypeVar, Type>) -> Type {
         match *ty {
             Type::App(ref tycon, ref types) => Type::App(
-                tycon.clone(),
+                match *tycon {
+                    TypeCon::Array(ref inner) => {
+                        TypeCon::Array(Box::new(self.subst(inner, substions)))
+                    }
+                    ref tycon => tycon.clone(),
+                },
                 types.iter().map(|ty| self.subst(ty, substions)).collect(),
             ),
 
@@ -69,4 +74,60 @@ impl Infer {
             }
         }
     }
+
+    /// Builds the substitutions that turn `generic` into `concrete` by walking both
+    /// types side by side and recording what each type variable lines up with
+    pub fn bind(&self, generic: &Type, concrete: &Type, substions: &mut HashMap<TypeVar, Type>) {
+        match (generic, concrete) {
+            (Type::Var(tvar), ty) => {
+                substions.entry(*tvar).or_insert_with(|| ty.clone());
+            }
+
+            (Type::Generic(_, generic), concrete) => self.bind(generic, concrete, substions),
+
+            (generic, Type::Generic(_, concrete)) => self.bind(generic, concrete, substions),
+
+            (Type::App(tycon1, types1), Type::App(tycon2, types2)) => {
+                if let (TypeCon::Array(inner1), TypeCon::Array(inner2)) = (tycon1, tycon2) {
+                    self.bind(inner1, inner2, substions);
+                }
+
+                for (generic, concrete) in types1.iter().zip(types2) {
+                    self.bind(generic, concrete, substions)
+                }
+            }
+
+            (Type::Class(_, fields1, _, _), Type::Class(_, fields2, _, _)) => {
+                for (generic, concrete) in fields1.iter().zip(fields2) {
+                    self.bind(&generic.ty, &concrete.ty, substions)
+                }
+            }
+
+            (
+                Type::Enum {
+                    variants: variants1,
+                    ..
+                },
+                Type::Enum {
+                    variants: variants2,
+                    ..
+                },
+            ) => {
+                for (name, variant) in variants1 {
+                    if let (
+                        Some(generic),
+                        Some(Variant {
+                            inner: Some(concrete),
+                            ..
+                        }),
+                    ) = (variant.inner.as_ref(), variants2.get(name))
+                    {
+                        self.bind(generic, concrete, substions)
+                    }
+                }
+            }
+
+            _ => (),
+        }
+    }
 }
@@ -8,6 +8,14 @@ use util::pos::Spanned;
 
 impl Infer {
     pub fn infer_enum(&mut self, _enum: Spanned<Enum>, ctx: &mut CompileCtx) -> InferResult<()> {
+        let name = &_enum.value.name.value.name;
+
+        // the vm creates the `Option` that `parse_int` returns using the tags of the builtin one
+        if ctx.name(name.value) == "Option" {
+            ctx.error("`Option` is built in and can't be declared", name.span);
+            return Err(());
+        }
+
         let mut generic_type_vars = Vec::with_capacity(_enum.value.name.value.type_params.len());
         let mut type_params = Vec::with_capacity(_enum.value.name.value.type_params.len());
 
@@ -121,13 +121,12 @@ impl Type {
                 if let TypeCon::Arrow = *tycon {
                     fmt_string.push_str("fn(");
 
-                    for i in 0..types.len() - 1 {
-                        if i + 1 == types.len() - 1 {
-                            fmt_string.push_str(&types[i].print(symbols).to_string());
-                        } else {
-                            fmt_string += ",";
-                            fmt_string.push_str(&types[i].print(symbols).to_string());
+                    for (i, ty) in types[..types.len() - 1].iter().enumerate() {
+                        if i > 0 {
+                            fmt_string += ", ";
                         }
+
+                        fmt_string.push_str(&ty.print(symbols));
                     }
 
                     fmt_string.push_str(") -> ");
@@ -137,15 +136,18 @@ impl Type {
                     return fmt_string;
                 }
 
+                if let TypeCon::Array(ref inner) = *tycon {
+                    return format!("[{}]", inner.print(symbols));
+                }
+
                 fmt_string.push_str(&format!("{}", tycon));
 
                 for (i, ty) in types.iter().enumerate() {
-                    if i + 1 == types.len() {
-                        fmt_string.push_str(&ty.print(symbols).to_string())
-                    } else {
-                        fmt_string += ",";
-                        fmt_string.push_str(&ty.print(symbols).to_string())
+                    if i > 0 {
+                        fmt_string += ", ";
                     }
+
+                    fmt_string.push_str(&ty.print(symbols))
                 }
 
                 fmt_string
@@ -165,12 +167,11 @@ impl Type {
                 if !properties.is_empty() {
                     fmt_string.push('<');
                     for (i, field) in properties.iter().enumerate() {
-                        if i + 1 == properties.len() {
-                            fmt_string.push_str(&field.ty.print(symbols).to_string());
-                        } else {
-                            fmt_string += ",";
-                            fmt_string.push_str(&field.ty.print(symbols).to_string());
+                        if i > 0 {
+                            fmt_string += ", ";
                         }
+
+                        fmt_string.push_str(&field.ty.print(symbols));
                     }
 
                     fmt_string.push('>');
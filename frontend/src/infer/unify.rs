@@ -53,6 +53,7 @@ impl Infer {
                 if name1 != name2 {
                     let msg = format!("Enum `{}` != Enum `{}`", ctx.name(*name1), ctx.name(*name2));
                     ctx.error(msg, span);
+                    return Err(());
                 }
 
                 // for(v1,v2) in variants1.iter().zip(variants2.iter()) {
@@ -9,9 +9,19 @@ extern crate util;
 extern crate vm;
 
 mod ast;
+mod capture;
 mod codegen;
 mod ctx;
+mod dump;
+mod fold;
 mod infer;
+mod monomorphise;
+pub mod query;
+mod session;
 
+pub use crate::ast::Program as TypedProgram;
 pub use crate::codegen::compile;
+pub use crate::fold::fold;
 pub use crate::infer::Infer;
+pub use crate::monomorphise::monomorphise;
+pub use crate::session::Session;
@@ -0,0 +1,533 @@
+//! Monomorphisation of generic functions and classes.
+//!
+//! Generic items are type checked once with their type params left as type variables.
+//! Codegen needs to know the concrete types so this pass creates a copy of the item for
+//! each set of type arguments it is used with i.e `add::<int>` and `add::<float>` become
+//! `add<int>` and `add<float>`. Enums are erased at runtime so they only need their
+//! types substituting. The type checker lets a generic body use any operator on its type
+//! params, so codegen reports an operator that a specialised copy uses on a type it isn't
+//! defined for.
+use crate::ast as t;
+use crate::infer::types::{Type, TypeCon, TypeVar};
+use crate::infer::Infer;
+use std::collections::{HashMap, HashSet};
+use util::pos::Spanned;
+use util::symbol::{Symbol, Symbols};
+
+/// Replaces every generic function and class within the program with a specialised copy
+/// for every set of types it is used with
+pub fn monomorphise(program: t::Program, symbols: &mut Symbols<()>) -> t::Program {
+    let mut pass = Monomorphiser {
+        infer: Infer::new(),
+        symbols,
+        generic_functions: HashMap::new(),
+        generic_classes: HashMap::new(),
+        instantiated: HashSet::new(),
+        queue: Vec::new(),
+    };
+
+    let mut new_program = t::Program {
+        functions: Vec::new(),
+        classes: Vec::new(),
+        globals: program.globals,
+    };
+
+    for class in program.classes {
+        if pass.class_type_vars(&class).is_empty() {
+            new_program.classes.push(class);
+        } else {
+            pass.generic_classes.insert(class.name, class);
+        }
+    }
+
+    for function in program.functions {
+        if pass.function_type_vars(&function).is_empty() {
+            new_program.functions.push(function);
+        } else {
+            pass.generic_functions.insert(function.name, function);
+        }
+    }
+
+    let mut mappings = HashMap::new();
+
+    for class in new_program.classes.iter_mut() {
+        for method in class.methods.iter_mut() {
+            pass.function(method, &mut mappings);
+        }
+    }
+
+    for function in new_program.functions.iter_mut() {
+        pass.function(function, &mut mappings);
+    }
+
+    for global in new_program.globals.iter_mut() {
+        if let Some(ref mut value) = global.value {
+            pass.expression(value, &mut mappings);
+        }
+    }
+
+    while let Some(instance) = pass.queue.pop() {
+        match instance {
+            Instance::Function {
+                generic,
+                name,
+                mut mappings,
+            } => {
+                let mut function = pass.generic_functions[&generic].clone();
+
+                function.name = name;
+
+                pass.function(&mut function, &mut mappings);
+
+                new_program.functions.push(function);
+            }
+
+            Instance::Class {
+                generic,
+                name,
+                mut mappings,
+            } => {
+                let mut class = pass.generic_classes[&generic].clone();
+
+                class.name = name;
+
+                for property in class.properties.iter_mut() {
+                    property.ty = pass.infer.subst(&property.ty, &mut mappings);
+                }
+
+                for method in class.methods.iter_mut() {
+                    pass.function(method, &mut mappings);
+                }
+
+                new_program.classes.push(class);
+            }
+        }
+    }
+
+    new_program
+}
+
+/// A specialised copy of a generic item that still needs to be created
+enum Instance {
+    Function {
+        generic: Symbol,
+        name: Symbol,
+        mappings: HashMap<TypeVar, Type>,
+    },
+    Class {
+        generic: Symbol,
+        name: Symbol,
+        mappings: HashMap<TypeVar, Type>,
+    },
+}
+
+struct Monomorphiser<'a> {
+    infer: Infer,
+    symbols: &'a mut Symbols<()>,
+    /// The generic functions as they were type checked
+    generic_functions: HashMap<Symbol, t::Function>,
+    /// The generic classes as they were type checked
+    generic_classes: HashMap<Symbol, t::Class>,
+    /// The names of every specialised item that has been queued
+    instantiated: HashSet<Symbol>,
+    queue: Vec<Instance>,
+}
+
+impl<'a> Monomorphiser<'a> {
+    /// The type variables found in the signature of a function in the order they appear
+    fn function_type_vars(&self, function: &t::Function) -> Vec<TypeVar> {
+        let mut vars = Vec::new();
+
+        for param in function.params.iter() {
+            type_vars(&param.ty, &mut vars);
+        }
+
+        type_vars(&function.returns, &mut vars);
+
+        vars
+    }
+
+    /// The type variables found in the properties and method signatures of a class in
+    /// the order they appear
+    fn class_type_vars(&self, class: &t::Class) -> Vec<TypeVar> {
+        let mut vars = Vec::new();
+
+        for property in class.properties.iter() {
+            type_vars(&property.ty, &mut vars);
+        }
+
+        for method in class.methods.iter() {
+            for var in self.function_type_vars(method) {
+                if !vars.contains(&var) {
+                    vars.push(var);
+                }
+            }
+        }
+
+        vars
+    }
+
+    /// Creates the name of a specialised item i.e `add<int>`
+    fn specialised_name(
+        &mut self,
+        name: Symbol,
+        vars: &[TypeVar],
+        mappings: &HashMap<TypeVar, Type>,
+    ) -> Symbol {
+        let types: Vec<String> = vars
+            .iter()
+            .map(|var| match mappings.get(var) {
+                Some(ty) => self.mangle(ty),
+                None => self.mangle(&Type::Var(*var)),
+            })
+            .collect();
+
+        let name = format!("{}<{}>", self.symbols.name(name), types.join(","));
+
+        self.symbols.symbol(&name)
+    }
+
+    /// Prints a type so that two different types never share the same name
+    fn mangle(&self, ty: &Type) -> String {
+        match *ty {
+            Type::App(TypeCon::Array(ref inner), _) => format!("[{}]", self.mangle(inner)),
+            Type::App(TypeCon::Arrow, ref types) => {
+                let (returns, params) = types.split_last().unwrap();
+
+                let params: Vec<String> = params.iter().map(|ty| self.mangle(ty)).collect();
+
+                format!("fn({})->{}", params.join(","), self.mangle(returns))
+            }
+            Type::App(ref tycon, _) => tycon.to_string(),
+            Type::Class(name, ref properties, _, _) => {
+                let properties: Vec<String> = properties
+                    .iter()
+                    .map(|property| self.mangle(&property.ty))
+                    .collect();
+
+                format!("{}<{}>", self.symbols.name(name), properties.join(","))
+            }
+            Type::Enum { name, ref variants } => {
+                let mut variants: Vec<_> = variants.values().collect();
+
+                variants.sort_by_key(|variant| variant.tag);
+
+                let inner: Vec<String> = variants
+                    .iter()
+                    .filter_map(|variant| variant.inner.as_ref().map(|ty| self.mangle(ty)))
+                    .collect();
+
+                format!("{}<{}>", self.symbols.name(name), inner.join(","))
+            }
+            Type::Generic(_, ref ty) => self.mangle(ty),
+            Type::Nil => "nil".into(),
+            Type::Var(ref var) => format!("{{T:{}}}", var),
+        }
+    }
+
+    /// Queues the specialisation of a generic function and returns its name
+    fn instantiate_function(
+        &mut self,
+        generic: Symbol,
+        mappings: HashMap<TypeVar, Type>,
+    ) -> Symbol {
+        let vars = self.function_type_vars(&self.generic_functions[&generic]);
+        let name = self.specialised_name(generic, &vars, &mappings);
+
+        if self.instantiated.insert(name) {
+            self.queue.push(Instance::Function {
+                generic,
+                name,
+                mappings,
+            });
+        }
+
+        name
+    }
+
+    /// Queues the specialisation of a generic class and returns its name
+    fn instantiate_class(&mut self, generic: Symbol, mappings: HashMap<TypeVar, Type>) -> Symbol {
+        let vars = self.class_type_vars(&self.generic_classes[&generic]);
+        let name = self.specialised_name(generic, &vars, &mappings);
+
+        if self.instantiated.insert(name) {
+            self.queue.push(Instance::Class {
+                generic,
+                name,
+                mappings,
+            });
+        }
+
+        name
+    }
+
+    fn function(&mut self, function: &mut t::Function, mappings: &mut HashMap<TypeVar, Type>) {
+        for param in function.params.iter_mut() {
+            param.ty = self.infer.subst(&param.ty, mappings);
+        }
+
+        function.returns = self.infer.subst(&function.returns, mappings);
+
+        self.statement(&mut function.body, mappings);
+    }
+
+    fn statement(
+        &mut self,
+        statement: &mut Spanned<t::TypedStatement>,
+        mappings: &mut HashMap<TypeVar, Type>,
+    ) {
+        statement.value.ty = self.infer.subst(&statement.value.ty, mappings);
+
+        match statement.value.statement.value {
+            t::Statement::Break | t::Statement::Continue => (),
+            t::Statement::Block(ref mut statements) => {
+                for statement in statements.iter_mut() {
+                    self.statement(statement, mappings);
+                }
+            }
+            t::Statement::Expr(ref mut expr)
+            | t::Statement::Print(ref mut expr)
+            | t::Statement::Return(ref mut expr) => self.expression(expr, mappings),
+            t::Statement::If {
+                ref mut cond,
+                ref mut then,
+                ref mut otherwise,
+            } => {
+                self.expression(cond, mappings);
+                self.statement(then, mappings);
+
+                if let Some(ref mut otherwise) = *otherwise {
+                    self.statement(otherwise, mappings);
+                }
+            }
+            t::Statement::While(ref mut cond, ref mut body) => {
+                self.expression(cond, mappings);
+                self.statement(body, mappings);
+            }
+            t::Statement::Let {
+                ref mut ty,
+                ref mut expr,
+                ..
+            } => {
+                *ty = self.infer.subst(ty, mappings);
+
+                if let Some(ref mut expr) = *expr {
+                    self.expression(expr, mappings);
+                }
+            }
+        }
+    }
+
+    fn pattern(
+        &mut self,
+        pattern: &mut Spanned<t::TypedPattern>,
+        mappings: &mut HashMap<TypeVar, Type>,
+    ) {
+        pattern.value.ty = self.infer.subst(&pattern.value.ty, mappings);
+
+        if let t::Pattern::Variant {
+            inner: Some(ref mut inner),
+            ..
+        } = pattern.value.pattern
+        {
+            self.pattern(inner, mappings);
+        }
+    }
+
+    fn expression(
+        &mut self,
+        expr: &mut Spanned<t::TypedExpression>,
+        mappings: &mut HashMap<TypeVar, Type>,
+    ) {
+        expr.value.ty = self.infer.subst(&expr.value.ty, mappings);
+
+        let ty = &expr.value.ty;
+
+        match expr.value.expr.value {
+            t::Expression::Literal(_) | t::Expression::VariantNoData { .. } => (),
+
+            t::Expression::Var(_, ref mut var_ty) => *var_ty = self.infer.subst(var_ty, mappings),
+
+            t::Expression::Cast(ref mut expr, ref mut cast_ty) => {
+                self.expression(expr, mappings);
+                *cast_ty = self.infer.subst(cast_ty, mappings);
+            }
+
+            t::Expression::Array(ref mut items)
+            | t::Expression::Builtin(_, ref mut items)
+            | t::Expression::Interpolation(ref mut items) => {
+                for item in items.iter_mut() {
+                    self.expression(item, mappings);
+                }
+            }
+
+            t::Expression::Slice(ref mut target, ref mut start, ref mut end) => {
+                self.expression(target, mappings);
+
+                for bound in start.iter_mut().chain(end) {
+                    self.expression(bound, mappings);
+                }
+            }
+
+            t::Expression::Assign(_, _, ref mut expr)
+            | t::Expression::Grouping(ref mut expr)
+            | t::Expression::Unary(_, ref mut expr)
+            | t::Expression::VariantWithData {
+                inner: ref mut expr,
+                ..
+            }
+            | t::Expression::GetProperty {
+                property: ref mut expr,
+                ..
+            }
+            | t::Expression::GetMethod {
+                method: ref mut expr,
+                ..
+            } => self.expression(expr, mappings),
+
+            t::Expression::Binary(ref mut lhs, _, ref mut rhs)
+            | t::Expression::Index(ref mut lhs, ref mut rhs)
+            | t::Expression::Set(_, ref mut lhs, ref mut rhs) => {
+                self.expression(lhs, mappings);
+                self.expression(rhs, mappings);
+            }
+
+            t::Expression::Ternary(ref mut cond, ref mut lhs, ref mut rhs)
+            | t::Expression::SetIndex(ref mut cond, ref mut lhs, _, ref mut rhs) => {
+                self.expression(cond, mappings);
+                self.expression(lhs, mappings);
+                self.expression(rhs, mappings);
+            }
+
+            t::Expression::Closure(ref mut function) => self.function(function, mappings),
+
+            t::Expression::Match {
+                ref mut cond,
+                ref mut arms,
+            } => {
+                self.expression(cond, mappings);
+
+                for arm in arms.value.iter_mut() {
+                    self.pattern(&mut arm.value.pattern, mappings);
+                    self.statement(&mut arm.value.body, mappings);
+                }
+            }
+
+            t::Expression::Call(ref mut symbol, ref mut args) => {
+                for arg in args.iter_mut() {
+                    self.expression(arg, mappings);
+                }
+
+                if let Some(generic) = self.generic_functions.get(symbol) {
+                    let mut instance = HashMap::new();
+
+                    for (param, arg) in generic.params.iter().zip(args.iter()) {
+                        self.infer.bind(&param.ty, &arg.value.ty, &mut instance);
+                    }
+
+                    self.infer.bind(&generic.returns, ty, &mut instance);
+
+                    *symbol = self.instantiate_function(*symbol, instance);
+                }
+            }
+
+            t::Expression::ClassLiteral {
+                ref mut symbol,
+                ref mut properties,
+            } => {
+                for property in properties.iter_mut() {
+                    self.expression(&mut property.value.expr, mappings);
+                }
+
+                if let Some(generic) = self.generic_classes.get(symbol) {
+                    let mut instance = HashMap::new();
+
+                    for (def_property, property) in generic.properties.iter().zip(properties.iter())
+                    {
+                        self.infer.bind(
+                            &def_property.ty,
+                            &property.value.expr.value.ty,
+                            &mut instance,
+                        );
+                    }
+
+                    *symbol = self.instantiate_class(*symbol, instance);
+                }
+            }
+
+            t::Expression::InstanceMethodCall {
+                ref mut instance,
+                ref mut params,
+                ..
+            } => {
+                self.expression(instance, mappings);
+
+                for param in params.iter_mut() {
+                    self.expression(param, mappings);
+                }
+            }
+
+            t::Expression::StaticMethodCall {
+                ref mut class_name,
+                method_name,
+                ref mut params,
+            } => {
+                for param in params.iter_mut() {
+                    self.expression(param, mappings);
+                }
+
+                if let Some(generic) = self.generic_classes.get(class_name) {
+                    let mut instance = HashMap::new();
+
+                    if let Some(method) = generic.methods.iter().find(|m| m.name == method_name) {
+                        for (param, arg) in method.params.iter().zip(params.iter()) {
+                            self.infer.bind(&param.ty, &arg.value.ty, &mut instance);
+                        }
+
+                        self.infer.bind(&method.returns, ty, &mut instance);
+                    }
+
+                    *class_name = self.instantiate_class(*class_name, instance);
+                }
+            }
+        }
+    }
+}
+
+/// Collects the type variables found in `ty` in the order they appear
+fn type_vars(ty: &Type, vars: &mut Vec<TypeVar>) {
+    match *ty {
+        Type::Var(var) => {
+            if !vars.contains(&var) {
+                vars.push(var)
+            }
+        }
+        Type::App(ref tycon, ref types) => {
+            if let TypeCon::Array(ref inner) = *tycon {
+                type_vars(inner, vars);
+            }
+
+            for ty in types {
+                type_vars(ty, vars);
+            }
+        }
+        Type::Class(_, ref properties, _, _) => {
+            for property in properties {
+                type_vars(&property.ty, vars);
+            }
+        }
+        Type::Enum { ref variants, .. } => {
+            let mut variants: Vec<_> = variants.values().collect();
+
+            variants.sort_by_key(|variant| variant.tag);
+
+            for variant in variants {
+                if let Some(ref inner) = variant.inner {
+                    type_vars(inner, vars);
+                }
+            }
+        }
+        Type::Generic(_, ref ty) => type_vars(ty, vars),
+        Type::Nil => (),
+    }
+}
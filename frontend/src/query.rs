@@ -0,0 +1,251 @@
+//! Queries about a type checked program used by editor tooling, such as the type of the
+//! expression under the cursor.
+use crate::ast as t;
+use crate::infer::types::{Type, TypeCon};
+use util::pos::{Position, Span, Spanned};
+use util::symbol::{Symbol, Symbols};
+use vm::NativeType;
+
+/// Returns the span and the type of the innermost expression that contains the position
+pub fn type_at(
+    program: &t::Program,
+    position: Position,
+    symbols: &Symbols<()>,
+) -> Option<(Span, String)> {
+    expressions(program)
+        .into_iter()
+        .filter(|expr| contains(expr.span, position))
+        .min_by_key(|expr| expr.span.end.absolute - expr.span.start.absolute)
+        .map(|expr| (expr.span, expr.value.ty.print(symbols)))
+}
+
+/// Returns the names of the properties and methods of the class instance whose expression
+/// ends just before the position i.e the `p` in `p.`
+pub fn members_at(program: &t::Program, position: Position, symbols: &Symbols<()>) -> Vec<String> {
+    // the span of an identifier ends on the char after it while the span of a `)` ends on it
+    let receiver = expressions(program)
+        .into_iter()
+        .filter(|expr| {
+            expr.span.end.line == position.line
+                && (expr.span.end.column == position.column
+                    || expr.span.end.column + 1 == position.column)
+        })
+        .max_by_key(|expr| expr.span.end.absolute - expr.span.start.absolute);
+
+    receiver.map_or(vec![], |expr| members(&expr.value.ty, symbols))
+}
+
+/// The types of the params and the return type of a function. A type that can't be passed
+/// between rust and tox, such as a class, is `None`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Option<NativeType>>,
+    pub returns: Option<NativeType>,
+}
+
+/// Returns the signature of a function or `None` if the program has no function with the
+/// name
+pub fn signature(program: &t::Program, name: Symbol) -> Option<Signature> {
+    let function = program
+        .functions
+        .iter()
+        .find(|function| function.name == name)?;
+
+    let params = function
+        .params
+        .iter()
+        .map(|param| native_type(&param.ty))
+        .collect();
+
+    Some(Signature {
+        params,
+        returns: native_type(&function.returns),
+    })
+}
+
+fn native_type(ty: &Type) -> Option<NativeType> {
+    match *ty {
+        Type::App(TypeCon::Int, _) => Some(NativeType::Int),
+        Type::App(TypeCon::Float, _) => Some(NativeType::Float),
+        Type::App(TypeCon::Bool, _) => Some(NativeType::Bool),
+        Type::App(TypeCon::Str, _) => Some(NativeType::Str),
+        Type::App(TypeCon::Void, _) | Type::Nil => Some(NativeType::Nil),
+        Type::App(TypeCon::Array(ref inner), _) => {
+            native_type(inner).map(|inner| NativeType::Array(Box::new(inner)))
+        }
+        Type::Generic(ref vars, ref ty) if vars.is_empty() => native_type(ty),
+        _ => None,
+    }
+}
+
+fn members(ty: &Type, symbols: &Symbols<()>) -> Vec<String> {
+    match *ty {
+        Type::Class(_, ref properties, ref methods, _) => properties
+            .iter()
+            .map(|property| symbols.name(property.name))
+            .chain(methods.iter().map(|method| symbols.name(method.name)))
+            .collect(),
+        Type::Generic(_, ref ty) => members(ty, symbols),
+        _ => vec![],
+    }
+}
+
+fn contains(span: Span, position: Position) -> bool {
+    if span.start.file != position.file {
+        return false;
+    }
+
+    let after_start = (span.start.line, span.start.column) <= (position.line, position.column);
+    let before_end = (position.line, position.column) <= (span.end.line, span.end.column);
+
+    after_start && before_end
+}
+
+/// Every expression within the program
+fn expressions(program: &t::Program) -> Vec<&Spanned<t::TypedExpression>> {
+    let mut exprs = Vec::new();
+
+    for function in program.functions.iter() {
+        statement(&function.body, &mut exprs);
+    }
+
+    for class in program.classes.iter() {
+        for method in class.methods.iter() {
+            statement(&method.body, &mut exprs);
+        }
+    }
+
+    for global in program.globals.iter() {
+        if let Some(ref value) = global.value {
+            expression(value, &mut exprs);
+        }
+    }
+
+    exprs
+}
+
+fn statement<'a>(
+    statement: &'a Spanned<t::TypedStatement>,
+    exprs: &mut Vec<&'a Spanned<t::TypedExpression>>,
+) {
+    match statement.value.statement.value {
+        t::Statement::Break | t::Statement::Continue => (),
+        t::Statement::Block(ref statements) => {
+            for s in statements.iter() {
+                self::statement(s, exprs);
+            }
+        }
+        t::Statement::Expr(ref expr)
+        | t::Statement::Print(ref expr)
+        | t::Statement::Return(ref expr) => expression(expr, exprs),
+        t::Statement::If {
+            ref cond,
+            ref then,
+            ref otherwise,
+        } => {
+            expression(cond, exprs);
+            self::statement(then, exprs);
+
+            if let Some(ref otherwise) = *otherwise {
+                self::statement(otherwise, exprs);
+            }
+        }
+        t::Statement::While(ref cond, ref body) => {
+            expression(cond, exprs);
+            self::statement(body, exprs);
+        }
+        t::Statement::Let { ref expr, .. } => {
+            if let Some(ref expr) = *expr {
+                expression(expr, exprs);
+            }
+        }
+    }
+}
+
+fn expression<'a>(
+    expr: &'a Spanned<t::TypedExpression>,
+    exprs: &mut Vec<&'a Spanned<t::TypedExpression>>,
+) {
+    exprs.push(expr);
+
+    match expr.value.expr.value {
+        t::Expression::Literal(_)
+        | t::Expression::Var(_, _)
+        | t::Expression::VariantNoData { .. } => (),
+
+        t::Expression::Array(ref items)
+        | t::Expression::Builtin(_, ref items)
+        | t::Expression::Interpolation(ref items)
+        | t::Expression::Call(_, ref items)
+        | t::Expression::StaticMethodCall {
+            params: ref items, ..
+        } => {
+            for item in items.iter() {
+                expression(item, exprs);
+            }
+        }
+
+        t::Expression::Slice(ref target, ref start, ref end) => {
+            expression(target, exprs);
+
+            for bound in start.iter().chain(end) {
+                expression(bound, exprs);
+            }
+        }
+
+        t::Expression::Assign(_, _, ref inner)
+        | t::Expression::Cast(ref inner, _)
+        | t::Expression::Grouping(ref inner)
+        | t::Expression::Unary(_, ref inner)
+        | t::Expression::GetProperty {
+            property: ref inner,
+            ..
+        }
+        | t::Expression::GetMethod {
+            method: ref inner, ..
+        }
+        | t::Expression::VariantWithData { ref inner, .. } => expression(inner, exprs),
+
+        t::Expression::Binary(ref lhs, _, ref rhs)
+        | t::Expression::Index(ref lhs, ref rhs)
+        | t::Expression::Set(_, ref lhs, ref rhs) => {
+            expression(lhs, exprs);
+            expression(rhs, exprs);
+        }
+
+        t::Expression::Ternary(ref cond, ref then, ref otherwise)
+        | t::Expression::SetIndex(ref cond, ref then, _, ref otherwise) => {
+            expression(cond, exprs);
+            expression(then, exprs);
+            expression(otherwise, exprs);
+        }
+
+        t::Expression::Closure(ref function) => statement(&function.body, exprs),
+
+        t::Expression::ClassLiteral { ref properties, .. } => {
+            for property in properties.iter() {
+                expression(&property.value.expr, exprs);
+            }
+        }
+
+        t::Expression::InstanceMethodCall {
+            ref instance,
+            ref params,
+            ..
+        } => {
+            expression(instance, exprs);
+
+            for param in params.iter() {
+                expression(param, exprs);
+            }
+        }
+
+        t::Expression::Match { ref cond, ref arms } => {
+            expression(cond, exprs);
+
+            for arm in arms.value.iter() {
+                statement(&arm.value.body, exprs);
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! Type checking for a repl.
+//!
+//! A repl type checks each input on its own but an input can use anything declared by
+//! the inputs before it. A `Session` keeps the type environment and the typed functions
+//! and classes between inputs. A `let` at the top of an input declares a global, so its
+//! value is kept by the vm once the input has run.
+use crate::ast as t;
+use crate::ctx::CompileCtx;
+use crate::infer::types::Type;
+use crate::infer::{Infer, InferResult};
+use std::rc::Rc;
+use syntax::ast::{Expression, Program, Statement};
+use util::emmiter::Reporter;
+use util::pos::Spanned;
+use util::symbol::{Symbol, SymbolFactory};
+use vm::NativeRegistry;
+
+#[derive(Debug)]
+pub struct Session {
+    infer: Infer,
+    ctx: CompileCtx,
+    /// The functions declared by earlier inputs
+    functions: Vec<t::Function>,
+    /// The classes declared by earlier inputs
+    classes: Vec<t::Class>,
+    /// The globals declared by earlier inputs
+    globals: Vec<t::Global>,
+}
+
+impl Session {
+    pub fn new(strings: &Rc<SymbolFactory>, reporter: &Reporter, natives: &NativeRegistry) -> Self {
+        Session {
+            infer: Infer::new(),
+            ctx: CompileCtx::new(strings, reporter, natives),
+            functions: Vec::new(),
+            classes: Vec::new(),
+            globals: Vec::new(),
+        }
+    }
+
+    /// Type checks the aliases, enums, classes and functions of the program and keeps
+    /// them for the inputs that follow. A function or class with the same name as an
+    /// earlier one replaces it
+    pub fn declare(&mut self, program: Program) -> InferResult<()> {
+        if let Some(statement) = program.statements.first() {
+            self.ctx.error(
+                "A statement can't be entered along with declarations",
+                statement.span,
+            );
+            return Err(());
+        }
+
+        let mut new_program = t::Program {
+            functions: Vec::new(),
+            classes: Vec::new(),
+            globals: Vec::new(),
+        };
+
+        self.checkpoint(|session| {
+            session
+                .infer
+                .infer_declarations(program, &mut new_program, &mut session.ctx)
+        })?;
+
+        for function in new_program.functions {
+            self.functions.retain(|old| old.name != function.name);
+            self.functions.push(function);
+        }
+
+        for class in new_program.classes {
+            self.classes.retain(|old| old.name != class.name);
+            self.classes.push(class);
+        }
+
+        Ok(())
+    }
+
+    /// Type checks a statement and returns a program whose `main` runs it along with
+    /// every function and class that has been declared
+    pub fn statement(
+        &mut self,
+        statement: Spanned<Statement>,
+        main: Symbol,
+    ) -> InferResult<t::Program> {
+        let span = statement.span;
+
+        let (body, global) = self.checkpoint(|session| {
+            if let Statement::VarDeclaration { expr: None, .. } = statement.value {
+                session.ctx.error("A global must be given a value", span);
+                Err(())
+            } else if let Statement::VarDeclaration { .. } = statement.value {
+                // declared in the outermost scope so it is kept for the next input
+                let typed = session.infer.infer_statement(statement, &mut session.ctx)?;
+
+                match typed.value.statement.value {
+                    t::Statement::Let { ident, ty, expr } => Ok((
+                        None,
+                        Some(t::Global {
+                            name: ident,
+                            ty,
+                            value: expr,
+                        }),
+                    )),
+                    _ => unreachable!(),
+                }
+            } else {
+                session.ctx.begin_scope();
+                let typed = session.infer.infer_statement(statement, &mut session.ctx);
+                session.ctx.end_scope();
+
+                Ok((Some(typed?), None))
+            }
+        })?;
+
+        let body = body.unwrap_or_else(|| {
+            Spanned::new(
+                t::TypedStatement {
+                    statement: Box::new(Spanned::new(t::Statement::Block(vec![]), span)),
+                    ty: Type::Nil,
+                },
+                span,
+            )
+        });
+
+        let mut functions = self.functions.clone();
+
+        functions.push(t::Function {
+            name: main,
+            params: vec![],
+            body: Box::new(body),
+            returns: Type::Nil,
+        });
+
+        let mut globals = self.globals.clone();
+
+        if let Some(global) = global {
+            self.globals.retain(|old| old.name != global.name);
+            self.globals.push(t::Global {
+                value: None,
+                ..global.clone()
+            });
+
+            globals.retain(|old| old.name != global.name);
+            globals.push(global);
+        }
+
+        Ok(t::Program {
+            functions,
+            classes: self.classes.clone(),
+            globals,
+        })
+    }
+
+    /// Type checks an expression and returns its type
+    pub fn type_of(&mut self, expr: Spanned<Expression>) -> InferResult<String> {
+        let ty = self.checkpoint(|session| {
+            session.ctx.begin_scope();
+            let typed = session.infer.infer_expr(expr, &mut session.ctx);
+            session.ctx.end_scope();
+
+            typed.map(|typed| typed.value.ty)
+        })?;
+
+        Ok(ty.print(self.ctx.symbols()))
+    }
+
+    /// The names of the functions, classes and globals that have been declared
+    pub fn names(&self) -> Vec<Symbol> {
+        self.functions
+            .iter()
+            .map(|function| function.name)
+            .chain(self.classes.iter().map(|class| class.name))
+            .chain(self.globals.iter().map(|global| global.name))
+            .collect()
+    }
+
+    /// Runs `f`, restoring the type environment if it fails so a bad input leaves no
+    /// half declared items behind
+    fn checkpoint<T, F>(&mut self, f: F) -> InferResult<T>
+    where
+        F: FnOnce(&mut Session) -> InferResult<T>,
+    {
+        let ctx = self.ctx.clone();
+        let result = f(self);
+
+        if result.is_err() {
+            self.ctx = ctx;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Session;
+    use crate::{compile, fold, monomorphise};
+    use pretty_assertions::assert_eq;
+    use std::rc::Rc;
+    use syntax::parser::Parser;
+    use util::emmiter::Reporter;
+    use util::symbol::{SymbolFactory, Symbols};
+    use vm::{NativeRegistry, State, VM};
+
+    /// Feeds inputs to a session the way the repl does and collects what they print
+    struct Repl {
+        symbols: Symbols<()>,
+        reporter: Reporter,
+        natives: NativeRegistry,
+        session: Session,
+        state: Option<State>,
+    }
+
+    impl Repl {
+        fn new() -> Self {
+            let strings = Rc::new(SymbolFactory::new());
+            let reporter = Reporter::new();
+            let natives = NativeRegistry::new();
+
+            Repl {
+                symbols: Symbols::new(Rc::clone(&strings)),
+                session: Session::new(&strings, &reporter, &natives),
+                reporter,
+                natives,
+                state: None,
+            }
+        }
+
+        fn declare(&mut self, input: &str) -> Result<(), ()> {
+            self.reporter.clear();
+
+            let program = Parser::new(input, self.reporter.clone(), &mut self.symbols).parse()?;
+
+            self.session.declare(program)
+        }
+
+        /// Runs the statements of the input and returns what they printed
+        fn run(&mut self, input: &str) -> Result<String, ()> {
+            self.reporter.clear();
+
+            let statements =
+                Parser::new(input, self.reporter.clone(), &mut self.symbols).parse_statements()?;
+
+            let main = self.symbols.symbol("main");
+            let mut output = Vec::new();
+
+            for statement in statements {
+                let typed_ast = self.session.statement(statement, main)?;
+                let typed_ast = fold(monomorphise(typed_ast, &mut self.symbols));
+
+                let (program, objects) = compile(&typed_ast, &self.symbols, &mut self.reporter)?;
+
+                let state = match self.state.take() {
+                    Some(state) => state,
+                    None => State::new(&self.natives, &mut self.symbols),
+                };
+
+                let mut vm = VM::with_state(main, &program, objects, state).unwrap();
+                vm.set_output(&mut output);
+
+                let result = vm.run();
+                self.state = Some(vm.into_state());
+
+                result.map_err(|_| ())?;
+            }
+
+            Ok(String::from_utf8(output).unwrap())
+        }
+
+        fn type_of(&mut self, input: &str) -> Result<String, ()> {
+            self.reporter.clear();
+
+            let expr =
+                Parser::new(input, self.reporter.clone(), &mut self.symbols).parse_expression()?;
+
+            self.session.type_of(expr)
+        }
+    }
+
+    #[test]
+    fn persists() {
+        let mut repl = Repl::new();
+
+        repl.declare("fn double(n: int) -> int { return n * 2; }")
+            .unwrap();
+        repl.declare("class Point { x: int, y: int; }").unwrap();
+
+        assert_eq!(repl.run("let total = double(4);"), Ok("".into()));
+        assert_eq!(repl.run("total = total + 1;"), Ok("".into()));
+        assert_eq!(repl.run("print total;"), Ok("9\n".into()));
+
+        assert_eq!(
+            repl.run("let p = Point { x: 1, y: total }; print p.y;"),
+            Ok("9\n".into())
+        );
+        assert_eq!(repl.run("print p.x + double(p.y);"), Ok("19\n".into()));
+    }
+
+    #[test]
+    fn redeclare() {
+        let mut repl = Repl::new();
+
+        repl.declare("fn answer() -> int { return 1; }").unwrap();
+        assert_eq!(repl.run("print answer();"), Ok("1\n".into()));
+
+        repl.declare("fn answer() -> str { return \"one\"; }")
+            .unwrap();
+        assert_eq!(repl.run("print answer();"), Ok("one\n".into()));
+
+        assert_eq!(repl.run("let a = 1;"), Ok("".into()));
+        assert_eq!(repl.run("let a = true; print a;"), Ok("true\n".into()));
+        assert_eq!(repl.type_of("a"), Ok("bool".into()));
+    }
+
+    #[test]
+    fn errors() {
+        let mut repl = Repl::new();
+
+        assert_eq!(repl.declare("fn broken( {"), Err(()));
+        assert!(!repl.reporter.diagnostics().is_empty());
+
+        assert_eq!(repl.run("let x = ;"), Err(()));
+        assert_eq!(repl.declare("fn bad() -> int { return true; }"), Err(()));
+        assert_eq!(repl.run("let y: int = \"y\";"), Err(()));
+
+        // nothing from the failed inputs is left behind
+        assert_eq!(repl.type_of("bad"), Err(()));
+        assert_eq!(repl.type_of("y"), Err(()));
+
+        repl.declare("fn good() -> int { return 2; }").unwrap();
+        assert_eq!(repl.run("let y = good(); print y;"), Ok("2\n".into()));
+        assert!(repl.reporter.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn type_of() {
+        let mut repl = Repl::new();
+
+        repl.declare("fn add(a: int, b: int) -> int { return a + b; }")
+            .unwrap();
+        repl.run("let name = \"tox\";").unwrap();
+
+        assert_eq!(repl.type_of("add(1, 2)"), Ok("int".into()));
+        assert_eq!(repl.type_of("name"), Ok("str".into()));
+        assert_eq!(repl.type_of("1.5 * 2.0"), Ok("float".into()));
+        assert_eq!(repl.type_of("[1, 2]"), Ok("[int]".into()));
+        assert_eq!(repl.type_of("add(name, 1)"), Err(()));
+    }
+}
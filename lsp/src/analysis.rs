@@ -0,0 +1,424 @@
+//! Runs the parser and the type checker over a document and answers the queries of the
+//! client about it.
+//!
+//! Positions sent by the client are zero based while a `Span` is one based. The span of a
+//! single char token ends on that char but the span of any longer token ends on the char
+//! after it. Columns are counted in chars rather than utf-16 code units, the server
+//! converts them for a client that can't use utf-32 positions.
+use frontend::{query, Infer, TypedProgram};
+use serde_json::Value;
+use std::path::Path;
+use std::rc::Rc;
+use syntax::ast::{Function, Program, Statement};
+use syntax::loader::Loader;
+use syntax::parser::Parser;
+use util::emmiter::{Diagnostic, Level, Reporter};
+use util::pos::{FileId, Position, Span};
+use util::source::SourceMap;
+use util::symbol::{SymbolFactory, Symbols};
+use vm::NativeRegistry;
+
+const KEYWORDS: &[&str] = &[
+    "class", "extends", "print", "type", "as", "match", "enum", "fn", "let", "if", "else", "for",
+    "while", "return", "break", "continue", "do", "true", "false", "or", "and", "nil", "import",
+];
+
+/// The kinds of completion item
+const FUNCTION: u32 = 3;
+const VARIABLE: u32 = 6;
+const CLASS: u32 = 7;
+const ENUM: u32 = 13;
+const KEYWORD: u32 = 14;
+const FIELD: u32 = 5;
+
+#[derive(Debug)]
+pub struct Analysis {
+    symbols: Symbols<()>,
+    natives: NativeRegistry,
+    /// The document and the files it imports
+    files: SourceMap,
+    diagnostics: Vec<Diagnostic>,
+    /// `None` if the document failed to parse
+    program: Option<Program>,
+    /// `None` if the document failed to parse or type check
+    typed: Option<TypedProgram>,
+}
+
+impl Analysis {
+    /// Checks the text of a document. The imports of a document are only loaded if it has
+    /// a path to resolve them against
+    pub fn new(text: &str, path: Option<&Path>) -> Self {
+        let natives = NativeRegistry::new();
+        let mut reporter = Reporter::new();
+        let strings = Rc::new(SymbolFactory::new());
+        let mut symbols = Symbols::new(Rc::clone(&strings));
+        let mut files = SourceMap::new();
+
+        let program = match path {
+            Some(path) => Loader::new(&mut files, reporter.clone(), &mut symbols)
+                .load_source(path, text.into())
+                .ok(),
+            None => Parser::new(text, reporter.clone(), &mut symbols)
+                .parse()
+                .ok(),
+        };
+
+        let typed = program.clone().and_then(|program| {
+            Infer::new()
+                .infer(program, &strings, &natives, &mut reporter)
+                .ok()
+        });
+
+        Analysis {
+            symbols,
+            natives,
+            files,
+            diagnostics: reporter.diagnostics(),
+            program,
+            typed,
+        }
+    }
+
+    /// Whether the document type checked
+    pub fn is_checked(&self) -> bool {
+        self.typed.is_some()
+    }
+
+    /// The parameters of a `textDocument/publishDiagnostics` notification. The diagnostics
+    /// of the files the document imports are left for those files
+    pub fn diagnostics(&self, uri: &str) -> Value {
+        let diagnostics: Vec<Value> = self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.span().start.file == FileId::default())
+            .map(|diagnostic| {
+                let severity = match diagnostic.level() {
+                    Level::Error | Level::RunTimeError => 1,
+                    Level::Warn => 2,
+                };
+
+                json!({
+                    "range": range(diagnostic.span()),
+                    "severity": severity,
+                    "source": "tox",
+                    "message": diagnostic.message(),
+                })
+            })
+            .collect();
+
+        json!({ "uri": uri, "diagnostics": diagnostics })
+    }
+
+    /// The type of the innermost expression under the cursor
+    pub fn hover(&self, position: &Value) -> Value {
+        let typed = match self.typed {
+            Some(ref typed) => typed,
+            None => return Value::Null,
+        };
+
+        match query::type_at(typed, span_position(position), &self.symbols) {
+            Some((span, ty)) => json!({
+                "contents": { "kind": "plaintext", "value": ty },
+                "range": range(span),
+            }),
+            None => Value::Null,
+        }
+    }
+
+    /// The location of the function, class or enum named by the identifier under the cursor.
+    /// An item of an imported file is located within that file
+    pub fn definition(&self, uri: &str, text: &str, position: &Value) -> Value {
+        let (word, _) = match word_at(text, position, true) {
+            Some(word) => word,
+            None => return Value::Null,
+        };
+
+        let program = match self.program {
+            Some(ref program) => program,
+            None => return Value::Null,
+        };
+
+        let items = program
+            .functions
+            .iter()
+            .map(|function| &function.value.name)
+            .chain(program.classes.iter().map(|class| &class.value.name))
+            .chain(program.enums.iter().map(|e| &e.value.name))
+            .chain(program.aliases.iter().map(|alias| &alias.value.alias));
+
+        for item in items {
+            if self.symbols.name(item.value.name.value) != word {
+                continue;
+            }
+
+            let file = item.value.name.span.start.file;
+
+            let uri = if file == FileId::default() {
+                uri.to_owned()
+            } else {
+                file_uri(self.files.name(file))
+            };
+
+            return json!({ "uri": uri, "range": range(item.value.name.span) });
+        }
+
+        Value::Null
+    }
+
+    /// The identifiers that start with the word before the cursor
+    pub fn completion(&self, text: &str, position: &Value) -> Value {
+        let prefix = word_at(text, position, false)
+            .map(|(word, _)| word)
+            .unwrap_or_default();
+
+        let mut items: Vec<(String, u32)> = KEYWORDS
+            .iter()
+            .map(|keyword| (keyword.to_string(), KEYWORD))
+            .chain(
+                self.natives
+                    .iter()
+                    .map(|native| (native.name.clone(), FUNCTION)),
+            )
+            .collect();
+
+        if let Some(ref program) = self.program {
+            self.names(program, &mut items);
+        }
+
+        items.sort();
+        items.dedup_by(|a, b| a.0 == b.0);
+
+        completion_items(
+            items
+                .into_iter()
+                .filter(|(label, _)| label.starts_with(&prefix)),
+        )
+    }
+
+    /// The properties and methods of the class instance before the `.` that precedes the
+    /// cursor
+    pub fn members(&self, text: &str, position: &Value) -> Value {
+        let typed = match self.typed {
+            Some(ref typed) => typed,
+            None => return completion_items(vec![]),
+        };
+
+        let prefix = word_at(text, position, false)
+            .map(|(word, _)| word)
+            .unwrap_or_default();
+
+        let mut dot = span_position(position);
+        dot.column -= prefix.chars().count() as u32 + 1;
+
+        completion_items(
+            query::members_at(typed, dot, &self.symbols)
+                .into_iter()
+                .filter(|member| member.starts_with(&prefix))
+                .map(|member| (member, FIELD)),
+        )
+    }
+
+    fn names(&self, program: &Program, items: &mut Vec<(String, u32)>) {
+        for function in program.functions.iter() {
+            items.push((
+                self.symbols.name(function.value.name.value.name.value),
+                FUNCTION,
+            ));
+            self.locals(&function.value, items);
+        }
+
+        for class in program.classes.iter() {
+            items.push((self.symbols.name(class.value.name.value.name.value), CLASS));
+
+            for method in class.value.methods.iter() {
+                self.locals(&method.value, items);
+            }
+        }
+
+        for e in program.enums.iter() {
+            items.push((self.symbols.name(e.value.name.value.name.value), ENUM));
+        }
+
+        for alias in program.aliases.iter() {
+            items.push((self.symbols.name(alias.value.alias.value.name.value), CLASS));
+        }
+
+        for statement in program.statements.iter() {
+            if let Statement::VarDeclaration { ref ident, .. } = statement.value {
+                items.push((self.symbols.name(ident.value), VARIABLE));
+            }
+        }
+    }
+
+    /// The params and the variables declared within a function
+    fn locals(&self, function: &Function, items: &mut Vec<(String, u32)>) {
+        for param in function.params.value.iter() {
+            items.push((self.symbols.name(param.value.name.value), VARIABLE));
+        }
+
+        let mut statements = vec![&function.body];
+
+        while let Some(statement) = statements.pop() {
+            match statement.value {
+                Statement::Block(ref block) => statements.extend(block.iter()),
+                Statement::VarDeclaration { ref ident, .. } => {
+                    items.push((self.symbols.name(ident.value), VARIABLE))
+                }
+                Statement::For {
+                    ref init, ref body, ..
+                } => {
+                    statements.extend(init.iter().map(|init| &**init));
+                    statements.push(body);
+                }
+                Statement::If {
+                    ref then,
+                    ref otherwise,
+                    ..
+                } => {
+                    statements.push(then);
+                    statements.extend(otherwise.iter().map(|otherwise| &**otherwise));
+                }
+                Statement::While { ref body, .. } => statements.push(body),
+                _ => (),
+            }
+        }
+    }
+}
+
+fn completion_items<I: IntoIterator<Item = (String, u32)>>(items: I) -> Value {
+    let items: Vec<Value> = items
+        .into_iter()
+        .map(|(label, kind)| json!({ "label": label, "kind": kind }))
+        .collect();
+
+    json!({ "isIncomplete": false, "items": items })
+}
+
+/// The path of a `file://` uri
+pub fn uri_path(uri: &str) -> Option<&Path> {
+    uri.strip_prefix("file://").map(Path::new)
+}
+
+fn file_uri(path: &str) -> String {
+    match Path::new(path).canonicalize() {
+        Ok(path) => format!("file://{}", path.display()),
+        Err(_) => format!("file://{}", path),
+    }
+}
+
+/// Converts a span into a range of the protocol
+pub fn range(span: Span) -> Value {
+    let end = if span.start == span.end {
+        span.end.column
+    } else {
+        span.end.column.saturating_sub(1)
+    };
+
+    json!({
+        "start": {
+            "line": span.start.line.saturating_sub(1),
+            "character": span.start.column.saturating_sub(1),
+        },
+        "end": {
+            "line": span.end.line.saturating_sub(1),
+            "character": end,
+        },
+    })
+}
+
+/// Converts a position of the protocol into the position used by a span
+fn span_position(position: &Value) -> Position {
+    Position {
+        line: position["line"].as_u64().unwrap_or(0) as u32 + 1,
+        column: position["character"].as_u64().unwrap_or(0) as u32 + 1,
+        absolute: 0,
+        file: FileId::default(),
+    }
+}
+
+/// Converts the column of a position sent by a utf-16 client into chars
+pub fn from_utf16(text: &str, position: &Value) -> Value {
+    let line = text
+        .lines()
+        .nth(position["line"].as_u64().unwrap_or(0) as usize);
+    let units = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let mut seen = 0;
+    let column = line.map_or(units, |line| {
+        line.chars()
+            .take_while(|ch| {
+                seen += ch.len_utf16();
+                seen <= units
+            })
+            .count()
+    });
+
+    json!({ "line": position["line"], "character": column })
+}
+
+/// Converts the column of every position within a reply from chars into utf-16 code units
+pub fn to_utf16(text: &str, value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if let (Some(line), Some(column)) = (
+                object.get("line").and_then(Value::as_u64),
+                object.get("character").and_then(Value::as_u64),
+            ) {
+                // a column past the end of the line is left as it is
+                let units = text.lines().nth(line as usize).map_or(column, |line| {
+                    let chars = line.chars().count() as u64;
+                    let units: usize = line
+                        .chars()
+                        .take(column as usize)
+                        .map(char::len_utf16)
+                        .sum();
+
+                    units as u64 + column.saturating_sub(chars)
+                });
+
+                object.insert("character".into(), json!(units));
+            } else {
+                object
+                    .iter_mut()
+                    .for_each(|(_, value)| to_utf16(text, value));
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| to_utf16(text, value)),
+        _ => (),
+    }
+}
+
+/// The identifier at the position. If `whole` is false only the part of the identifier
+/// before the position is returned. The column the identifier starts at is returned along
+/// with it
+fn word_at(text: &str, position: &Value, whole: bool) -> Option<(String, usize)> {
+    let line = text.lines().nth(position["line"].as_u64()? as usize)?;
+    let chars: Vec<char> = line.chars().collect();
+    let cursor = (position["character"].as_u64()? as usize).min(chars.len());
+
+    let is_ident = |ch: &char| ch.is_alphanumeric() || *ch == '_';
+
+    let start = chars[..cursor]
+        .iter()
+        .rposition(|ch| !is_ident(ch))
+        .map_or(0, |index| index + 1);
+
+    let end = if whole {
+        chars[cursor..]
+            .iter()
+            .position(|ch| !is_ident(ch))
+            .map_or(chars.len(), |index| cursor + index)
+    } else {
+        cursor
+    };
+
+    Some((chars[start..end].iter().collect(), start))
+}
+
+/// The char before the identifier under the cursor
+pub fn trigger(text: &str, position: &Value) -> Option<char> {
+    let (_, start) = word_at(text, position, false)?;
+    let line = text.lines().nth(position["line"].as_u64()? as usize)?;
+
+    line.chars().nth(start.checked_sub(1)?)
+}
@@ -0,0 +1,16 @@
+//! A language server for tox. It speaks the language server protocol over stdio and
+//! publishes the diagnostics of the parser and type checker, shows the type of the
+//! expression under the cursor, goes to the definition of functions, classes and enums and
+//! completes identifiers and class members.
+extern crate frontend;
+#[macro_use]
+extern crate serde_json;
+extern crate syntax;
+extern crate util;
+extern crate vm;
+
+mod analysis;
+mod server;
+mod transport;
+
+pub use crate::server::Server;
@@ -0,0 +1,19 @@
+extern crate lsp;
+
+use lsp::Server;
+use std::io;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    let code = match Server::new(stdin.lock(), stdout.lock()).run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    };
+
+    ::std::process::exit(code)
+}
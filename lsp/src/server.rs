@@ -0,0 +1,508 @@
+use crate::analysis::{self, Analysis};
+use crate::transport::{read_message, write_message};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+/// The error code of a request for a method the server does not support
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// An open document
+struct Document {
+    text: String,
+    analysis: Rc<Analysis>,
+    /// The last version of the document that type checked. Class members are completed
+    /// from it as a document with a trailing `.` never type checks
+    checked: Option<Rc<Analysis>>,
+}
+
+pub struct Server<R, W> {
+    input: R,
+    output: W,
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    /// The client counts columns in utf-16 code units as it didn't offer utf-32
+    utf16: bool,
+}
+
+impl<R: BufRead, W: Write> Server<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Server {
+            input,
+            output,
+            documents: HashMap::new(),
+            shutdown: false,
+            utf16: true,
+        }
+    }
+
+    /// Handles messages until the client sends `exit` and returns the code the server
+    /// should exit with
+    pub fn run(&mut self) -> io::Result<i32> {
+        while let Some(message) = read_message(&mut self.input)? {
+            let method = match message["method"].as_str() {
+                Some(method) => method.to_owned(),
+                None => continue, // a response to a request we never send
+            };
+
+            if method == "exit" {
+                return Ok(if self.shutdown { 0 } else { 1 });
+            }
+
+            match message.get("id") {
+                Some(id) => {
+                    let response = match self.request(&method, &message["params"]) {
+                        Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                        None => json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": {
+                                "code": METHOD_NOT_FOUND,
+                                "message": format!("Unknown method `{}`", method),
+                            },
+                        }),
+                    };
+
+                    write_message(&mut self.output, &response)?;
+                }
+                None => self.notification(&method, &message["params"])?,
+            }
+        }
+
+        Ok(1)
+    }
+
+    /// Returns `None` if the method is not supported
+    fn request(&mut self, method: &str, params: &Value) -> Option<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+
+        if method == "initialize" {
+            // columns are counted in chars so utf-32 needs no converting
+            self.utf16 = !params["capabilities"]["general"]["positionEncodings"]
+                .as_array()
+                .is_some_and(|encodings| encodings.contains(&json!("utf-32")));
+
+            return Some(json!({
+                "capabilities": {
+                    "positionEncoding": if self.utf16 { "utf-16" } else { "utf-32" },
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                },
+                "serverInfo": { "name": "tox-lsp" },
+            }));
+        }
+
+        let position = match self.documents.get(uri) {
+            Some(document) if self.utf16 => {
+                analysis::from_utf16(&document.text, &params["position"])
+            }
+            _ => params["position"].clone(),
+        };
+        let position = &position;
+
+        let mut result = match method {
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Value::Null)
+            }
+            "textDocument/hover" => Some(
+                self.documents
+                    .get(uri)
+                    .map_or(Value::Null, |document| document.analysis.hover(position)),
+            ),
+            "textDocument/definition" => {
+                Some(self.documents.get(uri).map_or(Value::Null, |document| {
+                    document.analysis.definition(uri, &document.text, position)
+                }))
+            }
+            "textDocument/completion" => {
+                Some(self.documents.get(uri).map_or(Value::Null, |document| {
+                    match (
+                        analysis::trigger(&document.text, position),
+                        &document.checked,
+                    ) {
+                        (Some('.'), Some(checked)) => checked.members(&document.text, position),
+                        (Some('.'), None) => json!([]),
+                        _ => document.analysis.completion(&document.text, position),
+                    }
+                }))
+            }
+            _ => None,
+        }?;
+
+        if self.utf16 {
+            self.to_utf16(uri, &mut result);
+        }
+
+        Some(result)
+    }
+
+    /// Converts the ranges of a reply about the document into utf-16. A location within
+    /// another file is converted using the text of that file
+    fn to_utf16(&self, uri: &str, result: &mut Value) {
+        let target = result["uri"].as_str().unwrap_or(uri);
+
+        let text = match self.documents.get(target) {
+            Some(document) => document.text.clone(),
+            None => analysis::uri_path(target)
+                .and_then(|path| fs::read_to_string(path).ok())
+                .unwrap_or_default(),
+        };
+
+        analysis::to_utf16(&text, result);
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or("")
+            .to_owned();
+
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.update(uri, text.to_owned())
+            }
+            "textDocument/didChange" => {
+                // only full syncs are supported so the last change is the whole document
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or("");
+
+                self.update(uri, text.to_owned())
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+
+                let params = json!({ "uri": uri, "diagnostics": [] });
+                self.publish_diagnostics(params)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the new text of a document and publishes its diagnostics
+    fn update(&mut self, uri: String, text: String) -> io::Result<()> {
+        let analysis = Rc::new(Analysis::new(&text, analysis::uri_path(&uri)));
+
+        let checked = if analysis.is_checked() {
+            Some(Rc::clone(&analysis))
+        } else {
+            self.documents
+                .remove(&uri)
+                .and_then(|document| document.checked)
+        };
+
+        let mut params = analysis.diagnostics(&uri);
+
+        if self.utf16 {
+            analysis::to_utf16(&text, &mut params);
+        }
+
+        self.documents.insert(
+            uri,
+            Document {
+                text,
+                analysis,
+                checked,
+            },
+        );
+
+        self.publish_diagnostics(params)
+    }
+
+    fn publish_diagnostics(&mut self, params: Value) -> io::Result<()> {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": params,
+        });
+
+        write_message(&mut self.output, &notification)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Server;
+    use crate::transport::read_message;
+    use serde_json::Value;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    const URI: &str = "file:///test.tox";
+
+    const SOURCE: &str = "class Name {
+    first: str, last: str;
+
+    fn full(name: Name) -> str {
+        return name.first + name.last;
+    }
+}
+
+enum Colour {
+    Red,
+    Blue
+}
+
+fn main() {
+    let name = Name { first: \"Ada\", last: \"Lovelace\" };
+    print name.first;
+}";
+
+    /// Plays the messages of a client to a server and returns what the server wrote back
+    fn script(messages: Vec<Value>) -> (i32, Vec<Value>) {
+        let mut input = Vec::new();
+
+        for message in messages {
+            let body = message.to_string();
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).bytes());
+        }
+
+        let mut output = Vec::new();
+
+        let code = Server::new(Cursor::new(input), &mut output).run().unwrap();
+
+        let mut output = Cursor::new(output);
+        let mut replies = Vec::new();
+
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+
+        (code, replies)
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": URI, "languageId": "tox", "version": 1, "text": text },
+            },
+        })
+    }
+
+    fn request(id: u64, method: &str, line: u64, character: u64) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            },
+        })
+    }
+
+    fn session(mut messages: Vec<Value>) -> Vec<Value> {
+        let mut all = vec![
+            json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+        ];
+
+        all.append(&mut messages);
+        all.push(json!({ "jsonrpc": "2.0", "id": 99, "method": "shutdown" }));
+        all.push(json!({ "jsonrpc": "2.0", "method": "exit" }));
+
+        let (code, replies) = script(all);
+
+        assert_eq!(code, 0);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+        assert_eq!(replies.last().unwrap()["id"], 99);
+
+        replies
+    }
+
+    fn reply(replies: &[Value], id: u64) -> &Value {
+        &replies
+            .iter()
+            .find(|reply| reply["id"] == id)
+            .expect("No reply to the request")["result"]
+    }
+
+    fn labels(completion: &Value) -> Vec<&str> {
+        completion["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn diagnostics() {
+        let replies = session(vec![open("fn main() {\n    let a:int = true;\n}")]);
+
+        let diagnostics = &replies[1]["params"]["diagnostics"];
+
+        assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+
+        let replies = session(vec![open(SOURCE)]);
+
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn hover() {
+        let replies = session(vec![
+            open(SOURCE),
+            request(1, "textDocument/hover", 15, 11),
+            request(2, "textDocument/hover", 15, 15),
+            request(3, "textDocument/hover", 4, 27),
+        ]);
+
+        assert_eq!(reply(&replies, 1)["contents"]["value"], "Name<str, str>");
+        assert_eq!(reply(&replies, 2)["contents"]["value"], "str");
+        assert_eq!(
+            reply(&replies, 3)["range"],
+            json!({
+                "start": { "line": 4, "character": 15 },
+                "end": { "line": 4, "character": 37 },
+            })
+        );
+    }
+
+    #[test]
+    fn definition() {
+        let replies = session(vec![
+            open(SOURCE),
+            request(1, "textDocument/definition", 14, 16),
+            request(2, "textDocument/definition", 15, 5),
+        ]);
+
+        assert_eq!(reply(&replies, 1)["uri"], URI);
+        assert_eq!(
+            reply(&replies, 1)["range"],
+            json!({
+                "start": { "line": 0, "character": 6 },
+                "end": { "line": 0, "character": 10 },
+            })
+        );
+        assert_eq!(*reply(&replies, 2), Value::Null);
+    }
+
+    #[test]
+    fn completion() {
+        let edited = SOURCE.replace("print name.first;", "print name.");
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": edited }],
+            },
+        });
+
+        let replies = session(vec![
+            open(SOURCE),
+            request(1, "textDocument/completion", 15, 11),
+            change,
+            request(2, "textDocument/completion", 15, 15),
+        ]);
+
+        let identifiers = labels(reply(&replies, 1));
+
+        assert!(identifiers.contains(&"name"));
+        assert!(identifiers.contains(&"nil"));
+        assert!(!identifiers.contains(&"main"));
+
+        assert_eq!(labels(reply(&replies, 2)), vec!["first", "last", "full"]);
+    }
+
+    #[test]
+    fn imports() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/pass/import");
+        let uri = format!("file://{}", dir.join("import.tox").display());
+        let text = fs::read_to_string(dir.join("import.tox")).unwrap();
+
+        let mut definition = request(1, "textDocument/definition", 5, 10);
+        definition["params"]["textDocument"]["uri"] = json!(uri);
+
+        let replies = session(vec![
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": { "uri": uri, "languageId": "tox", "version": 1, "text": text },
+                },
+            }),
+            definition,
+        ]);
+
+        let math = dir.join("modules/math.tox").canonicalize().unwrap();
+
+        assert_eq!(replies[1]["params"]["diagnostics"], json!([]));
+        assert_eq!(
+            reply(&replies, 1)["uri"],
+            format!("file://{}", math.display())
+        );
+        assert_eq!(
+            reply(&replies, 1)["range"]["start"],
+            json!({ "line": 2, "character": 3 })
+        );
+    }
+
+    #[test]
+    fn position_encoding() {
+        // the emoji is two utf-16 code units but a single char
+        let error = "fn main() {\n    let e = \"\u{1F600}\"; let a: int = true;\n}";
+        let text = "fn main() {\n    let e = \"\u{1F600}\"; print e;\n}";
+
+        let replies = session(vec![open(error)]);
+
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["positionEncoding"],
+            "utf-16"
+        );
+        assert_eq!(
+            replies[1]["params"]["diagnostics"][0]["range"]["start"],
+            json!({ "line": 1, "character": 25 })
+        );
+
+        let replies = session(vec![open(text), request(1, "textDocument/hover", 1, 24)]);
+
+        assert_eq!(reply(&replies, 1)["contents"]["value"], "str");
+        assert_eq!(
+            reply(&replies, 1)["range"]["start"],
+            json!({ "line": 1, "character": 24 })
+        );
+
+        let (_, replies) = script(vec![
+            json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "initialize",
+                "params": { "capabilities": { "general": { "positionEncodings": ["utf-32", "utf-16"] } } },
+            }),
+            open(text),
+            request(1, "textDocument/hover", 1, 23),
+        ]);
+
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["positionEncoding"],
+            "utf-32"
+        );
+        assert_eq!(
+            reply(&replies, 1)["range"]["start"],
+            json!({ "line": 1, "character": 23 })
+        );
+    }
+
+    #[test]
+    fn unknown_method() {
+        let replies = session(vec![request(1, "textDocument/rename", 0, 0)]);
+
+        assert_eq!(replies[1]["error"]["code"], super::METHOD_NOT_FOUND);
+    }
+}
@@ -1,5 +1,7 @@
 //! Instruction in the VM;
 //! Each Instruction in the VM is 8 bits
+//! The operands of an instruction, such as a constant, slot or symbol, are encoded as
+//! variable length ints so they can be larger than a byte. Jump offsets are always 16 bits
 pub mod opcode {
     /// ILLEGAL INST
     pub const IGL: u8 = 0;
@@ -133,4 +135,79 @@ pub mod opcode {
 
     // declare an enum with associated data
     pub const ENUMDATA: u8 = 53;
+    /// Pops an enum and pushes its tag
+    pub const ENUMTAG: u8 = 54;
+    /// Pops an enum and pushes the data it stores
+    pub const ENUMPAYLOAD: u8 = 55;
+    /// CLOSURE $constant $count ($kind $index)*
+    /// Wraps the function at $constant in a closure that captures $count variables.
+    /// $kind is 0 for a local, 1 for a param and 2 for an upvalue of the enclosing function
+    pub const CLOSURE: u8 = 56;
+    /// GETUPVALUE $index
+    /// Pushes the value of the captured variable at $index
+    pub const GETUPVALUE: u8 = 57;
+    /// SETUPVALUE $index
+    /// Sets the captured variable at $index to the value on the top of the stack
+    pub const SETUPVALUE: u8 = 58;
+    /// CLOSEUPVALUE $slot
+    /// Moves the local at $slot into the upvalue that captured it as it goes out of scope
+    pub const CLOSEUPVALUE: u8 = 59;
+    /// MOD $x $y
+    /// Returns the remainder of $x / $y
+    pub const MOD: u8 = 60;
+    /// MOD Float $x $y
+    pub const MODF: u8 = 61;
+    /// POW $x $y
+    /// Returns $x raised to the power of $y
+    pub const POW: u8 = 62;
+    /// POW Float $x $y
+    pub const POWF: u8 = 63;
+    /// GETGLOBAL $symbol
+    /// Pushes the value of the global variable named $symbol
+    pub const GETGLOBAL: u8 = 64;
+    /// SETGLOBAL $symbol
+    /// Sets the global variable named $symbol to the value on the top of the stack
+    pub const SETGLOBAL: u8 = 65;
+    /// SETINDEX $array $index $value
+    /// Sets the item of the array at the index to the value and leaves the value on the stack
+    pub const SETINDEX: u8 = 66;
+    /// DUP2
+    /// Pushes a copy of the top two values on the stack
+    pub const DUP2: u8 = 67;
+    /// ARRAYLEN $array
+    /// Pushes the number of items in the array
+    pub const ARRAYLEN: u8 = 68;
+    /// ARRAYPUSH $array $value
+    /// Appends the value to the array
+    pub const ARRAYPUSH: u8 = 69;
+    /// ARRAYPOP $array
+    /// Removes the last item of the array and pushes it
+    pub const ARRAYPOP: u8 = 70;
+    /// ARRAYINSERT $array $index $value
+    /// Inserts the value at the index, shifting the items after it along
+    pub const ARRAYINSERT: u8 = 71;
+    /// ARRAYREMOVE $array $index
+    /// Removes the item at the index and pushes it
+    pub const ARRAYREMOVE: u8 = 72;
+    /// ARRAYCONTAINS $array $value
+    /// Pushes true if an item of the array is equal to the value
+    pub const ARRAYCONTAINS: u8 = 73;
+    /// SLICEARRAY $array $start $end
+    /// Pushes a new array with the items between $start and $end. A nil bound is the start or end of the array
+    pub const SLICEARRAY: u8 = 74;
+    /// CONCATARRAY $x:array $y:array
+    pub const CONCATARRAY: u8 = 75;
+    /// CONCATN $count
+    /// Pops $count strings and pushes them joined together in the order they were pushed
+    pub const CONCATN: u8 = 76;
+    /// STRLEN $string
+    /// Pushes the number of chars in the string
+    pub const STRLEN: u8 = 77;
+    /// PARSEINT $enum $string
+    /// Pushes `$enum::Some(int)` (tag 0) if the string is an int and `$enum::None` (tag 1)
+    /// if it isn't
+    pub const PARSEINT: u8 = 78;
+    /// PARSEFLOAT $enum $string
+    /// The same as PARSEINT but for a float
+    pub const PARSEFLOAT: u8 = 79;
 }
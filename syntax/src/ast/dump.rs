@@ -0,0 +1,483 @@
+//! Writes a syntax tree out with a node on each line and its children indented below it,
+//! which is what `--emit=ast` shows. Names are written out and spans are left out.
+use super::*;
+use util::pos::Position;
+use util::symbol::Symbols;
+
+const INDENT: &str = "  ";
+
+impl Program {
+    /// Writes out the items of the program in the order they were written in
+    pub fn dump(&self, symbols: &Symbols<()>) -> String {
+        let mut items: Vec<(Position, Item)> = Vec::new();
+
+        items.extend(self.imports.iter().map(|i| (i.span.start, Item::Import(i))));
+        items.extend(self.aliases.iter().map(|a| (a.span.start, Item::Alias(a))));
+        items.extend(self.enums.iter().map(|e| (e.span.start, Item::Enum(e))));
+        items.extend(self.classes.iter().map(|c| (c.span.start, Item::Class(c))));
+        items.extend(
+            self.functions
+                .iter()
+                .map(|f| (f.span.start, Item::Function(f))),
+        );
+        items.extend(
+            self.statements
+                .iter()
+                .map(|s| (s.span.start, Item::Statement(s))),
+        );
+
+        // an imported file's items come before the items of the files that import it
+        items.sort_by_key(|&(start, _)| {
+            let file = self.files.iter().position(|file| *file == start.file);
+            (file, start.absolute)
+        });
+
+        let mut tree = Tree {
+            symbols,
+            depth: 0,
+            out: String::new(),
+        };
+
+        for (_, item) in items {
+            match item {
+                Item::Import(import) => tree.line(format!("import {}", import.value)),
+                Item::Alias(alias) => tree.alias(&alias.value),
+                Item::Enum(e) => tree.enumeration(&e.value),
+                Item::Class(class) => tree.class(&class.value),
+                Item::Function(function) => tree.function(&function.value),
+                Item::Statement(statement) => tree.statement(&statement.value),
+            }
+        }
+
+        tree.out
+    }
+}
+
+enum Item<'a> {
+    Import(&'a Spanned<String>),
+    Alias(&'a Spanned<TypeAlias>),
+    Enum(&'a Spanned<Enum>),
+    Class(&'a Spanned<Class>),
+    Function(&'a Spanned<Function>),
+    Statement(&'a Spanned<Statement>),
+}
+
+struct Tree<'a> {
+    symbols: &'a Symbols<()>,
+    depth: usize,
+    out: String,
+}
+
+impl<'a> Tree<'a> {
+    fn line(&mut self, text: String) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+
+        self.out.push_str(&text);
+        self.out.push('\n');
+    }
+
+    /// Writes a line with the children written by `children` below it
+    fn node<F: FnOnce(&mut Self)>(&mut self, text: String, children: F) {
+        self.line(text);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn name(&self, symbol: Symbol) -> String {
+        self.symbols.name(symbol)
+    }
+
+    fn item_name(&self, name: &ItemName) -> String {
+        let mut out = self.name(name.name.value);
+
+        if !name.type_params.is_empty() {
+            let params: Vec<String> = name
+                .type_params
+                .iter()
+                .map(|param| self.name(param.value))
+                .collect();
+
+            out.push_str(&format!("<{}>", params.join(", ")));
+        }
+
+        out
+    }
+
+    fn ty(&self, ty: &Type) -> String {
+        match *ty {
+            Type::Simple(ref name) => self.name(name.value),
+            Type::Arr(ref inner) => format!("[{}]", self.ty(&inner.value)),
+            Type::Func(ref params, ref returns) => {
+                let params: Vec<String> =
+                    params.iter().map(|param| self.ty(&param.value)).collect();
+
+                match *returns {
+                    Some(ref returns) => {
+                        format!("fn({}) -> {}", params.join(", "), self.ty(&returns.value))
+                    }
+                    None => format!("fn({})", params.join(", ")),
+                }
+            }
+            Type::Nil => "nil".into(),
+            Type::Generic(ref name, ref types) => {
+                format!("{}<{}>", self.name(name.value), self.types(types))
+            }
+        }
+    }
+
+    fn types(&self, types: &[Spanned<Type>]) -> String {
+        let types: Vec<String> = types.iter().map(|ty| self.ty(&ty.value)).collect();
+        types.join(", ")
+    }
+
+    fn alias(&mut self, alias: &TypeAlias) {
+        let text = format!(
+            "type {} = {}",
+            self.item_name(&alias.alias.value),
+            self.ty(&alias.ty.value)
+        );
+        self.line(text)
+    }
+
+    fn enumeration(&mut self, e: &Enum) {
+        let text = format!("enum {}", self.item_name(&e.name.value));
+
+        self.node(text, |tree| {
+            for variant in &e.variants {
+                let text = match variant.inner {
+                    Some(ref inner) => format!(
+                        "{}({})",
+                        tree.name(variant.name.value),
+                        tree.ty(&inner.value)
+                    ),
+                    None => tree.name(variant.name.value),
+                };
+
+                tree.line(text)
+            }
+        })
+    }
+
+    fn class(&mut self, class: &Class) {
+        let mut text = format!("class {}", self.item_name(&class.name.value));
+
+        if let Some(ref superclass) = class.superclass {
+            text.push_str(&format!(" extends {}", self.name(superclass.value)));
+        }
+
+        self.node(text, |tree| {
+            for field in &class.fields {
+                let text = format!(
+                    "{}: {}",
+                    tree.name(field.value.name.value),
+                    tree.ty(&field.value.ty.value)
+                );
+                tree.line(text)
+            }
+
+            for method in &class.methods {
+                tree.function(&method.value)
+            }
+        })
+    }
+
+    fn function(&mut self, function: &Function) {
+        let text = format!(
+            "fn {}{}",
+            self.item_name(&function.name.value),
+            self.signature(function)
+        );
+
+        self.node(text, |tree| tree.statement(&function.body.value))
+    }
+
+    fn signature(&self, function: &Function) -> String {
+        let params: Vec<String> = function
+            .params
+            .value
+            .iter()
+            .map(|param| {
+                format!(
+                    "{}: {}",
+                    self.name(param.value.name.value),
+                    self.ty(&param.value.ty.value)
+                )
+            })
+            .collect();
+
+        match function.returns {
+            Some(ref returns) => format!("({}) -> {}", params.join(", "), self.ty(&returns.value)),
+            None => format!("({})", params.join(", ")),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match *statement {
+            Statement::Block(ref statements) => self.node("block".into(), |tree| {
+                for statement in statements {
+                    tree.statement(&statement.value)
+                }
+            }),
+            Statement::Break => self.line("break".into()),
+            Statement::Continue => self.line("continue".into()),
+            Statement::Expr(ref expr) => {
+                self.node("expr".into(), |tree| tree.expression(&expr.value))
+            }
+            Statement::For {
+                ref init,
+                ref cond,
+                ref incr,
+                ref body,
+            } => self.node("for".into(), |tree| {
+                if let Some(ref init) = *init {
+                    tree.node("init".into(), |tree| tree.statement(&init.value))
+                }
+
+                if let Some(ref cond) = *cond {
+                    tree.node("cond".into(), |tree| tree.expression(&cond.value))
+                }
+
+                if let Some(ref incr) = *incr {
+                    tree.node("incr".into(), |tree| tree.expression(&incr.value))
+                }
+
+                tree.statement(&body.value)
+            }),
+            Statement::If {
+                ref cond,
+                ref then,
+                ref otherwise,
+            } => self.node("if".into(), |tree| {
+                tree.expression(&cond.value);
+                tree.statement(&then.value);
+
+                if let Some(ref otherwise) = *otherwise {
+                    tree.node("else".into(), |tree| tree.statement(&otherwise.value))
+                }
+            }),
+            Statement::Print(ref expr) => {
+                self.node("print".into(), |tree| tree.expression(&expr.value))
+            }
+            Statement::While { ref cond, ref body } => self.node("while".into(), |tree| {
+                tree.expression(&cond.value);
+                tree.statement(&body.value)
+            }),
+            Statement::VarDeclaration {
+                ref ident,
+                ref ty,
+                ref expr,
+            } => {
+                let mut text = format!("let {}", self.name(ident.value));
+
+                if let Some(ref ty) = *ty {
+                    text.push_str(&format!(": {}", self.ty(&ty.value)));
+                }
+
+                self.node(text, |tree| {
+                    if let Some(ref expr) = *expr {
+                        tree.expression(&expr.value)
+                    }
+                })
+            }
+            Statement::Return(ref expr) => {
+                self.node("return".into(), |tree| tree.expression(&expr.value))
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Array { ref items } => self.node("array".into(), |tree| {
+                for item in items {
+                    tree.expression(&item.value)
+                }
+            }),
+            Expression::Assign {
+                ref name,
+                ref kind,
+                ref value,
+            } => {
+                let text = format!("assign {} {}", self.name(name.value), kind.value);
+                self.node(text, |tree| tree.expression(&value.value))
+            }
+            Expression::Binary {
+                ref lhs,
+                ref op,
+                ref rhs,
+            } => self.node(format!("binary {}", op.value), |tree| {
+                tree.expression(&lhs.value);
+                tree.expression(&rhs.value)
+            }),
+            Expression::Call(ref call) => {
+                let mut text = "call".to_string();
+
+                if !call.value.types.value.is_empty() {
+                    text.push_str(&format!("::<{}>", self.types(&call.value.types.value)));
+                }
+
+                self.node(text, |tree| {
+                    tree.expression(&call.value.callee.value);
+
+                    for arg in &call.value.args {
+                        tree.expression(&arg.value)
+                    }
+                })
+            }
+            Expression::Cast { ref from, ref to } => {
+                let text = format!("cast as {}", self.ty(&to.value));
+                self.node(text, |tree| tree.expression(&from.value))
+            }
+            Expression::ClassLiteral(ref literal) => {
+                let mut text = format!("class literal {}", self.name(literal.value.symbol.value));
+
+                if !literal.value.types.value.is_empty() {
+                    text.push_str(&format!("::<{}>", self.types(&literal.value.types.value)));
+                }
+
+                self.node(text, |tree| {
+                    for prop in &literal.value.props {
+                        let text = tree.name(prop.value.symbol.value);
+                        tree.node(text, |tree| tree.expression(&prop.value.expr.value))
+                    }
+                })
+            }
+            Expression::Closure(ref function) => {
+                let text = format!("closure{}", self.signature(&function.value));
+                self.node(text, |tree| tree.statement(&function.value.body.value))
+            }
+            Expression::Get {
+                ref object,
+                ref property,
+            } => {
+                let text = format!("get {}", self.name(property.value));
+                self.node(text, |tree| tree.expression(&object.value))
+            }
+            Expression::Grouping { ref expr } => {
+                self.node("group".into(), |tree| tree.expression(&expr.value))
+            }
+            Expression::Interpolation(ref segments) => self.node("interpolation".into(), |tree| {
+                for segment in segments {
+                    match *segment {
+                        Segment::Text(ref text) => tree.line(format!("text {:?}", text.value)),
+                        Segment::Hole(ref expr) => tree.expression(&expr.value),
+                    }
+                }
+            }),
+            Expression::Match { ref cond, ref arms } => self.node("match".into(), |tree| {
+                tree.expression(&cond.value);
+
+                for arm in &arms.value {
+                    let text = format!("arm {}", tree.pattern(&arm.value.pattern.value));
+                    tree.node(text, |tree| tree.statement(&arm.value.body.value))
+                }
+            }),
+            Expression::SubScript {
+                ref target,
+                ref index,
+            } => self.node("index".into(), |tree| {
+                tree.expression(&target.value);
+                tree.expression(&index.value)
+            }),
+            Expression::Slice {
+                ref target,
+                ref start,
+                ref end,
+            } => self.node("slice".into(), |tree| {
+                tree.expression(&target.value);
+
+                if let Some(ref start) = *start {
+                    tree.node("start".into(), |tree| tree.expression(&start.value))
+                }
+
+                if let Some(ref end) = *end {
+                    tree.node("end".into(), |tree| tree.expression(&end.value))
+                }
+            }),
+            Expression::SetSubScript {
+                ref target,
+                ref index,
+                ref kind,
+                ref value,
+            } => self.node(format!("set index {}", kind.value), |tree| {
+                tree.expression(&target.value);
+                tree.expression(&index.value);
+                tree.expression(&value.value)
+            }),
+            Expression::Literal(ref literal) => self.line(format!("literal {}", literal)),
+            Expression::Set {
+                ref object,
+                ref name,
+                ref value,
+            } => {
+                let text = format!("set {}", self.name(name.value));
+
+                self.node(text, |tree| {
+                    tree.expression(&object.value);
+                    tree.expression(&value.value)
+                })
+            }
+            Expression::Ternary {
+                ref condition,
+                ref then_branch,
+                ref else_branch,
+            } => self.node("ternary".into(), |tree| {
+                tree.expression(&condition.value);
+                tree.expression(&then_branch.value);
+                tree.expression(&else_branch.value)
+            }),
+            Expression::Unary { ref op, ref expr } => self
+                .node(format!("unary {}", op.value), |tree| {
+                    tree.expression(&expr.value)
+                }),
+            Expression::Var(ref name) => {
+                let text = format!("var {}", self.name(name.value));
+                self.line(text)
+            }
+            Expression::Variant {
+                ref enum_name,
+                ref variant,
+                ref inner,
+            } => {
+                let text = format!(
+                    "variant {}::{}",
+                    self.name(enum_name.value),
+                    self.name(variant.value)
+                );
+
+                self.node(text, |tree| {
+                    if let Some(ref inner) = *inner {
+                        tree.expression(&inner.value)
+                    }
+                })
+            }
+        }
+    }
+
+    fn pattern(&self, pattern: &Pattern) -> String {
+        match *pattern {
+            Pattern::Wildcard => "_".into(),
+            Pattern::Binding(ref name) => self.name(name.value),
+            Pattern::Literal(ref literal) => literal.to_string(),
+            Pattern::Variant {
+                ref enum_name,
+                ref variant,
+                ref inner,
+            } => {
+                let mut text = format!(
+                    "{}::{}",
+                    self.name(enum_name.value),
+                    self.name(variant.value)
+                );
+
+                if let Some(ref inner) = *inner {
+                    text.push_str(&format!("({})", self.pattern(&inner.value)));
+                }
+
+                text
+            }
+        }
+    }
+}
@@ -1,12 +1,23 @@
-use util::pos::Spanned;
+use std::fmt::{self, Display};
+use util::pos::{FileId, Spanned};
 use util::symbol::Symbol;
 
+mod dump;
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub functions: Vec<Spanned<Function>>,
     pub classes: Vec<Spanned<Class>>,
     pub aliases: Vec<Spanned<TypeAlias>>,
     pub enums: Vec<Spanned<Enum>>,
+    /// The statements outside of any function. A `let` declares a global; the others
+    /// can only be used by a script
+    pub statements: Vec<Spanned<Statement>>,
+    /// The path of each file the program imports relative to its own file.
+    /// `import math;` imports `math.tox`
+    pub imports: Vec<Spanned<String>>,
+    /// The files the program was loaded from. Each file comes after the files it imports
+    pub files: Vec<FileId>,
 }
 
 #[derive(Debug, Clone)]
@@ -132,6 +143,9 @@ pub enum Expression {
         expr: Box<Spanned<Expression>>,
     },
 
+    /// `"text ${expr} text"`
+    Interpolation(Vec<Segment>),
+
     Match {
         cond: Box<Spanned<Expression>>,
         arms: Spanned<Vec<Spanned<MatchArm>>>,
@@ -142,6 +156,21 @@ pub enum Expression {
         index: Box<Spanned<Expression>>,
     },
 
+    /// `target[start..end]`, either of the bounds can be left out
+    Slice {
+        target: Box<Spanned<Expression>>,
+        start: Option<Box<Spanned<Expression>>>,
+        end: Option<Box<Spanned<Expression>>>,
+    },
+
+    /// `target[index] = value` or `target[index] += value` etc
+    SetSubScript {
+        target: Box<Spanned<Expression>>,
+        index: Box<Spanned<Expression>>,
+        kind: Spanned<AssignOperator>,
+        value: Box<Spanned<Expression>>,
+    },
+
     Literal(Literal),
 
     Set {
@@ -169,11 +198,36 @@ pub enum Expression {
     },
 }
 
+/// A piece of an interpolated string
+#[derive(Debug, Clone)]
+pub enum Segment {
+    /// The text around the holes. The span is of the token that the text came from, which
+    /// includes the `"`, `}` and `${` around it
+    Text(Spanned<String>),
+    /// The expression within a `${}`
+    Hole(Spanned<Expression>),
+}
+
 #[derive(Debug, Clone)]
 pub struct MatchArm {
-    pub pattern: Option<Spanned<Expression>>,
+    pub pattern: Spanned<Pattern>,
     pub body: Spanned<Statement>,
-    pub is_all: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`
+    Wildcard,
+    /// Binds the matched value to a name i.e `x`
+    Binding(Spanned<Symbol>),
+    /// `1`,`"foo"`,`true` etc
+    Literal(Literal),
+    /// `Foo::Bar` or `Foo::Bar(pattern)`
+    Variant {
+        enum_name: Spanned<Symbol>,
+        variant: Spanned<Symbol>,
+        inner: Option<Box<Spanned<Pattern>>>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -261,8 +315,67 @@ pub enum AssignOperator {
     SlashEqual,
 }
 
+impl Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match *self {
+            Op::BangEqual => "!=",
+            Op::EqualEqual => "==",
+            Op::LessThan => "<",
+            Op::LessThanEqual => "<=",
+            Op::GreaterThanEqual => ">=",
+            Op::GreaterThan => ">",
+            Op::Plus => "+",
+            Op::Minus => "-",
+            Op::Star => "*",
+            Op::Slash => "/",
+            Op::Modulo => "%",
+            Op::Exponential => "^",
+            Op::And => "and",
+            Op::Or => "or",
+        };
+
+        write!(f, "{}", op)
+    }
+}
+
+impl Display for AssignOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match *self {
+            AssignOperator::Equal => "=",
+            AssignOperator::MinusEqual => "-=",
+            AssignOperator::PlusEqual => "+=",
+            AssignOperator::StarEqual => "*=",
+            AssignOperator::SlashEqual => "/=",
+        };
+
+        write!(f, "{}", op)
+    }
+}
+
 #[derive(Debug, Clone, Hash)]
 pub enum UnaryOp {
     Bang,
     Minus,
 }
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UnaryOp::Bang => write!(f, "!"),
+            UnaryOp::Minus => write!(f, "-"),
+        }
+    }
+}
+
+impl Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Literal::Float(float) => write!(f, "{:?}", float),
+            Literal::Int(int) => write!(f, "{}", int),
+            Literal::Str(ref s) => write!(f, "{:?}", s),
+            Literal::True(_) => write!(f, "true"),
+            Literal::False(_) => write!(f, "false"),
+            Literal::Nil => write!(f, "nil"),
+        }
+    }
+}
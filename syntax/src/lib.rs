@@ -1,11 +1,12 @@
 #[cfg(test)]
 extern crate pretty_assertions;
-extern crate rand;
 extern crate util;
 
 #[macro_use]
 mod macros;
 
 pub mod ast;
+pub mod loader;
 pub mod parser;
+pub mod printer;
 mod token;
@@ -0,0 +1,803 @@
+//! Loads a program from a file along with the files it imports.
+//!
+//! An import is resolved relative to the file that contains it. Each file is only loaded
+//! once however many files import it, and a file that imports itself through its imports
+//! is reported as a cycle.
+//!
+//! Each file has its own namespace. A file can use its own items and the items of the
+//! files it imports but not those of the files they import in turn. An item that a file
+//! declares and also imports is reported, as is the use of an item that two of its imports
+//! declare. Otherwise any number of files can declare an item with the same name: the
+//! program's file keeps the names of its items and then the first file to declare a name
+//! keeps it, while the same item of any other file is renamed to `path::name`, where `path`
+//! is the path of its file relative to the program's without the extension.
+//!
+//! The items of every file are then merged into one program with the items of a file
+//! after those of the files it imports.
+use crate::ast::{
+    Class, Enum, Expression, Function, MatchArm, Pattern, Program, Segment, Statement, Type,
+    TypeAlias,
+};
+use crate::parser::{Parser, ParserResult};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use util::emmiter::Reporter;
+use util::pos::{FileId, Span, Spanned};
+use util::source::SourceMap;
+use util::symbol::{Symbol, Symbols};
+
+pub struct Loader<'a> {
+    files: &'a mut SourceMap,
+    reporter: Reporter,
+    symbols: &'a mut Symbols<()>,
+    /// The directory of the program's file, which the name of every file is relative to
+    root: PathBuf,
+    /// The files that are being loaded, each imported by the one before it
+    loading: Vec<PathBuf>,
+    /// Every file that has been loaded
+    loaded: HashMap<PathBuf, FileId>,
+    /// The files that have been parsed, each after the files it imports
+    modules: Vec<Module>,
+    had_error: bool,
+}
+
+/// A file of the program
+struct Module {
+    file: FileId,
+    /// The path of the file relative to the program's without the extension
+    name: String,
+    program: Program,
+    /// The files that the file imports along with the span of each import
+    imports: Vec<Spanned<FileId>>,
+}
+
+impl<'a> Loader<'a> {
+    pub fn new(files: &'a mut SourceMap, reporter: Reporter, symbols: &'a mut Symbols<()>) -> Self {
+        Loader {
+            files,
+            reporter,
+            symbols,
+            root: PathBuf::new(),
+            loading: Vec::new(),
+            loaded: HashMap::new(),
+            modules: Vec::new(),
+            had_error: false,
+        }
+    }
+
+    /// Loads the file at the path and the files it imports
+    pub fn load(self, path: &Path) -> ParserResult<Program> {
+        match fs::read_to_string(path) {
+            Ok(source) => self.load_source(path, source),
+            Err(e) => {
+                self.reporter
+                    .global_error(&format!("Couldn't read `{}`: {}", path.display(), e));
+                Err(())
+            }
+        }
+    }
+
+    /// Loads a file that has already been read and the files it imports
+    pub fn load_source(mut self, path: &Path, source: String) -> ParserResult<Program> {
+        self.root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+        self.file(path, source);
+
+        if self.had_error {
+            return Err(());
+        }
+
+        self.resolve();
+
+        if self.had_error {
+            Err(())
+        } else {
+            Ok(self.merge())
+        }
+    }
+
+    /// Parses a file and loads its imports
+    fn file(&mut self, path: &Path, source: String) -> FileId {
+        let file = self.files.add(path.display().to_string(), source);
+
+        self.loading.push(canonical(path));
+        self.loaded.insert(canonical(path), file);
+
+        let program = Parser::with_file(
+            self.files.source(file),
+            file,
+            self.reporter.clone(),
+            self.symbols,
+        )
+        .parse();
+
+        let program = match program {
+            Ok(program) => program,
+            Err(_) => {
+                self.had_error = true;
+                self.loading.pop();
+                return file;
+            }
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut imports = Vec::new();
+
+        for import in program.imports.iter() {
+            if let Some(imported) = self.import(&dir.join(&import.value), import.span) {
+                imports.push(Spanned::new(imported, import.span));
+            }
+        }
+
+        self.loading.pop();
+
+        let name = path
+            .strip_prefix(&self.root)
+            .unwrap_or(path)
+            .with_extension("")
+            .display()
+            .to_string();
+
+        self.modules.push(Module {
+            file,
+            name,
+            program,
+            imports,
+        });
+
+        file
+    }
+
+    fn import(&mut self, path: &Path, span: Span) -> Option<FileId> {
+        let canonical = canonical(path);
+
+        if let Some(start) = self.loading.iter().position(|file| *file == canonical) {
+            let cycle: Vec<String> = self.loading[start..]
+                .iter()
+                .chain(Some(&canonical))
+                .map(|file| file_name(file))
+                .collect();
+
+            let msg = format!(
+                "Importing `{}` creates a cycle: {}",
+                path.display(),
+                cycle.join(" -> ")
+            );
+
+            self.reporter.error(msg, span);
+            self.had_error = true;
+            return None;
+        }
+
+        if let Some(file) = self.loaded.get(&canonical) {
+            return Some(*file);
+        }
+
+        match fs::read_to_string(path) {
+            Ok(source) => Some(self.file(path, source)),
+            Err(e) => {
+                let msg = format!("Couldn't import `{}`: {}", path.display(), e);
+
+                self.reporter.error(msg, span);
+                self.had_error = true;
+                None
+            }
+        }
+    }
+
+    /// Gives every item the name it is known by and points each file at the items it can
+    /// see, reporting the items that a file can't see or sees twice
+    fn resolve(&mut self) {
+        let items: HashMap<FileId, Vec<Spanned<Symbol>>> = self
+            .modules
+            .iter()
+            .map(|module| (module.file, items(&module.program)))
+            .collect();
+
+        // the program's file is loaded last as it comes after the files it imports
+        let (program, imports) = self.modules.split_last().unwrap();
+
+        let mut renamed = HashMap::new();
+        let mut declared = HashMap::new();
+
+        for module in Some(program).into_iter().chain(imports) {
+            for item in items[&module.file].iter() {
+                let first = *declared.entry(item.value).or_insert(module.file);
+
+                if first != module.file {
+                    let name = format!("{}::{}", module.name, self.symbols.name(item.value));
+                    renamed.insert((module.file, item.value), self.symbols.symbol(&name));
+                }
+            }
+        }
+
+        let name = |file: FileId, item: Symbol| *renamed.get(&(file, item)).unwrap_or(&item);
+
+        for module in self.modules.iter_mut() {
+            let mut visible = HashMap::new();
+            let mut own = HashMap::new();
+            // the items that two imports declare can only be used by importing one of them
+            let mut ambiguous = HashMap::new();
+
+            for item in items[&module.file].iter() {
+                own.entry(item.value).or_insert(item.span);
+                visible.insert(item.value, (name(module.file, item.value), module.file));
+            }
+
+            for import in module.imports.iter() {
+                for item in items[&import.value].iter() {
+                    if ambiguous.contains_key(&item.value) {
+                        continue;
+                    }
+
+                    match visible.get(&item.value) {
+                        None => {
+                            visible
+                                .insert(item.value, (name(import.value, item.value), import.value));
+                        }
+                        Some((_, file)) if *file == import.value => (),
+                        Some((_, file)) if *file == module.file => {
+                            let msg = format!(
+                                "`{}` is already declared in `{}`",
+                                self.symbols.name(item.value),
+                                self.files.name(import.value)
+                            );
+
+                            self.reporter.error(msg, own[&item.value]);
+                            self.had_error = true;
+                        }
+                        Some((_, file)) => {
+                            ambiguous.insert(item.value, (*file, import.value));
+                            visible.remove(&item.value);
+                        }
+                    }
+                }
+            }
+
+            let mut names = Names {
+                visible: visible
+                    .into_iter()
+                    .map(|(item, (name, _))| (item, name))
+                    .collect(),
+                declared: &declared,
+                locals: Vec::new(),
+                type_params: Vec::new(),
+                unresolved: Vec::new(),
+            };
+
+            names.program(&mut module.program);
+
+            for (item, span) in names.unresolved {
+                let msg = match ambiguous.get(&item) {
+                    Some((first, second)) => format!(
+                        "`{}` is declared in both `{}` and `{}`",
+                        self.symbols.name(item),
+                        self.files.name(*first),
+                        self.files.name(*second)
+                    ),
+                    None => format!(
+                        "`{}` is declared in `{}`, which this file doesn't import",
+                        self.symbols.name(item),
+                        self.files.name(declared[&item])
+                    ),
+                };
+
+                self.reporter.error(msg, span);
+                self.had_error = true;
+            }
+        }
+    }
+
+    /// Merges the items of every file into one program
+    fn merge(self) -> Program {
+        let mut program = Program {
+            functions: Vec::new(),
+            classes: Vec::new(),
+            aliases: Vec::new(),
+            enums: Vec::new(),
+            statements: Vec::new(),
+            imports: Vec::new(),
+            files: Vec::new(),
+        };
+
+        for module in self.modules {
+            program.functions.extend(module.program.functions);
+            program.classes.extend(module.program.classes);
+            program.aliases.extend(module.program.aliases);
+            program.enums.extend(module.program.enums);
+            program.statements.extend(module.program.statements);
+            program.files.extend(module.program.files);
+        }
+
+        program
+    }
+}
+
+/// The name of every item of a file. A file can redeclare its own items
+fn items(program: &Program) -> Vec<Spanned<Symbol>> {
+    let mut names = Vec::new();
+
+    names.extend(program.functions.iter().map(|f| &f.value.name.value.name));
+    names.extend(program.classes.iter().map(|c| &c.value.name.value.name));
+    names.extend(program.enums.iter().map(|e| &e.value.name.value.name));
+    names.extend(program.aliases.iter().map(|a| &a.value.alias.value.name));
+
+    for statement in program.statements.iter() {
+        if let Statement::VarDeclaration { ref ident, .. } = statement.value {
+            names.push(ident);
+        }
+    }
+
+    names.into_iter().cloned().collect()
+}
+
+/// Points the names used by a file at the items they refer to
+struct Names<'a> {
+    /// The items the file can see along with the name each is known by
+    visible: HashMap<Symbol, Symbol>,
+    /// The file that declares each item under its own name
+    declared: &'a HashMap<Symbol, FileId>,
+    /// The variables declared within each scope, which hide the items with the same name
+    locals: Vec<HashSet<Symbol>>,
+    /// The type params of each generic item the names are within
+    type_params: Vec<HashSet<Symbol>>,
+    /// The items that are used but that the file can't see, either as it doesn't import
+    /// them or as two of its imports declare them
+    unresolved: Vec<(Symbol, Span)>,
+}
+
+impl<'a> Names<'a> {
+    fn program(&mut self, program: &mut Program) {
+        for function in program.functions.iter_mut() {
+            self.item(&mut function.value.name.value.name);
+            self.function(&mut function.value);
+        }
+
+        for class in program.classes.iter_mut() {
+            self.item(&mut class.value.name.value.name);
+            self.class(&mut class.value);
+        }
+
+        for sum in program.enums.iter_mut() {
+            self.item(&mut sum.value.name.value.name);
+            self.sum(&mut sum.value);
+        }
+
+        for alias in program.aliases.iter_mut() {
+            self.item(&mut alias.value.alias.value.name);
+            self.alias(&mut alias.value);
+        }
+
+        for statement in program.statements.iter_mut() {
+            match statement.value {
+                Statement::VarDeclaration {
+                    ref mut ident,
+                    ref mut ty,
+                    ref mut expr,
+                } => {
+                    if let Some(ty) = ty {
+                        self.ty(ty);
+                    }
+
+                    if let Some(expr) = expr {
+                        self.expression(expr);
+                    }
+
+                    self.item(ident);
+                }
+                _ => self.statement(statement),
+            }
+        }
+    }
+
+    /// Renames the declaration of an item of the file
+    fn item(&mut self, name: &mut Spanned<Symbol>) {
+        name.value = self.visible[&name.value];
+    }
+
+    /// Points a name used as a value at the item it refers to unless a variable hides it
+    fn value(&mut self, name: &mut Spanned<Symbol>) {
+        if !self.locals.iter().any(|scope| scope.contains(&name.value)) {
+            self.name(name)
+        }
+    }
+
+    /// Points a name used as a type at the item it refers to unless a type param hides it
+    fn type_name(&mut self, name: &mut Spanned<Symbol>) {
+        if !self
+            .type_params
+            .iter()
+            .any(|scope| scope.contains(&name.value))
+        {
+            self.name(name)
+        }
+    }
+
+    fn name(&mut self, name: &mut Spanned<Symbol>) {
+        if let Some(item) = self.visible.get(&name.value) {
+            name.value = *item;
+        } else if self.declared.contains_key(&name.value) {
+            self.unresolved.push((name.value, name.span));
+        }
+    }
+
+    fn declare(&mut self, name: Symbol) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(name);
+        }
+    }
+
+    fn function(&mut self, function: &mut Function) {
+        self.type_params.push(
+            function
+                .name
+                .value
+                .type_params
+                .iter()
+                .map(|param| param.value)
+                .collect(),
+        );
+
+        for param in function.params.value.iter_mut() {
+            self.ty(&mut param.value.ty);
+        }
+
+        if let Some(ref mut returns) = function.returns {
+            self.ty(returns);
+        }
+
+        self.locals.push(
+            function
+                .params
+                .value
+                .iter()
+                .map(|param| param.value.name.value)
+                .collect(),
+        );
+
+        self.statement(&mut function.body);
+
+        self.locals.pop();
+        self.type_params.pop();
+    }
+
+    fn class(&mut self, class: &mut Class) {
+        self.type_params.push(
+            class
+                .name
+                .value
+                .type_params
+                .iter()
+                .map(|param| param.value)
+                .collect(),
+        );
+
+        if let Some(ref mut superclass) = class.superclass {
+            self.type_name(superclass);
+        }
+
+        for field in class.fields.iter_mut() {
+            self.ty(&mut field.value.ty);
+        }
+
+        for method in class.methods.iter_mut() {
+            self.function(&mut method.value);
+        }
+
+        self.type_params.pop();
+    }
+
+    fn sum(&mut self, sum: &mut Enum) {
+        self.type_params.push(
+            sum.name
+                .value
+                .type_params
+                .iter()
+                .map(|param| param.value)
+                .collect(),
+        );
+
+        for variant in sum.variants.iter_mut() {
+            if let Some(ref mut inner) = variant.inner {
+                self.ty(inner);
+            }
+        }
+
+        self.type_params.pop();
+    }
+
+    fn alias(&mut self, alias: &mut TypeAlias) {
+        self.type_params.push(
+            alias
+                .alias
+                .value
+                .type_params
+                .iter()
+                .map(|param| param.value)
+                .collect(),
+        );
+
+        self.ty(&mut alias.ty);
+
+        self.type_params.pop();
+    }
+
+    fn statement(&mut self, statement: &mut Spanned<Statement>) {
+        match statement.value {
+            Statement::Block(ref mut statements) => {
+                self.locals.push(HashSet::new());
+
+                for statement in statements.iter_mut() {
+                    self.statement(statement);
+                }
+
+                self.locals.pop();
+            }
+            Statement::Break | Statement::Continue => (),
+            Statement::Expr(ref mut expr)
+            | Statement::Print(ref mut expr)
+            | Statement::Return(ref mut expr) => self.expression(expr),
+            Statement::For {
+                ref mut init,
+                ref mut cond,
+                ref mut incr,
+                ref mut body,
+            } => {
+                self.locals.push(HashSet::new());
+
+                if let Some(init) = init {
+                    self.statement(init);
+                }
+
+                if let Some(cond) = cond {
+                    self.expression(cond);
+                }
+
+                if let Some(incr) = incr {
+                    self.expression(incr);
+                }
+
+                self.statement(body);
+
+                self.locals.pop();
+            }
+            Statement::If {
+                ref mut cond,
+                ref mut then,
+                ref mut otherwise,
+            } => {
+                self.expression(cond);
+                self.statement(then);
+
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            Statement::While {
+                ref mut cond,
+                ref mut body,
+            } => {
+                self.expression(cond);
+                self.statement(body);
+            }
+            Statement::VarDeclaration {
+                ref ident,
+                ref mut ty,
+                ref mut expr,
+            } => {
+                if let Some(ty) = ty {
+                    self.ty(ty);
+                }
+
+                if let Some(expr) = expr {
+                    self.expression(expr);
+                }
+
+                self.declare(ident.value);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Spanned<Expression>) {
+        match expr.value {
+            Expression::Array { ref mut items } => {
+                for item in items.iter_mut() {
+                    self.expression(item);
+                }
+            }
+            Expression::Assign {
+                ref mut name,
+                ref mut value,
+                ..
+            } => {
+                self.expression(value);
+                self.value(name);
+            }
+            Expression::Binary {
+                ref mut lhs,
+                ref mut rhs,
+                ..
+            } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            Expression::Call(ref mut call) => {
+                self.expression(&mut call.value.callee);
+
+                for arg in call.value.args.iter_mut() {
+                    self.expression(arg);
+                }
+
+                for ty in call.value.types.value.iter_mut() {
+                    self.ty(ty);
+                }
+            }
+            Expression::Cast {
+                ref mut from,
+                ref mut to,
+            } => {
+                self.expression(from);
+                self.ty(to);
+            }
+            Expression::ClassLiteral(ref mut literal) => {
+                self.type_name(&mut literal.value.symbol);
+
+                for ty in literal.value.types.value.iter_mut() {
+                    self.ty(ty);
+                }
+
+                for prop in literal.value.props.iter_mut() {
+                    self.expression(&mut prop.value.expr);
+                }
+            }
+            Expression::Closure(ref mut function) => self.function(&mut function.value),
+            Expression::Get { ref mut object, .. } => self.expression(object),
+            Expression::Grouping { ref mut expr } => self.expression(expr),
+            Expression::Interpolation(ref mut segments) => {
+                for segment in segments.iter_mut() {
+                    if let Segment::Hole(ref mut expr) = segment {
+                        self.expression(expr);
+                    }
+                }
+            }
+            Expression::Match {
+                ref mut cond,
+                ref mut arms,
+            } => {
+                self.expression(cond);
+
+                for arm in arms.value.iter_mut() {
+                    self.arm(&mut arm.value);
+                }
+            }
+            Expression::SubScript {
+                ref mut target,
+                ref mut index,
+            } => {
+                self.expression(target);
+                self.expression(index);
+            }
+            Expression::Slice {
+                ref mut target,
+                ref mut start,
+                ref mut end,
+            } => {
+                self.expression(target);
+
+                if let Some(start) = start {
+                    self.expression(start);
+                }
+
+                if let Some(end) = end {
+                    self.expression(end);
+                }
+            }
+            Expression::SetSubScript {
+                ref mut target,
+                ref mut index,
+                ref mut value,
+                ..
+            } => {
+                self.expression(target);
+                self.expression(index);
+                self.expression(value);
+            }
+            Expression::Literal(_) => (),
+            Expression::Set {
+                ref mut object,
+                ref mut value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(value);
+            }
+            Expression::Ternary {
+                ref mut condition,
+                ref mut then_branch,
+                ref mut else_branch,
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expression::Unary { ref mut expr, .. } => self.expression(expr),
+            Expression::Var(ref mut name) => self.value(name),
+            Expression::Variant {
+                ref mut enum_name,
+                ref mut inner,
+                ..
+            } => {
+                self.type_name(enum_name);
+
+                if let Some(inner) = inner {
+                    self.expression(inner);
+                }
+            }
+        }
+    }
+
+    fn arm(&mut self, arm: &mut MatchArm) {
+        self.locals.push(HashSet::new());
+
+        self.pattern(&mut arm.pattern);
+        self.statement(&mut arm.body);
+
+        self.locals.pop();
+    }
+
+    fn pattern(&mut self, pattern: &mut Spanned<Pattern>) {
+        match pattern.value {
+            Pattern::Wildcard | Pattern::Literal(_) => (),
+            Pattern::Binding(ref name) => self.declare(name.value),
+            Pattern::Variant {
+                ref mut enum_name,
+                ref mut inner,
+                ..
+            } => {
+                self.type_name(enum_name);
+
+                if let Some(inner) = inner {
+                    self.pattern(inner);
+                }
+            }
+        }
+    }
+
+    fn ty(&mut self, ty: &mut Spanned<Type>) {
+        match ty.value {
+            Type::Simple(ref mut name) => self.type_name(name),
+            Type::Arr(ref mut inner) => self.ty(inner),
+            Type::Func(ref mut params, ref mut returns) => {
+                for param in params.iter_mut() {
+                    self.ty(param);
+                }
+
+                if let Some(returns) = returns {
+                    self.ty(returns);
+                }
+            }
+            Type::Nil => (),
+            Type::Generic(ref mut name, ref mut types) => {
+                self.type_name(name);
+
+                for ty in types.iter_mut() {
+                    self.ty(ty);
+                }
+            }
+        }
+    }
+}
+
+/// The path with every link and relative part resolved so the same file is always given
+/// the same path. A file that doesn't exist keeps the path it was given
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
@@ -2,6 +2,24 @@ use crate::parser::{Parser, ParserResult};
 use crate::token::{Token, TokenType};
 use util::pos::{Position, Span, Spanned};
 
+/// A piece of the input along with what the lexer made of it. Unlike the tokens the
+/// lexemes include the comments and whitespace so joining their text gives back the input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lexeme<'a> {
+    pub kind: LexemeKind,
+    pub text: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LexemeKind {
+    Token,
+    /// A `//` or a `/* */` comment
+    Comment,
+    Whitespace,
+    /// Chars that the lexer reported an error for
+    Unknown,
+}
+
 impl<'a> Parser<'a> {
     /// Function that gets the next token and puts it within the VecDeque whilst returning the
     /// last token
@@ -15,14 +33,117 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Lexes the rest of the input and returns a description of each token up to and
+    /// including the EOF
+    pub fn tokens(&mut self) -> ParserResult<Vec<Spanned<String>>> {
+        let mut tokens = Vec::new();
+
+        loop {
+            let token = self.next()?;
+            let eof = token.value.token == TokenType::EOF;
+
+            tokens.push(Spanned::new(format!("{:?}", token.value.token), token.span));
+
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    /// Lexes the rest of the input and returns every lexeme of the input, including those
+    /// that were lexed before. The span of a lexeme ends on the char after it
+    pub fn lossless_tokens(&mut self) -> ParserResult<Vec<Spanned<Lexeme<'a>>>> {
+        while self.next()?.value.token != TokenType::EOF {}
+
+        Ok(self
+            .lexemes
+            .iter()
+            .map(|lexeme| {
+                let text = self.slice(lexeme.span.start, lexeme.span.end);
+
+                Spanned::new(
+                    Lexeme {
+                        kind: lexeme.value,
+                        text,
+                    },
+                    lexeme.span,
+                )
+            })
+            .collect())
+    }
+
     pub(crate) fn next_token(&mut self) -> ParserResult<Spanned<Token<'a>>> {
+        let token = self.lex()?;
+
+        self.record(LexemeKind::Token, token.span.start);
+
+        Ok(token)
+    }
+
+    /// Records the input from the start to the current position as a lexeme. Whitespace
+    /// that follows whitespace is added to it
+    fn record(&mut self, kind: LexemeKind, start: Position) {
+        if start.absolute == self.end.absolute {
+            return;
+        }
+
+        if let Some(last) = self.lexemes.last_mut() {
+            if kind == LexemeKind::Whitespace
+                && last.value == kind
+                && last.span.end.absolute == start.absolute
+            {
+                last.span.end = self.end;
+                return;
+            }
+        }
+
+        self.lexemes.push(Spanned::new(
+            kind,
+            Span {
+                start,
+                end: self.end,
+            },
+        ));
+    }
+
+    fn lex(&mut self) -> ParserResult<Spanned<Token<'a>>> {
         while let Some((start, ch)) = self.advance() {
             return match ch {
-                '.' => Ok(span(TokenType::DOT, start)),
+                '.' => {
+                    if self.peek(|ch| ch == '.') {
+                        self.advance();
+                        Ok(spans(TokenType::DOTDOT, start, start.shift('.')))
+                    } else {
+                        Ok(span(TokenType::DOT, start))
+                    }
+                }
                 '?' => Ok(span(TokenType::QUESTION, start)),
                 ';' => Ok(span(TokenType::SEMICOLON, start)),
-                '{' => Ok(span(TokenType::LBRACE, start)),
-                '}' => Ok(span(TokenType::RBRACE, start)),
+                '{' => {
+                    if let Some(depth) = self.holes.last_mut() {
+                        *depth += 1;
+                    }
+
+                    Ok(span(TokenType::LBRACE, start))
+                }
+                '}' => match self.holes.last_mut() {
+                    Some(0) => {
+                        self.holes.pop();
+
+                        match self.string_literal(start, true) {
+                            Ok(token) => Ok(token),
+                            Err(_) => {
+                                self.record(LexemeKind::Unknown, start);
+                                continue;
+                            }
+                        }
+                    }
+                    Some(depth) => {
+                        *depth -= 1;
+                        Ok(span(TokenType::RBRACE, start))
+                    }
+                    None => Ok(span(TokenType::RBRACE, start)),
+                },
                 '[' => Ok(span(TokenType::LBRACKET, start)),
                 ']' => Ok(span(TokenType::RBRACKET, start)),
                 '(' => Ok(span(TokenType::LPAREN, start)),
@@ -107,9 +228,10 @@ impl<'a> Parser<'a> {
                     }
                 }
 
-                '"' => match self.string_literal(start) {
+                '"' => match self.string_literal(start, false) {
                     Ok(token) => Ok(token),
                     Err(_) => {
+                        self.record(LexemeKind::Unknown, start);
                         continue; // error is reported in the function
                     }
                 },
@@ -121,9 +243,11 @@ impl<'a> Parser<'a> {
                     } else if self.peek(|ch| ch == '/') {
                         self.advance();
                         self.line_comment(start);
+                        self.record(LexemeKind::Comment, start);
                         continue;
                     } else if self.peek(|ch| ch == '*') {
                         self.block_comment()?;
+                        self.record(LexemeKind::Comment, start);
                         continue;
                     } else {
                         Ok(span(TokenType::SLASH, start))
@@ -131,11 +255,22 @@ impl<'a> Parser<'a> {
                 }
 
                 ch if ch.is_numeric() => self.number(start),
+                'r' if self.peek(|ch| ch == '"') => match self.raw_string_literal(start) {
+                    Ok(token) => Ok(token),
+                    Err(_) => {
+                        self.record(LexemeKind::Unknown, start);
+                        continue;
+                    }
+                },
                 ch if is_letter_ch(ch) => Ok(self.identifier(start)),
-                ch if ch.is_whitespace() => continue,
+                ch if ch.is_whitespace() => {
+                    self.record(LexemeKind::Whitespace, start);
+                    continue;
+                }
                 ch => {
                     let msg = format!("Unexpected char {} on {}", ch, start);
                     self.error(msg, start);
+                    self.record(LexemeKind::Unknown, start);
                     continue;
                 }
             };
@@ -194,6 +329,7 @@ impl<'a> Parser<'a> {
 
     /// Reporter an error at the given character
     pub(crate) fn error<T: Into<String>>(&mut self, msg: T, pos: Position) {
+        self.reported_error = true;
         self.reporter.error(
             msg.into(),
             Span {
@@ -210,19 +346,16 @@ impl<'a> Parser<'a> {
         start: Position,
         end: Position,
     ) {
+        self.reported_error = true;
         self.reporter.error(msg.into(), Span { start, end })
     }
 
     /// Reporter an error with the given span
     pub(crate) fn span_error<T: Into<String>>(&mut self, msg: T, span: Span) {
+        self.reported_error = true;
         self.reporter.error(msg.into(), span)
     }
 
-    /// Reporter an error with the given span
-    pub(crate) fn span_warn<T: Into<String>>(&mut self, msg: T, span: Span) {
-        self.reporter.warn(msg.into(), span)
-    }
-
     /// Method that handles a line comment
     pub(crate) fn line_comment(&mut self, start: Position) {
         let (_, _) = self.take_whilst(start, |ch| ch != '\n');
@@ -254,39 +387,155 @@ impl<'a> Parser<'a> {
         }
     }
 
-    /// Handles a string.
-    pub(crate) fn string_literal(&mut self, start: Position) -> ParserResult<Spanned<Token<'a>>> {
+    /// Handles a string. The escapes in it are replaced with the chars they stand for. A bad
+    /// escape is reported and left out of the string, which is still returned so that the
+    /// parser doesn't report more errors because of it.
+    ///
+    /// A `${` ends the token and the expression in it is lexed as usual until the `}` that
+    /// closes it, where the rest of the string is lexed as a `continued` string
+    pub(crate) fn string_literal(
+        &mut self,
+        start: Position,
+        continued: bool,
+    ) -> ParserResult<Spanned<Token<'a>>> {
         let mut string = String::new();
-        let mut last = None; // placement value
+        let mut last = start; // placement value
 
         while let Some((next, ch)) = self.advance() {
             match ch {
                 '"' => {
                     let end = next.shift(ch);
 
-                    string.push('\0');
+                    let token = if continued {
+                        TokenType::STRINGEND(string)
+                    } else {
+                        TokenType::STRING(string)
+                    };
 
-                    return Ok(spans(TokenType::STRING(string), start, end));
+                    return Ok(spans(token, start, end));
+                }
+
+                '$' if self.peek(|ch| ch == '{') => {
+                    let (open, _) = self.advance().unwrap();
+
+                    self.holes.push(0);
+
+                    let token = if continued {
+                        TokenType::STRINGMIDDLE(string)
+                    } else {
+                        TokenType::STRINGSTART(string)
+                    };
+
+                    return Ok(spans(token, start, open.shift('{')));
+                }
+
+                '\\' => {
+                    last = next;
+
+                    if let Some(ch) = self.escape(next) {
+                        string.push(ch)
+                    }
                 }
 
                 ch => {
-                    last = Some(next); // the last thing in the string
+                    last = next; // the last thing in the string
                     string.push(ch)
                 }
             }
         }
 
-        self.error("Unclosed string", last.unwrap()); // has to be the end as we keep on adding to our string till we reach the end
+        self.error("Unclosed string", last); // has to be the end as we keep on adding to our string till we reach the end
 
         Err(())
     }
 
+    /// Handles a raw string `r"..."`, in which a `\` is just a `\`
+    pub(crate) fn raw_string_literal(
+        &mut self,
+        start: Position,
+    ) -> ParserResult<Spanned<Token<'a>>> {
+        self.advance(); // Eats the '"'
+
+        let (end, string) = self.take_whilst(start.shift('r').shift('"'), |ch| ch != '"');
+
+        match self.advance() {
+            Some((_, '"')) => Ok(spans(
+                TokenType::STRING(string.to_owned()),
+                start,
+                end.shift('"'),
+            )),
+            _ => {
+                self.error("Unclosed string", start);
+                Err(())
+            }
+        }
+    }
+
+    /// Handles the escape after the `\` at the given position, returning the char it stands
+    /// for. `\u{..}` is the unicode char with the hex code between the braces
+    fn escape(&mut self, backslash: Position) -> Option<char> {
+        let (pos, ch) = self.advance()?;
+
+        let ch = match ch {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+            '\'' => '\'',
+            'u' => return self.unicode_escape(backslash),
+            ch => {
+                let msg = format!("Unknown escape `\\{}`", ch);
+                self.spanned_error(msg, backslash, pos);
+                return None;
+            }
+        };
+
+        Some(ch)
+    }
+
+    fn unicode_escape(&mut self, backslash: Position) -> Option<char> {
+        if !self.peek(|ch| ch == '{') {
+            let end = self.start;
+            self.spanned_error("Expected a `{` after `\\u`", backslash, end);
+            return None;
+        }
+
+        let (open, _) = self.advance()?;
+        let (_, hex) = self.take_whilst(open.shift('{'), |ch| ch.is_ascii_hexdigit());
+
+        let ch = if self.peek(|ch| ch == '}') {
+            self.advance();
+
+            u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() <= 6)
+                .and_then(::std::char::from_u32)
+        } else {
+            None
+        };
+
+        if ch.is_none() {
+            let msg = format!(
+                "`{}` is not a valid unicode escape",
+                self.slice(backslash, self.end)
+            );
+            let end = self.start;
+            self.spanned_error(msg, backslash, end);
+        }
+
+        ch
+    }
+
     /// Handles number,both ints and floats
     pub(crate) fn number(&mut self, start: Position) -> ParserResult<Spanned<Token<'a>>> {
         let (end, int) = self.take_whilst(start, char::is_numeric);
 
         let (token, start, end) = match self.lookahead {
-            Some((_, '.')) => {
+            // `1..2` is a range rather than the float `1.`
+            Some((pos, '.')) if !self.input[pos.absolute + 1..].starts_with('.') => {
                 self.advance();
 
                 let (end, float) = self.take_whilst(start, char::is_numeric);
@@ -366,6 +615,7 @@ fn look_up_identifier(id: &str) -> TokenType {
         "as" => TokenType::AS,
         "match" => TokenType::MATCH,
         "enum" => TokenType::ENUM,
+        "import" => TokenType::IMPORT,
         // Functions and vars
         "fn" => TokenType::FUNCTION,
         "let" => TokenType::LET,
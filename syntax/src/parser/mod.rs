@@ -1,11 +1,12 @@
 mod lexer;
 
+pub use self::lexer::{Lexeme, LexemeKind};
+
 use crate::ast::*;
 use crate::token::{Token, TokenType};
-use rand::{self, Rng};
 use std::collections::VecDeque;
 use util::emmiter::Reporter;
-use util::pos::{CharPosition, Position, Span, Spanned, EMPTYSPAN};
+use util::pos::{CharPosition, FileId, Position, Span, Spanned, EMPTYSPAN};
 use util::symbol::{Symbol, Symbols};
 
 pub type ParserResult<T> = Result<T, ()>;
@@ -30,11 +31,30 @@ pub struct Parser<'a> {
     parsing_cond: bool,
     /// Flag that manages whetere we are in a match_arm
     parsing_match_arm: bool,
+    /// Every piece of the input that has been lexed
+    lexemes: Vec<Spanned<LexemeKind>>,
+    /// The number of unclosed `{` within each `${` of a string that is being lexed,
+    /// innermost last. The string carries on at the `}` that closes the `${`
+    holes: Vec<usize>,
+    /// Set whenever an error is reported, including those that are recovered from without
+    /// failing the current rule, such as an unknown escape or too many params
+    reported_error: bool,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str, reporter: Reporter, symbols: &'a mut Symbols<()>) -> Self {
-        let mut chars = CharPosition::new(input);
+        Self::with_file(input, FileId::default(), reporter, symbols)
+    }
+
+    /// A parser for one of the files of a program. The spans it creates point into the
+    /// file
+    pub fn with_file(
+        input: &'a str,
+        file: FileId,
+        reporter: Reporter,
+        symbols: &'a mut Symbols<()>,
+    ) -> Self {
+        let mut chars = CharPosition::with_file(input, file);
         let end = chars.pos;
         let mut past_tokens = VecDeque::new();
 
@@ -49,6 +69,9 @@ impl<'a> Parser<'a> {
             symbols,
             parsing_cond: false,
             parsing_match_arm: false,
+            lexemes: Vec::new(),
+            holes: Vec::new(),
+            reported_error: false,
         };
 
         past_tokens.push_back(parser.next().unwrap());
@@ -63,6 +86,9 @@ impl<'a> Parser<'a> {
             functions: Vec::new(),
             aliases: Vec::new(),
             enums: Vec::new(),
+            statements: Vec::new(),
+            imports: Vec::new(),
+            files: vec![self.start.file],
         };
 
         let mut had_error = false;
@@ -101,13 +127,26 @@ impl<'a> Parser<'a> {
                         self.synchronize()?;
                     }
                 }
+            } else if self.recognise(TokenType::IMPORT) {
+                match self.parse_import() {
+                    Ok(import) => program.imports.push(import),
+                    Err(_) => {
+                        had_error = true;
+                        self.synchronize()?;
+                    }
+                }
             } else {
-                self.synchronize()?;
-                had_error = true;
+                match self.parse_statement() {
+                    Ok(statement) => program.statements.push(statement),
+                    Err(_) => {
+                        had_error = true;
+                        self.synchronize()?;
+                    }
+                }
             }
         }
 
-        if had_error {
+        if had_error || self.reported_error {
             Err(())
         } else {
             Ok(program)
@@ -248,13 +287,10 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn random_ident(&mut self) -> Symbol {
-        let mut rng = rand::thread_rng();
-        let letter: char = rng.gen_range(b'A', b'Z') as char;
-        let number: u32 = rng.gen_range(0, 999_999);
-        let s = format!("{}{:06}", letter, number);
-
-        self.symbols.symbol(&s)
+    /// Names a closure after where it starts so the same source always gets the same names
+    fn closure_name(&mut self, start: Position) -> Symbol {
+        self.symbols
+            .symbol(&format!("closure@{}:{}", start.line, start.column))
     }
 
     fn recognise(&mut self, expected: TokenType) -> bool {
@@ -441,18 +477,57 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parses `import "path/to/file.tox";` or `import file;`
+    fn parse_import(&mut self) -> ParserResult<Spanned<String>> {
+        let open_span = self.consume_get_span(&TokenType::IMPORT, "Expected 'import' ")?;
+
+        let path = match self.next()? {
+            Spanned {
+                value:
+                    Token {
+                        token: TokenType::STRING(ref path),
+                    },
+                ..
+            } => path.clone(),
+            Spanned {
+                value:
+                    Token {
+                        token: TokenType::IDENTIFIER(name),
+                    },
+                ..
+            } => format!("{}.tox", name),
+            Spanned {
+                value: Token { ref token },
+                span,
+            } => {
+                let msg = format!("Expected a path or a name but instead found `{}`", token);
+                self.span_error(msg, span);
+                return Err(());
+            }
+        };
+
+        let close_span = self.consume_get_span(&TokenType::SEMICOLON, "Expected ';' ")?;
+
+        Ok(Spanned {
+            span: open_span.to(close_span),
+            value: path,
+        })
+    }
+
     fn parse_type(&mut self) -> ParserResult<Spanned<Type>> {
         if self.recognise(TokenType::NIL) {
             Ok(Spanned {
                 value: Type::Nil,
                 span: self.consume_get_span(&TokenType::NIL, "Expected 'nil' ")?,
             })
-        } else if self.recognise(TokenType::RPAREN) {
-            self.next()?;
+        } else if self.recognise(TokenType::LBRACKET) {
+            let open_span = self.consume_get_span(&TokenType::LBRACKET, "Expected '[' ")?;
             let ty = self.parse_type()?;
+            let close_span = self.consume_get_span(&TokenType::RBRACKET, "Expected ']' ")?;
+
             Ok(Spanned {
                 value: Type::Arr(Box::new(ty)),
-                span: self.consume_get_span(&TokenType::RBRACKET, "Expected ']' ")?,
+                span: open_span.to(close_span),
             })
         } else if self.recognise(TokenType::FUNCTION) {
             let open_span = self.consume_get_span(&TokenType::FUNCTION, "Expected 'fun' ")?;
@@ -603,6 +678,23 @@ impl<'a> Parser<'a> {
      * STATEMENT PARSERS
      *
      * ***************** */
+
+    /// Parses statements until the end of the input. Used by the repl where the input is
+    /// a list of statements rather than a program
+    pub fn parse_statements(&mut self) -> ParserResult<Vec<Spanned<Statement>>> {
+        let mut statements = vec![];
+
+        while !self.recognise(TokenType::EOF) {
+            statements.push(self.parse_statement()?);
+        }
+
+        if self.reported_error {
+            return Err(());
+        }
+
+        Ok(statements)
+    }
+
     pub fn parse_statement(&mut self) -> ParserResult<Spanned<Statement>> {
         if self.recognise(TokenType::LBRACE) {
             self.parse_block()
@@ -854,7 +946,7 @@ impl<'a> Parser<'a> {
      *
      * ***************** */
 
-    fn parse_expression(&mut self) -> ParserResult<Spanned<Expression>> {
+    pub fn parse_expression(&mut self) -> ParserResult<Spanned<Expression>> {
         self.assignment()
     }
 
@@ -905,6 +997,21 @@ impl<'a> Parser<'a> {
                     });
                 }
 
+                Spanned {
+                    span,
+                    value: Expression::SubScript { target, index },
+                } => {
+                    return Ok(Spanned {
+                        span: span.to(value.get_span()),
+                        value: Expression::SetSubScript {
+                            target,
+                            index,
+                            kind,
+                            value: Box::new(value),
+                        },
+                    });
+                }
+
                 Spanned { ref span, .. } => {
                     self.span_error("Not a valid assingment target", *span);
                     return Err(());
@@ -1009,12 +1116,7 @@ impl<'a> Parser<'a> {
 
         binary!(
             self,
-            vec![
-                TokenType::PLUS,
-                TokenType::MINUS,
-                TokenType::EXPONENTIAL,
-                TokenType::MODULO
-            ],
+            vec![TokenType::PLUS, TokenType::MINUS],
             lhs,
             parse_multiplication
         );
@@ -1027,7 +1129,7 @@ impl<'a> Parser<'a> {
 
         binary!(
             self,
-            vec![TokenType::SLASH, TokenType::STAR],
+            vec![TokenType::SLASH, TokenType::STAR, TokenType::MODULO],
             lhs,
             parse_unary
         );
@@ -1035,6 +1137,29 @@ impl<'a> Parser<'a> {
         Ok(lhs)
     }
 
+    /// `^` binds tighter than the other arithmetic operators, including a unary `-` so
+    /// `-2 ^ 2` is `-(2 ^ 2)`, and is right associative
+    fn parse_exponent(&mut self) -> ParserResult<Spanned<Expression>> {
+        let lhs = self.call()?;
+
+        if self.recognise(TokenType::EXPONENTIAL) {
+            let op = self.get_binary_op()?;
+
+            let rhs = self.parse_unary()?;
+
+            return Ok(Spanned {
+                span: lhs.get_span().to(rhs.get_span()),
+                value: Expression::Binary {
+                    lhs: Box::new(lhs),
+                    op,
+                    rhs: Box::new(rhs),
+                },
+            });
+        }
+
+        Ok(lhs)
+    }
+
     fn parse_unary(&mut self) -> ParserResult<Spanned<Expression>> {
         if self.recognise(TokenType::BANG) || self.recognise(TokenType::MINUS) {
             let op = self.get_unary_op()?;
@@ -1050,7 +1175,49 @@ impl<'a> Parser<'a> {
             });
         }
 
-        self.call()
+        self.parse_exponent()
+    }
+
+    /// Parses the rest of a string that has a `${` in it, `first` being the text before it
+    fn parse_interpolation(&mut self, first: Spanned<String>) -> ParserResult<Spanned<Expression>> {
+        let start = first.span;
+        let mut segments = vec![Segment::Text(first)];
+
+        loop {
+            segments.push(Segment::Hole(self.parse_expression()?));
+
+            match self.next()? {
+                Spanned {
+                    value:
+                        Token {
+                            token: TokenType::STRINGMIDDLE(ref text),
+                        },
+                    span,
+                } => segments.push(Segment::Text(Spanned::new(text.clone(), span))),
+                Spanned {
+                    value:
+                        Token {
+                            token: TokenType::STRINGEND(ref text),
+                        },
+                    span,
+                } => {
+                    segments.push(Segment::Text(Spanned::new(text.clone(), span)));
+
+                    return Ok(Spanned::new(
+                        Expression::Interpolation(segments),
+                        start.to(span),
+                    ));
+                }
+                Spanned {
+                    value: Token { ref token },
+                    span,
+                } => {
+                    let msg = format!("Expected a `}}` to close the `${{` but found `{}`", token);
+                    self.span_error(msg, span);
+                    return Err(());
+                }
+            }
+        }
     }
 
     fn primary(&mut self) -> ParserResult<Spanned<Expression>> {
@@ -1075,6 +1242,9 @@ impl<'a> Parser<'a> {
                     span: *span,
                     value: Expression::Literal(Literal::Str(s.clone())),
                 }),
+                TokenType::STRINGSTART(ref s) => {
+                    self.parse_interpolation(Spanned::new(s.clone(), *span))
+                }
                 TokenType::INT(n) => Ok(Spanned {
                     span: *span,
                     value: Expression::Literal(Literal::Int(n)),
@@ -1164,48 +1334,12 @@ impl<'a> Parser<'a> {
         let open_span = self.consume_get_span(&TokenType::LBRACE, "Expected `{` ")?;
 
         let mut arms = Vec::new();
-        // counter for how many times we've seen the `_` pattern
-        let mut seen_catch_all = 0;
 
         if !self.recognise(TokenType::RBRACE) {
             loop {
                 self.parsing_match_arm = true;
 
-                if self.recognise(TokenType::UNDERSCORE) {
-                    seen_catch_all += 1;
-
-                    let pattern = self.consume_get_span(&TokenType::UNDERSCORE, "Expected `_` ")?;
-
-                    self.consume(&TokenType::MATCHARROW, "Expected `=>` ")?;
-
-                    let body = self.parse_statement()?;
-
-                    self.parsing_match_arm = false;
-
-                    if seen_catch_all > 1 {
-                        self.span_warn("`_` pattern is allready present", pattern.to(body.span));
-                    }
-
-                    let span = pattern.to(body.span);
-
-                    arms.push(Spanned {
-                        value: MatchArm {
-                            pattern: None,
-                            body,
-                            is_all: true,
-                        },
-                        span,
-                    });
-
-                    if self.recognise(TokenType::COMMA) {
-                        self.next()?;
-                        continue;
-                    } else {
-                        break;
-                    }
-                }
-
-                let pattern = self.parse_expression()?;
+                let pattern = self.parse_pattern()?;
 
                 self.consume(&TokenType::MATCHARROW, "Expected `=>` ")?;
 
@@ -1216,11 +1350,7 @@ impl<'a> Parser<'a> {
                 let span = pattern.span.to(body.span);
 
                 arms.push(Spanned {
-                    value: MatchArm {
-                        pattern: Some(pattern),
-                        body,
-                        is_all: false,
-                    },
+                    value: MatchArm { pattern, body },
                     span,
                 });
 
@@ -1243,6 +1373,120 @@ impl<'a> Parser<'a> {
         })
     }
 
+    fn parse_pattern(&mut self) -> ParserResult<Spanned<Pattern>> {
+        match self.next() {
+            Ok(Spanned {
+                ref span,
+                ref value,
+            }) => match value.token {
+                TokenType::UNDERSCORE => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Wildcard,
+                }),
+                TokenType::TRUE(_) => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Literal(Literal::True(true)),
+                }),
+                TokenType::FALSE(_) => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Literal(Literal::False(false)),
+                }),
+                TokenType::NIL => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Literal(Literal::Nil),
+                }),
+                TokenType::STRING(ref s) => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Literal(Literal::Str(s.clone())),
+                }),
+                TokenType::INT(n) => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Literal(Literal::Int(n)),
+                }),
+                TokenType::FLOAT(n) => Ok(Spanned {
+                    span: *span,
+                    value: Pattern::Literal(Literal::Float(n)),
+                }),
+
+                TokenType::MINUS => match self.next() {
+                    Ok(Spanned {
+                        span: ref number_span,
+                        value:
+                            Token {
+                                token: TokenType::INT(n),
+                            },
+                    }) => Ok(Spanned {
+                        span: span.to(*number_span),
+                        value: Pattern::Literal(Literal::Int(-n)),
+                    }),
+                    Ok(Spanned {
+                        span: ref number_span,
+                        value:
+                            Token {
+                                token: TokenType::FLOAT(n),
+                            },
+                    }) => Ok(Spanned {
+                        span: span.to(*number_span),
+                        value: Pattern::Literal(Literal::Float(-n)),
+                    }),
+                    Ok(Spanned {
+                        span: ref number_span,
+                        value: Token { ref token },
+                    }) => {
+                        let msg = format!("Expected a number but instead found `{}`", token);
+                        self.span_error(msg, *number_span);
+                        Err(())
+                    }
+                    Err(_) => Err(()),
+                },
+
+                TokenType::IDENTIFIER(ident) => {
+                    let ident = Spanned {
+                        value: self.symbols.symbol(ident),
+                        span: *span,
+                    };
+
+                    if !self.recognise(TokenType::NAMESPACE) {
+                        return Ok(Spanned {
+                            span: ident.span,
+                            value: Pattern::Binding(ident),
+                        });
+                    }
+
+                    self.next()?; // Eat the ::
+
+                    let variant = self.consume_get_symbol("Expected an identifier")?;
+                    let mut span = ident.span.to(variant.span);
+                    let mut inner = None;
+
+                    if self.recognise(TokenType::LPAREN) {
+                        self.next()?; // Eat the (
+                        inner = Some(Box::new(self.parse_pattern()?));
+                        span = span.to(self.consume_get_span(&TokenType::RPAREN, "Expected `)`")?);
+                    }
+
+                    Ok(Spanned {
+                        span,
+                        value: Pattern::Variant {
+                            enum_name: ident,
+                            variant,
+                            inner,
+                        },
+                    })
+                }
+
+                ref other => {
+                    let msg = format!("Expected a pattern but instead found `{}`", other);
+
+                    self.span_error(msg, *span);
+
+                    Err(())
+                }
+            },
+            Err(_) => Err(()),
+        }
+    }
+
     fn parse_closure(&mut self, open_span: Span) -> ParserResult<Spanned<Function>> {
         let params = self.parse_params(open_span, "closure")?;
 
@@ -1265,7 +1509,7 @@ impl<'a> Parser<'a> {
                     value: ItemName {
                         name: Spanned {
                             span: params_span,
-                            value: self.random_ident(),
+                            value: self.closure_name(open_span.start),
                         },
                         type_params: vec![],
                     },
@@ -1415,18 +1659,40 @@ impl<'a> Parser<'a> {
             } else if self.recognise(TokenType::LPAREN) {
                 expr = self.finish_call(expr)?;
             } else if self.recognise(TokenType::LBRACKET) {
-                let open_span = self.consume_get_span(&TokenType::LBRACKET, "Expected '[' ")?;
+                self.consume(&TokenType::LBRACKET, "Expected '[' ")?;
 
-                let index = Box::new(self.parse_expression()?);
+                let target_span = expr.get_span();
+                let target = Box::new(expr);
+
+                let start = if self.recognise(TokenType::DOTDOT) {
+                    None
+                } else {
+                    Some(Box::new(self.parse_expression()?))
+                };
+
+                let value = match start {
+                    Some(index) if !self.recognise(TokenType::DOTDOT) => {
+                        Expression::SubScript { target, index }
+                    }
+                    start => {
+                        self.consume(&TokenType::DOTDOT, "Expected '..' ")?;
+
+                        let end = if self.recognise(TokenType::RBRACKET) {
+                            None
+                        } else {
+                            Some(Box::new(self.parse_expression()?))
+                        };
+
+                        Expression::Slice { target, start, end }
+                    }
+                };
 
                 let close_span = self.consume_get_span(&TokenType::RBRACKET, "Expected ']' ")?;
-                return Ok(Spanned {
-                    span: open_span.to(close_span),
-                    value: Expression::SubScript {
-                        target: Box::new(expr),
-                        index,
-                    },
-                });
+
+                expr = Spanned {
+                    span: target_span.to(close_span),
+                    value,
+                };
             } else if self.recognise(TokenType::DOT) {
                 self.next()?;
 
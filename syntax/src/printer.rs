@@ -0,0 +1,1045 @@
+//! Prints a program in the standard style of tox.
+//!
+//! The program is printed from its syntax tree so the layout of the source is thrown away
+//! apart from the blank lines between statements, of which at most one is kept. Comments
+//! are put back before the statement or item that follows them, or at the end of the line
+//! if they were at the end of a line in the source. Expressions are kept on one line,
+//! except for an array with a comment within it which has an item on each line, and a
+//! comment within any other expression is moved to after its statement.
+//! Printing a program that has already been printed gives the same program back.
+use crate::ast::*;
+use crate::parser::{LexemeKind, Parser, ParserResult};
+use util::emmiter::Reporter;
+use util::pos::{Position, Span, Spanned};
+use util::symbol::{Symbol, Symbols};
+
+const INDENT: &str = "    ";
+
+/// Formats the source of a program. Any error in the source is reported and the source
+/// is left alone
+pub fn format(input: &str, reporter: Reporter, symbols: &mut Symbols<()>) -> ParserResult<String> {
+    let (program, comments) = {
+        let mut parser = Parser::new(input, reporter.clone(), symbols);
+
+        let program = parser.parse()?;
+
+        let comments: Vec<Span> = parser
+            .lossless_tokens()?
+            .into_iter()
+            .filter(|lexeme| lexeme.value.kind == LexemeKind::Comment)
+            .map(|lexeme| lexeme.span)
+            .collect();
+
+        (program, comments)
+    };
+
+    let comments = comments
+        .into_iter()
+        .map(|span| Spanned::new(&input[span.start.absolute..span.end.absolute], span))
+        .collect();
+
+    let mut printer = Printer {
+        input,
+        symbols,
+        comments,
+        next_comment: 0,
+        last_line: None,
+        blank: false,
+        indent: 0,
+        out: String::new(),
+    };
+
+    printer.program(&program);
+
+    Ok(printer.out)
+}
+
+/// Anything that is printed on its own line
+enum Entry<'a> {
+    Import(&'a Spanned<String>),
+    Alias(&'a Spanned<TypeAlias>),
+    Enum(&'a Spanned<Enum>),
+    Class(&'a Spanned<Class>),
+    Function(&'a Spanned<Function>),
+    Statement(&'a Spanned<Statement>),
+}
+
+struct Printer<'a> {
+    input: &'a str,
+    symbols: &'a Symbols<()>,
+    comments: Vec<Spanned<&'a str>>,
+    /// The first comment that hasn't been printed
+    next_comment: usize,
+    /// The line of the source that the last thing printed ended on. `None` at the start
+    /// of a block
+    last_line: Option<u32>,
+    /// Whether a blank line is left before the next thing printed
+    blank: bool,
+    indent: usize,
+    out: String,
+}
+
+impl<'a> Printer<'a> {
+    fn program(&mut self, program: &'a Program) {
+        let mut entries: Vec<Entry> = Vec::new();
+
+        entries.extend(program.imports.iter().map(Entry::Import));
+        entries.extend(program.aliases.iter().map(Entry::Alias));
+        entries.extend(program.enums.iter().map(Entry::Enum));
+        entries.extend(program.classes.iter().map(Entry::Class));
+        entries.extend(program.functions.iter().map(Entry::Function));
+        entries.extend(program.statements.iter().map(Entry::Statement));
+
+        entries.sort_by_key(|entry| entry.span().start.absolute);
+
+        let mut last_item = false;
+
+        for (i, entry) in entries.iter().enumerate() {
+            // functions, classes and enums are always kept apart from what is around them
+            let item = matches!(
+                *entry,
+                Entry::Enum(_) | Entry::Class(_) | Entry::Function(_)
+            );
+
+            if item || last_item {
+                self.force_blank_line();
+            }
+
+            last_item = item;
+
+            let span = entry.span();
+
+            self.leading_comments(span.start);
+
+            match *entry {
+                Entry::Import(import) => self.import(import),
+                Entry::Alias(alias) => self.alias(&alias.value),
+                Entry::Enum(e) => self.enumeration(e),
+                Entry::Class(class) => self.class(class),
+                Entry::Function(function) => self.function(&function.value),
+                Entry::Statement(statement) => self.statement(statement),
+            }
+
+            let next = entries.get(i + 1).map(|next| next.span().start);
+
+            self.trailing_comments(span.end, next);
+            self.newline();
+        }
+
+        while self.next_comment < self.comments.len() {
+            self.comment();
+        }
+    }
+
+    fn import(&mut self, import: &Spanned<String>) {
+        let written = &self.input[import.span.start.absolute + "import".len()..];
+
+        // `import math;` was written rather than `import "math.tox";`
+        let name = if written.trim_start().starts_with('"') {
+            None
+        } else {
+            import
+                .value
+                .strip_suffix(".tox")
+                .filter(|name| name.chars().all(|ch| ch.is_alphanumeric() || ch == '_'))
+        };
+
+        match name {
+            Some(name) => self.write(&format!("import {};", name)),
+            None => self.write(&format!("import \"{}\";", import.value.escape_debug())),
+        }
+    }
+
+    fn alias(&mut self, alias: &TypeAlias) {
+        let name = self.item_name(&alias.alias.value);
+        let ty = self.ty(&alias.ty.value);
+
+        self.write(&format!("type {} = {};", name, ty));
+    }
+
+    fn enumeration(&mut self, e: &Spanned<Enum>) {
+        let span = e.span;
+        let e = &e.value;
+        let name = self.item_name(&e.name.value);
+
+        self.write(&format!("enum {} {{", name));
+
+        if e.variants.is_empty() && !self.comment_before(span.end) {
+            self.write("}");
+            return;
+        }
+
+        let first = e.variants.first().map(|variant| variant.name.span.start);
+        self.open(e.name.span.end, first.unwrap_or(span.end));
+
+        for (i, variant) in e.variants.iter().enumerate() {
+            self.leading_comments(variant.name.span.start);
+
+            self.write(&self.name(variant.name.value));
+
+            if let Some(ref inner) = variant.inner {
+                let inner = self.ty(&inner.value);
+                self.write(&format!("({})", inner));
+            }
+
+            if i + 1 < e.variants.len() {
+                self.write(",");
+            }
+
+            let end = variant
+                .inner
+                .as_ref()
+                .map_or(variant.name.span, |inner| inner.span);
+
+            let next = e
+                .variants
+                .get(i + 1)
+                .map_or(span.end, |next| next.name.span.start);
+
+            self.trailing_comments(end.end, Some(next));
+            self.newline();
+        }
+
+        self.close(span.end, '}');
+    }
+
+    fn class(&mut self, class: &Spanned<Class>) {
+        let span = class.span;
+        let class = &class.value;
+        let name = self.item_name(&class.name.value);
+
+        self.write(&format!("class {}", name));
+
+        if let Some(ref superclass) = class.superclass {
+            self.write(&format!(" extends {}", self.name(superclass.value)));
+        }
+
+        self.write(" {");
+
+        if class.fields.is_empty() && class.methods.is_empty() && !self.comment_before(span.end) {
+            self.write("}");
+            return;
+        }
+
+        let first = class
+            .fields
+            .first()
+            .map(|field| field.span.start)
+            .or_else(|| class.methods.first().map(|method| method.span.start));
+        self.open(class.name.span.end, first.unwrap_or(span.end));
+
+        if let (Some(first), Some(last)) = (class.fields.first(), class.fields.last()) {
+            self.leading_comments(first.span.start);
+
+            let fields: Vec<String> = class
+                .fields
+                .iter()
+                .map(|field| {
+                    format!(
+                        "{}: {}",
+                        self.name(field.value.name.value),
+                        self.ty(&field.value.ty.value)
+                    )
+                })
+                .collect();
+
+            // the fields of a class are declared together
+            self.write(&format!("{};", fields.join(", ")));
+            let next = class
+                .methods
+                .first()
+                .map_or(span.end, |method| method.span.start);
+
+            self.trailing_comments(last.span.end, Some(next));
+            self.newline();
+        }
+
+        for (i, method) in class.methods.iter().enumerate() {
+            // the methods are kept apart from the fields
+            if i == 0 {
+                self.force_blank_line();
+            }
+
+            self.leading_comments(method.span.start);
+            self.function(&method.value);
+            let next = class
+                .methods
+                .get(i + 1)
+                .map_or(span.end, |next| next.span.start);
+
+            self.trailing_comments(method.span.end, Some(next));
+            self.newline();
+        }
+
+        self.close(span.end, '}');
+    }
+
+    fn function(&mut self, function: &Function) {
+        let name = self.item_name(&function.name.value);
+        let params = self.params(&function.params.value);
+
+        self.write(&format!("fn {}({})", name, params));
+
+        if let Some(ref returns) = function.returns {
+            self.write(&format!(" -> {}", self.ty(&returns.value)));
+        }
+
+        self.write(" ");
+        self.statement(&function.body);
+    }
+
+    fn params(&self, params: &[Spanned<FunctionParam>]) -> String {
+        params
+            .iter()
+            .map(|param| {
+                format!(
+                    "{}: {}",
+                    self.name(param.value.name.value),
+                    self.ty(&param.value.ty.value)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>) {
+        match statement.value {
+            Statement::Block(ref statements) => self.block(statements, statement.span),
+            Statement::Break => self.write("break;"),
+            Statement::Continue => self.write("continue;"),
+            Statement::Expr(ref expr) => {
+                let expr = self.expression(expr);
+                self.write(&format!("{};", expr));
+            }
+            Statement::For {
+                ref init,
+                ref cond,
+                ref incr,
+                ref body,
+            } => {
+                self.write("for (");
+
+                match *init {
+                    Some(ref init) => self.statement(init),
+                    None => self.write(";"),
+                }
+
+                if let Some(ref cond) = *cond {
+                    let cond = self.expression(cond);
+                    self.write(&format!(" {}", cond));
+                }
+
+                self.write(";");
+
+                if let Some(ref incr) = *incr {
+                    let incr = self.expression(incr);
+                    self.write(&format!(" {}", incr));
+                }
+
+                self.write(")");
+                self.body(body);
+            }
+            Statement::If {
+                ref cond,
+                ref then,
+                ref otherwise,
+            } => {
+                let cond = self.expression(cond);
+                self.write(&format!("if {}", cond));
+                self.body(then);
+
+                if let Some(ref otherwise) = *otherwise {
+                    let comment = self.comment_after(end(then), start(otherwise));
+
+                    if is_block(then) && !comment {
+                        self.write(" else");
+                    } else {
+                        self.newline();
+                        self.write("else");
+                    }
+
+                    match otherwise.value {
+                        Statement::If { .. } => {
+                            self.write(" ");
+                            self.statement(otherwise);
+                        }
+                        _ => self.body(otherwise),
+                    }
+                }
+            }
+            Statement::Print(ref expr) => {
+                let text = self.expression(expr);
+
+                // `print(a)` reads like a call so the group isn't set apart
+                match expr.value {
+                    Expression::Grouping { .. } => self.write(&format!("print{};", text)),
+                    _ => self.write(&format!("print {};", text)),
+                }
+            }
+            Statement::While { ref cond, ref body } => {
+                let cond_start = cond.span.start;
+                let cond = self.expression(cond);
+
+                // the parser turns `do body while cond` into a while loop
+                if self.input[statement.span.start.absolute..].starts_with("do") {
+                    self.write("do");
+                    self.body(body);
+
+                    let comment = self.comment_after(end(body), cond_start);
+
+                    if is_block(body) && !comment {
+                        self.write(&format!(" while {}", cond));
+                    } else {
+                        self.newline();
+                        self.write(&format!("while {}", cond));
+                    }
+                } else {
+                    self.write(&format!("while {}", cond));
+                    self.body(body);
+                }
+            }
+            Statement::VarDeclaration {
+                ref ident,
+                ref ty,
+                ref expr,
+            } => {
+                self.write(&format!("let {}", self.name(ident.value)));
+
+                if let Some(ref ty) = *ty {
+                    self.write(&format!(": {}", self.ty(&ty.value)));
+                }
+
+                if let Some(ref expr) = *expr {
+                    let expr = self.expression(expr);
+                    self.write(&format!(" = {}", expr));
+                }
+
+                self.write(";");
+            }
+            Statement::Return(ref expr) => {
+                // `return;` returns a nil that is given the span of the `;`
+                if expr.span.start.absolute == statement.span.end.absolute {
+                    self.write("return;");
+                } else {
+                    let expr = self.expression(expr);
+                    self.write(&format!("return {};", expr));
+                }
+            }
+        }
+    }
+
+    /// The body of an `if` or a loop. A block follows on the same line and any other
+    /// statement goes on the next line
+    fn body(&mut self, body: &Spanned<Statement>) {
+        if is_block(body) {
+            self.write(" ");
+            self.statement(body);
+        } else {
+            self.indent += 1;
+            self.newline();
+            self.statement(body);
+            self.indent -= 1;
+        }
+    }
+
+    fn block(&mut self, statements: &[Spanned<Statement>], span: Span) {
+        self.write("{");
+
+        if statements.is_empty() && !self.comment_before(span.end) {
+            self.write("}");
+            return;
+        }
+
+        self.open(span.start, statements.first().map_or(span.end, start));
+
+        for (i, statement) in statements.iter().enumerate() {
+            let next = statements.get(i + 1).map_or(span.end, start);
+
+            self.leading_comments(start(statement));
+            self.statement(statement);
+            self.trailing_comments(end(statement), Some(next));
+            self.newline();
+        }
+
+        self.close(span.end, '}');
+    }
+
+    fn expression(&mut self, expr: &Spanned<Expression>) -> String {
+        match expr.value {
+            Expression::Array { ref items } => {
+                // a comment within the array keeps its place by putting each item on its own line
+                if self.comment_within(expr.span) {
+                    self.nested(|printer| printer.items(items, expr.span))
+                } else {
+                    format!("[{}]", self.expressions(items))
+                }
+            }
+            Expression::Assign {
+                ref name,
+                ref kind,
+                ref value,
+            } => format!(
+                "{} {} {}",
+                self.name(name.value),
+                kind.value,
+                self.expression(value)
+            ),
+            Expression::Binary {
+                ref lhs,
+                ref op,
+                ref rhs,
+            } => format!(
+                "{} {} {}",
+                self.expression(lhs),
+                op.value,
+                self.expression(rhs)
+            ),
+            Expression::Call(ref call) => {
+                let callee = self.expression(&call.value.callee);
+                let types = self.type_args(&call.value.types.value);
+                let args = self.expressions(&call.value.args);
+
+                format!("{}{}({})", callee, types, args)
+            }
+            Expression::Cast { ref from, ref to } => {
+                format!("{} as {}", self.expression(from), self.ty(&to.value))
+            }
+            Expression::ClassLiteral(ref literal) => {
+                let literal = &literal.value;
+
+                let name = self.name(literal.symbol.value);
+                let types = self.type_args(&literal.types.value);
+
+                if literal.props.is_empty() {
+                    return format!("{}{} {{}}", name, types);
+                }
+
+                let props: Vec<String> = literal
+                    .props
+                    .iter()
+                    .map(|prop| {
+                        format!(
+                            "{}: {}",
+                            self.name(prop.value.symbol.value),
+                            self.expression(&prop.value.expr)
+                        )
+                    })
+                    .collect();
+
+                format!("{}{} {{ {} }}", name, types, props.join(", "))
+            }
+            Expression::Closure(ref closure) => {
+                let function = &closure.value;
+                let mut out = format!("|{}|", self.params(&function.params.value));
+
+                if let Some(ref returns) = function.returns {
+                    out.push_str(&format!(" -> {}", self.ty(&returns.value)));
+                }
+
+                out.push(' ');
+                out.push_str(&self.nested(|printer| printer.statement(&function.body)));
+                out
+            }
+            Expression::Get {
+                ref object,
+                ref property,
+            } => format!("{}.{}", self.expression(object), self.name(property.value)),
+            Expression::Grouping { ref expr } => format!("({})", self.expression(expr)),
+            Expression::Match { ref cond, ref arms } => {
+                let cond = self.expression(cond);
+
+                if arms.value.is_empty() && !self.comment_before(arms.span.end) {
+                    return format!("match {} {{}}", cond);
+                }
+
+                let arms_text = self.nested(|printer| printer.arms(&arms.value, arms.span));
+
+                format!("match {} {}", cond, arms_text)
+            }
+            Expression::SubScript {
+                ref target,
+                ref index,
+            } => format!("{}[{}]", self.expression(target), self.expression(index)),
+            Expression::Slice {
+                ref target,
+                ref start,
+                ref end,
+            } => {
+                let target = self.expression(target);
+                let start = start
+                    .as_ref()
+                    .map_or_else(String::new, |start| self.expression(start));
+                let end = end
+                    .as_ref()
+                    .map_or_else(String::new, |end| self.expression(end));
+
+                format!("{}[{}..{}]", target, start, end)
+            }
+            Expression::SetSubScript {
+                ref target,
+                ref index,
+                ref kind,
+                ref value,
+            } => format!(
+                "{}[{}] {} {}",
+                self.expression(target),
+                self.expression(index),
+                kind.value,
+                self.expression(value)
+            ),
+            Expression::Literal(ref literal) => self.literal(literal, expr.span),
+            Expression::Interpolation(ref segments) => {
+                let mut out = String::new();
+
+                // the text is written with the `"`, `}` and `${` around it
+                for segment in segments {
+                    match *segment {
+                        Segment::Text(ref text) => out.push_str(
+                            &self.input[text.span.start.absolute..text.span.end.absolute],
+                        ),
+                        Segment::Hole(ref expr) => out.push_str(&self.expression(expr)),
+                    }
+                }
+
+                out
+            }
+            Expression::Set {
+                ref object,
+                ref name,
+                ref value,
+            } => format!(
+                "{}.{} = {}",
+                self.expression(object),
+                self.name(name.value),
+                self.expression(value)
+            ),
+            Expression::Ternary {
+                ref condition,
+                ref then_branch,
+                ref else_branch,
+            } => format!(
+                "{} ? {} : {}",
+                self.expression(condition),
+                self.expression(then_branch),
+                self.expression(else_branch)
+            ),
+            Expression::Unary { ref op, ref expr } => {
+                let op = match op.value {
+                    UnaryOp::Bang => "!",
+                    UnaryOp::Minus => "-",
+                };
+
+                format!("{}{}", op, self.expression(expr))
+            }
+            Expression::Var(ref name) => self.name(name.value),
+            Expression::Variant {
+                ref enum_name,
+                ref variant,
+                ref inner,
+            } => {
+                let mut out = format!(
+                    "{}::{}",
+                    self.name(enum_name.value),
+                    self.name(variant.value)
+                );
+
+                if let Some(ref inner) = *inner {
+                    out.push_str(&format!("({})", self.expression(inner)));
+                }
+
+                out
+            }
+        }
+    }
+
+    fn expressions(&mut self, exprs: &[Spanned<Expression>]) -> String {
+        exprs
+            .iter()
+            .map(|expr| self.expression(expr))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The items of an array, each on its own line
+    fn items(&mut self, items: &[Spanned<Expression>], span: Span) {
+        self.write("[");
+        self.open(
+            span.start,
+            items.first().map_or(span.end, |item| item.span.start),
+        );
+
+        for (i, item) in items.iter().enumerate() {
+            self.leading_comments(item.span.start);
+
+            let text = self.expression(item);
+            self.write(&text);
+
+            if i + 1 < items.len() {
+                self.write(",");
+            }
+
+            let next = items.get(i + 1).map_or(span.end, |next| next.span.start);
+
+            self.trailing_comments(item.span.end, Some(next));
+            self.newline();
+        }
+
+        self.close(span.end, ']');
+    }
+
+    /// The arms of a match, each on its own line
+    fn arms(&mut self, arms: &[Spanned<MatchArm>], span: Span) {
+        self.write("{");
+        self.open(
+            span.start,
+            arms.first().map_or(span.end, |arm| arm.span.start),
+        );
+
+        for (i, arm) in arms.iter().enumerate() {
+            self.leading_comments(arm.span.start);
+
+            let pattern = self.pattern(&arm.value.pattern);
+            self.write(&format!("{} => ", pattern));
+
+            match arm.value.body.value {
+                // an expression within an arm has no `;`
+                Statement::Expr(ref expr) => {
+                    let expr = self.expression(expr);
+                    self.write(&expr);
+                }
+                _ => self.statement(&arm.value.body),
+            }
+
+            if i + 1 < arms.len() {
+                self.write(",");
+            }
+
+            let next = arms.get(i + 1).map_or(span.end, |next| next.span.start);
+
+            self.trailing_comments(end(&arm.value.body), Some(next));
+            self.newline();
+        }
+
+        self.close(span.end, '}');
+    }
+
+    fn pattern(&self, pattern: &Spanned<Pattern>) -> String {
+        match pattern.value {
+            Pattern::Wildcard => "_".into(),
+            Pattern::Binding(ref name) => self.name(name.value),
+            Pattern::Literal(ref literal) => self.literal(literal, pattern.span),
+            Pattern::Variant {
+                ref enum_name,
+                ref variant,
+                ref inner,
+            } => {
+                let mut out = format!(
+                    "{}::{}",
+                    self.name(enum_name.value),
+                    self.name(variant.value)
+                );
+
+                if let Some(ref inner) = *inner {
+                    out.push_str(&format!("({})", self.pattern(inner)));
+                }
+
+                out
+            }
+        }
+    }
+
+    fn ty(&self, ty: &Type) -> String {
+        match *ty {
+            Type::Simple(ref name) => self.name(name.value),
+            Type::Arr(ref inner) => format!("[{}]", self.ty(&inner.value)),
+            Type::Func(ref params, ref returns) => {
+                let params: Vec<String> =
+                    params.iter().map(|param| self.ty(&param.value)).collect();
+
+                match *returns {
+                    Some(ref returns) => {
+                        format!("fn({}) -> {}", params.join(", "), self.ty(&returns.value))
+                    }
+                    None => format!("fn({})", params.join(", ")),
+                }
+            }
+            Type::Nil => "nil".into(),
+            Type::Generic(ref name, ref types) => {
+                let types: Vec<String> = types.iter().map(|ty| self.ty(&ty.value)).collect();
+
+                format!("{}<{}>", self.name(name.value), types.join(", "))
+            }
+        }
+    }
+
+    /// The types given to a call or a class literal i.e `::<int>`
+    fn type_args(&self, types: &[Spanned<Type>]) -> String {
+        if types.is_empty() {
+            return String::new();
+        }
+
+        let types: Vec<String> = types.iter().map(|ty| self.ty(&ty.value)).collect();
+
+        format!("::<{}>", types.join(", "))
+    }
+
+    fn item_name(&self, name: &ItemName) -> String {
+        let mut out = self.name(name.name.value);
+
+        if !name.type_params.is_empty() {
+            let params: Vec<String> = name
+                .type_params
+                .iter()
+                .map(|param| self.name(param.value))
+                .collect();
+
+            out.push_str(&format!("<{}>", params.join(", ")));
+        }
+
+        out
+    }
+
+    /// Strings are printed as they were written so that their escapes are kept
+    fn literal(&self, literal: &Literal, span: Span) -> String {
+        match *literal {
+            Literal::Float(f) => format!("{:?}", f),
+            Literal::Int(i) => i.to_string(),
+            Literal::Str(_) => self.input[span.start.absolute..span.end.absolute].to_owned(),
+            Literal::True(_) => "true".into(),
+            Literal::False(_) => "false".into(),
+            Literal::Nil => "nil".into(),
+        }
+    }
+
+    fn name(&self, symbol: Symbol) -> String {
+        self.symbols.name(symbol)
+    }
+
+    /// Prints something that spans several lines within an expression and returns it
+    fn nested<F: FnOnce(&mut Self)>(&mut self, print: F) -> String {
+        let out = ::std::mem::take(&mut self.out);
+
+        print(self);
+
+        ::std::mem::replace(&mut self.out, out)
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// Starts the lines within a `{`, keeping any comment after the `{` on its line
+    fn open(&mut self, brace: Position, first: Position) {
+        self.trailing_comments(brace, Some(first));
+
+        self.indent += 1;
+        self.last_line = None;
+        self.newline();
+    }
+
+    /// Prints the comments left before the end of a block and the bracket that closes it
+    fn close(&mut self, end: Position, bracket: char) {
+        while self.comment_before(end) {
+            self.comment();
+        }
+
+        // the line that was started for the next statement is outdented
+        self.out.truncate(self.out.len() - INDENT.len());
+        self.out.push(bracket);
+
+        self.indent -= 1;
+        self.last_line = Some(end.line);
+    }
+
+    /// Leaves a blank line before the next thing unless at the start of a block
+    fn force_blank_line(&mut self) {
+        self.blank = self.last_line.is_some();
+    }
+
+    fn comment_within(&self, span: Span) -> bool {
+        self.comments.get(self.next_comment).is_some_and(|comment| {
+            comment.span.start.absolute > span.start.absolute
+                && comment.span.start.absolute < span.end.absolute
+        })
+    }
+
+    fn comment_before(&self, position: Position) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.span.start.absolute < position.absolute)
+    }
+
+    /// Prints each comment before the position on its own line. A blank line between two
+    /// things in the source is kept
+    fn leading_comments(&mut self, position: Position) {
+        while self.comment_before(position) {
+            self.comment();
+        }
+
+        self.blank_line(position.line);
+    }
+
+    /// Prints the next comment on its own line
+    fn comment(&mut self) {
+        let comment = self.comments[self.next_comment].clone();
+        self.next_comment += 1;
+
+        self.blank_line(comment.span.start.line);
+        self.write(comment.value);
+        self.newline();
+
+        // a comment that was moved down from within a statement doesn't leave a gap
+        self.last_line = self.last_line.max(Some(comment.span.end.line));
+    }
+
+    /// Prints the comments at the end of the line of a body that something follows. The
+    /// comments end the line so what follows goes on the next one
+    fn comment_after(&mut self, end: Position, next: Position) -> bool {
+        let printed = self.next_comment;
+
+        self.trailing_comments(end, Some(next));
+
+        self.next_comment != printed
+    }
+
+    /// Prints the comments that start on the line the source of what was just printed
+    /// ends on and before the next thing on that line
+    fn trailing_comments(&mut self, end: Position, next: Option<Position>) {
+        while let Some(comment) = self.comments.get(self.next_comment).cloned() {
+            if comment.span.start.line != end.line
+                || next.is_some_and(|next| next.absolute < comment.span.start.absolute)
+            {
+                break;
+            }
+
+            self.next_comment += 1;
+
+            self.write(" ");
+            self.write(comment.value);
+        }
+
+        self.last_line = Some(end.line);
+    }
+
+    fn blank_line(&mut self, line: u32) {
+        let gap = self.last_line.is_some_and(|last| line > last + 1);
+
+        if self.blank || gap {
+            self.newline_before();
+        }
+
+        self.blank = false;
+    }
+
+    /// Adds an empty line before the line that has been started
+    fn newline_before(&mut self) {
+        let indent = INDENT.len() * self.indent;
+        let len = self.out.len() - indent;
+
+        self.out.insert(len, '\n');
+    }
+}
+
+impl<'a> Entry<'a> {
+    fn span(&self) -> Span {
+        match *self {
+            Entry::Import(import) => import.span,
+            Entry::Alias(alias) => alias.span,
+            Entry::Enum(e) => e.span,
+            Entry::Class(class) => class.span,
+            Entry::Function(function) => function.span,
+            Entry::Statement(statement) => Span {
+                start: start(statement),
+                end: end(statement),
+            },
+        }
+    }
+}
+
+fn is_block(statement: &Spanned<Statement>) -> bool {
+    matches!(statement.value, Statement::Block(_))
+}
+
+/// Where the statement starts in the source. The span of an expression statement only
+/// covers its `;`
+fn start(statement: &Spanned<Statement>) -> Position {
+    match statement.value {
+        Statement::Expr(ref expr) => expr.span.start,
+        _ => statement.span.start,
+    }
+}
+
+/// Where the statement ends in the source
+fn end(statement: &Spanned<Statement>) -> Position {
+    match statement.value {
+        Statement::If {
+            ref then,
+            ref otherwise,
+            ..
+        } => otherwise
+            .as_ref()
+            .map_or_else(|| end(then), |otherwise| end(otherwise)),
+        Statement::While { ref cond, .. } if cond.span.start > statement.span.end => cond.span.end,
+        _ => statement.span.end,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::Parser;
+    use crate::printer::format;
+    use pretty_assertions::assert_eq;
+    use std::rc::Rc;
+    use util::emmiter::Reporter;
+    use util::symbol::{SymbolFactory, Symbols};
+
+    fn print(input: &str) -> String {
+        let mut symbols = Symbols::new(Rc::new(SymbolFactory::new()));
+
+        format(input, Reporter::new(), &mut symbols).unwrap()
+    }
+
+    #[test]
+    fn lossless() {
+        let input = "fn main() {\n  /* a */ print 1;// b\n\n}\n";
+        let mut symbols = Symbols::new(Rc::new(SymbolFactory::new()));
+        let mut parser = Parser::new(input, Reporter::new(), &mut symbols);
+
+        let text: String = parser
+            .lossless_tokens()
+            .unwrap()
+            .iter()
+            .map(|lexeme| lexeme.value.text)
+            .collect();
+
+        assert_eq!(input, text);
+    }
+
+    #[test]
+    fn layout() {
+        let input = "// a\nclass Foo{a:int;fn get()->int{return this.a;}}\n\n\n\nfn main(){ // b\nlet foo=Foo{a:1};\nif(foo.get()>0)print foo.get();else{print 0;}\n}";
+
+        let expected = "// a\nclass Foo {\n    a: int;\n\n    fn get() -> int {\n        return this.a;\n    }\n}\n\nfn main() { // b\n    let foo = Foo { a: 1 };\n    if (foo.get() > 0)\n        print foo.get();\n    else {\n        print 0;\n    }\n}\n";
+
+        assert_eq!(expected, print(input));
+        assert_eq!(expected, print(expected));
+    }
+
+    #[test]
+    fn comments_in_place() {
+        let input = "fn main() {\n    if (true) {\n        print 1;\n    } // a\n    else {\n        print 2;\n    }\n\n    let xs = [\n        1, // b\n        2\n    ];\n    print xs[0 // c\n    ];\n}\n";
+
+        let expected = "fn main() {\n    if (true) {\n        print 1;\n    } // a\n    else {\n        print 2;\n    }\n\n    let xs = [\n        1, // b\n        2\n    ];\n    print xs[0];\n    // c\n}\n";
+
+        assert_eq!(expected, print(input));
+        assert_eq!(expected, print(expected));
+    }
+}
@@ -43,6 +43,9 @@ impl<'a> Display for TokenType<'a> {
             TokenType::LESSTHANEQUAL => write!(f, "<="), // <=
             TokenType::GREATERTHANEQUAL => write!(f, "=>"), // =>
             TokenType::STRING(ref s) => write!(f, "{:?}", s),
+            TokenType::STRINGSTART(ref s) => write!(f, "{:?}${{", s),
+            TokenType::STRINGMIDDLE(ref s) => write!(f, "}}{:?}${{", s),
+            TokenType::STRINGEND(ref s) => write!(f, "}}{:?}", s),
             TokenType::COMMA => write!(f, ","),     // ,
             TokenType::COMMENT => write!(f, "//"),  // //
             TokenType::SEMICOLON => write!(f, ";"), //
@@ -56,6 +59,7 @@ impl<'a> Display for TokenType<'a> {
             TokenType::MATCHARROW => write!(f, "=>"),
             TokenType::BAR => write!(f, "|"),
             TokenType::NAMESPACE => write!(f, "::"),
+            TokenType::DOTDOT => write!(f, ".."),
             TokenType::UNDERSCORE => write!(f, "_"),
             // Keywords,
             TokenType::FUNCTION => write!(f, "fun"),
@@ -80,6 +84,7 @@ impl<'a> Display for TokenType<'a> {
             TokenType::EXTENDS => write!(f, "extends"),
             TokenType::AS => write!(f, "as"),
             TokenType::ENUM => write!(f, "enum"),
+            TokenType::IMPORT => write!(f, "import"),
         }
     }
 }
@@ -91,6 +96,10 @@ pub enum TokenType<'a> {
     INT(i64),
     FLOAT(f64),
     STRING(String),
+    // The pieces of a string with `${expr}` in it
+    STRINGSTART(String),  // "text${
+    STRINGMIDDLE(String), // }text${
+    STRINGEND(String),    // }text"
 
     // Assignment
     ASSIGN,      // =
@@ -123,6 +132,7 @@ pub enum TokenType<'a> {
     RBRACE,     // }
     BAR,        // |
     NAMESPACE,  // ::
+    DOTDOT,     // ..
     MATCHARROW, // =>
     UNDERSCORE, // _
 
@@ -157,6 +167,7 @@ pub enum TokenType<'a> {
     TYPE,
     AS,
     ENUM,
+    IMPORT,
 
     // Other
     EOF,
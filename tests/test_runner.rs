@@ -1,144 +1,549 @@
+//! Runs every program in `tests/pass` and `tests/fail` and checks it against the
+//! expectations written in its comments.
+//!
+//! * `// expect: text` the next line the program prints is `text`, apart from the
+//!   whitespace around it. Every line the program prints must be expected
+//! * `// expect error: message` a compile error whose message contains `message` is
+//!   reported on this line
+//! * `// expect runtime error: message` the program stops with a runtime error raised on
+//!   this line
+//! * `// flags: --emit=tokens` the flags the program is run with. `--emit`, `--script` and
+//!   `--stress-gc` are understood
+//! * `//skip` the program isn't run
+//!
+//! The files in a `modules` directory are only imported by the programs beside it so they
+//! aren't run themselves. Only the errors reported in the program itself can be expected.
+//!
+//! Every error the program reports must be expected and the exit code the cli would
+//! return must match: `65` for a compile error, `70` for a runtime error and `0` otherwise.
+//! A program that parses must format to the same tokens and comments, and formatting it a
+//! second time must not change it.
+//!
+//! The programs are compiled and run in-process on a thread per core. Pass a path to only
+//! run the programs whose path contains it and `--bless` to rewrite the expectations of
+//! the programs that fail to match what they actually do.
 #![crate_name = "test_runner"]
 #![crate_type = "bin"]
 
 extern crate ansi_term;
-extern crate tempfile;
+extern crate frontend;
+extern crate syntax;
+extern crate util;
+extern crate vm;
 extern crate walkdir;
 
 use ansi_term::Colour::{Green, Red, Yellow};
-
-use std::fs::File;
-use std::io::Read;
-use std::process::Command;
+use frontend::{compile, fold, monomorphise, Infer};
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use syntax::loader::Loader;
+use syntax::parser::{LexemeKind, Parser};
+use syntax::printer;
+use util::emmiter::{ColourMode, Level, Reporter};
+use util::pos::{FileId, Span};
+use util::source::SourceMap;
+use util::symbol::{SymbolFactory, Symbols};
+use vm::{NativeRegistry, VM};
 use walkdir::WalkDir;
 
+const EXPECT: &str = "// expect";
+const FLAGS: &str = "// flags:";
+const SKIP: &str = "//skip";
+
+/// The exit codes the cli returns
+const COMPILE_ERROR: i32 = 65;
+const RUNTIME_ERROR: i32 = 70;
+
+/// What a program is expected to do
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    /// The line and message of each compile error
+    errors: Vec<(u32, String)>,
+    runtime_error: Option<(u32, String)>,
+    flags: Vec<String>,
+}
+
+/// What a program actually did
+#[derive(Debug, Default)]
+struct Outcome {
+    output: String,
+    errors: Vec<(u32, String)>,
+    runtime_error: Option<(u32, String)>,
+    exit_code: i32,
+}
+
+enum Status {
+    Pass,
+    Skip,
+    Fail(Vec<String>),
+    Blessed,
+}
+
 fn main() {
-    let mut pass = 0i32;
-    let mut fail = 0i32;
+    let mut bless = false;
+    let mut filters = Vec::new();
+
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            arg if arg.starts_with('-') => (),
+            _ => filters.push(arg),
+        }
+    }
+
+    let mut paths: Vec<PathBuf> = ["../tests/pass", "../tests/fail"]
+        .iter()
+        .flat_map(|dir| WalkDir::new(dir).sort_by(|a, b| a.file_name().cmp(b.file_name())))
+        .map(|entry| entry.unwrap().into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "tox"))
+        .filter(|path| !path.components().any(|part| part.as_os_str() == "modules"))
+        .filter(|path| {
+            filters.is_empty()
+                || filters
+                    .iter()
+                    .any(|filter| path.to_string_lossy().contains(filter.as_str()))
+        })
+        .collect();
+
+    // the workers pop from the end
+    paths.reverse();
+
+    let total = paths.len();
+    let paths = Arc::new(Mutex::new(paths));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers = thread::available_parallelism().map_or(4, |n| n.get());
+
+    for _ in 0..workers {
+        let paths = Arc::clone(&paths);
+        let sender = sender.clone();
+
+        thread::spawn(move || loop {
+            let path = match paths.lock().unwrap().pop() {
+                Some(path) => path,
+                None => break,
+            };
+
+            let status = check(&path, bless);
+
+            sender.send((path, status)).unwrap();
+        });
+    }
+
+    drop(sender);
+
+    let (mut pass, mut skip, mut blessed) = (0, 0, 0);
     let mut failed = Vec::new();
 
-    'outer: for entry in WalkDir::new("../tests/pass") {
-        let entry = entry.unwrap();
+    for (path, status) in receiver.iter().take(total) {
+        match status {
+            Status::Pass => pass += 1,
+            Status::Skip => skip += 1,
+            Status::Blessed => blessed += 1,
+            Status::Fail(reasons) => failed.push((path, reasons)),
+        }
+    }
+
+    failed.sort();
 
-        if entry.path().is_dir() {
-            continue;
+    for (path, reasons) in failed.iter() {
+        println!(
+            "Test {} failed",
+            Yellow.bold().paint(path.display().to_string())
+        );
+
+        for reason in reasons {
+            println!("    {}", reason);
         }
+    }
+
+    println!(
+        "Pass:{} Fail:{} Skip:{}",
+        Green.bold().paint(pass.to_string()),
+        Red.bold().paint(failed.len().to_string()),
+        skip
+    );
 
-        let mut undisclosedc = Command::new("cargo");
+    if bless {
+        println!("Blessed:{}", blessed);
+    }
 
-        let mut expected = Vec::new();
+    assert!(failed.is_empty());
+}
 
-        let mut source = String::new();
+/// Runs the program at the path and compares what it did with what it was expected to do
+fn check(path: &Path, bless: bool) -> Status {
+    let source = fs::read_to_string(path).expect("Couldn't read the test");
 
-        let mut file = File::open(entry.path().to_str().unwrap()).expect("File not found");
+    if source.lines().any(|line| line.contains(SKIP)) {
+        return Status::Skip;
+    }
 
-        file.read_to_string(&mut source)
-            .expect("something went wrong reading the file");
+    let expected = match parse_expectations(&source) {
+        Ok(expected) => expected,
+        Err(reason) => return Status::Fail(vec![reason]),
+    };
+
+    // the compiler's types aren't thread safe so each program is compiled on the thread
+    // that runs it
+    let (outcome, formatting) = match panic::catch_unwind(AssertUnwindSafe(|| {
+        (run(path, &source, &expected.flags), check_format(&source))
+    })) {
+        Ok(outcome) => outcome,
+        Err(_) => return Status::Fail(vec!["The compiler panicked".into()]),
+    };
+
+    let mut reasons = compare(&expected, &outcome);
+    reasons.extend(formatting);
+
+    if reasons.is_empty() {
+        Status::Pass
+    } else if bless {
+        fs::write(path, rewrite(&source, &outcome)).expect("Couldn't bless the test");
+        Status::Blessed
+    } else {
+        Status::Fail(reasons)
+    }
+}
 
-        let expect_pattern = "// expect:";
-        let skip_pattern = "//skip";
+fn parse_expectations(source: &str) -> Result<Expectations, String> {
+    let mut expected = Expectations::default();
 
-        for line in source.lines() {
-            match line.match_indices(&skip_pattern).next() {
-                Some((_, _)) => continue 'outer,
-                None => (),
-            }
+    for (line, text) in (1..).zip(source.lines()) {
+        if let Some(index) = text.find(FLAGS) {
+            let flags = text[index + FLAGS.len()..].split_whitespace();
+            expected.flags.extend(flags.map(str::to_owned));
+        }
 
-            if let Some((index, _)) = line.match_indices(&expect_pattern).next() {
-                let from = index + expect_pattern.len();
-                let expects = line[from..].to_string();
-                expected.push(expects);
+        for annotation in text.split(EXPECT).skip(1) {
+            if let Some(message) = annotation.strip_prefix(" runtime error:") {
+                if expected.runtime_error.is_some() {
+                    return Err(format!("Line {} expects a second runtime error", line));
+                }
+
+                expected.runtime_error = Some((line, message.trim().to_owned()));
+            } else if let Some(message) = annotation.strip_prefix(" error:") {
+                expected.errors.push((line, message.trim().to_owned()));
+            } else if let Some(output) = annotation.strip_prefix(':') {
+                expected.output.push(output.trim().to_owned());
+            } else {
+                return Err(format!("Line {} has an unknown expectation", line));
             }
         }
+    }
 
-        undisclosedc.args(&["run", entry.path().to_str().unwrap()]);
+    Ok(expected)
+}
 
-        let output = undisclosedc.output().expect("failed to execute process");
+/// Compiles and runs the program the way `tox` does
+fn run(path: &Path, source: &str, flags: &[String]) -> Outcome {
+    let strings = Rc::new(SymbolFactory::new());
+    let mut symbols = Symbols::new(Rc::clone(&strings));
+    let mut reporter = Reporter::new();
+    reporter.set_colour(ColourMode::Never);
 
-        let output = String::from_utf8_lossy(&output.stdout);
+    let natives = NativeRegistry::new();
+    let mut outcome = Outcome::default();
 
-        let mut got = 0;
+    // like the cli an empty program does nothing rather than missing a `main` function
+    if source.trim().is_empty() {
+        return outcome;
+    }
 
-        for expects in expected.iter() {
-            if output.contains(expects) {
-                got += 1;
-            } else {
-                println!("{:?}", expects);
-            }
-        }
+    let emit = flags.iter().find_map(|flag| flag.strip_prefix("--emit="));
 
-        if got == expected.len() {
-            pass += 1
-        } else {
-            fail += 1;
-
-            failed.push(
-                ::std::fs::canonicalize(entry.path())
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string(),
-            );
+    if emit == Some("tokens") {
+        let tokens = Parser::new(source, reporter.clone(), &mut symbols).tokens();
+
+        // like the cli any error fails the lexing even if it could carry on
+        let tokens = match tokens {
+            Ok(ref tokens) if reporter.diagnostics().is_empty() => tokens,
+            _ => return outcome.failed(&reporter),
+        };
+
+        for token in tokens {
+            outcome.output += &format!("{:<16} {}\n", token.span.to_string(), token.value);
         }
+
+        return outcome.with_errors(&reporter);
     }
 
-    println!(
-        "Pass:{} Fail:{}",
-        Green.bold().paint(pass.to_string()),
-        Red.bold().paint(fail.to_string())
-    );
+    let mut files = SourceMap::new();
+    let loader = Loader::new(&mut files, reporter.clone(), &mut symbols);
 
-    if !failed.is_empty() {
-        for test in failed {
-            println!("Test {} failed ", Yellow.bold().paint(test));
-        }
+    let ast = match loader.load_source(path, source.into()) {
+        Ok(ast) => ast,
+        Err(_) => return outcome.failed(&reporter),
+    };
+
+    if emit == Some("ast") {
+        outcome.output = ast.dump(&symbols);
+        return outcome.with_errors(&reporter);
+    }
+
+    let mut infer = if flags.iter().any(|flag| flag == "--script") {
+        Infer::script()
+    } else {
+        Infer::new()
+    };
+
+    let typed_ast = match infer.infer(ast, &strings, &natives, &mut reporter) {
+        Ok(typed_ast) => typed_ast,
+        Err(_) => return outcome.failed(&reporter),
+    };
+
+    if emit == Some("typed-ast") {
+        outcome.output = typed_ast.dump(&symbols);
+        return outcome.with_errors(&reporter);
     }
 
-    assert!(fail == 0);
+    let typed_ast = monomorphise(typed_ast, &mut symbols);
+    let typed_ast = fold(typed_ast);
 
-    for entry in WalkDir::new("../tests/fail") {
-        let mut undisclosedc = Command::new("cargo");
-        let entry = entry.unwrap();
+    let (program, objects) = match compile(&typed_ast, &symbols, &mut reporter) {
+        Ok(program) => program,
+        Err(_) => return outcome.failed(&reporter),
+    };
 
-        if entry.path().is_dir() {
-            continue;
+    if emit == Some("bytecode") {
+        outcome.output = program.disassemble(&symbols);
+        return outcome.with_errors(&reporter);
+    }
+
+    let mut output = Vec::new();
+
+    {
+        let main = symbols.symbol("main");
+        let mut vm = VM::new(main, &program, objects, &natives, &mut symbols).unwrap();
+
+        vm.set_stress_gc(flags.iter().any(|flag| flag == "--stress-gc"));
+        vm.set_output(&mut output);
+
+        if let Err(error) = vm.run() {
+            outcome.runtime_error = Some((line(error.span), error.message));
+            outcome.exit_code = RUNTIME_ERROR;
         }
+    }
 
-        let mut source = String::new();
+    outcome.output = String::from_utf8_lossy(&output).into_owned();
+    outcome.with_errors(&reporter)
+}
 
-        let mut file = File::open(entry.path().to_str().unwrap()).expect("File not found");
+impl Outcome {
+    /// Records the compile errors that were reported. Warnings are ignored
+    fn with_errors(mut self, reporter: &Reporter) -> Self {
+        self.errors = reporter
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.level() == Level::Error)
+            .map(|diagnostic| {
+                let message = diagnostic.message().trim().to_owned();
+                (line(diagnostic.span()), message)
+            })
+            .collect();
+
+        self
+    }
 
-        file.read_to_string(&mut source)
-            .expect("something went wrong reading the file");
+    /// Records the compile errors of a stage that failed, which stops the program with
+    /// the exit code of a compile error
+    fn failed(mut self, reporter: &Reporter) -> Self {
+        self.exit_code = COMPILE_ERROR;
+        self.with_errors(reporter)
+    }
+}
 
-        undisclosedc.args(&["run", entry.path().to_str().unwrap()]);
+/// The line of the program the span is on. A span in a file the program imports is given
+/// the line `0` so no expectation matches it
+fn line(span: Span) -> u32 {
+    if span.start.file == FileId::default() {
+        span.start.line
+    } else {
+        0
+    }
+}
 
-        let mut expected = Vec::new();
+/// Formats the source and checks that nothing but the layout changed. A source that
+/// doesn't parse can't be formatted so it isn't checked
+fn check_format(source: &str) -> Option<String> {
+    let formatted = format(source)?;
+
+    if format(&formatted).as_ref() != Some(&formatted) {
+        Some("Formatting the formatted program changes it".into())
+    } else if tokens(&formatted) != tokens(source) {
+        Some("Formatting the program changes its tokens".into())
+    } else if comments(&formatted) != comments(source) {
+        Some("Formatting the program changes its comments".into())
+    } else {
+        None
+    }
+}
 
-        let pattern = "//error:";
+fn format(source: &str) -> Option<String> {
+    let mut symbols = Symbols::new(Rc::new(SymbolFactory::new()));
 
-        for line in source.lines() {
-            if let Some((index, _)) = line.match_indices(&pattern).next() {
-                let from = index + pattern.len();
-                let expects = line[from..].to_string();
-                expected.push(expects);
+    printer::format(source, Reporter::new(), &mut symbols).ok()
+}
+
+fn tokens(source: &str) -> Vec<String> {
+    let mut symbols = Symbols::new(Rc::new(SymbolFactory::new()));
+    let mut parser = Parser::new(source, Reporter::new(), &mut symbols);
+
+    let tokens = parser.tokens().unwrap_or_default();
+
+    tokens.into_iter().map(|token| token.value).collect()
+}
+
+fn comments(source: &str) -> Vec<String> {
+    let mut symbols = Symbols::new(Rc::new(SymbolFactory::new()));
+    let mut parser = Parser::new(source, Reporter::new(), &mut symbols);
+
+    let lexemes = parser.lossless_tokens().unwrap_or_default();
+
+    lexemes
+        .into_iter()
+        .filter(|lexeme| lexeme.value.kind == LexemeKind::Comment)
+        .map(|lexeme| lexeme.value.text.trim_end().to_string())
+        .collect()
+}
+
+/// The ways the outcome differs from what was expected
+fn compare(expected: &Expectations, outcome: &Outcome) -> Vec<String> {
+    let mut reasons = Vec::new();
+
+    let mut lines = outcome.output.lines();
+    let mut matched = true;
+
+    for output in expected.output.iter() {
+        match lines.next() {
+            Some(line) if line.trim() == output => (),
+            Some(line) => {
+                reasons.push(format!(
+                    "Expected the output `{}` but found `{}`",
+                    output,
+                    line.trim()
+                ));
+                matched = false;
+                break;
+            }
+            None => {
+                reasons.push(format!("Expected the output `{}`", output));
+                matched = false;
+                break;
             }
         }
+    }
 
-        let output = undisclosedc.output().expect("failed to execute process");
+    if let Some(line) = lines.next().filter(|_| matched) {
+        reasons.push(format!("Unexpected output `{}`", line.trim()));
+    }
+
+    let mut errors: Vec<Option<&(u32, String)>> = outcome.errors.iter().map(Some).collect();
+
+    for &(line, ref message) in expected.errors.iter() {
+        let found = errors.iter_mut().find(|error| {
+            error.is_some_and(|(error_line, error)| {
+                *error_line == line && error.contains(message.as_str())
+            })
+        });
+
+        match found {
+            Some(error) => *error = None,
+            None => reasons.push(format!("Expected the error `{}` on line {}", message, line)),
+        }
+    }
 
-        let output = String::from_utf8_lossy(&output.stdout);
+    for &(line, ref message) in errors.into_iter().flatten() {
+        reasons.push(format!("Unexpected error `{}` on line {}", message, line));
+    }
+
+    match (&expected.runtime_error, &outcome.runtime_error) {
+        (Some((line, message)), Some((error_line, error)))
+            if line == error_line && error.contains(message.as_str()) => {}
+        (Some((line, message)), _) => reasons.push(format!(
+            "Expected the runtime error `{}` on line {}",
+            message, line
+        )),
+        (None, Some((line, message))) => reasons.push(format!(
+            "Unexpected runtime error `{}` on line {}",
+            message, line
+        )),
+        (None, None) => (),
+    }
 
-        let mut got = 0;
+    let exit_code = if !expected.errors.is_empty() {
+        COMPILE_ERROR
+    } else if expected.runtime_error.is_some() {
+        RUNTIME_ERROR
+    } else {
+        0
+    };
+
+    if exit_code != outcome.exit_code {
+        reasons.push(format!(
+            "Expected the exit code {} but found {}",
+            exit_code, outcome.exit_code
+        ));
+    }
+
+    reasons
+}
 
-        for expects in expected.iter() {
-            if output.contains(expects) {
-                got += 1;
+/// Rewrites the expectations of the source to match the outcome. The expected output is
+/// replaced line by line and any extra output is expected at the end of the program
+fn rewrite(source: &str, outcome: &Outcome) -> String {
+    let mut output = outcome.output.lines();
+    let mut lines = Vec::new();
+
+    for (line, text) in (1..).zip(source.lines()) {
+        let mut parts = text.split(EXPECT);
+        let code = parts.next().unwrap();
+        let mut annotations = Vec::new();
+
+        for annotation in parts {
+            if annotation.starts_with(':') {
+                if let Some(output) = output.next() {
+                    annotations.push(format!("{}:{}", EXPECT, output.trim()));
+                }
             }
         }
 
-        if got == expected.len() {
-            fail += 1
+        for (_, message) in outcome.errors.iter().filter(|error| error.0 == line) {
+            annotations.push(format!("{} error: {}", EXPECT, message));
+        }
+
+        if let Some((_, ref message)) = outcome.runtime_error.as_ref().filter(|e| e.0 == line) {
+            annotations.push(format!("{} runtime error: {}", EXPECT, message));
+        }
+
+        if annotations.is_empty() {
+            // a line that only held expectations that no longer apply is removed
+            if !(text.contains(EXPECT) && code.trim().is_empty()) {
+                lines.push(code.trim_end().to_owned());
+            }
+        } else if code.trim().is_empty() {
+            lines.push(format!("{}{}", code, annotations.join(" ")));
+        } else {
+            lines.push(format!("{} {}", code.trim_end(), annotations.join(" ")));
         }
     }
+
+    for output in output {
+        lines.push(format!("{}:{}", EXPECT, output.trim()));
+    }
+
+    let mut rewritten = lines.join("\n");
+
+    if source.ends_with('\n') {
+        rewritten.push('\n');
+    }
+
+    rewritten
 }
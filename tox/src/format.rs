@@ -0,0 +1,89 @@
+//! `tox fmt` formats source files in place or checks that they are formatted.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use syntax::printer;
+use util::emmiter::Reporter;
+use util::symbol::{SymbolFactory, Symbols};
+
+/// Formats each file, or each `.tox` file within a directory. With `check` the files are
+/// left alone and the ones that aren't formatted are listed. Exits with `1` if a file
+/// isn't formatted and `65` if one has an error
+pub fn run(paths: &[String], check: bool) {
+    let mut files = Vec::new();
+
+    for path in paths {
+        collect(Path::new(path), &mut files);
+    }
+
+    let mut unformatted = false;
+    let mut had_error = false;
+
+    for file in files.iter() {
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("Couldn't read `{}`: {}", file.display(), e);
+                ::std::process::exit(74)
+            }
+        };
+
+        let reporter = Reporter::new();
+        let mut symbols = Symbols::new(Rc::new(SymbolFactory::new()));
+
+        let formatted = match printer::format(&input, reporter.clone(), &mut symbols) {
+            Ok(formatted) => formatted,
+            Err(_) => {
+                println!("{}", file.display());
+                reporter.emit(&input);
+                had_error = true;
+                continue;
+            }
+        };
+
+        if formatted == input {
+            continue;
+        }
+
+        if check {
+            println!("{}", file.display());
+            unformatted = true;
+        } else if let Err(e) = fs::write(file, formatted) {
+            eprintln!("Couldn't write `{}`: {}", file.display(), e);
+            ::std::process::exit(74)
+        }
+    }
+
+    if had_error {
+        ::std::process::exit(65)
+    } else if unformatted {
+        ::std::process::exit(1)
+    }
+}
+
+/// Adds the path if it is a file or every `.tox` file within it if it is a directory
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+
+    let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .collect(),
+        Err(e) => {
+            eprintln!("Couldn't read `{}`: {}", path.display(), e);
+            ::std::process::exit(74)
+        }
+    };
+
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() || entry.extension().is_some_and(|ext| ext == "tox") {
+            collect(&entry, files);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 extern crate fnv;
 extern crate frontend;
+extern crate rustyline;
 extern crate structopt;
 #[macro_use]
 extern crate structopt_derive;
@@ -8,27 +9,44 @@ extern crate syntax;
 extern crate util;
 extern crate vm;
 
+mod format;
 mod repl;
 
 use frontend::compile;
-use frontend::Infer;
+use frontend::fold;
+use frontend::monomorphise;
+use frontend::{Infer, TypedProgram};
 // use interpreter::{interpret, Environment};
-use std::fs::File;
-use std::io::Read;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::Path;
 use std::rc::Rc;
+use std::str::FromStr;
 use structopt::StructOpt;
+use syntax::ast;
+use syntax::loader::Loader;
 use syntax::parser::Parser;
-use util::emmiter::Reporter;
+use util::emmiter::{ColourMode, Reporter};
+use util::source::SourceMap;
 use util::symbol::{SymbolFactory, Symbols};
-use vm::VM;
+use vm::{bytecode, NativeRegistry, Program, RawObject, RuntimeError, VM};
 
 fn main() {
     let opts = Cli::from_args();
 
-    if let Some(file) = opts.source {
-        run(file);
-    } else {
-        repl()
+    match opts.command {
+        Some(Command::Build {
+            ref source,
+            ref output,
+            script,
+        }) => build(source, output.as_ref(), script),
+        Some(Command::Run { ref file }) => run(file, &opts),
+        Some(Command::Fmt { ref files, check }) => format::run(files, check),
+        None => match opts.source {
+            Some(ref file) => run(file, &opts),
+            None => repl(),
+        },
     }
 }
 
@@ -38,59 +56,287 @@ pub fn repl() {
     Repl::new().run();
 }
 
-pub fn run(path: String) {
-    let mut file = File::open(path).expect("File not found");
+/// Runs a source file or a program compiled by `tox build`
+pub fn run(path: &str, opts: &Cli) {
+    let contents = read_file(path);
 
-    let mut contents = String::new();
+    if bytecode::is_bytecode(&contents) {
+        let file = match bytecode::decode(&contents) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                ::std::process::exit(65)
+            }
+        };
 
-    file.read_to_string(&mut contents)
-        .expect("something went wrong reading the file");
+        let strings = Rc::new(SymbolFactory::from_mappings(file.symbols));
+        let mut symbols = Symbols::new(strings);
+
+        match opts.emit {
+            Some(Emit::Bytecode) => write_output(&file.program.disassemble(&symbols), opts),
+            Some(stage) => {
+                eprintln!(
+                    "{}: Only the bytecode of a compiled program can be emitted, not the {}",
+                    path, stage
+                );
+                ::std::process::exit(64)
+            }
+            // there is no source to point at when a runtime error is reported
+            None => execute(
+                &file.program,
+                file.objects,
+                &SourceMap::new(),
+                &mut symbols,
+                opts,
+            ),
+        }
+    } else {
+        let contents = String::from_utf8(contents).expect("The file is not valid utf-8");
+        let input = contents.trim();
+
+        if input.is_empty() {
+            ::std::process::exit(0)
+        }
+
+        if let Some(stage) = opts.emit {
+            return emit(path, input, stage, opts);
+        }
+
+        let strings = Rc::new(SymbolFactory::new());
+        let mut symbols = Symbols::new(Rc::clone(&strings));
+        let mut files = SourceMap::new();
 
-    let input = contents.trim();
+        let (program, objects) =
+            compile_source(path, input, &mut files, &strings, &mut symbols, opts.script);
 
-    if contents.is_empty() {
-        ::std::process::exit(0)
+        execute(&program, objects, &files, &mut symbols, opts)
     }
+}
 
-    let mut reporter = Reporter::new();
+/// Compiles a source file and writes the program to a `.toxc` file. The output defaults
+/// to the source file with a `.toxc` extension
+pub fn build(path: &str, output: Option<&String>, script: bool) {
+    let contents = read_file(path);
+    let contents = String::from_utf8(contents).expect("The file is not valid utf-8");
 
     let strings = Rc::new(SymbolFactory::new());
     let mut symbols = Symbols::new(Rc::clone(&strings));
+    let mut files = SourceMap::new();
+
+    let (program, _) = compile_source(
+        path,
+        contents.trim(),
+        &mut files,
+        &strings,
+        &mut symbols,
+        script,
+    );
 
-    let ast = match Parser::new(input, reporter.clone(), &mut symbols).parse() {
-        Ok(statements) => statements,
+    let output = output.map_or_else(
+        || Path::new(path).with_extension("toxc"),
+        |output| Path::new(output).to_path_buf(),
+    );
+
+    if let Err(e) = fs::write(&output, bytecode::encode(&program, &strings.mappings())) {
+        eprintln!("Couldn't write `{}`: {}", output.display(), e);
+        ::std::process::exit(74)
+    }
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut file = File::open(path).expect("File not found");
+
+    let mut contents = Vec::new();
+
+    file.read_to_end(&mut contents)
+        .expect("something went wrong reading the file");
+
+    contents
+}
+
+/// Parses the source of the file at the path along with the files it imports. Exits if
+/// any of them has an error
+fn parse_source(
+    path: &str,
+    input: &str,
+    files: &mut SourceMap,
+    symbols: &mut Symbols<()>,
+    reporter: &Reporter,
+) -> ast::Program {
+    match Loader::new(files, reporter.clone(), symbols).load_source(Path::new(path), input.into()) {
+        Ok(ast) => ast,
         Err(_) => {
-            reporter.emit(input);
+            reporter.emit_files(files);
             ::std::process::exit(65)
         }
-    };
+    }
+}
 
-    let mut infer = Infer::new();
+/// Parses and type checks the source. Exits if the source has an error
+fn check_source(
+    path: &str,
+    input: &str,
+    files: &mut SourceMap,
+    strings: &Rc<SymbolFactory>,
+    symbols: &mut Symbols<()>,
+    reporter: &mut Reporter,
+    script: bool,
+) -> TypedProgram {
+    let ast = parse_source(path, input, files, symbols, reporter);
 
-    let typed_ast = match infer.infer(ast, &strings, &mut reporter) {
+    let mut infer = if script {
+        Infer::script()
+    } else {
+        Infer::new()
+    };
+
+    match infer.infer(ast, strings, &NativeRegistry::new(), reporter) {
         Ok(ast) => {
-            reporter.emit(input); //emit warnings
+            reporter.emit_files(files); //emit warnings
             ast
         }
         Err(_) => {
-            reporter.emit(input);
+            reporter.emit_files(files);
 
             ::std::process::exit(65)
         }
-    };
+    }
+}
+
+/// Parses, type checks and compiles the source. Exits if the source has an error
+fn compile_source(
+    path: &str,
+    input: &str,
+    files: &mut SourceMap,
+    strings: &Rc<SymbolFactory>,
+    symbols: &mut Symbols<()>,
+    script: bool,
+) -> (Program, RawObject) {
+    let mut reporter = Reporter::new();
+
+    let typed_ast = check_source(path, input, files, strings, symbols, &mut reporter, script);
+
+    let typed_ast = monomorphise(typed_ast, symbols);
+    let typed_ast = fold(typed_ast);
 
-    // if compile_vm {
-    let (program, objects) = match compile(&typed_ast, &symbols, &mut reporter) {
-        Ok(functions) => functions,
+    match compile(&typed_ast, symbols, &mut reporter) {
+        Ok(program) => program,
         Err(_) => {
-            reporter.emit(input);
+            reporter.emit_files(files);
             ::std::process::exit(65)
         }
+    }
+}
+
+/// Writes the output of a stage of the compiler instead of running the program
+fn emit(path: &str, input: &str, stage: Emit, opts: &Cli) {
+    let strings = Rc::new(SymbolFactory::new());
+    let mut symbols = Symbols::new(Rc::clone(&strings));
+    let mut files = SourceMap::new();
+    let mut reporter = Reporter::new();
+
+    let output = match stage {
+        Emit::Tokens => {
+            let tokens = Parser::new(input, reporter.clone(), &mut symbols).tokens();
+
+            if tokens.is_err() || !reporter.has_error() {
+                reporter.emit(input);
+                ::std::process::exit(65)
+            }
+
+            tokens
+                .unwrap()
+                .iter()
+                .map(|token| format!("{:<16} {}\n", token.span.to_string(), token.value))
+                .collect()
+        }
+        Emit::Ast => parse_source(path, input, &mut files, &mut symbols, &reporter).dump(&symbols),
+        Emit::TypedAst => check_source(
+            path,
+            input,
+            &mut files,
+            &strings,
+            &mut symbols,
+            &mut reporter,
+            opts.script,
+        )
+        .dump(&symbols),
+        Emit::Bytecode => {
+            let (program, _) =
+                compile_source(path, input, &mut files, &strings, &mut symbols, opts.script);
+            program.disassemble(&symbols)
+        }
     };
 
-    let mut vm = VM::new(symbols.symbol("main"), &program, objects).unwrap();
-    vm.run();
-    // }
+    write_output(&output, opts)
+}
+
+/// Writes to the file given by `--file` or to stdout if there isn't one
+fn write_output(output: &str, opts: &Cli) {
+    match opts.ir_file {
+        Some(ref path) => {
+            if let Err(e) = fs::write(path, output) {
+                eprintln!("Couldn't write `{}`: {}", path, e);
+                ::std::process::exit(74)
+            }
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+
+            match stdout
+                .write_all(output.as_bytes())
+                .and_then(|_| stdout.flush())
+            {
+                Ok(()) => (),
+                // the reader went away, e.g. `tox --emit=bytecode file.tox | head`
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+                Err(e) => {
+                    eprintln!("Couldn't write to stdout: {}", e);
+                    ::std::process::exit(74)
+                }
+            }
+        }
+    }
+}
+
+/// Runs the `main` function of the program
+fn execute(
+    program: &Program,
+    objects: RawObject,
+    files: &SourceMap,
+    symbols: &mut Symbols<()>,
+    opts: &Cli,
+) {
+    let main = symbols.symbol("main");
+    let mut vm = VM::new(main, program, objects, &NativeRegistry::new(), symbols).unwrap();
+    vm.set_stress_gc(opts.stress_gc);
+
+    if let Err(error) = vm.run() {
+        report_run_time_error(&error, files, symbols);
+        ::std::process::exit(70)
+    }
+
+    if opts.gc_stats {
+        let stats = vm.gc_stats();
+
+        eprintln!(
+            "gc: {} collections, {} objects allocated, {} objects freed, {} bytes in use",
+            stats.collections, stats.objects_allocated, stats.objects_freed, stats.bytes_allocated
+        );
+    }
+}
+
+/// Reports an error raised by the vm along with the calls that lead to it. A compiled
+/// program has no files to point into
+pub fn report_run_time_error(error: &RuntimeError, files: &SourceMap, symbols: &Symbols<()>) {
+    let colour = ColourMode::Auto.resolve(io::stdout().is_terminal());
+
+    if files.is_empty() {
+        print!("{}", error.render("", symbols, colour))
+    } else {
+        print!("{}", error.render_files(files, symbols, colour))
+    }
 }
 
 #[derive(StructOpt, Debug)]
@@ -102,7 +348,95 @@ pub struct Cli {
     #[structopt(long = "interpter", short = "-i")]
     pub interpreter: bool,
 
-    /// Dump the ir to the given file
+    /// Print the output of a stage of the compiler instead of running the program; one of
+    /// tokens, ast, typed-ast or bytecode
+    #[structopt(long = "emit")]
+    pub emit: Option<Emit>,
+
+    /// Write the output of --emit to the given file instead of stdout
     #[structopt(long = "file", short = "-f")]
     pub ir_file: Option<String>,
+
+    /// Run the garbage collector before every instruction
+    #[structopt(long = "stress-gc")]
+    pub stress_gc: bool,
+
+    /// Print statistics about the garbage collector once the program finishes
+    #[structopt(long = "gc-stats")]
+    pub gc_stats: bool,
+
+    /// Run the statements outside of the functions in order instead of calling `main`
+    #[structopt(long = "script")]
+    pub script: bool,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Compiles a source file to a `.toxc` file that runs without the source
+    #[structopt(name = "build")]
+    Build {
+        /// The source code file
+        source: String,
+        /// The file to write the program to
+        #[structopt(long = "output", short = "o")]
+        output: Option<String>,
+        /// Compile the source as a script
+        #[structopt(long = "script")]
+        script: bool,
+    },
+    /// Runs a source file or a `.toxc` file
+    #[structopt(name = "run")]
+    Run {
+        /// The source code or `.toxc` file
+        file: String,
+    },
+    /// Formats source files in place
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// The source files or directories of source files
+        files: Vec<String>,
+        /// List the files that aren't formatted instead of formatting them
+        #[structopt(long = "check")]
+        check: bool,
+    },
+}
+
+/// A stage of the compiler whose output can be printed with `--emit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    TypedAst,
+    Bytecode,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tokens" => Ok(Emit::Tokens),
+            "ast" => Ok(Emit::Ast),
+            "typed-ast" => Ok(Emit::TypedAst),
+            "bytecode" => Ok(Emit::Bytecode),
+            _ => Err(format!(
+                "`{}` isn't one of tokens, ast, typed-ast or bytecode",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Emit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Emit::Tokens => write!(f, "tokens"),
+            Emit::Ast => write!(f, "ast"),
+            Emit::TypedAst => write!(f, "typed-ast"),
+            Emit::Bytecode => write!(f, "bytecode"),
+        }
+    }
 }
@@ -1,109 +1,485 @@
-use frontend::{compile, Infer};
-use std::io::{self, Write};
+use frontend::{compile, fold, monomorphise, Session};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use std::cell::RefCell;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
 use std::rc::Rc;
-use syntax::ast::{Function, ItemName, Program};
-
+use syntax::ast::Statement;
 use syntax::parser::Parser;
-use util::emmiter::Reporter;
-use util::pos::Spanned;
+use util::emmiter::{ColourMode, Reporter};
+use util::pos::{Spanned, EMPTYSPAN};
 use util::symbol::{SymbolFactory, Symbols};
-use vm::VM;
+use vm::{NativeRegistry, State, VM};
+
+const PROMPT: &str = "lexer>> ";
+const CONTINUATION_PROMPT: &str = "...... ";
+/// The name of the file within the home directory the history is saved to
+const HISTORY_FILE: &str = ".tox_history";
+
+const KEYWORDS: &[&str] = &[
+    "class", "extends", "print", "type", "as", "match", "enum", "fn", "let", "if", "else", "for",
+    "while", "return", "break", "continue", "do", "true", "false", "or", "and", "nil",
+];
+
+const COMMANDS: &[&str] = &[".quit", ".history", ":type", ":dis"];
 
-pub struct Repl {}
+/// A repl where every input can use the functions, classes, enums and globals declared
+/// by the inputs before it
+pub struct Repl {
+    strings: Rc<SymbolFactory>,
+    symbols: Symbols<()>,
+    session: Session,
+    reporter: Reporter,
+    natives: NativeRegistry,
+    /// The heap and globals left behind by the last input that was run
+    state: Option<State>,
+    /// The names that can be tab completed
+    names: Rc<RefCell<Vec<String>>>,
+}
 
 impl Repl {
     pub fn new() -> Self {
-        Repl {}
+        let strings = Rc::new(SymbolFactory::new());
+        let reporter = Reporter::new();
+        let natives = NativeRegistry::new();
+
+        Repl {
+            symbols: Symbols::new(Rc::clone(&strings)),
+            session: Session::new(&strings, &reporter, &natives),
+            strings,
+            reporter,
+            natives,
+            state: None,
+            names: Rc::new(RefCell::new(Vec::new())),
+        }
     }
 
     pub fn run(&mut self) {
         println!("Welcome to the tox programming language");
 
-        let strings = Rc::new(SymbolFactory::new());
-        let mut symbols = Symbols::new(Rc::clone(&strings));
+        let mut editor: Editor<ReplHelper, DefaultHistory> =
+            Editor::new().expect("Couldn't create the line editor");
+
+        editor.set_helper(Some(ReplHelper {
+            names: Rc::clone(&self.names),
+        }));
 
-        loop {
-            let mut input = String::with_capacity(1024);
+        let history = history_file();
 
-            let _ = io::stdout().write(b"lexer>> ");
-            let _ = io::stdout().flush();
+        if let Some(ref history) = history {
+            let _ = editor.load_history(history); // there is no history the first time
+        }
 
-            input.clear();
+        self.update_names();
 
-            io::stdin()
-                .read_line(&mut input)
-                .expect("Couldn't read input");
+        while let Some(input) = read_input(&mut editor) {
+            let input = input.trim();
 
             if input.is_empty() {
                 continue;
             }
 
-            match input.as_ref() {
-                ".quit\n" => break,
-                ".next\n" => continue,
-                ".history\n" => unimplemented!(),
-                _ => (),
-            }
+            let _ = editor.add_history_entry(input);
 
-            let mut reporter = Reporter::new();
+            // the source any errors are reported against
+            let source = match input {
+                ".quit" => break,
+                ".history" => {
+                    for (i, entry) in editor.history().iter().enumerate() {
+                        println!("{:4} {}", i + 1, entry);
+                    }
 
-            let expr = match Parser::new(&input, reporter.clone(), &mut symbols).parse_statement() {
-                Ok(statements) => statements,
-                Err(_) => {
-                    reporter.emit(&input);
-                    ::std::process::exit(65)
+                    input
+                }
+                _ if input.starts_with(":type") => {
+                    let expr = input[":type".len()..].trim();
+                    self.type_of(expr);
+                    expr
+                }
+                _ if input.starts_with(":dis") => {
+                    self.disassemble(input[":dis".len()..].trim());
+                    input
+                }
+                _ if is_declaration(input) => {
+                    self.declare(input);
+                    input
+                }
+                _ => {
+                    self.statements(input);
+                    input
                 }
             };
 
-            let pusedo_main_symbol = symbols.symbol("main");
-
-            let pusedo_main_span = expr.get_span();
-
-            let pusedo_main = Spanned::new(
-                Function {
-                    name: Spanned::new(
-                        ItemName {
-                            name: Spanned::new(pusedo_main_symbol, pusedo_main_span),
-                            type_params: vec![],
-                        },
-                        pusedo_main_span,
-                    ),
-                    params: Spanned::new(vec![], pusedo_main_span),
-                    returns: None,
-                    body: expr,
-                },
-                pusedo_main_span,
-            );
-
-            let ast = Program {
-                functions: vec![pusedo_main],
-                classes: vec![],
-                aliases: vec![],
-                enums: vec![],
+            self.reporter.emit(source);
+            self.reporter.clear();
+
+            self.update_names();
+        }
+
+        if let Some(ref history) = history {
+            let _ = editor.save_history(history);
+        }
+    }
+
+    /// Declares the functions, classes, enums and aliases within the input
+    fn declare(&mut self, input: &str) {
+        let program = match Parser::new(input, self.reporter.clone(), &mut self.symbols).parse() {
+            Ok(program) => program,
+            Err(_) => return,
+        };
+
+        let _ = self.session.declare(program);
+    }
+
+    /// Runs each statement of the input in turn
+    fn statements(&mut self, input: &str) {
+        let statements =
+            match Parser::new(input, self.reporter.clone(), &mut self.symbols).parse_statements() {
+                Ok(statements) => statements,
+                Err(_) => return,
             };
 
-            let mut infer = Infer::new();
+        for statement in statements {
+            if self.run_statement(statement, input).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn run_statement(&mut self, statement: Spanned<Statement>, input: &str) -> Result<(), ()> {
+        let main = self.symbols.symbol("main");
 
-            let typed_ast = match infer.infer(ast, &strings, &mut reporter) {
-                Ok(ast) => ast,
-                Err(_) => {
-                    reporter.emit(&input);
+        let typed_ast = self.session.statement(statement, main)?;
+        let typed_ast = monomorphise(typed_ast, &mut self.symbols);
+        let typed_ast = fold(typed_ast);
 
-                    continue;
-                }
+        let (program, objects) = compile(&typed_ast, &self.symbols, &mut self.reporter)?;
+
+        let state = self.state();
+
+        let mut vm = VM::with_state(main, &program, objects, state).unwrap();
+
+        let result = vm.run();
+
+        let mut state = vm.into_state();
+
+        if let Err(error) = result {
+            // a global that was being declared may still hold the value of an older one
+            for global in typed_ast
+                .globals
+                .iter()
+                .filter(|global| global.value.is_some())
+            {
+                state.remove_global(global.name);
+            }
+
+            self.state = Some(state);
+
+            let colour = ColourMode::Auto.resolve(io::stdout().is_terminal());
+            print!("{}", error.render(input, &self.symbols, colour));
+            return Err(());
+        }
+
+        self.state = Some(state);
+
+        Ok(())
+    }
+
+    /// Prints the type of the expression
+    fn type_of(&mut self, input: &str) {
+        let expr =
+            match Parser::new(input, self.reporter.clone(), &mut self.symbols).parse_expression() {
+                Ok(expr) => expr,
+                Err(_) => return,
             };
 
-            let (program, objects) = match compile(&typed_ast, &symbols, &mut reporter) {
-                Ok(functions) => functions,
-                Err(_) => {
-                    reporter.emit(&input);
-                    continue;
+        if let Ok(ty) = self.session.type_of(expr) {
+            println!("{}", ty);
+        }
+    }
+
+    /// Prints the bytecode of a function or of a method written as `Class.method`
+    fn disassemble(&mut self, name: &str) {
+        let main = self.symbols.symbol("main");
+        let empty = Spanned::new(Statement::Block(vec![]), EMPTYSPAN);
+
+        let typed_ast = match self.session.statement(empty, main) {
+            Ok(typed_ast) => typed_ast,
+            Err(_) => return,
+        };
+
+        let typed_ast = monomorphise(typed_ast, &mut self.symbols);
+        let typed_ast = fold(typed_ast);
+
+        let (program, objects) = match compile(&typed_ast, &self.symbols, &mut self.reporter) {
+            Ok(program) => program,
+            Err(_) => return,
+        };
+
+        let function = match name.find('.') {
+            Some(dot) => {
+                let class = self.symbols.symbol(&name[..dot]);
+                let method = self.symbols.symbol(&name[dot + 1..]);
+
+                program
+                    .classes
+                    .get(&class)
+                    .and_then(|class| class.methods.get(&method))
+            }
+            None => program.functions.get(&self.symbols.symbol(name)),
+        };
+
+        match function {
+            Some(function) => print!("{}", function.body.disassemble(name)),
+            None => println!("There is no function called `{}`", name),
+        }
+
+        // the heap takes ownership of the objects the program allocated
+        let state = self.state();
+        let vm = VM::with_state(main, &program, objects, state).unwrap();
+        self.state = Some(vm.into_state());
+    }
+
+    /// The state left behind by the last input or a new one if no input has been run
+    fn state(&mut self) -> State {
+        match self.state.take() {
+            Some(state) => state,
+            None => State::new(&self.natives, &mut self.symbols),
+        }
+    }
+
+    fn update_names(&mut self) {
+        let mut names: Vec<String> = KEYWORDS
+            .iter()
+            .chain(COMMANDS.iter())
+            .map(|name| name.to_string())
+            .chain(self.natives.iter().map(|native| native.name.clone()))
+            .collect();
+
+        let symbols = Symbols::<()>::new(Rc::clone(&self.strings));
+
+        names.extend(
+            self.session
+                .names()
+                .into_iter()
+                .map(|symbol| symbols.name(symbol)),
+        );
+
+        names.sort();
+        names.dedup();
+
+        *self.names.borrow_mut() = names;
+    }
+}
+
+/// Reads lines until every brace, string and comment that was opened is closed. Returns
+/// `None` once the input has ended
+fn read_input(editor: &mut Editor<ReplHelper, DefaultHistory>) -> Option<String> {
+    let mut input = String::new();
+    let mut prompt = PROMPT;
+
+    loop {
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                input.push('\n');
+            }
+            Err(ReadlineError::Interrupted) => return Some(String::new()),
+            Err(_) => {
+                return if input.is_empty() { None } else { Some(input) };
+            }
+        }
+
+        if unclosed_braces(&input) <= 0 {
+            return Some(input);
+        }
+
+        prompt = CONTINUATION_PROMPT;
+    }
+}
+
+/// What the chars of the input are being read as
+#[derive(PartialEq)]
+enum Scan {
+    Code,
+    Str,
+    RawStr,
+    LineComment,
+    BlockComment,
+}
+
+/// The number of braces that have been opened but not closed, ignoring the braces within
+/// strings and comments. A string or block comment that is still open counts as one more
+/// unclosed brace so that the input carries on to the next line
+fn unclosed_braces(input: &str) -> i32 {
+    let mut depth = 0;
+    // the braces opened within each `${}` of a string, like the lexer counts them
+    let mut holes: Vec<i32> = Vec::new();
+    let mut scan = Scan::Code;
+    let mut last = ' ';
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match scan {
+            Scan::Code => match ch {
+                '"' if last == 'r' => scan = Scan::RawStr,
+                '"' => scan = Scan::Str,
+                '/' if chars.peek() == Some(&'/') => scan = Scan::LineComment,
+                '/' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    scan = Scan::BlockComment
                 }
-            };
+                '{' => match holes.last_mut() {
+                    Some(braces) => *braces += 1,
+                    None => depth += 1,
+                },
+                '}' => match holes.last_mut() {
+                    Some(0) => {
+                        holes.pop();
+                        scan = Scan::Str
+                    }
+                    Some(braces) => *braces -= 1,
+                    None => depth -= 1,
+                },
+                _ => (),
+            },
+            Scan::Str => match ch {
+                '"' => scan = Scan::Code,
+                '\\' => {
+                    chars.next();
+                }
+                '$' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    holes.push(0);
+                    scan = Scan::Code
+                }
+                _ => (),
+            },
+            Scan::RawStr if ch == '"' => scan = Scan::Code,
+            Scan::LineComment if ch == '\n' => scan = Scan::Code,
+            Scan::BlockComment if ch == '*' && chars.peek() == Some(&'/') => {
+                chars.next();
+                scan = Scan::Code
+            }
+            _ => (),
+        }
 
-            let mut vm = VM::new(symbols.symbol("main"), &program, objects).unwrap();
+        // a raw string starts with an `r` that isn't the end of a name
+        last = if ch == 'r' && !(last.is_alphanumeric() || last == '_') {
+            'r'
+        } else {
+            ch
+        };
+    }
+
+    let open = scan == Scan::Str || scan == Scan::RawStr || scan == Scan::BlockComment;
+
+    if open || !holes.is_empty() {
+        depth + 1
+    } else {
+        depth
+    }
+}
+
+/// Declarations are parsed as a program rather than as statements
+fn is_declaration(input: &str) -> bool {
+    let keyword = input.split_whitespace().next().unwrap_or("");
+
+    ["fn", "class", "enum", "type"].contains(&keyword)
+}
+
+fn history_file() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Completes the word before the cursor from the keywords, builtins and every name that
+/// has been declared
+struct ReplHelper {
+    names: Rc<RefCell<Vec<String>>>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == ':'))
+            .map_or(0, |index| index + 1);
+
+        let word = &line[start..pos];
 
-            vm.run()
+        if word.is_empty() {
+            return Ok((start, vec![]));
         }
+
+        let candidates = self
+            .names
+            .borrow()
+            .iter()
+            .filter(|name| name.starts_with(word))
+            .cloned()
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::{is_declaration, unclosed_braces};
+
+    #[test]
+    fn braces() {
+        assert_eq!(unclosed_braces("fn main() {"), 1);
+        assert_eq!(unclosed_braces("fn main() {\n    if true {\n    }\n"), 1);
+        assert_eq!(unclosed_braces("fn main() {\n}\n"), 0);
+        assert_eq!(unclosed_braces("print \"{\"; // {"), 0);
+        assert_eq!(unclosed_braces("}"), -1);
+    }
+
+    #[test]
+    fn strings_and_comments() {
+        assert_eq!(unclosed_braces("let s = \"two\n"), 1);
+        assert_eq!(unclosed_braces("let s = \"two\nlines {\";\n"), 0);
+        assert_eq!(unclosed_braces("print \"\\\"{\";"), 0);
+        assert_eq!(unclosed_braces("print \"${ {1} } {\";"), 0);
+        assert_eq!(unclosed_braces("print \"a ${len(\"}\")"), 1);
+        assert_eq!(unclosed_braces("print r\"\\\"; {"), 1);
+        assert_eq!(unclosed_braces("for\"{\""), 0);
+        assert_eq!(unclosed_braces("/* {\n"), 1);
+        assert_eq!(unclosed_braces("/* {\n} */ fn main() {"), 1);
+    }
+
+    #[test]
+    fn declarations() {
+        assert!(is_declaration("fn double(n: int) -> int { return n * 2; }"));
+        assert!(is_declaration("class Point { x: int; }"));
+        assert!(is_declaration("enum Colour { Red }"));
+        assert!(is_declaration("type Id = int;"));
+        assert!(!is_declaration("let f = 1;"));
+        assert!(!is_declaration("print fn_name;"));
+        assert!(!is_declaration(""));
     }
 }
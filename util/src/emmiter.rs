@@ -1,32 +1,90 @@
 //! Error reporting that reports all compiler errors.
 use crate::pos::Span;
 use crate::pos::EMPTYSPAN;
+use crate::source::SourceMap;
 use ansi_term::Colour::{Blue, Fixed, Purple, Red, Yellow};
+use ansi_term::Style;
 use std::cell::RefCell;
-use std::fmt::{self, Display};
+use std::fmt::{self, Display, Write};
+use std::io::{self, IsTerminal};
 use std::iter::repeat;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Diagnostic {
     msg: String,
     level: Level,
     span: Span,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Level {
     Warn,
     Error,
     RunTimeError,
 }
 
+impl Diagnostic {
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl Level {
+    /// The style the level and the carets under the span are painted with
+    fn style(self) -> Style {
+        match self {
+            Level::Warn => Yellow.bold(),
+            Level::Error => Red.bold(),
+            Level::RunTimeError => Purple.bold(),
+        }
+    }
+}
+
 impl Display for Level {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Level::Warn => write!(f, "{}", Yellow.bold().paint("warning")),
-            Level::Error => write!(f, "{}", Red.bold().paint("error")),
-            Level::RunTimeError => write!(f, "{}", Purple.bold().paint("Runtime Error")),
+            Level::Warn => write!(f, "warning"),
+            Level::Error => write!(f, "error"),
+            Level::RunTimeError => write!(f, "Runtime Error"),
+        }
+    }
+}
+
+/// Whether diagnostics are rendered with colours
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColourMode {
+    Always,
+    Never,
+    /// Colours are used when the diagnostics are written to a terminal
+    #[default]
+    Auto,
+}
+
+impl ColourMode {
+    /// Whether colours are used for diagnostics written to a terminal or to anything else
+    pub fn enabled(self, terminal: bool) -> bool {
+        match self {
+            ColourMode::Always => true,
+            ColourMode::Never => false,
+            ColourMode::Auto => terminal,
+        }
+    }
+
+    /// Settles `Auto` for diagnostics written to a terminal or to anything else
+    pub fn resolve(self, terminal: bool) -> ColourMode {
+        if self.enabled(terminal) {
+            ColourMode::Always
+        } else {
+            ColourMode::Never
         }
     }
 }
@@ -35,6 +93,7 @@ impl Display for Level {
 pub struct Reporter {
     diagnostics: Rc<RefCell<Vec<Diagnostic>>>,
     end: Span,
+    colour: ColourMode,
 }
 
 impl Reporter {
@@ -74,6 +133,16 @@ impl Reporter {
         self.diagnostics.borrow_mut().pop();
     }
 
+    /// Every diagnostic that has been reported
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.diagnostics.borrow().clone()
+    }
+
+    /// Removes every diagnostic that has been reported
+    pub fn clear(&self) {
+        self.diagnostics.borrow_mut().clear();
+    }
+
     pub fn error<T: Into<String>>(&self, msg: T, span: Span) {
         self.diagnostics.borrow_mut().push(Diagnostic {
             msg: msg.into(),
@@ -98,10 +167,79 @@ impl Reporter {
         })
     }
 
+    /// Sets whether diagnostics are rendered with colours
+    pub fn set_colour(&mut self, colour: ColourMode) {
+        self.colour = colour;
+    }
+
     pub fn emit(&self, input: &str) {
+        print!("{}", self.render_for(input, io::stdout().is_terminal()))
+    }
+
+    /// Writes every diagnostic rendered against the source to the writer
+    pub fn emit_to<W: io::Write + IsTerminal + ?Sized>(
+        &self,
+        input: &str,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let rendered = self.render_for(input, out.is_terminal());
+        out.write_all(rendered.as_bytes())
+    }
+
+    /// Renders every diagnostic against the source in the form `emit` prints them. The
+    /// string isn't written to a terminal yet so `Auto` renders it without colours
+    pub fn render(&self, input: &str) -> String {
+        self.render_for(input, false)
+    }
+
+    fn render_for(&self, input: &str, terminal: bool) -> String {
+        let colour = self.colour.enabled(terminal);
+        let mut out = String::new();
+
         for diagnostic in self.diagnostics.borrow().iter() {
-            print(input, diagnostic)
+            render(input, None, diagnostic, colour, &mut out)
         }
+
+        out
+    }
+
+    /// Prints every diagnostic rendered against the file it was reported in
+    pub fn emit_files(&self, files: &SourceMap) {
+        print!(
+            "{}",
+            self.render_files_for(files, io::stdout().is_terminal())
+        )
+    }
+
+    /// Renders every diagnostic against the file it was reported in, naming the file. Like
+    /// `render` the colours of `Auto` are left out
+    pub fn render_files(&self, files: &SourceMap) -> String {
+        self.render_files_for(files, false)
+    }
+
+    fn render_files_for(&self, files: &SourceMap, terminal: bool) -> String {
+        let colour = self.colour.enabled(terminal);
+        let mut out = String::new();
+
+        for diagnostic in self.diagnostics.borrow().iter() {
+            let file = diagnostic.span.start.file;
+
+            // an error raised before any file was read isn't in a file
+            if file.0 as usize >= files.len() {
+                render("", None, diagnostic, colour, &mut out);
+                continue;
+            }
+
+            render(
+                files.source(file),
+                Some(files.name(file)),
+                diagnostic,
+                colour,
+                &mut out,
+            )
+        }
+
+        out
     }
 }
 
@@ -110,17 +248,58 @@ impl Default for Reporter {
         Self {
             diagnostics: Rc::new(RefCell::new(Vec::new())),
             end: EMPTYSPAN,
+            colour: ColourMode::default(),
         }
     }
 }
 
 pub fn print(input: &str, d: &Diagnostic) {
-    let prefix = Blue.paint("| ");
+    let mut out = String::new();
+    render(
+        input,
+        None,
+        d,
+        ColourMode::Auto.enabled(io::stdout().is_terminal()),
+        &mut out,
+    );
+    print!("{}", out)
+}
 
-    println!("{}: {}", d.level, Fixed(252).bold().paint(d.msg.clone()));
+/// Renders the diagnostic against the input, naming the file it is in if it has a name
+fn render(input: &str, file: Option<&str>, d: &Diagnostic, colour: bool, out: &mut String) {
+    let paint = |style: Style, text: &str| {
+        if colour {
+            style.paint(text).to_string()
+        } else {
+            text.to_owned()
+        }
+    };
+
+    let prefix = paint(Blue.normal(), "| ");
+    let level = d.level.style();
+
+    writeln!(
+        out,
+        "{}: {}",
+        paint(level, &d.level.to_string()),
+        paint(Fixed(252).bold(), &d.msg)
+    )
+    .unwrap();
 
     let span = d.span;
 
+    if let Some(file) = file {
+        writeln!(
+            out,
+            "  {} {}:{}:{}",
+            paint(Blue.normal(), "-->"),
+            file,
+            span.start.line,
+            span.start.column
+        )
+        .unwrap();
+    }
+
     let start_line = if span.start.line >= 4 {
         span.start.line - 4
     } else {
@@ -130,44 +309,31 @@ pub fn print(input: &str, d: &Diagnostic) {
     for (idx, line) in input.lines().enumerate().skip(start_line as usize) {
         let line = line;
         let line_idx = idx + 1;
-        println!("{:>4} {}{}", line_idx, prefix, line);
+        writeln!(out, "{:>4} {}{}", line_idx, prefix, line).unwrap();
         if line_idx == span.start.line as usize {
             let end = if line_idx == span.end.line as usize {
                 span.end.column as usize
             } else {
                 line.len()
             };
-            let carets = repeat_string("^", end - span.start.column as usize + 1);
-
-            let carets = match d.level {
-                Level::Warn => Yellow.bold().paint(carets),
-                Level::Error => Red.bold().paint(carets),
-                Level::RunTimeError => Purple.bold().paint(carets),
-            };
+            let carets = paint(
+                level,
+                &repeat_string("^", end - span.start.column as usize + 1),
+            );
 
             if span.start.column != 0 {
                 let whitespace = repeat_string(" ", span.start.column as usize - 1);
-                println!("     {}{}{}", prefix, whitespace, carets);
+                writeln!(out, "     {}{}{}", prefix, whitespace, carets).unwrap();
             }
         } else if line_idx == span.end.line as usize {
-            let carets = repeat_string("^", span.end.column as usize);
-            let carets = match d.level {
-                Level::Warn => Yellow.bold().paint(carets),
-                Level::Error => Red.bold().paint(carets),
-                Level::RunTimeError => Purple.bold().paint(carets),
-            };
-            println!("     {}{}", prefix, carets);
+            let carets = paint(level, &repeat_string("^", span.end.column as usize));
+            writeln!(out, "     {}{}", prefix, carets).unwrap();
         } else if line_idx > span.start.line as usize
             && line_idx < span.end.line as usize
             && !line.is_empty()
         {
-            let carets = repeat_string("^", line.len());
-            let carets = match d.level {
-                Level::Warn => Yellow.bold().paint(carets),
-                Level::Error => Red.bold().paint(carets),
-                Level::RunTimeError => Purple.bold().paint(carets),
-            };
-            println!("     {}{}", prefix, carets);
+            let carets = paint(level, &repeat_string("^", line.len()));
+            writeln!(out, "     {}{}", prefix, carets).unwrap();
         }
 
         if line_idx >= span.end.line as usize + 3 {
@@ -179,3 +345,33 @@ pub fn print(input: &str, d: &Diagnostic) {
 fn repeat_string(s: &str, count: usize) -> String {
     repeat(s).take(count).collect()
 }
+
+#[cfg(test)]
+mod test {
+    use crate::emmiter::{ColourMode, Reporter};
+    use crate::pos::EMPTYSPAN;
+    use std::env;
+    use std::fs::{self, File};
+
+    #[test]
+    fn auto_colour() {
+        assert!(ColourMode::Auto.enabled(true));
+        assert!(!ColourMode::Auto.enabled(false));
+        assert_eq!(ColourMode::Auto.resolve(true), ColourMode::Always);
+
+        let reporter = Reporter::new();
+        reporter.error("oops", EMPTYSPAN);
+
+        assert!(!reporter.render("").contains('\u{1b}'));
+
+        // a file isn't a terminal whatever stdout is
+        let path = env::temp_dir().join(format!("tox-colour-{}", std::process::id()));
+        reporter
+            .emit_to("", &mut File::create(&path).unwrap())
+            .unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(written.contains("oops") && !written.contains('\u{1b}'));
+    }
+}
@@ -4,6 +4,7 @@ extern crate fnv;
 extern crate itertools;
 pub mod emmiter;
 pub mod pos;
+pub mod source;
 pub mod symbol;
 
 static mut UNIQUE_COUNT: u64 = 0;
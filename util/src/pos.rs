@@ -35,11 +35,13 @@ pub const EMPTYSPAN: Span = Span {
         line: 1,
         column: 0,
         absolute: 1,
+        file: FileId(0),
     },
     end: Position {
         line: 1,
         column: 0,
         absolute: 1,
+        file: FileId(0),
     },
 };
 
@@ -48,15 +50,28 @@ pub struct Position {
     pub line: u32,
     pub column: u32,
     pub absolute: usize,
+    /// The file the position is within
+    pub file: FileId,
 }
 
+/// Identifies a source file of a program. The file the program was run from is always
+/// the first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FileId(pub u32);
+
 impl<'a> CharPosition<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_file(input, FileId::default())
+    }
+
+    /// The positions of the characters of the file
+    pub fn with_file(input: &'a str, file: FileId) -> Self {
         CharPosition {
             pos: Position {
                 line: 1,
                 column: 1,
                 absolute: 0,
+                file,
             },
             chars: multipeek(input.chars()),
         }
@@ -70,6 +85,7 @@ impl<'a> BytePosition<'a> {
                 line: 1,
                 column: 1,
                 absolute: 0,
+                file: FileId::default(),
             },
             bytes: input.bytes(),
         }
@@ -0,0 +1,46 @@
+//! The source files a program is loaded from.
+use crate::pos::FileId;
+
+/// The name and source of each file of a program, indexed by its `FileId`
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<(String, String)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, returning the id the positions within it are given
+    pub fn add<N: Into<String>, S: Into<String>>(&mut self, name: N, source: S) -> FileId {
+        self.files.push((name.into(), source.into()));
+
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    /// The name of the file, usually its path
+    pub fn name(&self, file: FileId) -> &str {
+        &self.files[file.0 as usize].0
+    }
+
+    pub fn source(&self, file: FileId) -> &str {
+        &self.files[file.0 as usize].1
+    }
+
+    /// The id of the file with the name
+    pub fn find(&self, name: &str) -> Option<FileId> {
+        self.files
+            .iter()
+            .position(|(file, _)| file == name)
+            .map(|index| FileId(index as u32))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
@@ -96,6 +96,38 @@ impl SymbolFactory {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Every `Symbol` along with its name ordered by the `Symbol`
+    pub fn mappings(&self) -> Vec<(Symbol, String)> {
+        let mut mappings: Vec<(Symbol, String)> = self
+            .mappings
+            .borrow()
+            .iter()
+            .map(|(symbol, name)| (*symbol, name.clone()))
+            .collect();
+
+        mappings.sort_by_key(|(symbol, _)| symbol.0);
+
+        mappings
+    }
+
+    /// Creates a SymbolFactory that maps each `Symbol` to the same name as the factory
+    /// the mappings came from
+    pub fn from_mappings(mappings: Vec<(Symbol, String)>) -> Self {
+        let factory = Self::default();
+
+        for (symbol, name) in mappings {
+            let mut next = factory.next.borrow_mut();
+
+            if symbol.0 >= *next {
+                *next = symbol.0 + 1;
+            }
+
+            factory.mappings.borrow_mut().insert(symbol, name);
+        }
+
+        factory
+    }
 }
 
 impl Default for SymbolFactory {
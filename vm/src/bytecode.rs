@@ -0,0 +1,599 @@
+//! The `.toxc` file format a compiled program is saved in so it can be run without
+//! parsing, type checking and compiling the source again.
+//!
+//! A file starts with a header:
+//!
+//! | bytes | contents                                  |
+//! |-------|-------------------------------------------|
+//! | 4     | the magic number `TOXC`                   |
+//! | 2     | the version of the format, little endian  |
+//! | 8     | a FNV-1a checksum of the body, little endian |
+//!
+//! The body holds the name of every symbol, the functions and then the classes. Each
+//! function holds its name, its params and its chunk; the code, the span of each byte and
+//! the constant pool. Counts, lengths and symbols are written as LEB128 variable length
+//! ints like the operands of an instruction.
+use crate::object::{FunctionObject, ObjectType, RawObject, StringObject};
+use crate::value::{Value, ValueType};
+use crate::{Chunk, Class, Function, Program};
+use fnv::FnvHashMap;
+use std::fmt::{self, Display};
+use std::ptr;
+use util::pos::{FileId, Position, Span};
+use util::symbol::Symbol;
+
+pub const MAGIC: &[u8; 4] = b"TOXC";
+
+/// Bumped whenever the layout of the body or the meaning of an opcode changes
+pub const VERSION: u16 = 2;
+
+const HEADER_LEN: usize = 14;
+
+const NIL: u8 = 0;
+const BOOL: u8 = 1;
+const INT: u8 = 2;
+const FLOAT: u8 = 3;
+const STRING: u8 = 4;
+const FUNCTION: u8 = 5;
+
+/// A program read from a `.toxc` file
+#[derive(Debug)]
+pub struct BytecodeFile {
+    pub program: Program,
+    /// The string and function constants of the program
+    pub objects: RawObject,
+    /// The name of every symbol the program uses
+    pub symbols: Vec<(Symbol, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    /// The file does not start with `TOXC`
+    BadMagic,
+    /// The file was written by a different version of tox
+    UnsupportedVersion(u16),
+    /// The body of the file does not match its checksum
+    BadChecksum,
+    /// The file ended before the program did
+    Truncated,
+    /// The file is not a valid program
+    Malformed(String),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LoadError::BadMagic => write!(f, "Not a compiled tox program"),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "The program was compiled for version `{}` of the bytecode but version `{}` is supported",
+                version, VERSION
+            ),
+            LoadError::BadChecksum => write!(f, "The program is corrupt; its checksum does not match"),
+            LoadError::Truncated => write!(f, "The program is corrupt; the file ends early"),
+            LoadError::Malformed(ref msg) => write!(f, "The program is corrupt; {}", msg),
+        }
+    }
+}
+
+type LoadResult<T> = Result<T, LoadError>;
+
+/// Writes the program to bytes.
+/// `symbols` must hold the name of every symbol the program uses
+pub fn encode(program: &Program, symbols: &[(Symbol, String)]) -> Vec<u8> {
+    let mut body = Writer { bytes: Vec::new() };
+
+    body.usize(symbols.len());
+
+    for (symbol, name) in symbols.iter() {
+        body.symbol(*symbol);
+        body.string(name);
+    }
+
+    body.usize(program.functions.len());
+
+    for function in sorted(&program.functions) {
+        body.function(function);
+    }
+
+    body.usize(program.classes.len());
+
+    for class in sorted(&program.classes) {
+        body.symbol(class.name);
+        body.usize(class.methods.len());
+
+        for method in sorted(&class.methods) {
+            body.function(method);
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + body.bytes.len());
+
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&checksum(&body.bytes).to_le_bytes());
+    bytes.extend_from_slice(&body.bytes);
+
+    bytes
+}
+
+/// Reads a program from bytes written by `encode`, checking the magic number, the
+/// version and the checksum before anything else
+pub fn decode(bytes: &[u8]) -> LoadResult<BytecodeFile> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+
+    if bytes.len() < HEADER_LEN {
+        return Err(LoadError::Truncated);
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+
+    if version != VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let mut expected = [0; 8];
+    expected.copy_from_slice(&bytes[6..HEADER_LEN]);
+
+    let body = &bytes[HEADER_LEN..];
+
+    if checksum(body) != u64::from_le_bytes(expected) {
+        return Err(LoadError::BadChecksum);
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        offset: 0,
+        objects: ptr::null_mut(),
+    };
+
+    let result = reader.file();
+
+    if result.is_err() {
+        free(reader.objects);
+    }
+
+    result
+}
+
+/// Checks if the bytes start with the magic number of a `.toxc` file
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// A 64 bit FNV-1a hash
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The values of the map ordered by their `Symbol` so the same program is always written
+/// to the same bytes
+fn sorted<T>(map: &FnvHashMap<Symbol, T>) -> Vec<&T> {
+    let mut entries: Vec<(&Symbol, &T)> = map.iter().collect();
+    entries.sort_by_key(|(symbol, _)| symbol.0);
+    entries.into_iter().map(|(_, value)| value).collect()
+}
+
+/// Frees the objects allocated for a program that failed to load
+fn free(mut object: RawObject) {
+    while !object.is_null() {
+        unsafe {
+            let next = (*object).next;
+
+            match (*object).ty {
+                ObjectType::String => drop(Box::from_raw(object as *mut StringObject)),
+                ObjectType::Func => drop(Box::from_raw(object as *mut FunctionObject)),
+                _ => unreachable!(),
+            }
+
+            object = next;
+        }
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn usize(&mut self, mut value: usize) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                self.bytes.push(byte);
+                return;
+            }
+
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn symbol(&mut self, symbol: Symbol) {
+        self.usize(symbol.0 as usize)
+    }
+
+    fn string(&mut self, string: &str) {
+        self.usize(string.len());
+        self.bytes.extend_from_slice(string.as_bytes());
+    }
+
+    fn position(&mut self, position: Position) {
+        self.usize(position.line as usize);
+        self.usize(position.column as usize);
+        self.usize(position.absolute);
+    }
+
+    fn function(&mut self, function: &Function) {
+        self.symbol(function.name);
+
+        let mut params: Vec<(&Symbol, &usize)> = function.params.iter().collect();
+        params.sort_by_key(|(_, index)| **index);
+
+        self.usize(params.len());
+
+        for (param, index) in params {
+            self.symbol(*param);
+            self.usize(*index);
+        }
+
+        self.chunk(&function.body);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.usize(chunk.code.len());
+        self.bytes.extend_from_slice(&chunk.code);
+
+        for span in chunk.lines.iter() {
+            self.position(span.start);
+            self.position(span.end);
+        }
+
+        self.usize(chunk.constants.len());
+
+        for constant in chunk.constants.iter() {
+            self.constant(*constant);
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        match value.ty() {
+            ValueType::Nil => self.bytes.push(NIL),
+            ValueType::Bool => {
+                self.bytes.push(BOOL);
+                self.bytes.push(value.as_bool() as u8);
+            }
+            ValueType::Int => {
+                self.bytes.push(INT);
+                self.bytes.extend_from_slice(&value.as_int().to_le_bytes());
+            }
+            ValueType::Float => {
+                self.bytes.push(FLOAT);
+                self.bytes
+                    .extend_from_slice(&value.as_float().to_bits().to_le_bytes());
+            }
+            ValueType::Object if value.is_string() => {
+                self.bytes.push(STRING);
+                self.string(value.as_string().value());
+            }
+            ValueType::Object if value.is_function() => {
+                let function = value.as_function();
+
+                self.bytes.push(FUNCTION);
+                self.usize(function.arity);
+                self.function(&function.function);
+            }
+            ValueType::Object => unreachable!("Only strings and functions are constants"),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    /// The objects allocated for the constants
+    objects: RawObject,
+}
+
+impl<'a> Reader<'a> {
+    fn file(&mut self) -> LoadResult<BytecodeFile> {
+        let mut symbols = Vec::new();
+
+        for _ in 0..self.usize()? {
+            let symbol = self.symbol()?;
+            let name = self.string()?;
+
+            symbols.push((symbol, name));
+        }
+
+        let mut functions = FnvHashMap::default();
+
+        for _ in 0..self.usize()? {
+            let function = self.function()?;
+            functions.insert(function.name, function);
+        }
+
+        let mut classes = FnvHashMap::default();
+
+        for _ in 0..self.usize()? {
+            let name = self.symbol()?;
+            let mut methods = FnvHashMap::default();
+
+            for _ in 0..self.usize()? {
+                let method = self.function()?;
+                methods.insert(method.name, method);
+            }
+
+            classes.insert(name, Class { name, methods });
+        }
+
+        if self.offset != self.bytes.len() {
+            return Err(LoadError::Malformed(
+                "there are bytes after the end of the program".into(),
+            ));
+        }
+
+        Ok(BytecodeFile {
+            program: Program { functions, classes },
+            objects: self.objects,
+            symbols,
+        })
+    }
+
+    fn byte(&mut self) -> LoadResult<u8> {
+        let byte = *self.bytes.get(self.offset).ok_or(LoadError::Truncated)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn take(&mut self, len: usize) -> LoadResult<&'a [u8]> {
+        if self.bytes.len() - self.offset < len {
+            return Err(LoadError::Truncated);
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn usize(&mut self) -> LoadResult<usize> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.byte()?;
+
+            if shift >= 64 {
+                return Err(LoadError::Malformed("an int is too large".into()));
+            }
+
+            value |= ((byte & 0x7f) as usize) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+
+            shift += 7;
+        }
+    }
+
+    fn u64(&mut self) -> LoadResult<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn symbol(&mut self) -> LoadResult<Symbol> {
+        Ok(Symbol(self.usize()? as u64))
+    }
+
+    fn string(&mut self) -> LoadResult<String> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| LoadError::Malformed("a string is not valid utf-8".into()))
+    }
+
+    fn position(&mut self) -> LoadResult<Position> {
+        Ok(Position {
+            line: self.usize()? as u32,
+            column: self.usize()? as u32,
+            absolute: self.usize()?,
+            // a compiled program has no source to point into
+            file: FileId::default(),
+        })
+    }
+
+    fn function(&mut self) -> LoadResult<Function> {
+        let name = self.symbol()?;
+        let mut params = FnvHashMap::default();
+
+        for _ in 0..self.usize()? {
+            let param = self.symbol()?;
+            params.insert(param, self.usize()?);
+        }
+
+        Ok(Function {
+            name,
+            params,
+            body: self.chunk()?,
+        })
+    }
+
+    fn chunk(&mut self) -> LoadResult<Chunk> {
+        let len = self.usize()?;
+        let code = self.take(len)?.to_vec();
+
+        let mut lines = Vec::with_capacity(len);
+
+        for _ in 0..len {
+            lines.push(Span {
+                start: self.position()?,
+                end: self.position()?,
+            });
+        }
+
+        let mut constants = Vec::new();
+
+        for _ in 0..self.usize()? {
+            constants.push(self.constant()?);
+        }
+
+        Ok(Chunk {
+            code,
+            constants,
+            lines,
+        })
+    }
+
+    fn constant(&mut self) -> LoadResult<Value> {
+        match self.byte()? {
+            NIL => Ok(Value::nil()),
+            BOOL => Ok(Value::bool(self.byte()? != 0)),
+            INT => Ok(Value::int(self.u64()? as i64)),
+            FLOAT => Ok(Value::float(f64::from_bits(self.u64()?))),
+            STRING => {
+                let string = self.string()?;
+                Ok(Value::object(
+                    self.allocate(StringObject::from_owned(string, self.objects)),
+                ))
+            }
+            FUNCTION => {
+                let arity = self.usize()?;
+                let function = self.function()?;
+
+                Ok(Value::object(self.allocate(FunctionObject::new(
+                    arity,
+                    function,
+                    self.objects,
+                ))))
+            }
+            tag => Err(LoadError::Malformed(format!(
+                "unknown constant tag `{}`",
+                tag
+            ))),
+        }
+    }
+
+    fn allocate(&mut self, object: RawObject) -> RawObject {
+        self.objects = object;
+        object
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, free, LoadError, MAGIC};
+    use crate::object::{FunctionObject, StringObject};
+    use crate::{Chunk, Function, Program, Value};
+    use fnv::FnvHashMap;
+    use std::ptr;
+    use util::pos::EMPTYSPAN;
+    use util::symbol::Symbol;
+
+    fn program() -> (Program, Vec<(Symbol, String)>) {
+        let objects = ptr::null_mut();
+        let string = StringObject::from_owned("hello".into(), objects);
+
+        let mut closure = Chunk::new();
+        closure.add_constant(Value::float(1.5));
+        closure.write(1, EMPTYSPAN);
+
+        let closure = FunctionObject::new(
+            0,
+            Function {
+                name: Symbol(6),
+                body: closure,
+                params: FnvHashMap::default(),
+            },
+            string,
+        );
+
+        let mut chunk = Chunk::new();
+        chunk.add_constant(Value::int(-42));
+        chunk.add_constant(Value::object(string));
+        chunk.add_constant(Value::object(closure));
+        chunk.write_operand(300, EMPTYSPAN);
+
+        let mut params = FnvHashMap::default();
+        params.insert(Symbol(7), 0);
+
+        let mut functions = FnvHashMap::default();
+        functions.insert(
+            Symbol(5),
+            Function {
+                name: Symbol(5),
+                body: chunk,
+                params,
+            },
+        );
+
+        let symbols = vec![
+            (Symbol(5), "main".to_string()),
+            (Symbol(6), "closure".to_string()),
+            (Symbol(7), "x".to_string()),
+        ];
+
+        let program = Program {
+            functions,
+            classes: FnvHashMap::default(),
+        };
+
+        (program, symbols)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (program, symbols) = program();
+        let bytes = encode(&program, &symbols);
+
+        assert_eq!(&bytes[..4], MAGIC);
+
+        let file = decode(&bytes).unwrap();
+        let main = &file.program.functions[&Symbol(5)];
+
+        assert_eq!(file.symbols, symbols);
+        assert_eq!(main.body.code, program.functions[&Symbol(5)].body.code);
+        assert_eq!(main.params[&Symbol(7)], 0);
+        assert_eq!(main.body.constants[0].as_int(), -42);
+        assert_eq!(main.body.constants[1].as_string().value(), "hello");
+        assert_eq!(
+            main.body.constants[2].as_function().function.body.constants[0].as_float(),
+            1.5
+        );
+
+        assert_eq!(encode(&file.program, &file.symbols), bytes);
+
+        free(file.objects);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let (program, symbols) = program();
+        let bytes = encode(&program, &symbols);
+
+        assert_eq!(decode(b"fn main() {}").unwrap_err(), LoadError::BadMagic);
+
+        let mut version = bytes.clone();
+        version[4] = 99;
+        assert_eq!(
+            decode(&version).unwrap_err(),
+            LoadError::UnsupportedVersion(99)
+        );
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(decode(&corrupt).unwrap_err(), LoadError::BadChecksum);
+
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]).unwrap_err(),
+            LoadError::BadChecksum
+        );
+    }
+}
@@ -1,15 +1,15 @@
 use crate::value::Value;
-#[cfg(feature = "debug")]
 use opcode;
-
-type Line = u32;
+use std::fmt::Write;
+use util::pos::{Span, EMPTYSPAN};
 
 #[derive(Debug, Clone, Default, PartialEq)]
 /// A wrapper around an array of bytes
 pub struct Chunk {
     pub code: Vec<u8>,
     pub constants: Vec<Value>,
-    pub lines: Vec<Line>,
+    /// The span of the source code each byte was compiled from
+    pub lines: Vec<Span>,
 }
 
 impl Chunk {
@@ -22,132 +22,268 @@ impl Chunk {
         self.constants.len() - 1
     }
 
-    pub fn write(&mut self, byte: u8, line: Line) {
+    pub fn write(&mut self, byte: u8, span: Span) {
         self.code.push(byte);
-        self.lines.push(line)
+        self.lines.push(span)
+    }
+
+    /// The span of the source code the byte at offset was compiled from
+    pub fn span(&self, offset: usize) -> Span {
+        self.lines.get(offset).copied().unwrap_or(EMPTYSPAN)
+    }
+
+    /// Writes an operand as a LEB128 variable length int. Each byte stores seven bits of
+    /// the operand and has its high bit set if more bytes follow, so an operand below
+    /// 128 only takes a single byte
+    pub fn write_operand(&mut self, mut operand: usize, span: Span) {
+        loop {
+            let byte = (operand & 0x7f) as u8;
+            operand >>= 7;
+
+            if operand == 0 {
+                self.write(byte, span);
+                return;
+            }
+
+            self.write(byte | 0x80, span);
+        }
+    }
+
+    /// Reads the operand that starts at `offset`.
+    /// Returns the operand and the offset of the byte after it
+    pub fn read_operand(&self, mut offset: usize) -> (usize, usize) {
+        let mut operand = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.code[offset];
+            offset += 1;
+
+            operand |= ((byte & 0x7f) as usize) << shift;
+
+            if byte & 0x80 == 0 {
+                return (operand, offset);
+            }
+
+            shift += 7;
+        }
     }
 
-    #[cfg(feature = "debug")]
-    pub fn disassemble(&self, name: &str) {
-        println!("== {} ==\n", name);
+    /// Returns the instructions of the chunk, one per line, along with the offset and the
+    /// source line of each
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut out = String::new();
+
+        writeln!(out, "== {} ==\n", name).unwrap();
 
         let mut i = 0;
 
         while i < self.code.len() {
-            i = self.disassemble_instruction(i);
+            i = self.disassemble_instruction(i, &mut out);
         }
+
+        out
     }
 
-    #[cfg(feature = "debug")]
-    pub fn disassemble_instruction(&self, offset: usize) -> usize {
-        print!("{:04}", offset);
+    /// Writes the instruction at offset to out and returns the offset of the next one
+    pub fn disassemble_instruction(&self, offset: usize, out: &mut String) -> usize {
+        write!(out, "{:04}", offset).unwrap();
 
-        if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
-            print!("   | ")
+        if offset > 0 && self.lines[offset].start.line == self.lines[offset - 1].start.line {
+            write!(out, "   | ").unwrap()
         } else {
-            print!("{:4} ", self.lines[offset])
+            write!(out, "{:4} ", self.lines[offset].start.line).unwrap()
         }
 
         let instruction = self.code[offset];
 
         match instruction {
-            opcode::IGL => simple_instruction("OPCODE::IGL", offset),
-            opcode::HLT => simple_instruction("OPCODE::HLT", offset),
-            opcode::RETURN => simple_instruction("OPCODE::RETURN", offset),
-            opcode::CONSTANT => self.constant_instruction("OPCODE::CONSTANT", offset),
-            opcode::PRINT => simple_instruction("OPCODE::PRINT", offset),
-            opcode::NEGATE => simple_instruction("OPCODE::NEGATE", offset),
-            opcode::NEGATEF => simple_instruction("OPCODE::NEGATEF", offset),
-            opcode::NIL => simple_instruction("OPCODE::NIL", offset),
-            opcode::TRUE => simple_instruction("OPCODE::TRUE", offset),
-            opcode::FALSE => simple_instruction("OPCODE::FALSE", offset),
-            opcode::NOT => simple_instruction("OPCODE::NOT", offset),
-            opcode::EQUAL => simple_instruction("OPCODE::EQUAL", offset),
-            opcode::GREATER => simple_instruction("OPCODE::GREATER", offset),
-            opcode::GREATERF => simple_instruction("OPCODE::GREATERF", offset),
-            opcode::LESS => simple_instruction("OPCODE::LESS", offset),
-            opcode::LESSF => simple_instruction("OPCODE::LESSF", offset),
-            opcode::ADD => simple_instruction("OPCODE::ADD", offset),
-            opcode::ADDF => simple_instruction("OPCODE::ADDF", offset),
-            opcode::SUB => simple_instruction("OPCODE::SUB", offset),
-            opcode::SUBF => simple_instruction("OPCODE::SUBF", offset),
-            opcode::MUL => simple_instruction("OPCODE::MUL", offset),
-            opcode::MULF => simple_instruction("OPCODE::MULF", offset),
-            opcode::DIV => simple_instruction("OPCODE::DIV", offset),
-            opcode::DIVF => simple_instruction("OPCODE::DIVF", offset),
-            opcode::JUMP => simple_instruction("OPCODE::JUMP", offset),
-            opcode::GETLOCAL => self.local_instruction("OPCODE::GETLOCAL", offset),
-            opcode::SETLOCAL => self.local_instruction("OPCODE::SETLOCAL", offset),
-            opcode::CALL => self.call_instruction("OPCODE::CALL", offset),
-            opcode::CALLCLOSURE => simple_instruction("OPCODE::CALLCLOSURE", offset),
-            opcode::JUMPIF => self.jump_instruction("OPCODE::JUMPIF", offset),
-            opcode::JUMPNOT => self.jump_instruction("OPCODE::JUMPNOT", offset),
-            opcode::LOOP => self.jump_instruction("OPCODE::LOOP", offset),
-            opcode::POP => simple_instruction("OPCODE::POP", offset),
-            opcode::CONCAT => simple_instruction("OPCODE::CONCAT", offset),
-            opcode::GETPARAM => self.local_instruction("OPCODE::GETPARAM", offset),
-            opcode::SETPARAM => self.local_instruction("OPCODE::SETPARAM", offset),
-            opcode::ARRAY => simple_instruction("OPCODE::ARRAY", offset),
-            opcode::INDEXARRAY => simple_instruction("OPCODE::INDEXARRAY", offset),
-            opcode::INDEXSTRING => simple_instruction("OPCODE::INDEXSTRING", offset),
-            opcode::GETPROPERTY => self.local_instruction("OPCODE::GETPROPERTY", offset),
-            opcode::SETPROPERTY => self.local_instruction("OPCODE::SETPROPERTY", offset),
-            opcode::GETMETHOD => self.local_instruction("OPCODE::GETMETHOD", offset),
+            opcode::IGL => simple_instruction("OPCODE::IGL", offset, out),
+            opcode::HLT => simple_instruction("OPCODE::HLT", offset, out),
+            opcode::RETURN => simple_instruction("OPCODE::RETURN", offset, out),
+            opcode::CONSTANT => self.constant_instruction("OPCODE::CONSTANT", offset, out),
+            opcode::PRINT => simple_instruction("OPCODE::PRINT", offset, out),
+            opcode::NEGATE => simple_instruction("OPCODE::NEGATE", offset, out),
+            opcode::NEGATEF => simple_instruction("OPCODE::NEGATEF", offset, out),
+            opcode::NIL => simple_instruction("OPCODE::NIL", offset, out),
+            opcode::TRUE => simple_instruction("OPCODE::TRUE", offset, out),
+            opcode::FALSE => simple_instruction("OPCODE::FALSE", offset, out),
+            opcode::NOT => simple_instruction("OPCODE::NOT", offset, out),
+            opcode::EQUAL => simple_instruction("OPCODE::EQUAL", offset, out),
+            opcode::GREATER => simple_instruction("OPCODE::GREATER", offset, out),
+            opcode::GREATERF => simple_instruction("OPCODE::GREATERF", offset, out),
+            opcode::LESS => simple_instruction("OPCODE::LESS", offset, out),
+            opcode::LESSF => simple_instruction("OPCODE::LESSF", offset, out),
+            opcode::ADD => simple_instruction("OPCODE::ADD", offset, out),
+            opcode::ADDF => simple_instruction("OPCODE::ADDF", offset, out),
+            opcode::SUB => simple_instruction("OPCODE::SUB", offset, out),
+            opcode::SUBF => simple_instruction("OPCODE::SUBF", offset, out),
+            opcode::MUL => simple_instruction("OPCODE::MUL", offset, out),
+            opcode::MULF => simple_instruction("OPCODE::MULF", offset, out),
+            opcode::DIV => simple_instruction("OPCODE::DIV", offset, out),
+            opcode::DIVF => simple_instruction("OPCODE::DIVF", offset, out),
+            opcode::MOD => simple_instruction("OPCODE::MOD", offset, out),
+            opcode::MODF => simple_instruction("OPCODE::MODF", offset, out),
+            opcode::POW => simple_instruction("OPCODE::POW", offset, out),
+            opcode::POWF => simple_instruction("OPCODE::POWF", offset, out),
+            opcode::JUMP => self.jump_instruction("OPCODE::JUMP", offset, out),
+            opcode::GETLOCAL => self.local_instruction("OPCODE::GETLOCAL", offset, out),
+            opcode::SETLOCAL => self.local_instruction("OPCODE::SETLOCAL", offset, out),
+            opcode::CALL => self.call_instruction("OPCODE::CALL", offset, out),
+            opcode::CALLNATIVE => self.local_instruction("OPCODE::CALLNATIVE", offset, out),
+            opcode::CALLCLOSURE => self.local_instruction("OPCODE::CALLCLOSURE", offset, out),
+            opcode::JUMPIF => self.jump_instruction("OPCODE::JUMPIF", offset, out),
+            opcode::JUMPNOT => self.jump_instruction("OPCODE::JUMPNOT", offset, out),
+            opcode::LOOP => self.jump_instruction("OPCODE::LOOP", offset, out),
+            opcode::POP => simple_instruction("OPCODE::POP", offset, out),
+            opcode::CONCAT => simple_instruction("OPCODE::CONCAT", offset, out),
+            opcode::GETPARAM => self.local_instruction("OPCODE::GETPARAM", offset, out),
+            opcode::SETPARAM => self.local_instruction("OPCODE::SETPARAM", offset, out),
+            opcode::ARRAY => self.local_instruction("OPCODE::ARRAY", offset, out),
+            opcode::INDEXARRAY => simple_instruction("OPCODE::INDEXARRAY", offset, out),
+            opcode::INDEXSTRING => simple_instruction("OPCODE::INDEXSTRING", offset, out),
+            opcode::SETINDEX => simple_instruction("OPCODE::SETINDEX", offset, out),
+            opcode::DUP2 => simple_instruction("OPCODE::DUP2", offset, out),
+            opcode::ARRAYLEN => simple_instruction("OPCODE::ARRAYLEN", offset, out),
+            opcode::ARRAYPUSH => simple_instruction("OPCODE::ARRAYPUSH", offset, out),
+            opcode::ARRAYPOP => simple_instruction("OPCODE::ARRAYPOP", offset, out),
+            opcode::ARRAYINSERT => simple_instruction("OPCODE::ARRAYINSERT", offset, out),
+            opcode::ARRAYREMOVE => simple_instruction("OPCODE::ARRAYREMOVE", offset, out),
+            opcode::ARRAYCONTAINS => simple_instruction("OPCODE::ARRAYCONTAINS", offset, out),
+            opcode::SLICEARRAY => simple_instruction("OPCODE::SLICEARRAY", offset, out),
+            opcode::CONCATARRAY => simple_instruction("OPCODE::CONCATARRAY", offset, out),
+            opcode::CONCATN => self.local_instruction("OPCODE::CONCATN", offset, out),
+            opcode::STRLEN => simple_instruction("OPCODE::STRLEN", offset, out),
+            opcode::PARSEINT => self.local_instruction("OPCODE::PARSEINT", offset, out),
+            opcode::PARSEFLOAT => self.local_instruction("OPCODE::PARSEFLOAT", offset, out),
+            opcode::GETPROPERTY => self.local_instruction("OPCODE::GETPROPERTY", offset, out),
+            opcode::SETPROPERTY => self.local_instruction("OPCODE::SETPROPERTY", offset, out),
+            opcode::GETMETHOD => self.local_instruction("OPCODE::GETMETHOD", offset, out),
             opcode::CALLINSTANCEMETHOD => {
-                self.call_instruction("OPCODE::CALLINSTANCEMETHOD", offset)
+                self.call_instruction("OPCODE::CALLINSTANCEMETHOD", offset, out)
+            }
+            opcode::ENUM => self.enum_instruction("OPCODE::ENUM", offset, out),
+            opcode::ENUMDATA => self.enum_instruction("OPCODE::ENUMDATA", offset, out),
+            opcode::ENUMTAG => simple_instruction("OPCODE::ENUMTAG", offset, out),
+            opcode::ENUMPAYLOAD => simple_instruction("OPCODE::ENUMPAYLOAD", offset, out),
+            opcode::CALLSTATICMETHOD => {
+                self.static_call_instruction("OPCODE::CALLSTATICMETHOD", offset, out)
             }
-            opcode::ENUM => self.enum_instruction("OPCODE::ENUM", offset),
-            opcode::CALLSTATICMETHOD => self.call_instruction("OPCODE::CALLSTATICMETHOD", offset),
-            opcode::CLASSINSTANCE => self.call_instruction("OPCODE::CLASSINSTANCE", offset),
+            opcode::CLOSURE => self.closure_instruction("OPCODE::CLOSURE", offset, out),
+            opcode::GETUPVALUE => self.local_instruction("OPCODE::GETUPVALUE", offset, out),
+            opcode::SETUPVALUE => self.local_instruction("OPCODE::SETUPVALUE", offset, out),
+            opcode::CLOSEUPVALUE => self.local_instruction("OPCODE::CLOSEUPVALUE", offset, out),
+            opcode::GETGLOBAL => self.local_instruction("OPCODE::GETGLOBAL", offset, out),
+            opcode::SETGLOBAL => self.local_instruction("OPCODE::SETGLOBAL", offset, out),
+            opcode::CLASSINSTANCE => self.class_instruction("OPCODE::CLASSINSTANCE", offset, out),
             _ => {
-                println!("UNKOWN OPCODE {}", instruction);
+                writeln!(out, "UNKOWN OPCODE {}", instruction).unwrap();
                 offset + 1
             }
         }
     }
 
-    #[cfg(feature = "debug")]
-    pub fn constant_instruction(&self, name: &str, offset: usize) -> usize {
-        let constant = self.code[offset + 1];
-        println!(
+    pub fn constant_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (constant, offset) = self.read_operand(offset + 1);
+        writeln!(
+            out,
             "{:16}{:4} '{}' ",
-            name, constant, self.constants[constant as usize]
-        );
-        offset + 2
+            name, constant, self.constants[constant]
+        )
+        .unwrap();
+        offset
     }
 
-    pub fn jump_instruction(&self, name: &str, offset: usize) -> usize {
+    pub fn jump_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
         let dest = u16::from(self.code[offset + 1]) << 8 | u16::from(self.code[offset + 2]);
 
-        println!("{:16}{:4}", name, dest,);
+        writeln!(out, "{:16}{:4}", name, dest,).unwrap();
 
         offset + 3
     }
 
-    pub fn local_instruction(&self, name: &str, offset: usize) -> usize {
-        let symbol = self.code[offset + 1];
+    pub fn local_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (symbol, offset) = self.read_operand(offset + 1);
 
-        println!("{:16}  '{}'", name, symbol,);
+        writeln!(out, "{:16}  '{}'", name, symbol,).unwrap();
 
-        offset + 2
+        offset
     }
 
-    pub fn call_instruction(&self, name: &str, offset: usize) -> usize {
-        let symbol = self.code[offset + 1];
-        println!("{:16}  '{}' ", name, symbol);
-        offset + 4
+    pub fn call_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (symbol, offset) = self.read_operand(offset + 1);
+        let (arg_count, offset) = self.read_operand(offset);
+        writeln!(out, "{:16}  '{}' args {}", name, symbol, arg_count).unwrap();
+        offset
     }
 
-    pub fn enum_instruction(&self, name: &str, offset: usize) -> usize {
-        let tag = self.code[offset + 2];
-        println!("{:16}  tag '{}' ", name, tag);
+    pub fn static_call_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (class, offset) = self.read_operand(offset + 1);
+        let (method, offset) = self.read_operand(offset);
+        let (arg_count, offset) = self.read_operand(offset);
+        writeln!(
+            out,
+            "{:16}  '{}' '{}' args {}",
+            name, class, method, arg_count
+        )
+        .unwrap();
+        offset
+    }
 
-        offset + 3
+    pub fn class_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (class, offset) = self.read_operand(offset + 1);
+        let (count, mut offset) = self.read_operand(offset);
+
+        let mut properties = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let (property, next) = self.read_operand(offset);
+            properties.push(property);
+            offset = next;
+        }
+
+        writeln!(out, "{:16}  '{}' properties {:?}", name, class, properties).unwrap();
+        offset
+    }
+
+    pub fn closure_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (constant, offset) = self.read_operand(offset + 1);
+        let (count, mut offset) = self.read_operand(offset);
+
+        writeln!(
+            out,
+            "{:16}{:4} '{}' ",
+            name, constant, self.constants[constant]
+        )
+        .unwrap();
+
+        for _ in 0..count {
+            let kind = match self.code[offset] {
+                0 => "local",
+                1 => "param",
+                _ => "upvalue",
+            };
+
+            let (index, next) = self.read_operand(offset + 1);
+
+            writeln!(out, "{:04}    |{:>22} {}", offset, kind, index).unwrap();
+
+            offset = next;
+        }
+
+        offset
+    }
+
+    pub fn enum_instruction(&self, name: &str, offset: usize, out: &mut String) -> usize {
+        let (_, offset) = self.read_operand(offset + 1);
+        let (tag, offset) = self.read_operand(offset);
+        writeln!(out, "{:16}  tag '{}' ", name, tag).unwrap();
+
+        offset
     }
 }
 
-#[cfg(feature = "debug")]
-pub fn simple_instruction(name: &str, offset: usize) -> usize {
-    println!("{}", name);
+pub fn simple_instruction(name: &str, offset: usize, out: &mut String) -> usize {
+    writeln!(out, "{}", name).unwrap();
     offset + 1
 }
@@ -0,0 +1,299 @@
+//! A mark and sweep garbage collector.
+//! Every object allocated by the vm is linked into the list of objects owned by the `Heap`.
+//! When a collection is done the vm marks its roots, the heap traces the objects reachable
+//! from them and any object that was not reached is freed.
+use crate::object::{
+    ArrayObject, ClassObject, ClosureObject, EnumObject, FunctionObject, InstanceObject,
+    NativeObject, ObjectType, RawObject, StringObject, Upvalue, UpvalueObject,
+};
+use crate::value::Value;
+use crate::Function;
+use std::mem;
+
+/// The number of bytes that can be allocated before the first collection
+const FIRST_GC: usize = 1024 * 1024;
+/// How much the heap is allowed to grow after a collection before the next one
+const HEAP_GROW_FACTOR: usize = 2;
+
+/// Statistics about the garbage collector
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcStats {
+    /// The number of collections that have been done
+    pub collections: usize,
+    /// The number of objects that have been allocated
+    pub objects_allocated: usize,
+    /// The number of objects that have been freed
+    pub objects_freed: usize,
+    /// The number of bytes currently allocated
+    pub bytes_allocated: usize,
+}
+
+#[derive(Debug)]
+pub struct Heap {
+    /// The head of the linked list of all the objects
+    objects: RawObject,
+    /// Objects that have been marked but whose children have not been traced
+    gray: Vec<RawObject>,
+    /// The number of bytes that can be allocated before the next collection
+    next_gc: usize,
+    /// Collect on every instruction that is run
+    stress: bool,
+    stats: GcStats,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Heap {
+            objects: ::std::ptr::null::<RawObject>() as RawObject,
+            gray: Vec::new(),
+            next_gc: FIRST_GC,
+            stress: false,
+            stats: GcStats::default(),
+        }
+    }
+
+    /// Takes ownership of the objects in the list starting at `objects`
+    pub fn adopt(&mut self, objects: RawObject) {
+        let mut object = objects;
+
+        while !object.is_null() {
+            let next = unsafe { (*object).next };
+            self.allocate(object);
+            object = next;
+        }
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress
+    }
+
+    /// The head of the list of objects. Use it as the `next` of a newly created object
+    pub fn objects(&self) -> RawObject {
+        self.objects
+    }
+
+    /// Links a newly created object into the list of objects
+    pub fn allocate(&mut self, object: RawObject) -> RawObject {
+        unsafe {
+            debug_assert!(!(*object).owned, "An object was allocated twice");
+
+            (*object).next = self.objects;
+            (*object).owned = true;
+        }
+
+        self.objects = object;
+        self.stats.objects_allocated += 1;
+        self.stats.bytes_allocated += size_of(object);
+
+        object
+    }
+
+    /// Counts the bytes an object took when it grew after it was allocated, so that
+    /// freeing it takes off the same number of bytes
+    pub fn grow(&mut self, bytes: usize) {
+        self.stats.bytes_allocated += bytes;
+    }
+
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.stats.bytes_allocated > self.next_gc
+    }
+
+    pub fn mark_value(&mut self, value: Value) {
+        if value.is_object() {
+            self.mark_object(value.as_object())
+        }
+    }
+
+    pub fn mark_object(&mut self, object: RawObject) {
+        if object.is_null() {
+            return;
+        }
+
+        unsafe {
+            if (*object).marked {
+                return;
+            }
+
+            (*object).marked = true;
+        }
+
+        self.gray.push(object);
+    }
+
+    /// Marks the constants used by the function
+    pub fn mark_function(&mut self, function: &Function) {
+        for constant in function.body.constants.iter() {
+            self.mark_value(*constant);
+        }
+    }
+
+    /// Traces the objects reachable from the marked roots and frees every object that
+    /// was not reached
+    pub fn collect(&mut self) {
+        #[cfg(feature = "debug")]
+        let before = self.stats.bytes_allocated;
+
+        while let Some(object) = self.gray.pop() {
+            self.blacken(object);
+        }
+
+        self.sweep();
+
+        self.stats.collections += 1;
+        self.next_gc = ::std::cmp::max(self.stats.bytes_allocated * HEAP_GROW_FACTOR, FIRST_GC);
+
+        #[cfg(feature = "debug")]
+        println!(
+            "-- gc collected {} bytes (from {} to {}) next at {}",
+            before - self.stats.bytes_allocated,
+            before,
+            self.stats.bytes_allocated,
+            self.next_gc
+        );
+    }
+
+    /// Marks the children of the object
+    fn blacken(&mut self, object: RawObject) {
+        let value = Value::object(object);
+
+        match unsafe { (*object).ty } {
+            ObjectType::String | ObjectType::Native => (),
+
+            ObjectType::Func => self.mark_function(&value.as_function().function),
+
+            ObjectType::Array => {
+                for item in value.as_array().items.iter() {
+                    self.mark_value(*item);
+                }
+            }
+
+            ObjectType::Class => {
+                for method in value.as_class().methods.values() {
+                    self.mark_function(&method.function);
+                }
+            }
+
+            ObjectType::Instance => {
+                let instance = value.as_instance();
+
+                for property in instance.properties.values() {
+                    self.mark_value(*property);
+                }
+
+                for method in instance.methods.values() {
+                    self.mark_function(method);
+                }
+            }
+
+            ObjectType::Enum => {
+                if let Some(data) = value.as_enum().data {
+                    self.mark_value(data)
+                }
+            }
+
+            ObjectType::Closure => {
+                let closure = value.as_closure();
+
+                self.mark_value(closure.function);
+
+                for upvalue in closure.upvalues.iter() {
+                    self.mark_object(*upvalue);
+                }
+            }
+
+            ObjectType::Upvalue => {
+                if let Upvalue::Closed(value) = value.as_mut_upvalue().value {
+                    self.mark_value(value)
+                }
+            }
+        }
+    }
+
+    /// Frees every unmarked object and clears the mark of the rest
+    fn sweep(&mut self) {
+        let mut previous: RawObject = ::std::ptr::null::<RawObject>() as RawObject;
+        let mut object = self.objects;
+
+        while !object.is_null() {
+            unsafe {
+                let next = (*object).next;
+
+                if (*object).marked {
+                    (*object).marked = false;
+                    previous = object;
+                } else {
+                    if previous.is_null() {
+                        self.objects = next;
+                    } else {
+                        (*previous).next = next;
+                    }
+
+                    self.stats.objects_freed += 1;
+                    self.stats.bytes_allocated -= size_of(object);
+
+                    free(object);
+                }
+
+                object = next;
+            }
+        }
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        // Nothing is marked so every object is freed
+        self.sweep();
+    }
+}
+
+/// The number of bytes used by the object, including what it owns on the heap
+fn size_of(object: RawObject) -> usize {
+    let value = Value::object(object);
+
+    match unsafe { (*object).ty } {
+        ObjectType::String => {
+            let string = value.as_string();
+
+            mem::size_of::<StringObject>() + string.value().len()
+        }
+        ObjectType::Func => mem::size_of::<FunctionObject>(),
+        ObjectType::Array => {
+            mem::size_of::<ArrayObject>()
+                + value.as_array().items.capacity() * mem::size_of::<Value>()
+        }
+        ObjectType::Class => mem::size_of::<ClassObject>(),
+        ObjectType::Instance => {
+            let instance = value.as_instance();
+
+            mem::size_of::<InstanceObject>()
+                + instance.properties.capacity() * mem::size_of::<Value>()
+        }
+        ObjectType::Native => mem::size_of::<NativeObject>(),
+        ObjectType::Enum => mem::size_of::<EnumObject>(),
+        ObjectType::Closure => {
+            mem::size_of::<ClosureObject>()
+                + value.as_closure().upvalues.capacity() * mem::size_of::<RawObject>()
+        }
+        ObjectType::Upvalue => mem::size_of::<UpvalueObject>(),
+    }
+}
+
+/// Frees the object as the type it was allocated as
+unsafe fn free(object: RawObject) {
+    match (*object).ty {
+        ObjectType::String => drop(Box::from_raw(object as *mut StringObject)),
+        ObjectType::Func => drop(Box::from_raw(object as *mut FunctionObject)),
+        ObjectType::Array => drop(Box::from_raw(object as *mut ArrayObject)),
+        ObjectType::Class => drop(Box::from_raw(object as *mut ClassObject)),
+        ObjectType::Instance => drop(Box::from_raw(object as *mut InstanceObject)),
+        ObjectType::Native => drop(Box::from_raw(object as *mut NativeObject)),
+        ObjectType::Enum => drop(Box::from_raw(object as *mut EnumObject)),
+        ObjectType::Closure => drop(Box::from_raw(object as *mut ClosureObject)),
+        ObjectType::Upvalue => drop(Box::from_raw(object as *mut UpvalueObject)),
+    }
+}
@@ -6,16 +6,20 @@ use opcode;
 
 #[macro_use]
 mod macros;
+pub mod bytecode;
 mod chunk;
+mod gc;
 mod native;
 mod object;
 mod value;
 mod vm;
 
 pub use crate::chunk::Chunk;
-pub use crate::object::{FunctionObject, RawObject, StringObject};
-pub use crate::value::Value;
-pub use crate::vm::VM;
+pub use crate::gc::GcStats;
+pub use crate::native::{Native, NativeRegistry, NativeType};
+pub use crate::object::{ArrayObject, FunctionObject, RawObject, StringObject};
+pub use crate::value::{Value, ValueType};
+pub use crate::vm::{RuntimeError, State, VM};
 use fnv::FnvHashMap;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -36,3 +40,51 @@ pub struct Program {
     pub functions: FnvHashMap<::util::symbol::Symbol, Function>,
     pub classes: FnvHashMap<::util::symbol::Symbol, Class>,
 }
+
+impl Program {
+    /// Returns the bytecode of every function and method ordered by name. The closures
+    /// created by a function follow it
+    pub fn disassemble(&self, symbols: &::util::symbol::Symbols<()>) -> String {
+        let mut functions: Vec<(String, &Function)> = self
+            .functions
+            .values()
+            .map(|function| (symbols.name(function.name), function))
+            .collect();
+
+        for class in self.classes.values() {
+            for method in class.methods.values() {
+                let name = format!("{}.{}", symbols.name(class.name), symbols.name(method.name));
+                functions.push((name, method));
+            }
+        }
+
+        functions.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut out = String::new();
+
+        for (name, function) in functions {
+            disassemble_function(&name, function, symbols, &mut out);
+        }
+
+        out
+    }
+}
+
+fn disassemble_function(
+    name: &str,
+    function: &Function,
+    symbols: &::util::symbol::Symbols<()>,
+    out: &mut String,
+) {
+    out.push_str(&function.body.disassemble(name));
+    out.push('\n');
+
+    for constant in function.body.constants.iter() {
+        if constant.is_function() {
+            let closure = &constant.as_function().function;
+            let name = format!("{}::{}", name, symbols.name(closure.name));
+
+            disassemble_function(&name, closure, symbols, out);
+        }
+    }
+}
@@ -10,3 +10,17 @@ macro_rules! binary_op {
 
     }};
 }
+
+/// An int operation that raises a runtime error rather than overflowing
+macro_rules! checked_int_op {
+    ($method:ident,$_self:ident,$offset:ident) => {{
+        let b = $_self.pop().as_int();
+
+        let a = $_self.pop().as_int();
+
+        match a.$method(b) {
+            Some(result) => $_self.push(Value::int(result)),
+            None => return Err($_self.error($offset, "Integer overflow")),
+        }
+    }};
+}
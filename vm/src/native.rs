@@ -1,25 +1,193 @@
-use crate::object::{RawObject, StringObject};
+//! The functions written in rust that tox programs can call.
+//!
+//! A native is registered once with a `NativeRegistry` along with the types of its params
+//! and its return type. The type checker declares each native of the registry and the vm
+//! creates a `NativeObject` for it, so both look natives up by name.
+use crate::object::{ArrayObject, NativeFn, RawObject, StringObject};
 use crate::value::Value;
 use rand::{thread_rng, Rng};
+use std::fmt::{self, Debug, Display};
+use std::rc::Rc;
 use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The types a native function can take and return
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeType {
+    Int,
+    Float,
+    Bool,
+    Str,
+    Nil,
+    Array(Box<NativeType>),
+}
+
+/// A function written in rust that can be called from tox
+#[derive(Clone)]
+pub struct Native {
+    pub name: String,
+    pub params: Vec<NativeType>,
+    pub returns: NativeType,
+    pub function: NativeFn,
+}
+
+/// The native functions a program can call
+#[derive(Clone)]
+pub struct NativeRegistry {
+    natives: Vec<Native>,
+}
+
+impl NativeRegistry {
+    /// A registry with the builtin natives; `clock`, `random`, `read`, `fopen` and the
+    /// string functions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A registry without any natives
+    pub fn empty() -> Self {
+        NativeRegistry { natives: vec![] }
+    }
+
+    /// Registers a native function. The function is passed exactly one value for each of
+    /// the params. The vm takes ownership of any new object it returns, and of any new
+    /// object in an array it returns, while one of its arguments can be returned as it is.
+    /// The arguments are only alive during the call so they must not be kept. A native with
+    /// the same name as an earlier one replaces it
+    pub fn register<F>(
+        &mut self,
+        name: &str,
+        params: Vec<NativeType>,
+        returns: NativeType,
+        function: F,
+    ) where
+        F: Fn(&[Value]) -> Value + 'static,
+    {
+        self.natives.retain(|native| native.name != name);
+
+        self.natives.push(Native {
+            name: name.to_owned(),
+            params,
+            returns,
+            function: Rc::new(function),
+        });
+    }
+
+    /// Finds the native with the given name
+    pub fn get(&self, name: &str) -> Option<&Native> {
+        self.natives.iter().find(|native| native.name == name)
+    }
+
+    /// Every native in the order they were registered
+    pub fn iter(&self) -> impl Iterator<Item = &Native> {
+        self.natives.iter()
+    }
+}
+
+impl Default for NativeRegistry {
+    fn default() -> Self {
+        let mut registry = NativeRegistry::empty();
+
+        registry.register("clock", vec![], NativeType::Float, clock);
+        registry.register(
+            "random",
+            vec![NativeType::Int, NativeType::Int],
+            NativeType::Int,
+            random,
+        );
+        registry.register("read", vec![], NativeType::Str, read);
+        registry.register("fopen", vec![NativeType::Str], NativeType::Str, fopen);
+
+        registry.register(
+            "substring",
+            vec![NativeType::Str, NativeType::Int, NativeType::Int],
+            NativeType::Str,
+            substring,
+        );
+        registry.register(
+            "find",
+            vec![NativeType::Str, NativeType::Str],
+            NativeType::Int,
+            find,
+        );
+        registry.register(
+            "starts_with",
+            vec![NativeType::Str, NativeType::Str],
+            NativeType::Bool,
+            starts_with,
+        );
+        registry.register(
+            "ends_with",
+            vec![NativeType::Str, NativeType::Str],
+            NativeType::Bool,
+            ends_with,
+        );
+        registry.register(
+            "split",
+            vec![NativeType::Str, NativeType::Str],
+            NativeType::Array(Box::new(NativeType::Str)),
+            split,
+        );
+        registry.register("trim", vec![NativeType::Str], NativeType::Str, trim);
+        registry.register("to_upper", vec![NativeType::Str], NativeType::Str, to_upper);
+        registry.register("to_lower", vec![NativeType::Str], NativeType::Str, to_lower);
+        registry.register(
+            "replace",
+            vec![NativeType::Str, NativeType::Str, NativeType::Str],
+            NativeType::Str,
+            replace,
+        );
+
+        registry
+    }
+}
+
+impl Display for NativeType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NativeType::Int => write!(fmt, "int"),
+            NativeType::Float => write!(fmt, "float"),
+            NativeType::Bool => write!(fmt, "bool"),
+            NativeType::Str => write!(fmt, "str"),
+            NativeType::Nil => write!(fmt, "nil"),
+            NativeType::Array(ref ty) => write!(fmt, "[{}]", ty),
+        }
+    }
+}
+
+impl Debug for Native {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("Native")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("returns", &self.returns)
+            .finish()
+    }
+}
+
+impl Debug for NativeRegistry {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_list().entries(self.natives.iter()).finish()
+    }
+}
+
 /// Calculate the number of seconds since the UNIX_EPOCH
-pub fn clock(_: *const Value) -> Value {
+pub fn clock(_: &[Value]) -> Value {
     let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
     Value::float(time.as_secs() as f64 + f64::from(time.subsec_nanos()) * 1e-9)
 }
 /// Return a random number between the min and max range
 /// Panics if min is larger than the max
-pub fn random(args: *const Value) -> Value {
-    let min = unsafe { (*args.add(0)).as_int() };
+pub fn random(args: &[Value]) -> Value {
+    let min = args[0].as_int();
+    let max = args[1].as_int();
 
-    let max = unsafe { (*args.add(1)).as_int() };
     let mut rng = thread_rng();
     Value::int(rng.gen_range(min, max))
 }
 
 /// Reads input from stdin until the user presses enter
-pub fn read(_: *const Value) -> Value {
+pub fn read(_: &[Value]) -> Value {
     let mut input = String::new();
     use std::io;
     io::stdin()
@@ -33,8 +201,8 @@ pub fn read(_: *const Value) -> Value {
 }
 
 /// Open a file and returns the contents
-pub fn fopen(args: *const Value) -> Value {
-    let path = unsafe { (*args.add(0)).as_string().value().trim_end_matches('\0') };
+pub fn fopen(args: &[Value]) -> Value {
+    let path = args[0].as_string().value();
 
     let mut input = String::new();
 
@@ -50,3 +218,148 @@ pub fn fopen(args: *const Value) -> Value {
         ::std::ptr::null::<RawObject>() as RawObject,
     ))
 }
+
+// The string functions count in chars rather than bytes
+
+/// `substring(s, start, end)` the chars of `s` from `start` up to `end`. The bounds are
+/// clamped to the string so a bound past the end gives the rest of it
+pub fn substring(args: &[Value]) -> Value {
+    let string = args[0].as_string().value();
+    let len = string.chars().count() as i64;
+
+    let start = args[1].as_int().max(0).min(len);
+    let end = args[2].as_int().max(start).min(len);
+
+    let chars = string
+        .chars()
+        .skip(start as usize)
+        .take((end - start) as usize);
+
+    new_string(chars.collect())
+}
+
+/// `find(s, pattern)` the index of the first char of the first `pattern` in `s` or -1 if
+/// there isn't one
+pub fn find(args: &[Value]) -> Value {
+    let string = args[0].as_string().value();
+    let pattern = args[1].as_string().value();
+
+    Value::int(match string.find(pattern) {
+        Some(byte) => string[..byte].chars().count() as i64,
+        None => -1,
+    })
+}
+
+pub fn starts_with(args: &[Value]) -> Value {
+    let string = args[0].as_string().value();
+
+    Value::bool(string.starts_with(args[1].as_string().value()))
+}
+
+pub fn ends_with(args: &[Value]) -> Value {
+    let string = args[0].as_string().value();
+
+    Value::bool(string.ends_with(args[1].as_string().value()))
+}
+
+/// `split(s, separator)` the pieces of `s` between each `separator`. An empty separator
+/// splits the string into its chars
+pub fn split(args: &[Value]) -> Value {
+    let string = args[0].as_string().value();
+    let separator = args[1].as_string().value();
+
+    let items = if separator.is_empty() {
+        string
+            .chars()
+            .map(|ch| new_string(ch.to_string()))
+            .collect()
+    } else {
+        string
+            .split(separator)
+            .map(|piece| new_string(piece.to_owned()))
+            .collect()
+    };
+
+    Value::object(ArrayObject::new(
+        items,
+        ::std::ptr::null::<RawObject>() as RawObject,
+    ))
+}
+
+/// Removes the whitespace at the start and the end of a string
+pub fn trim(args: &[Value]) -> Value {
+    new_string(args[0].as_string().value().trim().to_owned())
+}
+
+pub fn to_upper(args: &[Value]) -> Value {
+    new_string(args[0].as_string().value().to_uppercase())
+}
+
+pub fn to_lower(args: &[Value]) -> Value {
+    new_string(args[0].as_string().value().to_lowercase())
+}
+
+/// `replace(s, from, to)` replaces every `from` in `s` with `to`. An empty `from` leaves
+/// the string as it is
+pub fn replace(args: &[Value]) -> Value {
+    let string = args[0].as_string().value();
+    let from = args[1].as_string().value();
+
+    if from.is_empty() {
+        return new_string(string.to_owned());
+    }
+
+    new_string(string.replace(from, args[2].as_string().value()))
+}
+
+fn new_string(string: String) -> Value {
+    Value::object(StringObject::from_owned(
+        string,
+        ::std::ptr::null::<RawObject>() as RawObject,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NativeRegistry, NativeType};
+    use crate::value::Value;
+
+    #[test]
+    fn register() {
+        let mut registry = NativeRegistry::new();
+
+        registry.register("clock", vec![], NativeType::Int, |_| Value::int(1));
+        registry.register("double", vec![NativeType::Int], NativeType::Int, |args| {
+            Value::int(args[0].as_int() * 2)
+        });
+
+        let names: Vec<&str> = registry.iter().map(|native| native.name.as_str()).collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "random",
+                "read",
+                "fopen",
+                "substring",
+                "find",
+                "starts_with",
+                "ends_with",
+                "split",
+                "trim",
+                "to_upper",
+                "to_lower",
+                "replace",
+                "clock",
+                "double"
+            ]
+        );
+
+        let double = registry.get("double").unwrap();
+
+        assert_eq!(double.params, vec![NativeType::Int]);
+        assert_eq!((double.function)(&[Value::int(21)]).as_int(), 42);
+        assert_eq!(registry.get("clock").unwrap().returns, NativeType::Int);
+        assert!(NativeRegistry::empty().get("clock").is_none());
+    }
+}
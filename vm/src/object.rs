@@ -6,10 +6,12 @@ use crate::value::Value;
 use fnv::FnvHashMap;
 use std::fmt::{self, Debug, Display};
 use std::ops::Deref;
+use std::rc::Rc;
 use util::symbol::Symbol;
 
 pub type RawObject = *mut Object;
-pub type NativeFn = fn(*const Value) -> Value;
+/// A native function is passed the values of its params
+pub type NativeFn = Rc<dyn Fn(&[Value]) -> Value>;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 #[repr(C)]
@@ -21,9 +23,11 @@ pub enum ObjectType {
     Instance,
     Native,
     Enum,
+    Closure,
+    Upvalue,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Clone)]
 #[repr(C)]
 pub struct NativeObject {
     pub obj: Object,
@@ -36,6 +40,10 @@ pub struct NativeObject {
 pub struct Object {
     pub ty: ObjectType,
     pub next: RawObject,
+    /// Set by the garbage collector when the object is reachable
+    pub marked: bool,
+    /// Set once the heap has linked the object into its list so it is never linked twice
+    pub owned: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -84,6 +92,37 @@ pub struct FunctionObject {
     pub function: super::Function,
 }
 
+/// A function along with the variables it captured from the scopes that enclose it
+#[derive(Debug, Clone, PartialEq)]
+#[repr(C)]
+pub struct ClosureObject {
+    pub obj: Object,
+    /// The `FunctionObject` that holds the body of the closure
+    pub function: Value,
+    /// Pointers to the `UpvalueObject` of each captured variable
+    pub upvalues: Vec<RawObject>,
+}
+
+/// A variable captured by a closure
+#[derive(Debug, Clone, PartialEq)]
+#[repr(C)]
+pub struct UpvalueObject {
+    pub obj: Object,
+    pub value: Upvalue,
+}
+
+/// Where the value of a captured variable currently lives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Upvalue {
+    /// A local within the stack frame at the given depth
+    Local { frame: usize, slot: usize },
+    /// A param of the stack frame at the given depth
+    Param { frame: usize, slot: usize },
+    /// The stack frame has returned or the variable went out of scope so the upvalue
+    /// owns the value
+    Closed(Value),
+}
+
 #[derive(Clone, PartialEq)]
 #[repr(C)]
 pub enum ObjectValue<'a> {
@@ -98,7 +137,28 @@ pub enum ObjectValue<'a> {
 
 impl Object {
     pub fn new(ty: ObjectType, next: RawObject) -> Self {
-        Object { ty, next }
+        Object {
+            ty,
+            next,
+            marked: false,
+            owned: false,
+        }
+    }
+}
+
+impl Debug for NativeObject {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("NativeObject")
+            .field("obj", &self.obj)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+impl PartialEq for NativeObject {
+    fn eq(&self, other: &NativeObject) -> bool {
+        self.arity == other.arity
+            && Rc::as_ptr(&self.function) as *const u8 == Rc::as_ptr(&other.function) as *const u8
     }
 }
 
@@ -150,6 +210,29 @@ impl EnumObject {
     }
 }
 
+impl ClosureObject {
+    pub fn new(function: Value, upvalues: Vec<RawObject>, next: RawObject) -> RawObject {
+        let closure = ClosureObject {
+            obj: Object::new(ObjectType::Closure, next),
+            function,
+            upvalues,
+        };
+
+        Box::into_raw(Box::new(closure)) as RawObject
+    }
+}
+
+impl UpvalueObject {
+    pub fn new(value: Upvalue, next: RawObject) -> RawObject {
+        let upvalue = UpvalueObject {
+            obj: Object::new(ObjectType::Upvalue, next),
+            value,
+        };
+
+        Box::into_raw(Box::new(upvalue)) as RawObject
+    }
+}
+
 impl InstanceObject {
     pub fn new(
         methods: FnvHashMap<Symbol, Function>,
@@ -177,6 +177,13 @@ mod NAN_tagging {
             unsafe { mem::transmute(ptr) }
         }
 
+        #[inline]
+        pub fn as_mut_array<'a>(&self) -> &'a mut ArrayObject {
+            let ptr = self.as_object();
+
+            unsafe { mem::transmute(ptr) }
+        }
+
         #[inline]
         pub fn as_class<'a>(&self) -> &'a ClassObject {
             let ptr = self.as_object();
@@ -329,8 +336,8 @@ mod NAN_tagging {
 #[cfg(not(feature = "NAN_tagging"))]
 mod normal {
     use crate::object::{
-        ArrayObject, ClassObject, EnumObject, FunctionObject, InstanceObject, NativeObject, Object,
-        ObjectType, RawObject, StringObject,
+        ArrayObject, ClassObject, ClosureObject, EnumObject, FunctionObject, InstanceObject,
+        NativeObject, Object, ObjectType, RawObject, StringObject, UpvalueObject,
     };
 
     use std::fmt::{self, Debug, Display};
@@ -465,6 +472,13 @@ mod normal {
             unsafe { &*(ptr as *const ArrayObject) }
         }
 
+        #[inline]
+        pub fn as_mut_array<'a>(&self) -> &'a mut ArrayObject {
+            let ptr = self.as_object();
+
+            unsafe { &mut *(ptr as *mut ArrayObject) }
+        }
+
         #[inline]
         pub fn as_class<'a>(&self) -> &'a ClassObject {
             let ptr = self.as_object();
@@ -500,11 +514,47 @@ mod normal {
             unsafe { &mut *(ptr as *mut InstanceObject) }
         }
 
+        #[inline]
+        pub fn as_enum<'a>(&self) -> &'a EnumObject {
+            let ptr = self.as_object();
+
+            unsafe { &*(ptr as *const EnumObject) }
+        }
+
+        #[inline]
+        pub fn as_closure<'a>(&self) -> &'a ClosureObject {
+            let ptr = self.as_object();
+
+            unsafe { &*(ptr as *const ClosureObject) }
+        }
+
+        #[inline]
+        pub fn as_mut_upvalue<'a>(&self) -> &'a mut UpvalueObject {
+            let ptr = self.as_object();
+
+            unsafe { &mut *(ptr as *mut UpvalueObject) }
+        }
+
+        #[inline]
+        pub fn ty(&self) -> ValueType {
+            self.ty
+        }
+
+        #[inline]
+        pub fn is_nil(&self) -> bool {
+            self.ty == ValueType::Nil
+        }
+
         #[inline]
         pub fn is_object(&self) -> bool {
             self.ty == ValueType::Object
         }
 
+        #[inline]
+        pub fn is_function(&self) -> bool {
+            unsafe { self.is_object() && (*self.as_object()).ty == ObjectType::Func }
+        }
+
         #[inline]
         pub fn is_class(&self) -> bool {
             unsafe { self.is_object() && (*self.as_object()).ty == ObjectType::Class }
@@ -514,6 +564,12 @@ mod normal {
         pub fn is_string(&self) -> bool {
             unsafe { self.is_object() && (*self.as_object()).ty == ObjectType::String }
         }
+
+        #[inline]
+        pub fn is_array(&self) -> bool {
+            unsafe { self.is_object() && (*self.as_object()).ty == ObjectType::Array }
+        }
+
         #[inline]
         pub fn is_native(&self) -> bool {
             unsafe { self.is_object() && (*self.as_object()).ty == ObjectType::Native }
@@ -562,6 +618,14 @@ mod normal {
                             ObjectType::Native => {
                                 write!(fmt, "{:#?}", &*(self.val.object as *const NativeObject))?
                             }
+
+                            ObjectType::Closure => {
+                                write!(fmt, "{:#?}", &*(self.val.object as *const ClosureObject))?
+                            }
+
+                            ObjectType::Upvalue => {
+                                write!(fmt, "{:#?}", &*(self.val.object as *const UpvalueObject))?
+                            }
                         }
                     }
                 }
@@ -595,6 +659,8 @@ mod normal {
                         ObjectType::Instance => write!(fmt, "instance")?,
                         ObjectType::Native => write!(fmt, "native")?,
                         ObjectType::Enum => write!(fmt, "enum")?,
+                        ObjectType::Closure => write!(fmt, "closure")?,
+                        ObjectType::Upvalue => write!(fmt, "upvalue")?,
                     }
                 }
             }
@@ -680,6 +746,24 @@ mod normal {
 
                                 self_native == other_native
                             }
+
+                            ObjectType::Closure => {
+                                let self_closure: &ClosureObject =
+                                    &*(self.as_object() as *const ClosureObject);
+                                let other_closure: &ClosureObject =
+                                    &*(other.as_object() as *const ClosureObject);
+
+                                self_closure == other_closure
+                            }
+
+                            ObjectType::Upvalue => {
+                                let self_upvalue: &UpvalueObject =
+                                    &*(self.as_object() as *const UpvalueObject);
+                                let other_upvalue: &UpvalueObject =
+                                    &*(other.as_object() as *const UpvalueObject);
+
+                                self_upvalue == other_upvalue
+                            }
                         }
                     },
                 }
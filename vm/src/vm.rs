@@ -1,31 +1,62 @@
 use super::{Function, Program};
-use crate::native;
+use crate::gc::{GcStats, Heap};
+use crate::native::NativeRegistry;
 use crate::object::{
-    ArrayObject, EnumObject, FunctionObject, InstanceObject, NativeObject, RawObject, StringObject,
+    ArrayObject, ClosureObject, EnumObject, FunctionObject, InstanceObject, NativeObject,
+    RawObject, StringObject, Upvalue, UpvalueObject,
 };
 use crate::opcode;
 use crate::value::Value;
 use fnv::FnvHashMap;
-use util::symbol::Symbol;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::rc::Rc;
+use util::emmiter::{ColourMode, Reporter};
+use util::pos::Span;
+use util::source::SourceMap;
+use util::symbol::{Symbol, Symbols};
 /// The max size of the stack
 const STACK_MAX: usize = 256;
+/// The max number of calls that can be running at once
+const FRAMES_MAX: usize = 1024;
 
 #[derive(Debug)]
 pub struct StackFrame<'a> {
     ip: usize,
-    locals: FnvHashMap<u8, Value>,
+    locals: FnvHashMap<usize, Value>,
     function: &'a Function,
-    params: FnvHashMap<u8, Value>,
+    params: FnvHashMap<usize, Value>,
+    /// The closure being run, if the function is a closure
+    closure: Option<&'a ClosureObject>,
+    /// The instance the method being run belongs to
+    instance: Option<Value>,
 }
 
 pub struct VM<'a> {
-    stack: [Value; STACK_MAX],
+    /// Grows past `STACK_MAX` so the instruction that overflows it can finish before
+    /// the overflow is reported
+    stack: Vec<Value>,
     frames: Vec<StackFrame<'a>>,
     current_frame: StackFrame<'a>,
     native_functions: FnvHashMap<Symbol, Value>,
     program: &'a Program,
-    objects: RawObject,
+    heap: Heap,
+    globals: FnvHashMap<Symbol, Value>,
     stack_top: usize,
+    /// Upvalues that still point at a variable within a stack frame
+    open_upvalues: Vec<RawObject>,
+    /// Where `print` writes to
+    output: Box<dyn Write + 'a>,
+}
+
+/// The parts of a vm that outlive the program it runs; the heap, the globals and the
+/// native functions. A repl passes it from one vm to the next so the values created by
+/// an earlier input can still be used
+#[derive(Debug)]
+pub struct State {
+    heap: Heap,
+    globals: FnvHashMap<Symbol, Value>,
+    native_functions: FnvHashMap<Symbol, Value>,
 }
 
 #[derive(Debug)]
@@ -34,8 +65,132 @@ pub enum Error {
     UnknownOpcode,
 }
 
+/// An error raised while the program is running
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    /// The function that was running when the error was raised
+    pub function: Symbol,
+    /// The offset of the instruction that raised the error
+    pub offset: usize,
+    /// The span of the code the instruction was compiled from
+    pub span: Span,
+    /// The function of each frame on the call stack and the span of the code it was
+    /// running, starting with the frame that raised the error
+    pub trace: Vec<(Symbol, Span)>,
+}
+
+type RuntimeResult<T> = Result<T, RuntimeError>;
+
+impl RuntimeError {
+    /// Renders the error against the source along with the calls that lead to it
+    pub fn render(&self, input: &str, symbols: &Symbols<()>, colour: ColourMode) -> String {
+        let mut reporter = Reporter::new();
+        reporter.set_colour(colour);
+
+        reporter.run_time_error(self.message.as_str(), self.span);
+
+        let mut out = reporter.render(input);
+
+        for (function, span) in self.trace.iter() {
+            writeln!(
+                out,
+                "    in `{}` on line {}",
+                symbols.name(*function),
+                span.start.line
+            )
+            .unwrap();
+        }
+
+        out
+    }
+
+    /// Renders the error against the file it was raised in along with the calls that lead
+    /// to it and the files they were made in
+    pub fn render_files(
+        &self,
+        files: &SourceMap,
+        symbols: &Symbols<()>,
+        colour: ColourMode,
+    ) -> String {
+        let mut reporter = Reporter::new();
+        reporter.set_colour(colour);
+
+        reporter.run_time_error(self.message.as_str(), self.span);
+
+        let mut out = reporter.render_files(files);
+
+        for (function, span) in self.trace.iter() {
+            writeln!(
+                out,
+                "    in `{}` at {}:{}",
+                symbols.name(*function),
+                files.name(span.start.file),
+                span.start.line
+            )
+            .unwrap();
+        }
+
+        out
+    }
+}
+
+impl State {
+    /// A state with an empty heap, no globals and a native function for each native of
+    /// the registry
+    pub fn new(natives: &NativeRegistry, symbols: &mut Symbols<()>) -> Self {
+        let mut heap = Heap::new();
+
+        let mut native_functions = FnvHashMap::default();
+
+        for native in natives.iter() {
+            let function = NativeObject::new(
+                native.params.len() as u8,
+                Rc::clone(&native.function),
+                heap.objects(),
+            );
+
+            native_functions.insert(
+                symbols.symbol(&native.name),
+                Value::object(heap.allocate(function)),
+            );
+        }
+
+        State {
+            heap,
+            globals: FnvHashMap::default(),
+            native_functions,
+        }
+    }
+
+    /// Removes a global i.e one whose value was never set because the program failed
+    pub fn remove_global(&mut self, name: Symbol) {
+        self.globals.remove(&name);
+    }
+}
+
 impl<'a> VM<'a> {
-    pub fn new(main: Symbol, program: &'a Program, objects: RawObject) -> Result<Self, Error> {
+    /// Creates a vm that runs the `main` function of the program.
+    /// The vm takes ownership of the objects that were allocated when the program was
+    /// compiled and frees them when it is dropped
+    pub fn new(
+        main: Symbol,
+        program: &'a Program,
+        objects: RawObject,
+        natives: &NativeRegistry,
+        symbols: &mut Symbols<()>,
+    ) -> Result<Self, Error> {
+        Self::with_state(main, program, objects, State::new(natives, symbols))
+    }
+
+    /// Creates a vm that runs the `main` function of the program using the heap and
+    /// globals left behind by an earlier vm
+    pub fn with_state(
+        main: Symbol,
+        program: &'a Program,
+        objects: RawObject,
+        state: State,
+    ) -> Result<Self, Error> {
         let main_function = program.functions.get(&main);
 
         if main_function.is_none() {
@@ -47,59 +202,101 @@ impl<'a> VM<'a> {
             locals: FnvHashMap::default(),
             function: main_function.unwrap(),
             params: FnvHashMap::default(),
+            closure: None,
+            instance: None,
         };
 
-        let mut native_functions = FnvHashMap::default();
-        native_functions.insert(
-            Symbol(1),
-            Value::object(NativeObject::new(2, native::random, objects)),
-        );
-
-        native_functions.insert(
-            Symbol(2),
-            Value::object(NativeObject::new(0, native::clock, objects)),
-        );
-
-        native_functions.insert(
-            Symbol(3),
-            Value::object(NativeObject::new(0, native::read, objects)),
-        );
-
-        native_functions.insert(
-            Symbol(4),
-            Value::object(NativeObject::new(1, native::fopen, objects)),
-        );
+        let State {
+            mut heap,
+            globals,
+            native_functions,
+        } = state;
+
+        heap.adopt(objects);
 
         Ok(VM {
-            stack: [Value::nil(); STACK_MAX],
+            stack: vec![Value::nil(); STACK_MAX],
             current_frame,
             program,
             frames: Vec::new(),
             stack_top: 4,
             native_functions,
-            objects,
+            heap,
+            globals,
+            open_upvalues: Vec::new(),
+            output: Box::new(io::stdout()),
         })
     }
 
-    pub fn run(&mut self) {
+    /// Consumes the vm returning the state an other vm can carry on from. Any variable
+    /// still captured by a closure is moved into its upvalue as the frames are discarded
+    pub fn into_state(mut self) -> State {
+        for upvalue in ::std::mem::take(&mut self.open_upvalues) {
+            let upvalue = Value::object(upvalue).as_mut_upvalue();
+            upvalue.value = Upvalue::Closed(self.read_location(upvalue.value));
+        }
+
+        State {
+            heap: self.heap,
+            globals: self.globals,
+            native_functions: self.native_functions,
+        }
+    }
+
+    /// Runs a function of the program with the arguments and returns the value it returns.
+    /// The vm takes ownership of any object within the arguments so they must have been
+    /// created outside of a vm with a null `next` pointer
+    pub fn call(&mut self, function: Symbol, args: Vec<Value>) -> RuntimeResult<Value> {
+        let function = match self.program.functions.get(&function) {
+            Some(function) => function,
+            None => return Err(self.error(0, "Undefined function")),
+        };
+
+        let params = args
+            .into_iter()
+            .map(|arg| self.adopt(arg))
+            .enumerate()
+            .collect();
+
+        self.current_frame = StackFrame {
+            ip: 0,
+            locals: FnvHashMap::default(),
+            function,
+            params,
+            closure: None,
+            instance: None,
+        };
+
+        self.run()?;
+
+        Ok(self.pop())
+    }
+
+    pub fn run(&mut self) -> RuntimeResult<()> {
         #[cfg(feature = "debug")]
         {
             for (_, func) in self.program.functions.iter() {
-                func.body.disassemble(&format!("{}", func.name))
+                print!("{}", func.body.disassemble(&format!("{}", func.name)))
             }
 
             for (_, class) in self.program.classes.iter() {
-                let _: Vec<()> = class
-                    .methods
-                    .iter()
-                    .map(|(_, func)| func.body.disassemble(&format!("{}", func.name)))
-                    .collect();
+                for (_, func) in class.methods.iter() {
+                    print!("{}", func.body.disassemble(&format!("{}", func.name)))
+                }
             }
         }
 
         loop {
             if self.current_frame.ip >= self.current_frame.function.body.code.len() {
-                return;
+                return Ok(());
+            }
+
+            if self.stack_top > STACK_MAX {
+                return Err(self.error(self.current_frame.ip, "Stack overflow"));
+            }
+
+            if self.heap.should_collect() {
+                self.collect_garbage();
             }
 
             #[cfg(feature = "stack")]
@@ -113,6 +310,8 @@ impl<'a> VM<'a> {
                 println!("]")
             }
 
+            let offset = self.current_frame.ip;
+
             match self.read_byte() {
                 opcode::HLT => {
                     break;
@@ -121,6 +320,8 @@ impl<'a> VM<'a> {
                 opcode::RETURN => {
                     let value = self.pop();
 
+                    self.close_upvalues(|_| true); // The frame's variables are about to disappear
+
                     match self.frames.pop() {
                         Some(frame) => {
                             self.current_frame = frame;
@@ -128,7 +329,9 @@ impl<'a> VM<'a> {
                         }
 
                         None => {
-                            continue; // Were are return from a top level function main
+                            // returning from the function the vm was started with
+                            self.push(value);
+                            return Ok(());
                         }
                     }
                 }
@@ -140,14 +343,17 @@ impl<'a> VM<'a> {
 
                 opcode::PRINT => {
                     let value = self.pop();
-                    println!("{}", value);
-                }
 
-                opcode::NEGATE => {
-                    let val = Value::int(-self.pop().as_int());
-                    self.push(val)
+                    if writeln!(self.output, "{}", value).is_err() {
+                        return Err(self.error(offset, "Couldn't write to the output"));
+                    }
                 }
 
+                opcode::NEGATE => match self.pop().as_int().checked_neg() {
+                    Some(val) => self.push(Value::int(val)),
+                    None => return Err(self.error(offset, "Integer overflow")),
+                },
+
                 opcode::NEGATEF => {
                     let val = Value::float(-self.pop().as_float());
                     self.push(val)
@@ -169,11 +375,11 @@ impl<'a> VM<'a> {
                 }
 
                 opcode::ARRAY => {
-                    let len = self.read_byte();
+                    let len = self.read_operand();
 
                     let items: Vec<Value> = (0..len).map(|_| self.pop()).collect();
 
-                    let array = ArrayObject::new(items, self.objects);
+                    let array = self.allocate(ArrayObject::new(items, self.heap.objects()));
 
                     self.push(Value::object(array));
                 }
@@ -182,14 +388,50 @@ impl<'a> VM<'a> {
                 opcode::LESSF => binary_op!(<,as_float,bool,self),
                 opcode::GREATER => binary_op!(>,as_int,bool,self),
                 opcode::GREATERF => binary_op!(>,as_float,bool,self),
-                opcode::ADD => binary_op!(+,as_int,int,self),
+                opcode::ADD => checked_int_op!(checked_add, self, offset),
                 opcode::ADDF => binary_op!(+,as_float,float,self),
-                opcode::SUB => binary_op!(-,as_int,int,self),
+                opcode::SUB => checked_int_op!(checked_sub, self, offset),
                 opcode::SUBF => binary_op!(-,as_float,float,self),
-                opcode::MUL => binary_op!(*,as_int,int,self),
+                opcode::MUL => checked_int_op!(checked_mul, self, offset),
                 opcode::MULF => binary_op!(*,as_float,float,self),
-                opcode::DIV => binary_op!(/,as_int,int,self),
+                opcode::DIV => {
+                    let b = self.pop().as_int();
+                    let a = self.pop().as_int();
+
+                    match a.checked_div(b) {
+                        Some(result) => self.push(Value::int(result)),
+                        None if b == 0 => return Err(self.error(offset, "Division by zero")),
+                        None => return Err(self.error(offset, "Integer overflow")),
+                    }
+                }
                 opcode::DIVF => binary_op!(/,as_float,float,self),
+                opcode::MOD => {
+                    let b = self.pop().as_int();
+                    let a = self.pop().as_int();
+
+                    match a.checked_rem(b) {
+                        Some(result) => self.push(Value::int(result)),
+                        None if b == 0 => return Err(self.error(offset, "Modulo by zero")),
+                        None => return Err(self.error(offset, "Integer overflow")),
+                    }
+                }
+                opcode::MODF => binary_op!(%,as_float,float,self),
+                opcode::POW => {
+                    let b = self.pop().as_int();
+                    let a = self.pop().as_int();
+
+                    match int_pow(a, b) {
+                        Some(result) => self.push(Value::int(result)),
+                        None if a == 0 => return Err(self.error(offset, "Division by zero")),
+                        None => return Err(self.error(offset, "Integer overflow")),
+                    }
+                }
+                opcode::POWF => {
+                    let b = self.pop().as_float();
+                    let a = self.pop().as_float();
+
+                    self.push(Value::float(a.powf(b)))
+                }
                 opcode::INT2FLOAT => {
                     let value = self.pop().as_int();
                     self.push(Value::float(value as f64))
@@ -206,13 +448,15 @@ impl<'a> VM<'a> {
                 opcode::FLOAT2STR => {
                     let value = self.pop().as_float();
                     let value = format!("{}", value);
-                    self.push(Value::object(StringObject::from_owned(value, self.objects)));
+                    let value = self.allocate(StringObject::from_owned(value, self.heap.objects()));
+                    self.push(Value::object(value));
                 }
 
                 opcode::INT2STR => {
                     let value = self.pop().as_int();
                     let value = format!("{}", value);
-                    self.push(Value::object(StringObject::from_owned(value, self.objects)));
+                    let value = self.allocate(StringObject::from_owned(value, self.heap.objects()));
+                    self.push(Value::object(value));
                 }
 
                 opcode::LOOP => {
@@ -240,35 +484,71 @@ impl<'a> VM<'a> {
                     }
                 }
                 opcode::GETLOCAL => {
-                    let local = self.read_byte();
+                    let local = self.read_operand();
 
-                    let val = self.current_frame.locals[&local];
+                    let val = match self.current_frame.locals.get(&local) {
+                        Some(val) => *val,
+                        None => return Err(self.error(offset, "Undefined local")),
+                    };
 
                     self.push(val);
                 }
 
                 opcode::SETLOCAL => {
-                    let ident = self.read_byte();
+                    let ident = self.read_operand();
 
                     let val = self.stack[self.stack_top - 1]; // do it manually because we don't  want to modify the stack
 
                     self.current_frame.locals.insert(ident, val);
                 }
 
+                opcode::SETPARAM => {
+                    let param = self.read_operand();
+
+                    let val = self.stack[self.stack_top - 1]; // like SETLOCAL the value is left on the stack
+
+                    self.current_frame.params.insert(param, val);
+                }
+
                 opcode::GETPARAM => {
-                    let param = self.read_byte();
+                    let param = self.read_operand();
+
+                    let val = match self.current_frame.params.get(&param) {
+                        Some(val) => *val,
+                        None => return Err(self.error(offset, "Undefined param")),
+                    };
+
+                    self.push(val);
+                }
 
-                    let val = self.current_frame.params[&param];
+                opcode::GETGLOBAL => {
+                    let global = self.read_symbol();
+
+                    let val = match self.globals.get(&global) {
+                        Some(val) => *val,
+                        None => return Err(self.error(offset, "Undefined global")),
+                    };
 
                     self.push(val);
                 }
 
+                opcode::SETGLOBAL => {
+                    let global = self.read_symbol();
+
+                    let val = self.stack[self.stack_top - 1]; // like SETLOCAL the value is left on the stack
+
+                    self.globals.insert(global, val);
+                }
+
                 opcode::GETPROPERTY => {
                     let instance = self.pop();
                     let instance = instance.as_instance();
 
-                    let property = Symbol(u64::from(self.read_byte()));
-                    let value = instance.properties[&property];
+                    let property = self.read_symbol();
+                    let value = match instance.properties.get(&property) {
+                        Some(value) => *value,
+                        None => return Err(self.error(offset, "Undefined property")),
+                    };
 
                     self.push(value);
                 }
@@ -277,77 +557,94 @@ impl<'a> VM<'a> {
                     let instance = self.pop();
                     let instance = instance.as_instance();
 
-                    let method_name = Symbol(u64::from(self.read_byte()));
+                    let method_name = self.read_symbol();
 
-                    let method = &instance.methods[&method_name];
-                    let value = Value::object(FunctionObject::new(
+                    let method = match instance.methods.get(&method_name) {
+                        Some(method) => method,
+                        None => return Err(self.error(offset, "Undefined method")),
+                    };
+                    let value = Value::object(self.allocate(FunctionObject::new(
                         method.params.len(),
                         method.clone(),
-                        self.objects,
-                    ));
+                        self.heap.objects(),
+                    )));
 
                     self.push(value)
                 }
 
                 opcode::ENUM => {
-                    let enum_name = Symbol(u64::from(self.read_byte()));
-                    let tag = u32::from(self.read_byte());
-                    let object = EnumObject::new(enum_name, tag, None, self.objects);
+                    let enum_name = self.read_symbol();
+                    let tag = self.read_operand() as u32;
+                    let object = EnumObject::new(enum_name, tag, None, self.heap.objects());
+                    let object = self.allocate(object);
                     self.push(Value::object(object))
                 }
 
                 opcode::ENUMDATA => {
-                    let enum_name = Symbol(u64::from(self.read_byte()));
-                    let tag = u32::from(self.read_byte());
+                    let enum_name = self.read_symbol();
+                    let tag = self.read_operand() as u32;
                     let data = self.pop();
-                    let object = EnumObject::new(enum_name, tag, Some(data), self.objects);
+                    let object = EnumObject::new(enum_name, tag, Some(data), self.heap.objects());
+                    let object = self.allocate(object);
                     self.push(Value::object(object))
                 }
 
+                opcode::ENUMTAG => {
+                    let tag = self.pop().as_enum().tag;
+                    self.push(Value::int(i64::from(tag)))
+                }
+
+                opcode::ENUMPAYLOAD => {
+                    let data = self.pop().as_enum().data.unwrap_or_else(Value::nil);
+                    self.push(data)
+                }
+
                 opcode::SETPROPERTY => {
                     let instance = self.pop();
                     let instance = instance.as_mut_instance();
 
                     let value = self.pop();
 
-                    let property = Symbol(u64::from(self.read_byte()));
+                    let property = self.read_symbol();
 
                     instance.properties.insert(property, value);
                 }
 
                 opcode::CALLCLOSURE => {
-                    let arg_count = self.read_byte();
+                    let arg_count = self.read_operand();
 
                     let mut params = FnvHashMap::default();
 
-                    for i in 0..arg_count {
-                        params.insert(i, self.pop());
+                    for i in (0..arg_count).rev() {
+                        params.insert(i, self.pop()); // the last argument is on top of the stack
                     }
 
-                    let closure = self.pop();
-
-                    let closure = &closure.as_function().function;
+                    let closure = self.pop().as_closure();
 
                     let call_frame = StackFrame {
                         ip: 0,
                         locals: FnvHashMap::default(),
-                        function: closure,
+                        function: &closure.function.as_function().function,
                         params,
+                        closure: Some(closure),
+                        instance: None,
                     };
 
-                    self.frames
-                        .push(::std::mem::replace(&mut self.current_frame, call_frame));
+                    self.push_frame(call_frame, offset)?;
                 }
 
                 opcode::CALL => {
-                    let function_name = Symbol(u64::from(self.read_byte()));
-                    let arg_count = self.read_byte();
+                    let function_name = self.read_symbol();
+                    let arg_count = self.read_operand();
 
-                    let function = &self.program.functions[&function_name];
+                    let function = match self.program.functions.get(&function_name) {
+                        Some(function) => function,
+                        None => return Err(self.error(offset, "Undefined function")),
+                    };
 
                     let mut params = FnvHashMap::default();
 
-                    for i in 0..arg_count {
+                    for i in (0..arg_count).rev() {
                         params.insert(i, self.pop());
                     }
 
@@ -356,41 +653,47 @@ impl<'a> VM<'a> {
                         locals: FnvHashMap::default(),
                         function,
                         params,
+                        closure: None,
+                        instance: None,
                     };
 
-                    self.frames
-                        .push(::std::mem::replace(&mut self.current_frame, call_frame));
+                    self.push_frame(call_frame, offset)?;
                     // swaps the current frame with the one we are one and then
                 }
 
                 opcode::CALLNATIVE => {
-                    let function_name = Symbol(u64::from(self.read_byte()));
-                    let function = &self.native_functions[&function_name];
-                    let function = function.as_native();
+                    let function_name = self.read_symbol();
+                    let function = match self.native_functions.get(&function_name) {
+                        Some(function) => function.as_native(),
+                        None => return Err(self.error(offset, "Undefined native function")),
+                    };
 
                     let arg_count = function.arity;
                     let result = (function.function)(
-                        self.stack[self.stack_top - arg_count as usize..self.stack_top].as_ptr(),
+                        &self.stack[self.stack_top - arg_count as usize..self.stack_top],
                     );
 
                     self.stack_top -= arg_count as usize;
-                    {
-                        self.push(result);
-                    }
+
+                    self.adopt_native(result);
+                    self.push(result);
                 }
 
                 opcode::CALLINSTANCEMETHOD => {
-                    let method_name = Symbol(u64::from(self.read_byte()));
-                    let arg_count = self.read_byte();
+                    let method_name = self.read_symbol();
+                    let arg_count = self.read_operand();
 
-                    let instance = self.pop();
-                    let instance = instance.as_instance();
+                    let receiver = self.pop();
+                    let instance = receiver.as_instance();
 
-                    let function = &instance.methods[&method_name];
+                    let function = match instance.methods.get(&method_name) {
+                        Some(function) => function,
+                        None => return Err(self.error(offset, "Undefined method")),
+                    };
 
                     let mut params = FnvHashMap::default();
 
-                    for i in 0..arg_count {
+                    for i in (0..arg_count).rev() {
                         params.insert(i, self.pop());
                     }
 
@@ -399,21 +702,30 @@ impl<'a> VM<'a> {
                         locals: FnvHashMap::default(),
                         function,
                         params,
+                        closure: None,
+                        instance: Some(receiver), // keeps the method alive while it runs
                     };
 
-                    self.frames
-                        .push(::std::mem::replace(&mut self.current_frame, call_frame));
+                    self.push_frame(call_frame, offset)?;
                 }
 
                 opcode::CALLSTATICMETHOD => {
-                    let class_name = Symbol(u64::from(self.read_byte()));
-                    let method_name = Symbol(u64::from(self.read_byte()));
-                    let arg_count = self.read_byte();
-                    let function = &self.program.classes[&class_name].methods[&method_name];
+                    let class_name = self.read_symbol();
+                    let method_name = self.read_symbol();
+                    let arg_count = self.read_operand();
+                    let function = match self
+                        .program
+                        .classes
+                        .get(&class_name)
+                        .and_then(|class| class.methods.get(&method_name))
+                    {
+                        Some(function) => function,
+                        None => return Err(self.error(offset, "Undefined method")),
+                    };
 
                     let mut params = FnvHashMap::default();
 
-                    for i in 0..arg_count {
+                    for i in (0..arg_count).rev() {
                         params.insert(i, self.pop());
                     }
 
@@ -422,10 +734,11 @@ impl<'a> VM<'a> {
                         locals: FnvHashMap::default(),
                         function,
                         params,
+                        closure: None,
+                        instance: None,
                     };
 
-                    self.frames
-                        .push(::std::mem::replace(&mut self.current_frame, call_frame));
+                    self.push_frame(call_frame, offset)?;
                 }
 
                 opcode::POP => {
@@ -433,62 +746,576 @@ impl<'a> VM<'a> {
                 }
 
                 opcode::INDEXARRAY => {
-                    let index = self.pop().as_int() as usize;
+                    let index = self.pop().as_int();
+
+                    let array = self.pop();
+                    let array = array.as_array();
+
+                    match array.items.get(index as usize) {
+                        Some(item) if index >= 0 => self.push(*item),
+                        _ => {
+                            let message = format!(
+                                "Index `{}` is out of bounds for an array of length `{}`",
+                                index,
+                                array.items.len()
+                            );
+
+                            return Err(self.error(offset, message));
+                        }
+                    }
+                }
+
+                opcode::SETINDEX => {
+                    let value = self.pop();
+                    let index = self.pop().as_int();
+
+                    let array = self.pop();
+                    let array = array.as_mut_array();
+                    let len = array.items.len();
+
+                    match array.items.get_mut(index as usize) {
+                        Some(item) if index >= 0 => *item = value,
+                        _ => {
+                            let message = format!(
+                                "Index `{}` is out of bounds for an array of length `{}`",
+                                index, len
+                            );
+
+                            return Err(self.error(offset, message));
+                        }
+                    }
+
+                    self.push(value)
+                }
+
+                opcode::DUP2 => {
+                    let top = self.pop();
+                    let below = self.pop();
+
+                    self.push(below);
+                    self.push(top);
+                    self.push(below);
+                    self.push(top);
+                }
+
+                opcode::ARRAYLEN => {
+                    let array = self.pop();
+
+                    self.push(Value::int(array.as_array().items.len() as i64))
+                }
+
+                opcode::ARRAYPUSH => {
+                    let value = self.pop();
+
+                    let array = self.pop();
+                    let array = array.as_mut_array();
+                    let capacity = array.items.capacity();
+
+                    array.items.push(value);
+
+                    self.grow(capacity, array.items.capacity());
+                    self.push(Value::nil())
+                }
+
+                opcode::ARRAYPOP => {
+                    let array = self.pop();
+
+                    match array.as_mut_array().items.pop() {
+                        Some(item) => self.push(item),
+                        None => return Err(self.error(offset, "Can't pop from an empty array")),
+                    }
+                }
+
+                opcode::ARRAYINSERT => {
+                    let value = self.pop();
+                    let index = self.pop().as_int();
+
+                    let array = self.pop();
+                    let array = array.as_mut_array();
+                    let len = array.items.len();
+
+                    if index < 0 || index as usize > len {
+                        let message = format!(
+                            "Index `{}` is out of bounds for an array of length `{}`",
+                            index, len
+                        );
+
+                        return Err(self.error(offset, message));
+                    }
+
+                    let capacity = array.items.capacity();
+
+                    array.items.insert(index as usize, value);
+
+                    self.grow(capacity, array.items.capacity());
+                    self.push(Value::nil())
+                }
+
+                opcode::ARRAYREMOVE => {
+                    let index = self.pop().as_int();
+
+                    let array = self.pop();
+                    let array = array.as_mut_array();
+                    let len = array.items.len();
+
+                    if index < 0 || index as usize >= len {
+                        let message = format!(
+                            "Index `{}` is out of bounds for an array of length `{}`",
+                            index, len
+                        );
+
+                        return Err(self.error(offset, message));
+                    }
+
+                    let item = array.items.remove(index as usize);
+
+                    self.push(item)
+                }
+
+                opcode::ARRAYCONTAINS => {
+                    let value = self.pop();
+
+                    let array = self.pop();
+                    let found = array.as_array().items.contains(&value);
+
+                    self.push(Value::bool(found))
+                }
+
+                opcode::SLICEARRAY => {
+                    let end = self.pop();
+                    let start = self.pop();
 
                     let array = self.pop();
                     let array = array.as_array();
+                    let len = array.items.len();
 
-                    self.push(array.items[index]);
+                    let start = if start.is_nil() { 0 } else { start.as_int() };
+                    let end = if end.is_nil() {
+                        len as i64
+                    } else {
+                        end.as_int()
+                    };
+
+                    if start < 0 || start > end || end as usize > len {
+                        let message = format!(
+                            "Slice `{}..{}` is out of bounds for an array of length `{}`",
+                            start, end, len
+                        );
+
+                        return Err(self.error(offset, message));
+                    }
+
+                    let items = array.items[start as usize..end as usize].to_vec();
+                    let result = self.allocate(ArrayObject::new(items, self.heap.objects()));
+
+                    self.push(Value::object(result))
+                }
+
+                opcode::CONCATARRAY => {
+                    let b = self.pop();
+                    let a = self.pop();
+
+                    let mut items =
+                        Vec::with_capacity(a.as_array().items.len() + b.as_array().items.len());
+
+                    items.extend_from_slice(&a.as_array().items);
+                    items.extend_from_slice(&b.as_array().items);
+
+                    let result = self.allocate(ArrayObject::new(items, self.heap.objects()));
+
+                    self.push(Value::object(result))
+                }
+
+                opcode::STRLEN => {
+                    let string = self.pop();
+                    let len = string.as_string().value().chars().count();
+
+                    self.push(Value::int(len as i64))
+                }
+
+                opcode::PARSEINT => {
+                    let enum_name = self.read_symbol();
+                    let string = self.pop();
+
+                    let parsed = string.as_string().value().parse().ok().map(Value::int);
+
+                    self.push_option(enum_name, parsed)
+                }
+
+                opcode::PARSEFLOAT => {
+                    let enum_name = self.read_symbol();
+                    let string = self.pop();
+
+                    let parsed = string.as_string().value().parse().ok().map(Value::float);
+
+                    self.push_option(enum_name, parsed)
                 }
 
                 opcode::INDEXSTRING => {
-                    let index = self.pop().as_int() as usize;
+                    let index = self.pop().as_int();
 
                     let string = self.pop();
                     let string = string.as_string();
+                    let chars = string.chars.string();
 
-                    let slice = &string.chars.string()[index..=index];
-                    let result = StringObject::new(slice, self.objects);
+                    // strings are indexed by char like `len` counts them
+                    let ch = if index >= 0 {
+                        chars.chars().nth(index as usize)
+                    } else {
+                        None
+                    };
+
+                    let slice = match ch {
+                        Some(ch) => ch.to_string(),
+                        None => {
+                            let message = format!(
+                                "Index `{}` is out of bounds for a string of length `{}`",
+                                index,
+                                chars.chars().count()
+                            );
+
+                            return Err(self.error(offset, message));
+                        }
+                    };
+                    let result =
+                        self.allocate(StringObject::from_owned(slice, self.heap.objects()));
 
                     self.push(Value::object(result))
                 }
 
                 opcode::CLASSINSTANCE => {
-                    let class_name = Symbol(u64::from(self.read_byte()));
+                    let class_name = self.read_symbol();
 
-                    let num_properties = self.read_byte() as usize;
+                    let num_properties = self.read_operand();
 
-                    let class = &self.program.classes[&class_name];
+                    let class = match self.program.classes.get(&class_name) {
+                        Some(class) => class,
+                        None => return Err(self.error(offset, "Undefined class")),
+                    };
 
                     let methods = class.methods.clone();
 
                     let mut properties = FnvHashMap::default();
 
                     for _ in 0..num_properties {
-                        properties.insert(Symbol(u64::from(self.read_byte())), self.pop());
+                        properties.insert(self.read_symbol(), self.pop());
                     }
 
-                    let instance = InstanceObject::new(methods, properties, self.objects);
+                    let instance = InstanceObject::new(methods, properties, self.heap.objects());
+                    let instance = self.allocate(instance);
 
                     self.push(Value::object(instance));
                 }
 
                 opcode::CONCAT => self.concat(),
 
-                #[cfg(not(feature = "debug"))]
-                _ => unsafe {
-                    use std::hint::unreachable_unchecked;
-                    unreachable_unchecked()
-                },
-                #[cfg(feature = "debug")]
-                ref e => {
-                    {
-                        println!("unknown opcode found :{}", e);
+                opcode::CONCATN => {
+                    let count = self.read_operand();
+                    let first = self.stack_top - count;
+
+                    let mut new = String::new();
+
+                    for value in self.stack[first..self.stack_top].iter() {
+                        new.push_str(value.as_string().value());
                     }
-                    continue;
+
+                    self.stack_top = first;
+
+                    let result = self.allocate(StringObject::from_owned(new, self.heap.objects()));
+
+                    self.push(Value::object(result))
+                }
+
+                opcode::CLOSURE => {
+                    let function = self.read_constant();
+                    let count = self.read_operand();
+
+                    let frame = self.frames.len();
+                    let mut upvalues = Vec::with_capacity(count as usize);
+
+                    for _ in 0..count {
+                        let kind = self.read_byte();
+                        let index = self.read_operand();
+
+                        let upvalue = match kind {
+                            0 => self.capture_upvalue(Upvalue::Local { frame, slot: index }),
+                            1 => self.capture_upvalue(Upvalue::Param { frame, slot: index }),
+                            _ => self.current_frame.closure.unwrap().upvalues[index as usize],
+                        };
+
+                        upvalues.push(upvalue);
+                    }
+
+                    let closure = ClosureObject::new(function, upvalues, self.heap.objects());
+                    let closure = self.allocate(closure);
+
+                    self.push(Value::object(closure));
+                }
+
+                opcode::GETUPVALUE => {
+                    let index = self.read_operand();
+                    let upvalue = self.current_frame.closure.unwrap().upvalues[index];
+
+                    let value = match Value::object(upvalue).as_mut_upvalue().value {
+                        Upvalue::Closed(value) => value,
+                        location => self.read_location(location),
+                    };
+
+                    self.push(value);
+                }
+
+                opcode::SETUPVALUE => {
+                    let index = self.read_operand();
+                    let upvalue =
+                        Value::object(self.current_frame.closure.unwrap().upvalues[index])
+                            .as_mut_upvalue();
+
+                    let value = self.stack[self.stack_top - 1]; // assignment leaves the value on the stack
+
+                    match upvalue.value {
+                        Upvalue::Local { frame, slot } => {
+                            self.frame_mut(frame).locals.insert(slot, value);
+                        }
+                        Upvalue::Param { frame, slot } => {
+                            self.frame_mut(frame).params.insert(slot, value);
+                        }
+                        Upvalue::Closed(_) => upvalue.value = Upvalue::Closed(value),
+                    }
+                }
+
+                opcode::CLOSEUPVALUE => {
+                    let slot = self.read_operand();
+
+                    self.close_upvalues(|location| match location {
+                        Upvalue::Local { slot: local, .. } => local == slot,
+                        _ => false,
+                    });
+                }
+
+                unknown => {
+                    let message = format!("Unknown opcode `{}`", unknown);
+                    return Err(self.error(offset, message));
                 }
             }
         }
+
+        Ok(())
+    }
+
+    /// Starts running a called function. A call that leaves its operands on the stack
+    /// overflows the stack first but a call in tail position only adds a frame
+    fn push_frame(&mut self, frame: StackFrame<'a>, offset: usize) -> RuntimeResult<()> {
+        if self.frames.len() >= FRAMES_MAX {
+            return Err(self.error(offset, "Stack overflow"));
+        }
+
+        self.frames
+            .push(::std::mem::replace(&mut self.current_frame, frame));
+
+        Ok(())
+    }
+
+    /// Creates an error raised by the instruction at offset in the current frame along
+    /// with a trace of the call stack
+    fn error<T: Into<String>>(&self, offset: usize, message: T) -> RuntimeError {
+        let function = self.current_frame.function;
+        let span = function.body.span(offset);
+
+        let mut trace = vec![(function.name, span)];
+
+        for frame in self.frames.iter().rev() {
+            // The frame's ip is just past the call instruction it is running
+            trace.push((frame.function.name, frame.function.body.span(frame.ip - 1)));
+        }
+
+        RuntimeError {
+            message: message.into(),
+            function: function.name,
+            offset,
+            span,
+            trace,
+        }
+    }
+
+    /// Statistics about the garbage collector
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Sets where `print` writes to; stdout by default
+    pub fn set_output<W: Write + 'a>(&mut self, output: W) {
+        self.output = Box::new(output);
+    }
+
+    /// When set the garbage collector runs before every instruction
+    pub fn set_stress_gc(&mut self, stress: bool) {
+        self.heap.set_stress(stress)
+    }
+
+    /// Links an object created outside of the vm, along with the objects within it, into
+    /// the heap
+    fn adopt(&mut self, value: Value) -> Value {
+        if !value.is_object() {
+            return value;
+        }
+
+        if value.is_array() {
+            for item in value.as_array().items.iter() {
+                self.adopt(*item);
+            }
+        }
+
+        Value::object(self.allocate(value.as_object()))
+    }
+
+    fn allocate(&mut self, object: RawObject) -> RawObject {
+        self.heap.allocate(object)
+    }
+
+    /// Pushes `Some(value)` of the enum if there is a value or `None` if there isn't
+    fn push_option(&mut self, enum_name: Symbol, value: Option<Value>) {
+        let tag = if value.is_some() { 0 } else { 1 };
+
+        let object = EnumObject::new(enum_name, tag, value, self.heap.objects());
+        let object = self.allocate(object);
+
+        self.push(Value::object(object))
+    }
+
+    /// Native functions allocate the objects they return outside of the vm, including the
+    /// items of an array they return, so they are added to the heap here. An object the heap
+    /// already owns, such as one of the arguments, is left alone
+    fn adopt_native(&mut self, result: Value) {
+        if !result.is_object() || unsafe { (*result.as_object()).owned } {
+            return;
+        }
+
+        self.allocate(result.as_object());
+
+        if result.is_array() {
+            for item in result.as_array().items.iter() {
+                self.adopt_native(*item);
+            }
+        }
+    }
+
+    /// Counts the extra items an array made room for when it was pushed to
+    fn grow(&mut self, before: usize, after: usize) {
+        self.heap
+            .grow((after - before) * ::std::mem::size_of::<Value>())
+    }
+
+    /// Marks every value the vm can still reach and frees the rest.
+    /// This is only done between instructions so every live value is within a root
+    fn collect_garbage(&mut self) {
+        for value in self.stack[0..self.stack_top].iter() {
+            self.heap.mark_value(*value);
+        }
+
+        for frame in self
+            .frames
+            .iter()
+            .chain(::std::iter::once(&self.current_frame))
+        {
+            self.heap.mark_function(frame.function);
+
+            for value in frame.locals.values().chain(frame.params.values()) {
+                self.heap.mark_value(*value);
+            }
+
+            if let Some(closure) = frame.closure {
+                self.heap
+                    .mark_object(closure as *const ClosureObject as RawObject);
+            }
+
+            if let Some(instance) = frame.instance {
+                self.heap.mark_value(instance);
+            }
+        }
+
+        for value in self.globals.values() {
+            self.heap.mark_value(*value);
+        }
+
+        for native in self.native_functions.values() {
+            self.heap.mark_value(*native);
+        }
+
+        for function in self.program.functions.values() {
+            self.heap.mark_function(function);
+        }
+
+        for class in self.program.classes.values() {
+            for method in class.methods.values() {
+                self.heap.mark_function(method);
+            }
+        }
+
+        for upvalue in self.open_upvalues.iter() {
+            self.heap.mark_object(*upvalue);
+        }
+
+        self.heap.collect();
+    }
+
+    /// The stack frame at the given depth; the current frame is the deepest
+    fn frame_mut(&mut self, depth: usize) -> &mut StackFrame<'a> {
+        if depth == self.frames.len() {
+            &mut self.current_frame
+        } else {
+            &mut self.frames[depth]
+        }
+    }
+
+    fn read_location(&mut self, location: Upvalue) -> Value {
+        match location {
+            Upvalue::Local { frame, slot } => self.frame_mut(frame).locals[&slot],
+            Upvalue::Param { frame, slot } => self.frame_mut(frame).params[&slot],
+            Upvalue::Closed(value) => value,
+        }
+    }
+
+    /// Returns the open upvalue that points at the location or creates a new one so
+    /// that closures capturing the same variable share it
+    fn capture_upvalue(&mut self, location: Upvalue) -> RawObject {
+        for upvalue in self.open_upvalues.iter() {
+            if Value::object(*upvalue).as_mut_upvalue().value == location {
+                return *upvalue;
+            }
+        }
+
+        let upvalue = UpvalueObject::new(location, self.heap.objects());
+        let upvalue = self.allocate(upvalue);
+
+        self.open_upvalues.push(upvalue);
+
+        upvalue
+    }
+
+    /// Closes the open upvalues of the current frame whose location matches by copying
+    /// the variable into the upvalue
+    fn close_upvalues<F>(&mut self, matches: F)
+    where
+        F: Fn(Upvalue) -> bool,
+    {
+        let depth = self.frames.len();
+        let mut i = 0;
+
+        while i < self.open_upvalues.len() {
+            let upvalue = Value::object(self.open_upvalues[i]).as_mut_upvalue();
+
+            let in_frame = match upvalue.value {
+                Upvalue::Local { frame, .. } | Upvalue::Param { frame, .. } => frame == depth,
+                Upvalue::Closed(_) => false,
+            };
+
+            if in_frame && matches(upvalue.value) {
+                upvalue.value = Upvalue::Closed(self.read_location(upvalue.value));
+                self.open_upvalues.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
     }
 
     fn concat(&mut self) {
@@ -510,13 +1337,13 @@ impl<'a> VM<'a> {
             println!("{:?}", b.chars);
         }
 
-        let result = StringObject::from_owned(new, self.objects);
+        let result = self.allocate(StringObject::from_owned(new, self.heap.objects()));
 
         self.push(Value::object(result));
     }
 
     fn read_constant(&mut self) -> Value {
-        let index = self.read_byte() as usize;
+        let index = self.read_operand();
         self.current_frame.function.body.constants[index]
     }
 
@@ -529,6 +1356,22 @@ impl<'a> VM<'a> {
         result
     }
 
+    fn read_operand(&mut self) -> usize {
+        let (operand, ip) = self
+            .current_frame
+            .function
+            .body
+            .read_operand(self.current_frame.ip);
+
+        self.current_frame.ip = ip;
+
+        operand
+    }
+
+    fn read_symbol(&mut self) -> Symbol {
+        Symbol(self.read_operand() as u64)
+    }
+
     fn read_byte(&mut self) -> u8 {
         let byte = self.current_frame.function.body.code[self.current_frame.ip];
         self.current_frame.ip += 1;
@@ -536,7 +1379,12 @@ impl<'a> VM<'a> {
     }
 
     fn push(&mut self, val: Value) {
-        self.stack[self.stack_top] = val;
+        if self.stack_top == self.stack.len() {
+            self.stack.push(val);
+        } else {
+            self.stack[self.stack_top] = val;
+        }
+
         self.stack_top += 1;
     }
 
@@ -546,6 +1394,31 @@ impl<'a> VM<'a> {
     }
 }
 
+/// Raises an int to an int power. A negative power is truncated towards zero like
+/// integer division. Returns `None` when the result overflows or the base is 0 and the
+/// power is negative
+fn int_pow(base: i64, exponent: i64) -> Option<i64> {
+    if exponent >= 0 {
+        if exponent > i64::from(u32::MAX) {
+            return match base {
+                0 | 1 => Some(base),
+                -1 => Some(if exponent % 2 == 0 { 1 } else { -1 }),
+                _ => None,
+            };
+        }
+
+        return base.checked_pow(exponent as u32);
+    }
+
+    match base {
+        0 => None,
+        1 => Some(1),
+        -1 if exponent % 2 == 0 => Some(1),
+        -1 => Some(-1),
+        _ => Some(0),
+    }
+}
+
 use std::fmt::{self, Debug};
 
 impl<'a> Debug for VM<'a> {